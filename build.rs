@@ -0,0 +1,48 @@
+// With the `baked-stations` feature, builds the perfect hash over the station
+// list named by `BRC_STATIONS` at compile time and writes it to
+// `$OUT_DIR/baked_stations.rs`, so runs find station slots without loading
+// or hashing a list at startup. Without the feature this does nothing.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+#[allow(dead_code)]
+#[path = "src/perfect_hash.rs"]
+mod perfect_hash;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/perfect_hash.rs");
+    println!("cargo:rerun-if-env-changed=BRC_STATIONS");
+    if env::var_os("CARGO_FEATURE_BAKED_STATIONS").is_none() {
+        return;
+    }
+
+    let path = env::var("BRC_STATIONS").unwrap_or_else(|_| {
+        panic!("the `baked-stations` feature needs BRC_STATIONS=<path to a station list>")
+    });
+    println!("cargo:rerun-if-changed={}", path);
+    let hash = perfect_hash::load(&path, b';')
+        .unwrap_or_else(|e| panic!("failed to read BRC_STATIONS={}: {}", path, e));
+
+    let mut table = String::new();
+    writeln!(table, "pub const SALT: u64 = {};", hash.salt()).unwrap();
+    writeln!(
+        table,
+        "pub static SEEDS: [u32; {}] = {:?};",
+        hash.seeds().len(),
+        hash.seeds()
+    )
+    .unwrap();
+    writeln!(
+        table,
+        "pub static NAMES: [&str; {}] = {:?};",
+        hash.names().len(),
+        hash.names()
+    )
+    .unwrap();
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("baked_stations.rs");
+    fs::write(out, table).unwrap();
+}
@@ -0,0 +1,349 @@
+// The line parser for measurement files, using only `core`: no allocation,
+// no I/O and no panics, so firmware and WASM builds can reuse it. The `brc`
+// crate re-exports it from `brc::parse`, which adds the parsers that build
+// owned keys.
+
+#![no_std]
+
+use core::str::FromStr;
+
+// Reasons a line can be rejected by the parser.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineError {
+    MissingDelimiter,
+    EmptyStation,
+    // A station name longer than the spec's `MAX_STATION_BYTES`.
+    StationTooLong,
+    InvalidTemperature,
+    // A well-formed temperature outside the spec's -99.9..=99.9.
+    OutOfRange,
+    // A `--time-col` field that is not a timestamp `window` understands.
+    InvalidTimestamp,
+}
+
+impl LineError {
+    // Stable reason code used in reports and error messages.
+    pub fn code(self) -> &'static str {
+        match self {
+            LineError::MissingDelimiter => "missing-delimiter",
+            LineError::EmptyStation => "empty-station",
+            LineError::StationTooLong => "station-too-long",
+            LineError::InvalidTemperature => "invalid-temperature",
+            LineError::OutOfRange => "out-of-range",
+            LineError::InvalidTimestamp => "invalid-timestamp",
+        }
+    }
+}
+
+// UTF-8 byte order mark that Windows tools write at the start of files. It
+// is skipped at the start of a line so the first station does not become a
+// distinct key.
+pub const BOM: &str = "\u{feff}";
+
+// Longest station name allowed by the spec, in bytes.
+pub const MAX_STATION_BYTES: usize = 100;
+
+// Largest temperature magnitude allowed by the spec, in tenths of a degree.
+pub const MAX_TENTHS: i16 = 999;
+
+// How strictly temperatures are read, selected with `--numbers`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Numbers {
+    // Only the challenge's own layouts, `N.N` and `NN.N` with an optional
+    // minus sign.
+    Strict,
+    // Plain decimal notation with at most one fractional digit, such as `7`
+    // or `07.0`.
+    Plain,
+    // Like `Plain`, ignoring spaces around the value, as in ` 7.0`.
+    Padded,
+}
+
+impl FromStr for Numbers {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(Numbers::Strict),
+            "plain" => Ok(Numbers::Plain),
+            "padded" => Ok(Numbers::Padded),
+            _ => Err(()),
+        }
+    }
+}
+
+// How temperatures are written: their decimal separator, `.` or `,`, and
+// which layouts are accepted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Notation {
+    pub decimal: u8,
+    pub numbers: Numbers,
+}
+
+impl Notation {
+    // The notation `parse_line` reads.
+    pub const DEFAULT: Notation = Notation {
+        decimal: b'.',
+        numbers: Numbers::Plain,
+    };
+}
+
+// Splits a line into its station name and temperature.
+pub fn parse_line(line: &str) -> Result<(&str, f32), LineError> {
+    parse_line_with(line, b';')
+}
+
+// Like `parse_line`, with a different field separator, which must be ASCII.
+#[inline]
+pub fn parse_line_with(line: &str, delimiter: u8) -> Result<(&str, f32), LineError> {
+    parse_line_in(line, delimiter, Notation::DEFAULT)
+}
+
+// Like `parse_line_with`, with temperatures written in `notation`.
+#[inline]
+pub fn parse_line_in(
+    line: &str,
+    delimiter: u8,
+    notation: Notation,
+) -> Result<(&str, f32), LineError> {
+    let line = line.strip_prefix(BOM).unwrap_or(line);
+    let (station, temp_str) = split_once(line, delimiter).ok_or(LineError::MissingDelimiter)?;
+    check_station(station.as_bytes())?;
+    let temp = parse_temperature_in(temp_str, notation)?;
+    Ok((station, temp))
+}
+
+// Splits a string once based on a given delimiter, which must be ASCII.
+#[inline]
+pub fn split_once(input: &str, delimiter: u8) -> Option<(&str, &str)> {
+    let pos = input.as_bytes().iter().position(|&b| b == delimiter)?;
+    Some((input.get(..pos)?, input.get(pos + 1..)?))
+}
+
+// Parses a temperature value from a string. Only the spec's plain decimal
+// notation with at most one fractional digit is accepted, within
+// -99.9..=99.9. Dividing the exact tenths by ten rounds to the same `f32` as
+// parsing the decimal directly.
+#[inline]
+pub fn parse_temperature(temp_str: &str) -> Result<f32, LineError> {
+    parse_temperature_in(temp_str, Notation::DEFAULT)
+}
+
+// Like `parse_temperature`, for a temperature written in `notation`.
+#[inline]
+pub fn parse_temperature_in(temp_str: &str, notation: Notation) -> Result<f32, LineError> {
+    Ok(parse_tenths_in(temp_str.as_bytes(), notation)? as f32 / 10.0)
+}
+
+// Splits a raw line into its station name and temperature in tenths of a
+// degree, without requiring valid UTF-8. A leading BOM and a trailing `\r`
+// are ignored.
+#[inline]
+pub fn parse_record(line: &[u8]) -> Result<(&[u8], i16), LineError> {
+    let line = line.strip_prefix(BOM.as_bytes()).unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let pos = line
+        .iter()
+        .position(|&b| b == b';')
+        .ok_or(LineError::MissingDelimiter)?;
+    let (station, temp) = (&line[..pos], &line[pos + 1..]);
+    check_station(station)?;
+    Ok((station, parse_tenths(temp)?))
+}
+
+// Checks that a station name is neither empty nor longer than the spec
+// allows.
+#[inline]
+pub fn check_station(station: &[u8]) -> Result<(), LineError> {
+    match station.len() {
+        0 => Err(LineError::EmptyStation),
+        len if len > MAX_STATION_BYTES => Err(LineError::StationTooLong),
+        _ => Ok(()),
+    }
+}
+
+// Parses a temperature such as `-12.3` into tenths of a degree. At most one
+// fractional digit is accepted, and the value must be within -99.9..=99.9.
+#[inline]
+pub fn parse_tenths(bytes: &[u8]) -> Result<i16, LineError> {
+    parse_tenths_in(bytes, Notation::DEFAULT)
+}
+
+// Like `parse_tenths`, for a temperature written in `notation`, such as
+// `-12,3` in European exports with a decimal comma.
+#[inline]
+pub fn parse_tenths_in(bytes: &[u8], notation: Notation) -> Result<i16, LineError> {
+    if let Some(tenths) = decode_shape(bytes, notation.decimal) {
+        return Ok(tenths);
+    }
+    match notation.numbers {
+        // A value only the other policies accept is still rejected; any
+        // other keeps the reason they would give.
+        Numbers::Strict => {
+            parse_tenths_generic(bytes, notation.decimal).and(Err(LineError::InvalidTemperature))
+        }
+        Numbers::Plain => parse_tenths_generic(bytes, notation.decimal),
+        Numbers::Padded => parse_tenths_generic(trim_spaces(bytes), notation.decimal),
+    }
+}
+
+// Strips leading and trailing spaces.
+fn trim_spaces(mut bytes: &[u8]) -> &[u8] {
+    while let [b' ', rest @ ..] = bytes {
+        bytes = rest;
+    }
+    while let [rest @ .., b' '] = bytes {
+        bytes = rest;
+    }
+    bytes
+}
+
+// Decodes the four layouts the challenge's values come in, `N.N`, `NN.N`,
+// `-N.N` and `-NN.N`. The sign byte and the separator's position select
+// which bytes are digits, which are then checked and combined without a
+// per-byte loop or a branch per digit. Any other input is left to
+// `parse_tenths_generic`.
+#[inline(always)]
+fn decode_shape(bytes: &[u8], decimal: u8) -> Option<i16> {
+    let negative = bytes.first() == Some(&b'-');
+    let (tens, ones, tenths) = match bytes[negative as usize..] {
+        [ones, separator, tenths] if separator == decimal => (b'0', ones, tenths),
+        [tens, ones, separator, tenths] if separator == decimal => (tens, ones, tenths),
+        _ => return None,
+    };
+    let (tens, ones, tenths) = (
+        tens.wrapping_sub(b'0'),
+        ones.wrapping_sub(b'0'),
+        tenths.wrapping_sub(b'0'),
+    );
+    if (tens > 9) | (ones > 9) | (tenths > 9) {
+        return None;
+    }
+    let value = tens as i16 * 100 + ones as i16 * 10 + tenths as i16;
+    Some(value * (1 - 2 * negative as i16))
+}
+
+// Parses any other accepted layout, such as `5` or `05.0`, and finds the
+// reason an input is rejected.
+#[cold]
+fn parse_tenths_generic(bytes: &[u8], decimal: u8) -> Result<i16, LineError> {
+    let (negative, digits) = match bytes.split_first() {
+        Some((b'-', rest)) => (true, rest),
+        _ => (false, bytes),
+    };
+    let (integer, fraction) = match digits.iter().position(|&b| b == decimal) {
+        Some(pos) => (&digits[..pos], &digits[pos + 1..]),
+        None => (digits, &b"0"[..]),
+    };
+    if integer.is_empty() || fraction.len() != 1 {
+        return Err(LineError::InvalidTemperature);
+    }
+    let mut tenths: i32 = 0;
+    for &b in integer.iter().chain(fraction) {
+        if !b.is_ascii_digit() {
+            return Err(LineError::InvalidTemperature);
+        }
+        tenths = tenths * 10 + (b - b'0') as i32;
+        // Saturates on long digit runs; the range is checked below.
+        tenths = tenths.min(i16::MAX as i32);
+    }
+    if tenths > MAX_TENTHS as i32 {
+        return Err(LineError::OutOfRange);
+    }
+    Ok(if negative { -tenths } else { tenths } as i16)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::format;
+
+    // Every value from -99.9 to 99.9 in the challenge's layout decodes to its
+    // tenths, and to the same `f32` as parsing the decimal, with either
+    // decimal separator.
+    #[test]
+    fn every_temperature() {
+        let comma = Notation {
+            decimal: b',',
+            ..Notation::DEFAULT
+        };
+        for tenths in -MAX_TENTHS..=MAX_TENTHS {
+            let sign = if tenths < 0 { "-" } else { "" };
+            let text = format!("{}{}.{}", sign, tenths.abs() / 10, tenths.abs() % 10);
+            assert_eq!(parse_tenths(text.as_bytes()), Ok(tenths), "{}", text);
+            assert_eq!(
+                parse_temperature(&text),
+                Ok(text.parse::<f32>().unwrap()),
+                "{}",
+                text
+            );
+            let text = text.replace('.', ",");
+            assert_eq!(
+                parse_tenths_in(text.as_bytes(), comma),
+                Ok(tenths),
+                "{}",
+                text
+            );
+        }
+    }
+
+    // The layouts outside the four fast ones are still accepted or rejected
+    // as before.
+    #[test]
+    fn other_layouts() {
+        let cases = [
+            ("-0.0", Ok(0)),
+            ("5", Ok(50)),
+            ("05.0", Ok(50)),
+            ("-099.9", Ok(-999)),
+            ("123.4", Err(LineError::OutOfRange)),
+            ("12.34", Err(LineError::InvalidTemperature)),
+            ("1.", Err(LineError::InvalidTemperature)),
+            (".5", Err(LineError::InvalidTemperature)),
+            ("-", Err(LineError::InvalidTemperature)),
+            ("", Err(LineError::InvalidTemperature)),
+            ("--1.0", Err(LineError::InvalidTemperature)),
+            ("+1.0", Err(LineError::InvalidTemperature)),
+            ("1x.0", Err(LineError::InvalidTemperature)),
+            ("1.x", Err(LineError::InvalidTemperature)),
+            ("1:.0", Err(LineError::InvalidTemperature)),
+            (" 7.0", Err(LineError::InvalidTemperature)),
+            ("1,5", Err(LineError::InvalidTemperature)),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse_tenths(text.as_bytes()), expected, "{:?}", text);
+        }
+    }
+
+    // `--numbers strict` and `padded` on either side of the default.
+    #[test]
+    fn number_policies() {
+        let cases = [
+            (Numbers::Strict, "07.0", Ok(70)),
+            (Numbers::Strict, "-7.0", Ok(-70)),
+            (Numbers::Strict, "7", Err(LineError::InvalidTemperature)),
+            (Numbers::Strict, "007.0", Err(LineError::InvalidTemperature)),
+            (Numbers::Strict, "123.4", Err(LineError::OutOfRange)),
+            (Numbers::Strict, " 7.0", Err(LineError::InvalidTemperature)),
+            (Numbers::Padded, " 7.0", Ok(70)),
+            (Numbers::Padded, "  -07 ", Ok(-70)),
+            (Numbers::Padded, "7 .0", Err(LineError::InvalidTemperature)),
+            (Numbers::Padded, " ", Err(LineError::InvalidTemperature)),
+        ];
+        for (numbers, text, expected) in cases {
+            let notation = Notation {
+                numbers,
+                ..Notation::DEFAULT
+            };
+            assert_eq!(
+                parse_tenths_in(text.as_bytes(), notation),
+                expected,
+                "{:?} with {:?}",
+                text,
+                numbers
+            );
+        }
+    }
+}
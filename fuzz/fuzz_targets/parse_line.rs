@@ -0,0 +1,27 @@
+#![no_main]
+
+use brc::parse::{parse_line, parse_record, parse_temperature, MAX_STATION_BYTES, MAX_TENTHS};
+use libfuzzer_sys::fuzz_target;
+
+// Feeds arbitrary input through the line parser the same way the aggregator
+// does and checks the invariants the hot path relies on.
+fuzz_target!(|data: &[u8]| {
+    for line in data.split(|&b| b == b'\n') {
+        if let Ok((station, tenths)) = parse_record(line) {
+            assert!(!station.is_empty() && station.len() <= MAX_STATION_BYTES);
+            assert!(!station.contains(&b';'));
+            assert!(tenths.abs() <= MAX_TENTHS);
+        }
+    }
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    for line in text.lines() {
+        if let Ok((station, temp)) = parse_line(line) {
+            assert!(!station.is_empty() && station.len() <= MAX_STATION_BYTES);
+            assert!(!station.contains(';'));
+            assert!(temp.abs() <= 99.9);
+        }
+        let _ = parse_temperature(line);
+    }
+});
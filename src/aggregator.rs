@@ -0,0 +1,215 @@
+// Incremental aggregation of a measurements file, one chunk at a time, for
+// callers that want progress reporting or early exit instead of a single
+// blocking call, and of records pushed as they arrive, for services that
+// query the statistics so far at any time.
+
+use crate::chunks;
+use crate::parse::{parse_line, parse_record};
+use crate::results::Results;
+use crate::station::StationData;
+use hashbrown::HashMap;
+use memmap::{Mmap, MmapOptions};
+use rayon::prelude::*;
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::ops::Range;
+use std::path::Path;
+
+// Default size of the chunks yielded by `ChunkedAggregator`.
+pub const DEFAULT_CHUNK_SIZE: usize = 32 * 1024 * 1024;
+
+// Iterator over a memory-mapped file yielding, per line-aligned chunk, the
+// total bytes processed so far and the stations aggregated from that chunk
+// alone. Lines of a chunk are folded in parallel. A malformed line or invalid
+// UTF-8 yields an `InvalidData` error and ends the iteration.
+pub struct ChunkedAggregator {
+    mmap: Mmap,
+    ranges: std::vec::IntoIter<Range<usize>>,
+    failed: bool,
+}
+
+impl ChunkedAggregator {
+    pub fn new(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::with_chunk_size(path, DEFAULT_CHUNK_SIZE)
+    }
+
+    pub fn with_chunk_size(path: impl AsRef<Path>, chunk_size: usize) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let mmap = unsafe { MmapOptions::new().map(&file)? };
+        let ranges = chunks::split(&mmap, chunk_size.max(1)).into_iter();
+        Ok(ChunkedAggregator {
+            mmap,
+            ranges,
+            failed: false,
+        })
+    }
+
+    // Size of the input in bytes, the final value of `bytes_processed`.
+    pub fn len(&self) -> usize {
+        self.mmap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mmap.is_empty()
+    }
+
+    fn aggregate(&self, range: Range<usize>) -> io::Result<HashMap<String, StationData>> {
+        let offset = range.start;
+        let content = std::str::from_utf8(&self.mmap[range]).map_err(|e| {
+            invalid_data(format!(
+                "invalid UTF-8 at byte {}",
+                offset + e.valid_up_to()
+            ))
+        })?;
+        content
+            .par_lines()
+            .try_fold(HashMap::new, |mut stations, line| {
+                let (station, temp) = parse_line(line).map_err(|reason| {
+                    let line_offset = line.as_ptr() as usize - content.as_ptr() as usize;
+                    invalid_data(format!(
+                        "malformed line at byte {}: {}",
+                        offset + line_offset,
+                        reason.code()
+                    ))
+                })?;
+                stations
+                    .entry_ref(station)
+                    .or_insert_with(StationData::new)
+                    .update(temp);
+                Ok(stations)
+            })
+            .try_reduce(HashMap::new, |mut acc, stations| {
+                for (station, data) in stations {
+                    acc.entry(station)
+                        .and_modify(|e: &mut StationData| e.aggregate(&data))
+                        .or_insert(data);
+                }
+                Ok(acc)
+            })
+    }
+}
+
+impl Iterator for ChunkedAggregator {
+    type Item = io::Result<(usize, HashMap<String, StationData>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let range = self.ranges.next()?;
+        let end = range.end;
+        let result = self.aggregate(range).map(|stations| (end, stations));
+        self.failed = result.is_err();
+        Some(result)
+    }
+}
+
+// Aggregates the whole file at `path`, chunk by chunk.
+pub fn aggregate(path: impl AsRef<Path>) -> io::Result<Results> {
+    let mut total: HashMap<String, StationData> = HashMap::new();
+    for chunk in ChunkedAggregator::new(path)? {
+        let (_, stations) = chunk?;
+        for (station, data) in stations {
+            total
+                .entry(station)
+                .and_modify(|e| e.aggregate(&data))
+                .or_insert(data);
+        }
+    }
+    Ok(Results::from(total))
+}
+
+// Aggregation of records fed as they arrive, with `push_line` for single
+// lines or `push_chunk` for arbitrary pieces of a stream, which may split
+// lines anywhere. `snapshot` returns the statistics of the complete lines
+// pushed so far, and aggregators fed in parallel combine with `merge`.
+#[derive(Clone, Default)]
+pub struct StreamingAggregator {
+    stations: HashMap<String, StationData>,
+    // The start of a line not yet ended by a newline, from `push_chunk`.
+    pending: Vec<u8>,
+    // Lines aggregated, to number malformed ones in errors.
+    lines: u64,
+}
+
+impl StreamingAggregator {
+    pub fn new() -> Self {
+        StreamingAggregator::default()
+    }
+
+    // Aggregates one line, without its newline; a trailing `\r` and a
+    // leading BOM are ignored. A malformed line or invalid UTF-8 is an
+    // `InvalidData` error, and the line is not aggregated.
+    pub fn push_line(&mut self, line: &[u8]) -> io::Result<()> {
+        self.lines += 1;
+        let number = self.lines;
+        let malformed =
+            |reason: &str| invalid_data(format!("malformed line {}: {}", number, reason));
+        let (station, tenths) = parse_record(line).map_err(|reason| malformed(reason.code()))?;
+        let station = std::str::from_utf8(station).map_err(|_| malformed("invalid UTF-8"))?;
+        self.stations
+            .entry_ref(station)
+            .or_insert_with(StationData::new)
+            .update(tenths as f32 / 10.0);
+        Ok(())
+    }
+
+    // Aggregates every line `chunk` completes. The bytes after its last
+    // newline are kept until a later chunk ends their line, or `flush`. On
+    // an error, the lines after the malformed one are dropped.
+    pub fn push_chunk(&mut self, chunk: &[u8]) -> io::Result<()> {
+        let Some(last) = chunk.iter().rposition(|&b| b == b'\n') else {
+            self.pending.extend_from_slice(chunk);
+            return Ok(());
+        };
+        let (complete, rest) = (&chunk[..last], &chunk[last + 1..]);
+        let mut lines = complete.split(|&b| b == b'\n');
+        if !self.pending.is_empty() {
+            let mut line = mem::take(&mut self.pending);
+            line.extend_from_slice(lines.next().unwrap_or_default());
+            let pushed = self.push_line(&line);
+            // Keeps the buffer's capacity for the next partial line.
+            line.clear();
+            self.pending = line;
+            pushed?;
+        }
+        for line in lines {
+            self.push_line(line)?;
+        }
+        self.pending.extend_from_slice(rest);
+        Ok(())
+    }
+
+    // Aggregates the partial line left by `push_chunk`, if any, as at the end
+    // of a stream without a final newline.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let line = mem::take(&mut self.pending);
+        self.push_line(&line)
+    }
+
+    // Statistics of the complete lines pushed so far.
+    pub fn snapshot(&self) -> Results {
+        Results::from(self.stations.clone())
+    }
+
+    // Adds the stations of `other`, flushing its partial line first.
+    pub fn merge(&mut self, mut other: StreamingAggregator) -> io::Result<()> {
+        other.flush()?;
+        for (station, data) in other.stations {
+            self.stations
+                .entry(station)
+                .and_modify(|e| e.aggregate(&data))
+                .or_insert(data);
+        }
+        self.lines += other.lines;
+        Ok(())
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,106 @@
+// Station aliases for `--aliases`: a file mapping raw station names to the
+// canonical names they are aggregated under, such as `St. Petersburg` to
+// `Saint Petersburg`. Every strategy resolves a parsed station through the
+// table before looking it up in its own map. The table hashes and compares
+// names the way the station maps do, so `--key-hash prefix` applies to it
+// too. It is loaded once per run and leaked, so canonical names can stand in
+// for names borrowed from the input.
+
+use crate::cli::Options;
+use crate::key_hash::{KeyHash, StationHashBuilder, StationKey};
+use brc::parse::{check_station, split_once};
+use hashbrown::HashMap;
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+
+pub struct Aliases {
+    canonical: HashMap<&'static str, &'static str, StationHashBuilder>,
+}
+
+impl Aliases {
+    // The canonical name of `station`, or `station` itself.
+    #[inline]
+    pub fn resolve<'a>(&self, station: &'a str) -> &'a str {
+        match self.canonical.get(&StationKey(station)) {
+            Some(canonical) => canonical,
+            None => station,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.canonical.len()
+    }
+}
+
+// Reads an alias file: one `raw` name per line, followed by `delimiter` and
+// its canonical name. Empty lines and lines starting with `#` are skipped. A
+// name aliased twice, or a canonical name that is itself aliased, is an
+// error.
+pub fn load(path: &str, delimiter: u8, key_hash: KeyHash) -> io::Result<&'static Aliases> {
+    let content: &'static str = Box::leak(fs::read_to_string(path)?.into_boxed_str());
+    let invalid = |number: usize, message: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}:{}: {}", path, number + 1, message),
+        )
+    };
+    let mut canonical = HashMap::with_hasher(StationHashBuilder::new(key_hash));
+    for (number, line) in content.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (raw, name) = split_once(line, delimiter).ok_or_else(|| {
+            invalid(
+                number,
+                "expected a name, the delimiter and its canonical name",
+            )
+        })?;
+        for field in [raw, name] {
+            check_station(field.as_bytes())
+                .map_err(|reason| invalid(number, &format!("station name {}", reason.code())))?;
+        }
+        if raw == name {
+            continue;
+        }
+        if canonical
+            .insert(raw, name)
+            .is_some_and(|other| other != name)
+        {
+            return Err(invalid(number, &format!("`{}` is aliased twice", raw)));
+        }
+    }
+    if let Some(name) = canonical
+        .values()
+        .find(|name| canonical.contains_key(*name))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}: canonical name `{}` is itself aliased", path, name),
+        ));
+    }
+    Ok(Box::leak(Box::new(Aliases { canonical })))
+}
+
+// The canonical name of `station` under `--aliases`, if given.
+#[inline]
+pub fn resolve<'a>(options: &Options, station: &'a str) -> &'a str {
+    match options.alias_map {
+        Some(aliases) => aliases.resolve(station),
+        None => station,
+    }
+}
+
+// Like `resolve`, for a `--group-by` or `--window` key whose first field,
+// up to `delimiter`, is the station.
+pub fn resolve_key<'a>(options: &Options, key: Cow<'a, str>) -> Cow<'a, str> {
+    let station = match split_once(&key, options.delimiter) {
+        Some((station, _)) => station,
+        None => &key,
+    };
+    let canonical = resolve(options, station);
+    if canonical.len() == station.len() && canonical.as_ptr() == station.as_ptr() {
+        return key;
+    }
+    Cow::Owned(format!("{}{}", canonical, &key[station.len()..]))
+}
@@ -0,0 +1,29 @@
+// The station table baked into the binary by `build.rs` with the
+// `baked-stations` feature: a perfect hash over the names listed in
+// `BRC_STATIONS` at build time, in static arrays. Without the feature the
+// table is empty and `--impl two-pass` collects stations with its first pass.
+
+#[cfg(feature = "baked-stations")]
+mod table {
+    include!(concat!(env!("OUT_DIR"), "/baked_stations.rs"));
+}
+
+// Whether this build has a baked table.
+pub const ENABLED: bool = cfg!(feature = "baked-stations");
+
+// The slot of `name`, or `None` if it is not baked in.
+#[inline]
+pub fn get(_name: &str) -> Option<usize> {
+    #[cfg(feature = "baked-stations")]
+    return crate::perfect_hash::lookup(table::SALT, &table::SEEDS, &table::NAMES, _name);
+    #[cfg(not(feature = "baked-stations"))]
+    None
+}
+
+// The baked names, indexed by slot.
+pub fn names() -> &'static [&'static str] {
+    #[cfg(feature = "baked-stations")]
+    return &table::NAMES;
+    #[cfg(not(feature = "baked-stations"))]
+    &[]
+}
@@ -0,0 +1,572 @@
+use crate::cli::{BenchOptions, Implementation};
+use crate::compare;
+use crate::compare::Results;
+use crate::error::Error;
+use crate::history::{self, Record};
+use crate::logging;
+use crate::system::System;
+use crate::topology::Topology;
+use serde_json::json;
+use std::borrow::Cow;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+// Placeholder in `--against` replaced by the input path.
+const PATH_PLACEHOLDER: &str = "{}";
+
+// The strategies `--compare-schedulers` runs, which differ only in how the
+// aggregation is spread over threads.
+const SCHEDULERS: [Implementation; 3] = [
+    Implementation::Rayon,
+    Implementation::Scoped,
+    Implementation::PerCore,
+];
+
+// Prefix of the line `--phase-times` prints.
+const PHASES_PREFIX: &str = "Phase times (s): ";
+
+// Modified z-score, in median absolute deviations scaled to standard
+// deviations, above which a run is flagged as an outlier (Iglewicz and
+// Hoaglin's recommendation).
+const OUTLIER_SCORE: f64 = 3.5;
+
+// Measures this binary, and the `--against` command or the other schedulers
+// when asked to compare, then appends the measurements to the `--history`
+// file, saves them with `--save-baseline` and checks them against
+// `--baseline`. Times are wall-clock times of whole processes, so they
+// include startup and mapping.
+pub fn run(options: &BenchOptions) -> Result<(), Error> {
+    let history = History::new(options);
+    println!("System: {}", history.system.summary());
+    let records = match &options.against {
+        Some(against) => compare_against(options, against, &history)?,
+        None if options.compare_schedulers => compare_schedulers(options, &history)?,
+        None => {
+            let (runs, _) = measure("brc", our_command(options)?, options)?;
+            print_ours(&runs, options);
+            vec![history.record("brc", Some(options.implementation), &runs)]
+        }
+    };
+    if let Some(path) = &options.history {
+        history::append(path, &records)?;
+    }
+    if let Some(path) = &options.save_baseline {
+        history::save_baseline(path, &records)?;
+        println!("Baseline saved to {}", path);
+    }
+    if let Some(path) = &options.baseline {
+        check_baseline(path, &records, options.max_regression)?;
+    }
+    Ok(())
+}
+
+// Runs this binary and the `--against` command on the same input, checks that
+// their results match and reports the speed ratio of the median runs. With
+// `--discard-output`, brc prints no results, so only the times are reported.
+fn compare_against(
+    options: &BenchOptions,
+    against: &str,
+    history: &History,
+) -> Result<Vec<Record>, Error> {
+    let theirs = shell(&against.replace(PATH_PLACEHOLDER, &options.path));
+    let (our_runs, our_output) = measure("brc", our_command(options)?, options)?;
+    let (their_runs, their_output) = measure(against, theirs, options)?;
+    let (our_time, their_time) = (our_runs.median(), their_runs.median());
+
+    print_ours(&our_runs, options);
+    println!("against: {}", their_runs.summary(options.warmup));
+    let records = vec![
+        history.record("brc", Some(options.implementation), &our_runs),
+        history.record(against, None, &their_runs),
+    ];
+
+    if options.discard_output {
+        // Only the timing: brc printed no results to compare.
+        println!(
+            "Outputs not compared with `--discard-output`; brc is {:.2}x {}",
+            ratio(our_time, their_time),
+            faster_or_slower(our_time, their_time)
+        );
+        return Ok(records);
+    }
+
+    let our_results = compare::parse_output("brc", &stdout(&our_output))?;
+    let their_results = compare::parse_output(against, &stdout(&their_output))?;
+    let differences = compare::differences(
+        ("brc", &our_results),
+        (against, &their_results),
+        options.epsilon,
+    );
+    if !differences.is_empty() {
+        for difference in &differences {
+            println!("{}", difference);
+        }
+        return Err(Error::Validation(format!(
+            "outputs differ in {} stations",
+            differences.len()
+        )));
+    }
+
+    println!(
+        "Outputs match ({} stations); brc is {:.2}x {}",
+        our_results.len(),
+        ratio(our_time, their_time),
+        faster_or_slower(our_time, their_time)
+    );
+    Ok(records)
+}
+
+// `brc run` with the forwarded options.
+fn our_command(options: &BenchOptions) -> Result<Command, Error> {
+    let mut command = Command::new(env::current_exe()?);
+    command.arg("run").arg("--no-cache").args(&options.run_args);
+    if options.phase_times {
+        // Parsed from the text line, whatever `--log-format` the options set.
+        command.args(["--phase-times", "--log-format", "text"]);
+    }
+    Ok(command)
+}
+
+fn print_ours(runs: &Runs, options: &BenchOptions) {
+    println!("brc:     {}", runs.summary(options.warmup));
+    if let Some([setup, aggregate, output]) = runs.median_phases() {
+        println!(
+            "         median phases: setup {:.3}s, aggregate {:.3}s, output {:.3}s",
+            setup, aggregate, output
+        );
+    }
+}
+
+// Compares the median of each of brc's runs in `records` with the baseline
+// run in `path` of the same strategy and thread count, failing if any is more
+// than `max_regression` percent slower. The baseline must have been measured
+// on the same input and machine for this to mean anything.
+fn check_baseline(path: &str, records: &[Record], max_regression: f64) -> Result<(), Error> {
+    let baseline = history::load_baseline(path)?;
+    let mut compared = 0;
+    let mut regressions = Vec::new();
+    for record in records
+        .iter()
+        .filter(|record| record.implementation.is_some())
+    {
+        let implementation = record.implementation.as_deref().unwrap_or_default();
+        let threads = record.threads.unwrap_or_default();
+        let Some(base) = baseline.iter().find(|base| {
+            base.implementation == record.implementation && base.threads == record.threads
+        }) else {
+            logging::event(
+                "warning",
+                format_args!(
+                    "warning: {} has no run of `--impl {}` with {} threads",
+                    path, implementation, threads
+                ),
+                json!({ "baseline": path, "implementation": implementation, "threads": threads }),
+            );
+            continue;
+        };
+        compared += 1;
+        if let Some(system) =
+            base.system
+                .as_ref()
+                .zip(record.system.as_ref())
+                .and_then(|(base, ours)| {
+                    (base.cpu_model != ours.cpu_model
+                        || base.physical_cores != ours.physical_cores
+                        || base.governor != ours.governor
+                        || base.turbo != ours.turbo)
+                        .then_some(base)
+                })
+        {
+            logging::event(
+                "warning",
+                format_args!(
+                    "warning: the baseline was measured on a different machine or CPU policy: {}",
+                    system.summary()
+                ),
+                json!({ "baseline": path, "baseline_system": system.summary() }),
+            );
+        }
+        let change = (record.median / base.median.max(f64::MIN_POSITIVE) - 1.0) * 100.0;
+        println!(
+            "{} with {} threads: median {:.3}s against {:.3}s in the baseline of {}: {:+.1}%",
+            implementation,
+            threads,
+            record.median,
+            base.median,
+            if base.version.is_empty() {
+                &base.timestamp
+            } else {
+                &base.version
+            },
+            change
+        );
+        if change > max_regression {
+            regressions.push(format!("{} by {:.1}%", implementation, change));
+        }
+    }
+    if compared == 0 {
+        return Err(Error::Validation(format!(
+            "{} has no run with the strategy and thread count measured",
+            path
+        )));
+    }
+    if !regressions.is_empty() {
+        return Err(Error::Validation(format!(
+            "slower than the baseline beyond {}%: {}",
+            max_regression,
+            regressions.join(", ")
+        )));
+    }
+    println!("No regression beyond {}%", max_regression);
+    Ok(())
+}
+
+// Runs every scheduler of this binary on the same input, checks that their
+// results match and prints a table of their best and median wall-clock times,
+// the median of each phase the runs report with `--phase-times`: setup
+// (mapping, decoding and sizing the maps), aggregation including the merge,
+// and output, and the outliers among the runs. What remains of the wall-clock
+// time is process startup and teardown.
+fn compare_schedulers(options: &BenchOptions, history: &History) -> Result<Vec<Record>, Error> {
+    let exe = env::current_exe()?;
+    let mut rows = Vec::new();
+    let mut first: Option<(&str, Results)> = None;
+    for scheduler in SCHEDULERS {
+        let mut command = Command::new(&exe);
+        command
+            .arg("run")
+            .arg("--no-cache")
+            .args(&options.run_args)
+            .args(["--phase-times", "--impl", scheduler.label()]);
+        let scheduler = scheduler.label();
+        let (runs, output) = measure(scheduler, command, options)?;
+        let phases = runs.median_phases().ok_or_else(|| {
+            Error::Io(io::Error::other(format!(
+                "`{}` reported no phase times",
+                scheduler
+            )))
+        })?;
+        rows.push((scheduler, runs, phases));
+
+        if options.discard_output {
+            continue;
+        }
+        let results = compare::parse_output(scheduler, &stdout(&output))?;
+        match &first {
+            None => first = Some((scheduler, results)),
+            Some((label, expected)) => {
+                let differences =
+                    compare::differences((label, expected), (scheduler, &results), options.epsilon);
+                if !differences.is_empty() {
+                    for difference in &differences {
+                        println!("{}", difference);
+                    }
+                    return Err(Error::Validation(format!(
+                        "outputs of `{}` and `{}` differ in {} stations",
+                        label,
+                        scheduler,
+                        differences.len()
+                    )));
+                }
+            }
+        }
+    }
+
+    let fastest = rows
+        .iter()
+        .map(|(_, runs, _)| runs.median())
+        .min()
+        .unwrap_or_default();
+    println!(
+        "{:<10} {:>5} {:>10} {:>10} {:>10} {:>10} {:>10} {:>8} {:>8}",
+        "scheduler",
+        "runs",
+        "best",
+        "median",
+        "setup",
+        "aggregate",
+        "output",
+        "outliers",
+        "relative"
+    );
+    for (scheduler, runs, [setup, aggregate, output]) in &rows {
+        println!(
+            "{:<10} {:>5} {:>9.3}s {:>9.3}s {:>9.3}s {:>9.3}s {:>9.3}s {:>8} {:>7.2}x",
+            scheduler,
+            runs.times.len(),
+            runs.best().as_secs_f64(),
+            runs.median().as_secs_f64(),
+            setup,
+            aggregate,
+            output,
+            runs.outliers().len(),
+            runs.median().as_secs_f64() / fastest.as_secs_f64().max(f64::MIN_POSITIVE)
+        );
+    }
+    let warmup = match options.warmup {
+        0 => String::new(),
+        warmup => format!(" after {} warmup runs", warmup),
+    };
+    match &first {
+        Some((_, results)) => println!(
+            "Relative times and phases are medians{}; outputs match ({} stations)",
+            warmup,
+            results.len()
+        ),
+        None => println!(
+            "Relative times and phases are medians{}; outputs not compared with `--discard-output`",
+            warmup
+        ),
+    }
+    Ok(SCHEDULERS
+        .into_iter()
+        .zip(&rows)
+        .map(|(scheduler, (_, runs, _))| history.record("brc", Some(scheduler), runs))
+        .collect())
+}
+
+// What the `--history` and baseline records of one benchmark share.
+struct History {
+    timestamp: String,
+    version: String,
+    threads: usize,
+    // Size of the input, for the throughput, unless it is not a local file.
+    bytes: Option<u64>,
+    system: System,
+}
+
+impl History {
+    fn new(options: &BenchOptions) -> Self {
+        History {
+            timestamp: history::timestamp(),
+            version: env::current_exe()
+                .map(|exe| history::version(&exe))
+                .unwrap_or_default(),
+            threads: options
+                .threads
+                .unwrap_or_else(|| Topology::detect().default_threads()),
+            bytes: fs::metadata(&options.path)
+                .ok()
+                .map(|metadata| metadata.len()),
+            system: System::detect(),
+        }
+    }
+
+    // The record of `runs` of `command`, with `implementation` for brc's.
+    fn record(&self, command: &str, implementation: Option<Implementation>, runs: &Runs) -> Record {
+        let phases = runs.median_phases();
+        let median = runs.median().as_secs_f64();
+        Record {
+            timestamp: self.timestamp.clone(),
+            version: self.version.clone(),
+            command: command.to_string(),
+            implementation: implementation.map(|implementation| implementation.label().to_string()),
+            threads: implementation.map(|_| self.threads),
+            runs: runs.times.len(),
+            best: runs.best().as_secs_f64(),
+            median,
+            setup: phases.map(|phases| phases[0]),
+            aggregate: phases.map(|phases| phases[1]),
+            output: phases.map(|phases| phases[2]),
+            throughput: self
+                .bytes
+                .map(|bytes| bytes as f64 / 1e6 / median.max(f64::MIN_POSITIVE)),
+            system: Some(self.system.clone()),
+        }
+    }
+}
+
+// The measured runs of one command: their wall-clock times, and the phase
+// times of those that reported them with `--phase-times`.
+pub struct Runs {
+    pub times: Vec<Duration>,
+    pub phases: Vec<[f64; 3]>,
+}
+
+impl Runs {
+    pub fn best(&self) -> Duration {
+        self.times.iter().copied().min().unwrap_or_default()
+    }
+
+    pub fn median(&self) -> Duration {
+        Duration::from_secs_f64(median(self.seconds()))
+    }
+
+    // The median of each phase across the runs, if they reported any.
+    pub fn median_phases(&self) -> Option<[f64; 3]> {
+        if self.phases.is_empty() {
+            return None;
+        }
+        Some(
+            [0, 1, 2].map(|phase| median(self.phases.iter().map(|phases| phases[phase]).collect())),
+        )
+    }
+
+    // Indices of the runs whose modified z-score, their distance from the
+    // median over the median absolute deviation (MAD), exceeds
+    // `OUTLIER_SCORE`. Runs that all take the same time have no outliers.
+    pub fn outliers(&self) -> Vec<usize> {
+        let seconds = self.seconds();
+        let center = median(seconds.clone());
+        let deviation = median(seconds.iter().map(|time| (time - center).abs()).collect());
+        if deviation == 0.0 {
+            return Vec::new();
+        }
+        seconds
+            .iter()
+            .enumerate()
+            // 0.6745 scales the MAD of a normal distribution to its standard
+            // deviation.
+            .filter(|(_, &time)| 0.6745 * (time - center).abs() / deviation > OUTLIER_SCORE)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    // The best and median times, the number of runs and the outliers, for
+    // the report.
+    pub fn summary(&self, warmup: usize) -> String {
+        let mut summary = format!(
+            "best {:.3}s, median {:.3}s of {} runs",
+            self.best().as_secs_f64(),
+            self.median().as_secs_f64(),
+            self.times.len()
+        );
+        if warmup > 0 {
+            summary += &format!(" after {} warmup runs", warmup);
+        }
+        let outliers: Vec<_> = self
+            .outliers()
+            .iter()
+            .map(|&index| format!("{:.3}s", self.times[index].as_secs_f64()))
+            .collect();
+        if !outliers.is_empty() {
+            summary += &format!("; {} outliers: {}", outliers.len(), outliers.join(", "));
+        }
+        summary
+    }
+
+    fn seconds(&self) -> Vec<f64> {
+        self.times.iter().map(Duration::as_secs_f64).collect()
+    }
+}
+
+// The median of `values`, the mean of the middle two for an even count, or
+// zero without values.
+fn median(mut values: Vec<f64>) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    }
+}
+
+// The setup, aggregation and output times in seconds of a `--phase-times`
+// line.
+fn parse_phases(line: &str) -> Option<[f64; 3]> {
+    let mut fields = line.strip_prefix(PHASES_PREFIX)?.split(", ");
+    let mut phases = [0.0; 3];
+    for (phase, name) in phases.iter_mut().zip(["setup", "aggregate", "output"]) {
+        *phase = fields.next()?.strip_prefix(name)?.trim().parse().ok()?;
+    }
+    Some(phases)
+}
+
+// Runs `command` `options.warmup` times unmeasured, then `options.runs` times,
+// and with `--max-time` more until that much time was spent measuring,
+// returning the measured runs and the output of the last one. Standard error
+// is passed through, except for the `--phase-times` lines, which are
+// collected.
+fn measure(
+    label: &str,
+    mut command: Command,
+    options: &BenchOptions,
+) -> Result<(Runs, Output), Error> {
+    command.stdin(Stdio::null());
+    for _ in 0..options.warmup {
+        run_once(label, &mut command)?;
+    }
+    let mut runs = Runs {
+        times: Vec::new(),
+        phases: Vec::new(),
+    };
+    let start = Instant::now();
+    loop {
+        let run_start = Instant::now();
+        let output = run_once(label, &mut command)?;
+        runs.times.push(run_start.elapsed());
+        runs.phases.extend(
+            String::from_utf8_lossy(&output.stderr)
+                .lines()
+                .find_map(parse_phases),
+        );
+
+        let more = runs.times.len() < options.runs
+            || options
+                .max_time
+                .is_some_and(|max_time| start.elapsed() < max_time);
+        if !more {
+            return Ok((runs, output));
+        }
+    }
+}
+
+// Runs `command` to completion, echoing its standard error but for
+// `--phase-times` lines, and fails if it does.
+fn run_once(label: &str, command: &mut Command) -> Result<Output, Error> {
+    let output = command.output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut echoed = io::stderr().lock();
+    for line in stderr
+        .lines()
+        .filter(|line| !line.starts_with(PHASES_PREFIX))
+    {
+        writeln!(echoed, "{}", line)?;
+    }
+    if !output.status.success() {
+        return Err(Error::Io(io::Error::other(format!(
+            "`{}` failed with {}",
+            label, output.status
+        ))));
+    }
+    Ok(output)
+}
+
+fn stdout(output: &Output) -> Cow<'_, str> {
+    String::from_utf8_lossy(&output.stdout)
+}
+
+// How many times faster the faster of the two times is.
+fn ratio(a: Duration, b: Duration) -> f64 {
+    let (fast, slow) = if a <= b { (a, b) } else { (b, a) };
+    slow.as_secs_f64() / fast.as_secs_f64().max(f64::MIN_POSITIVE)
+}
+
+// How our time `ours` compares to `theirs`.
+fn faster_or_slower(ours: Duration, theirs: Duration) -> &'static str {
+    if ours <= theirs {
+        "faster"
+    } else {
+        "slower"
+    }
+}
+
+// A command running `script` through the platform shell.
+fn shell(script: &str) -> Command {
+    if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.args(["/C", script]);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.args(["-c", script]);
+        command
+    }
+}
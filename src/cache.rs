@@ -0,0 +1,171 @@
+use rayon::prelude::*;
+use std::fs::File;
+use std::hint::black_box;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+
+// Page size assumed when touching a mapping; touching more often than the
+// real page size is harmless.
+const PAGE_SIZE: usize = 4096;
+
+// Page-cache state a run is measured in, selected with `--cache`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Cache {
+    // The input is evicted from the page cache before the run.
+    Cold,
+    // The whole mapping is prefaulted before the timer starts.
+    Warm,
+}
+
+impl Cache {
+    pub fn label(self) -> &'static str {
+        match self {
+            Cache::Cold => "cold cache",
+            Cache::Warm => "warm cache",
+        }
+    }
+}
+
+impl FromStr for Cache {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cold" => Ok(Cache::Cold),
+            "warm" => Ok(Cache::Warm),
+            _ => Err(()),
+        }
+    }
+}
+
+// Asks the kernel to drop the cached pages of `path`. Dirty pages are flushed
+// first, since only clean pages can be dropped.
+#[cfg(unix)]
+pub fn evict(path: impl AsRef<Path>) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let file = File::open(path)?;
+    file.sync_data()?;
+    let result = unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+    if result != 0 {
+        return Err(io::Error::from_raw_os_error(result));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn evict(_path: impl AsRef<Path>) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "`--cache cold` is only supported on Unix",
+    ))
+}
+
+// Locks `data` in RAM so it cannot be evicted between benchmark trials. The
+// lock ends when the mapping is dropped.
+#[cfg(unix)]
+pub fn lock(data: &[u8]) -> io::Result<()> {
+    if unsafe { libc::mlock(data.as_ptr().cast(), data.len()) } == 0 {
+        return Ok(());
+    }
+    let e = io::Error::last_os_error();
+    match e.raw_os_error() {
+        Some(libc::ENOMEM) | Some(libc::EPERM) | Some(libc::EAGAIN) => {
+            let mut limit = libc::rlimit {
+                rlim_cur: 0,
+                rlim_max: 0,
+            };
+            let limit = if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } == 0 {
+                if limit.rlim_cur == libc::RLIM_INFINITY {
+                    "unlimited".to_string()
+                } else {
+                    format!("{} bytes", limit.rlim_cur)
+                }
+            } else {
+                "unknown".to_string()
+            };
+            Err(io::Error::new(
+                io::ErrorKind::OutOfMemory,
+                format!(
+                    "cannot lock {} bytes of input in memory ({}); RLIMIT_MEMLOCK is {}, \
+                     raise it with `ulimit -l` or drop `--mlock`",
+                    data.len(),
+                    e,
+                    limit
+                ),
+            ))
+        }
+        _ => Err(e),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn lock(_data: &[u8]) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "`--mlock` is only supported on Unix",
+    ))
+}
+
+// Hints that `range` of `data`, a whole mapping, will be read soon, so the
+// kernel starts reading it in the background. Out-of-range parts are ignored.
+#[cfg(unix)]
+pub fn will_need(data: &[u8], range: Range<usize>) {
+    let end = range.end.min(data.len());
+    // The mapping starts on a page boundary, so aligning down stays inside it.
+    let start = range.start - range.start % PAGE_SIZE;
+    if start >= end {
+        return;
+    }
+    unsafe {
+        libc::madvise(
+            data.as_ptr().add(start) as *mut libc::c_void,
+            end - start,
+            libc::MADV_WILLNEED,
+        );
+    }
+}
+
+#[cfg(not(unix))]
+pub fn will_need(_data: &[u8], _range: Range<usize>) {}
+
+// Drops the whole pages of `range` of `data`, a read-only file mapping, from
+// the process's resident memory. They are read back from the file if touched
+// again, so this is only safe on mappings of files, never on copies.
+#[cfg(unix)]
+pub fn release(data: &[u8], range: Range<usize>) {
+    let base = data.as_ptr() as usize;
+    let end = (base + range.end.min(data.len())) / PAGE_SIZE * PAGE_SIZE;
+    let start = (base + range.start).next_multiple_of(PAGE_SIZE);
+    if start >= end {
+        return;
+    }
+    unsafe {
+        libc::madvise(start as *mut libc::c_void, end - start, libc::MADV_DONTNEED);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn release(_data: &[u8], _range: Range<usize>) {}
+
+// Faults in every page of `data` before timing, with each worker thread
+// touching one contiguous share so the page faults are spread over all cores.
+pub fn prefault(data: &[u8]) {
+    let share = data
+        .len()
+        .div_ceil(rayon::current_num_threads())
+        .next_multiple_of(PAGE_SIZE)
+        .max(PAGE_SIZE);
+    data.par_chunks(share).for_each(touch);
+}
+
+// Reads one byte of every page of `data`.
+fn touch(data: &[u8]) {
+    let mut sum = 0u8;
+    for page in data.chunks(PAGE_SIZE) {
+        sum = sum.wrapping_add(page[0]);
+    }
+    black_box(sum);
+}
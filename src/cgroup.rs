@@ -0,0 +1,105 @@
+// Limits of the cgroup the process runs in, as containers set them: read from
+// cgroup v2's unified hierarchy or from cgroup v1's per-controller ones, as
+// listed in `/proc/self/cgroup`. A limit on an ancestor applies as well, so
+// the lowest along the cgroup's path is taken. Containers without a cgroup
+// namespace see their host path but have their own cgroup mounted at the root.
+
+use std::fs;
+use std::path::Path;
+
+const V2_ROOT: &str = "/sys/fs/cgroup";
+const V1_MEMORY_ROOTS: [&str; 1] = ["/sys/fs/cgroup/memory"];
+const V1_CPU_ROOTS: [&str; 3] = [
+    "/sys/fs/cgroup/cpu,cpuacct",
+    "/sys/fs/cgroup/cpu",
+    "/sys/fs/cgroup/cpuacct,cpu",
+];
+
+// cgroup v1 reports no memory limit as a huge page-aligned value.
+const V1_UNLIMITED: u64 = 1 << 60;
+
+// The memory limit in bytes, if limited.
+pub fn memory_limit() -> Option<u64> {
+    lowest("memory", &V1_MEMORY_ROOTS, memory_max, |dir| {
+        read_number(&dir.join("memory.limit_in_bytes")).filter(|&limit| limit < V1_UNLIMITED)
+    })
+}
+
+// The CPU quota in CPUs, if limited.
+pub fn cpu_quota() -> Option<f64> {
+    lowest("cpu", &V1_CPU_ROOTS, cpu_max, |dir| {
+        // A quota of -1 fails to parse, and means none.
+        let quota = read_number(&dir.join("cpu.cfs_quota_us"))?;
+        let period = read_number(&dir.join("cpu.cfs_period_us")).filter(|&period| period > 0)?;
+        Some(quota as f64 / period as f64)
+    })
+}
+
+// `memory.max` of a cgroup v2 directory, unless `max`.
+pub fn memory_max(dir: &Path) -> Option<u64> {
+    read_number(&dir.join("memory.max"))
+}
+
+// `cpu.max` of a cgroup v2 directory, `<quota> <period>` or `max <period>`.
+pub fn cpu_max(dir: &Path) -> Option<f64> {
+    let max = fs::read_to_string(dir.join("cpu.max")).ok()?;
+    let (quota, period) = max.trim().split_once(' ')?;
+    let period = period.parse::<f64>().ok().filter(|&period| period > 0.0)?;
+    Some(quota.parse::<f64>().ok()? / period)
+}
+
+// The lowest limit over the process's cgroups: `v2` reads one from a cgroup
+// v2 directory, `v1` from a directory of `controller`'s v1 hierarchy, which
+// is mounted at the first of `v1_roots` that exists.
+fn lowest<T: PartialOrd>(
+    controller: &str,
+    v1_roots: &[&str],
+    v2: impl Fn(&Path) -> Option<T>,
+    v1: impl Fn(&Path) -> Option<T>,
+) -> Option<T> {
+    let cgroups = fs::read_to_string("/proc/self/cgroup").ok()?;
+    let v1_root = v1_roots.iter().map(Path::new).find(|root| root.is_dir());
+    cgroups
+        .lines()
+        .filter_map(|line| {
+            // `<id>:<controllers>:<path>`, with no controllers for v2.
+            let mut fields = line.splitn(3, ':');
+            let (_, controllers, path) = (fields.next()?, fields.next()?, fields.next()?);
+            if controllers.is_empty() {
+                hierarchy(Path::new(V2_ROOT), path, &v2)
+            } else if controllers.split(',').any(|name| name == controller) {
+                hierarchy(v1_root?, path, &v1)
+            } else {
+                None
+            }
+        })
+        .reduce(|a, b| if b < a { b } else { a })
+}
+
+// The lowest limit `read` finds for the cgroup at `path` in the hierarchy
+// mounted at `root` and its ancestors, up to the root.
+pub fn hierarchy<T: PartialOrd>(
+    root: &Path,
+    path: &str,
+    read: impl Fn(&Path) -> Option<T>,
+) -> Option<T> {
+    let mut dir = root.join(path.trim_start_matches('/'));
+    if !dir.is_dir() {
+        dir = root.to_path_buf();
+    }
+    let mut lowest: Option<T> = None;
+    loop {
+        if let Some(limit) = read(&dir) {
+            if lowest.as_ref().is_none_or(|lowest| limit < *lowest) {
+                lowest = Some(limit);
+            }
+        }
+        if dir == root || !dir.pop() {
+            return lowest;
+        }
+    }
+}
+
+fn read_number(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
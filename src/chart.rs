@@ -0,0 +1,127 @@
+use crate::error::Error;
+use crate::StationData;
+use std::io;
+
+// Number of stations drawn when `--chart-top` is not given.
+pub const DEFAULT_TOP: usize = 20;
+
+// Draws min/mean/max ranges for the `top` stations with the most readings to
+// `path`, as PNG or, for a `.svg` extension, SVG.
+#[cfg(feature = "chart")]
+pub fn draw(path: &str, stations: &[(String, StationData)], top: usize) -> Result<(), Error> {
+    use plotters::prelude::*;
+
+    let mut selected: Vec<_> = stations.iter().collect();
+    selected.sort_by(|a, b| b.1.count.cmp(&a.1.count).then_with(|| a.0.cmp(&b.0)));
+    selected.truncate(top);
+
+    let lowest = selected
+        .iter()
+        .map(|(_, data)| data.min_temp)
+        .fold(0.0, f32::min);
+    let highest = selected
+        .iter()
+        .map(|(_, data)| data.max_temp)
+        .fold(0.0, f32::max);
+    let padding = ((highest - lowest) * 0.05).max(1.0);
+
+    let size = ((selected.len() as u32 * 40).max(640), 480);
+    let result = if path.ends_with(".svg") {
+        plot(
+            SVGBackend::new(path, size).into_drawing_area(),
+            &selected,
+            lowest - padding,
+            highest + padding,
+        )
+    } else {
+        plot(
+            BitMapBackend::new(path, size).into_drawing_area(),
+            &selected,
+            lowest - padding,
+            highest + padding,
+        )
+    };
+    result.map_err(|e| Error::Io(io::Error::other(format!("failed to draw chart: {}", e))))
+}
+
+#[cfg(feature = "chart")]
+fn plot<DB: plotters::prelude::DrawingBackend>(
+    root: plotters::prelude::DrawingArea<DB, plotters::coord::Shift>,
+    selected: &[&(String, StationData)],
+    y_min: f32,
+    y_max: f32,
+) -> Result<(), Box<dyn std::error::Error + 'static>>
+where
+    DB::ErrorType: 'static,
+{
+    use plotters::prelude::*;
+
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption("Temperature range per station", ("sans-serif", 20))
+        .margin(10)
+        .x_label_area_size(100)
+        .y_label_area_size(50)
+        .build_cartesian_2d(-0.5f32..selected.len() as f32 - 0.5, y_min..y_max)?;
+
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .x_labels(selected.len())
+        .x_label_formatter(&|x| {
+            let index = x.round();
+            if (x - index).abs() > 0.01 || index < 0.0 {
+                return String::new();
+            }
+            selected
+                .get(index as usize)
+                .map(|(name, _)| name.clone())
+                .unwrap_or_default()
+        })
+        .x_label_style(
+            ("sans-serif", 12)
+                .into_font()
+                .transform(FontTransform::Rotate90),
+        )
+        .y_desc("°C")
+        .draw()?;
+
+    chart.draw_series(selected.iter().enumerate().map(|(i, (_, data))| {
+        let x = i as f32;
+        Rectangle::new(
+            [(x - 0.3, data.min_temp), (x + 0.3, data.max_temp)],
+            BLUE.mix(0.4).filled(),
+        )
+    }))?;
+    chart.draw_series(selected.iter().enumerate().map(|(i, (_, data))| {
+        let x = i as f32;
+        PathElement::new(
+            vec![(x - 0.3, data.mean()), (x + 0.3, data.mean())],
+            BLACK.stroke_width(2),
+        )
+    }))?;
+
+    root.present()?;
+    Ok(())
+}
+
+#[cfg(not(feature = "chart"))]
+pub fn draw(_path: &str, _stations: &[(String, StationData)], _top: usize) -> Result<(), Error> {
+    Err(disabled())
+}
+
+// Checks that charts are compiled in before a run that draws one.
+pub fn check_enabled() -> Result<(), Error> {
+    if cfg!(feature = "chart") {
+        Ok(())
+    } else {
+        Err(disabled())
+    }
+}
+
+fn disabled() -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "`--chart` requires building with the `chart` feature",
+    ))
+}
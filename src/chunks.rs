@@ -0,0 +1,45 @@
+use std::ops::Range;
+
+// A line-aligned slice of the input that can be aggregated on its own, with
+// its byte offset so lines can be traced back to the input.
+#[derive(Clone, Copy)]
+pub struct Chunk<'a> {
+    pub offset: usize,
+    pub data: &'a str,
+}
+
+// Splits `content` into line-aligned chunks of roughly `chunk_size` bytes.
+pub fn chunks(content: &str, chunk_size: usize) -> Vec<Chunk<'_>> {
+    split(content.as_bytes(), chunk_size)
+        .into_iter()
+        .map(|range| Chunk {
+            offset: range.start,
+            data: &content[range],
+        })
+        .collect()
+}
+
+// Splits `bytes` into consecutive ranges of roughly `chunk_size` bytes, each
+// starting at the beginning of a line and ending after a newline (or at the
+// end of the input).
+pub fn split(bytes: &[u8], chunk_size: usize) -> Vec<Range<usize>> {
+    let mut ranges = Vec::with_capacity(bytes.len() / chunk_size + 1);
+    let mut start = 0;
+    while start < bytes.len() {
+        let end = align_to_line(bytes, start.saturating_add(chunk_size));
+        ranges.push(start..end);
+        start = end;
+    }
+    ranges
+}
+
+// Moves `pos` forward to the start of the next line unless it already is one.
+pub fn align_to_line(bytes: &[u8], pos: usize) -> usize {
+    if pos == 0 || pos >= bytes.len() || bytes[pos - 1] == b'\n' {
+        return pos.min(bytes.len());
+    }
+    match bytes[pos..].iter().position(|&b| b == b'\n') {
+        Some(offset) => pos + offset + 1,
+        None => bytes.len(),
+    }
+}
@@ -0,0 +1,1329 @@
+use crate::aliases::Aliases;
+use crate::cache::Cache;
+use crate::chart;
+use crate::clickhouse;
+use crate::extra_stats::Stat;
+use crate::filter::StationFilter;
+use crate::generate::Profile;
+use crate::index_map::MapKind;
+use crate::interrupt;
+use crate::key_hash::KeyHash;
+use crate::logging::LogFormat;
+use crate::output::{Format, SortKey, SortOrder};
+use crate::postgres;
+use crate::remote;
+use crate::utf8::Utf8;
+use crate::ESTIMATED_UNIQUE_STATIONS;
+use brc::parse::Notation;
+use brc::rounding::Rounding;
+use brc::window;
+use std::io;
+use std::str::FromStr;
+use std::time::Duration;
+
+// Input file used when no path is given on the command line.
+const DEFAULT_PATH: &str = "C:\\BRC\\1brc\\measurements.txt";
+
+// Input path that reads the measurements from stdin.
+pub const STDIN_PATH: &str = "-";
+
+// File written by `generate` when no path is given, and its default size.
+const DEFAULT_GENERATE_PATH: &str = "measurements.txt";
+const DEFAULT_GENERATE_ROWS: usize = 1_000_000;
+
+// Retries of a failed remote request, and the wait before the first one.
+const DEFAULT_RETRIES: u32 = 3;
+const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+// Measured runs of `bench --max-time` before the time limit applies.
+const DEFAULT_MIN_RUNS: usize = 3;
+
+// Threads of `--reproducible` runs without `--threads`, the same on every
+// machine.
+const REPRODUCIBLE_THREADS: usize = 4;
+
+// Percentage by which `bench --baseline` lets the median run slow down.
+const DEFAULT_MAX_REGRESSION: f64 = 5.0;
+
+// Aggregation strategy selected with `--impl`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Implementation {
+    // Fold over lines with Rayon, holding all stations in memory.
+    Rayon,
+    // Write a sorted run per chunk to disk and k-way merge the runs.
+    External,
+    // One partition per thread on `std::thread::scope`, without Rayon.
+    Scoped,
+    // One pinned worker per physical core, merging in a tree among
+    // themselves.
+    PerCore,
+    // A reader thread fills reusable buffers with `read` calls and parser
+    // threads consume them through a bounded channel.
+    Pipeline,
+    // Collect the exact station set first, then fold readings into
+    // fixed-size per-thread tables.
+    TwoPass,
+    // Only the standard library: `File` reads, scoped threads and std
+    // `HashMap`s.
+    Std,
+    // Chunks uploaded to a GPU and aggregated in a compute shader.
+    Gpu,
+    // Chunks copied to an NVIDIA device and aggregated in a CUDA kernel.
+    Cuda,
+}
+
+impl Implementation {
+    // The name `--impl` takes.
+    pub fn label(self) -> &'static str {
+        match self {
+            Implementation::Rayon => "rayon",
+            Implementation::External => "external",
+            Implementation::Scoped => "scoped",
+            Implementation::PerCore => "per-core",
+            Implementation::Pipeline => "pipeline",
+            Implementation::TwoPass => "two-pass",
+            Implementation::Std => "std",
+            Implementation::Gpu => "gpu",
+            Implementation::Cuda => "cuda",
+        }
+    }
+}
+
+impl FromStr for Implementation {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rayon" => Ok(Implementation::Rayon),
+            "external" => Ok(Implementation::External),
+            "scoped" => Ok(Implementation::Scoped),
+            "per-core" => Ok(Implementation::PerCore),
+            "pipeline" => Ok(Implementation::Pipeline),
+            "two-pass" => Ok(Implementation::TwoPass),
+            "std" => Ok(Implementation::Std),
+            "gpu" => Ok(Implementation::Gpu),
+            "cuda" => Ok(Implementation::Cuda),
+            _ => Err(()),
+        }
+    }
+}
+
+// How the in-memory strategy divides the input between threads, selected
+// with `--schedule`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    // Rayon splits the lines adaptively and steals work between threads.
+    Adaptive,
+    // Threads pull fixed-size chunks from a shared atomic cursor.
+    Dynamic,
+    // One contiguous, equally sized partition per thread and no stealing,
+    // so the division of work is reproducible.
+    Static,
+}
+
+impl Schedule {
+    // The name `--schedule` takes.
+    pub fn label(self) -> &'static str {
+        match self {
+            Schedule::Adaptive => "adaptive",
+            Schedule::Dynamic => "dynamic",
+            Schedule::Static => "static",
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "adaptive" => Ok(Schedule::Adaptive),
+            "dynamic" => Ok(Schedule::Dynamic),
+            "static" => Ok(Schedule::Static),
+            _ => Err(()),
+        }
+    }
+}
+
+// How lines rejected by the parser are recovered, selected with `--recover`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Recover {
+    // Rejected lines are skipped in lenient mode and fail the run otherwise.
+    Off,
+    // Extra fields are dropped, taking the last field as the temperature,
+    // and lines with a missing temperature are skipped.
+    LastField,
+}
+
+impl FromStr for Recover {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Recover::Off),
+            "last-field" => Ok(Recover::LastField),
+            _ => Err(()),
+        }
+    }
+}
+
+// Subcommand given as the first command-line argument; `run` is implied.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Run,
+    Selftest,
+    Generate,
+    Compare,
+    Bench,
+}
+
+impl Command {
+    // Removes a leading subcommand name from `args`, defaulting to `run`.
+    pub fn take(args: &mut Vec<String>) -> Command {
+        let command = match args.first().map(String::as_str) {
+            Some("run") => Command::Run,
+            Some("selftest") => Command::Selftest,
+            Some("generate") => Command::Generate,
+            Some("compare") => Command::Compare,
+            Some("bench") => Command::Bench,
+            _ => return Command::Run,
+        };
+        args.remove(0);
+        command
+    }
+}
+
+// Options collected from the command line.
+#[derive(Clone)]
+pub struct Options {
+    pub path: String,
+    pub sample: Option<f64>,
+    pub count_only: bool,
+    pub discard_output: bool,
+    pub hot_path_stats: bool,
+    pub lenient: bool,
+    pub error_report: Option<String>,
+    pub max_memory: Option<usize>,
+    // `--memory-limit`, in place of the cgroup's, and whether chunks are
+    // released from memory once aggregated to stay within it.
+    pub memory_limit: Option<usize>,
+    pub windowed: bool,
+    pub implementation: Implementation,
+    pub schedule: Schedule,
+    pub threads: Option<usize>,
+    pub collate: Option<String>,
+    pub format: Format,
+    pub highlight: bool,
+    pub chart: Option<String>,
+    pub chart_top: usize,
+    pub load_state: Option<String>,
+    pub save_state: Option<String>,
+    pub cache: Option<Cache>,
+    pub prefault: bool,
+    pub mlock: bool,
+    pub chunk_size: Option<usize>,
+    pub queue_depth: Option<usize>,
+    pub readahead: Option<usize>,
+    pub verbose: bool,
+    pub thread_stats: bool,
+    pub phase_times: bool,
+    pub energy: bool,
+    // `--timeout`: when aggregation is stopped with partial results.
+    pub timeout: Option<Duration>,
+    pub status_socket: Option<String>,
+    pub log_format: LogFormat,
+    pub reproducible: bool,
+    pub counters: bool,
+    pub exact: bool,
+    pub detect_overflow: bool,
+    pub utf8: Utf8,
+    pub recover: Recover,
+    pub delimiter: u8,
+    pub sniff_delimiter: bool,
+    // How temperatures are written: `--decimal-comma` and `--numbers`.
+    pub notation: Notation,
+    pub sort: SortOrder,
+    pub rounding: Option<Rounding>,
+    pub output: Option<String>,
+    pub table: String,
+    pub retries: u32,
+    pub retry_backoff: Duration,
+    pub resume: Option<String>,
+    pub no_cache: bool,
+    // Field that `--group-by` adds to the station in the aggregation key,
+    // numbered from 1.
+    pub group_by: Option<usize>,
+    // Width in seconds of the `--window` buckets of the `--time-col` field,
+    // numbered from 1.
+    pub window: Option<u64>,
+    pub time_col: Option<usize>,
+    pub provenance: bool,
+    pub first_last: bool,
+    // Statistics written after min/mean/max, selected with `--stats`.
+    pub stats: Vec<Stat>,
+    // Initial capacity of per-thread station maps, set from a sample of the
+    // input before aggregating.
+    pub map_capacity: usize,
+    // Known station names, one per line, looked up with a perfect hash.
+    pub stations: Option<String>,
+    // The `--aliases` file, and its table once loaded.
+    pub aliases: Option<String>,
+    pub alias_map: Option<&'static Aliases>,
+    // Spellings merged after aggregation by `--trim` and `--case-insensitive`.
+    pub trim: bool,
+    pub case_insensitive: bool,
+    // Station lists for `--include-file` and `--exclude-file`, and both once
+    // loaded.
+    pub include_file: Option<String>,
+    pub exclude_file: Option<String>,
+    pub station_filter: Option<&'static StationFilter>,
+    // How station maps hash names, and whether to fail on stations that
+    // share a prefix hash.
+    pub key_hash: KeyHash,
+    pub check_key_hash: bool,
+    pub compensated: bool,
+    pub self_check: bool,
+    // Whether to rescan the input for `--key-hash fingerprint` collisions.
+    pub verify_fingerprints: bool,
+    pub map: MapKind,
+}
+
+impl Options {
+    // Whether individual readings are tracked beyond the station statistics,
+    // for `--provenance`, `--first-last` or `--stats`.
+    pub fn tracks_readings(&self) -> bool {
+        self.provenance || self.first_last || !self.stats.is_empty()
+    }
+
+    // Whether the input is read from stdin rather than a file or URL.
+    pub fn reads_stdin(&self) -> bool {
+        self.path == STDIN_PATH
+    }
+
+    // Parses options from command-line style arguments, excluding the program
+    // name. When an option is repeated, the last occurrence wins.
+    pub fn parse(mut args: impl Iterator<Item = String>) -> io::Result<Self> {
+        let mut options = Options {
+            path: DEFAULT_PATH.to_string(),
+            sample: None,
+            count_only: false,
+            discard_output: false,
+            hot_path_stats: false,
+            lenient: false,
+            error_report: None,
+            max_memory: None,
+            memory_limit: None,
+            windowed: false,
+            // Portable builds default to the strategy that needs no
+            // dependencies.
+            implementation: if cfg!(feature = "portable") {
+                Implementation::Std
+            } else {
+                Implementation::Rayon
+            },
+            schedule: Schedule::Adaptive,
+            threads: None,
+            collate: None,
+            format: Format::Text,
+            highlight: false,
+            chart: None,
+            chart_top: chart::DEFAULT_TOP,
+            load_state: None,
+            save_state: None,
+            cache: None,
+            prefault: false,
+            mlock: false,
+            chunk_size: None,
+            queue_depth: None,
+            readahead: None,
+            verbose: false,
+            thread_stats: false,
+            phase_times: false,
+            energy: false,
+            timeout: None,
+            status_socket: None,
+            log_format: LogFormat::Text,
+            reproducible: false,
+            counters: false,
+            exact: false,
+            detect_overflow: false,
+            utf8: Utf8::Trust,
+            recover: Recover::Off,
+            delimiter: b';',
+            sniff_delimiter: false,
+            notation: Notation::DEFAULT,
+            sort: SortOrder::by_name(),
+            rounding: None,
+            output: None,
+            table: postgres::DEFAULT_TABLE.to_string(),
+            retries: DEFAULT_RETRIES,
+            retry_backoff: DEFAULT_RETRY_BACKOFF,
+            resume: None,
+            no_cache: false,
+            group_by: None,
+            window: None,
+            time_col: None,
+            provenance: false,
+            first_last: false,
+            stats: Vec::new(),
+            map_capacity: ESTIMATED_UNIQUE_STATIONS,
+            stations: None,
+            aliases: None,
+            alias_map: None,
+            trim: false,
+            case_insensitive: false,
+            include_file: None,
+            exclude_file: None,
+            station_filter: None,
+            key_hash: KeyHash::Full,
+            check_key_hash: false,
+            compensated: false,
+            self_check: false,
+            verify_fingerprints: false,
+            map: MapKind::Hash,
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--sample" => {
+                    let fraction: f64 = parse_value(&arg, args.next())?;
+                    if !(fraction > 0.0 && fraction <= 1.0) {
+                        return Err(invalid_input(format!(
+                            "`--sample` expects a fraction in (0, 1], got {}",
+                            fraction
+                        )));
+                    }
+                    options.sample = Some(fraction);
+                }
+                "--count-only" => options.count_only = true,
+                "--discard-output" => options.discard_output = true,
+                "--hot-path-stats" => options.hot_path_stats = true,
+                "--lenient" => options.lenient = true,
+                "--error-report" => options.error_report = Some(parse_value(&arg, args.next())?),
+                "--max-memory" => options.max_memory = Some(parse_size_value(&arg, args.next())?),
+                "--memory-limit" => {
+                    options.memory_limit = Some(parse_size_value(&arg, args.next())?)
+                }
+                "--impl" => options.implementation = parse_value(&arg, args.next())?,
+                "--schedule" => options.schedule = parse_value(&arg, args.next())?,
+                "--threads" => options.threads = Some(parse_value(&arg, args.next())?),
+                "--collate" => options.collate = Some(parse_value(&arg, args.next())?),
+                "--format" => options.format = parse_value(&arg, args.next())?,
+                "--highlight" => options.highlight = true,
+                "--verbose" => options.verbose = true,
+                "--thread-stats" => options.thread_stats = true,
+                "--phase-times" => options.phase_times = true,
+                "--timeout" => {
+                    let value: String = parse_value(&arg, args.next())?;
+                    let seconds = window::parse_width(&value).ok_or_else(|| {
+                        invalid_input(format!("invalid duration `{}` for `{}`", value, arg))
+                    })?;
+                    options.timeout = Some(Duration::from_secs(seconds));
+                }
+                "--energy" => options.energy = true,
+                "--log-format" => options.log_format = parse_value(&arg, args.next())?,
+                "--status-socket" => options.status_socket = Some(parse_value(&arg, args.next())?),
+                "--reproducible" => options.reproducible = true,
+                "--counters" => options.counters = true,
+                "--exact" => options.exact = true,
+                "--detect-overflow" => options.detect_overflow = true,
+                "--utf8" => options.utf8 = parse_value(&arg, args.next())?,
+                "--recover" => options.recover = parse_value(&arg, args.next())?,
+                "--group-by" => {
+                    let value: String = parse_value(&arg, args.next())?;
+                    options.group_by = match value.split_once(',') {
+                        None if value == "station" => None,
+                        Some(("station", column)) => Some(
+                            column
+                                .parse()
+                                .ok()
+                                .filter(|&column| column >= 2)
+                                .ok_or_else(|| {
+                                    invalid_input(format!(
+                                        "invalid value `{}` for `{}`",
+                                        value, arg
+                                    ))
+                                })?,
+                        ),
+                        _ => {
+                            return Err(invalid_input(format!(
+                                "invalid value `{}` for `{}`",
+                                value, arg
+                            )))
+                        }
+                    };
+                }
+                "--sort-by" => {
+                    let value: String = parse_value(&arg, args.next())?;
+                    options.sort.keys = match value.as_str() {
+                        "none" => Vec::new(),
+                        _ => value
+                            .split(',')
+                            .map(|key| key.parse::<SortKey>())
+                            .collect::<Result<_, _>>()
+                            .map_err(|_| {
+                                invalid_input(format!("invalid value `{}` for `{}`", value, arg))
+                            })?,
+                    };
+                }
+                "--no-sort" => options.sort.keys = Vec::new(),
+                "--desc" => options.sort.desc = true,
+                "--rounding" => options.rounding = Some(parse_value(&arg, args.next())?),
+                "--output" => options.output = Some(parse_value(&arg, args.next())?),
+                "--table" => {
+                    options.table = parse_value(&arg, args.next())?;
+                    if !postgres::is_table_name(&options.table) {
+                        return Err(invalid_input(format!(
+                            "invalid table name `{}`",
+                            options.table
+                        )));
+                    }
+                }
+                "--delimiter" => {
+                    let value: String = parse_value(&arg, args.next())?;
+                    options.sniff_delimiter = value == "auto";
+                    if !options.sniff_delimiter {
+                        options.delimiter = parse_delimiter(&value).ok_or_else(|| {
+                            invalid_input(format!("invalid delimiter `{}`", value))
+                        })?;
+                    }
+                }
+                "--decimal-comma" => options.notation.decimal = b',',
+                "--numbers" => options.notation.numbers = parse_value(&arg, args.next())?,
+                "--chart" => options.chart = Some(parse_value(&arg, args.next())?),
+                "--chart-top" => options.chart_top = parse_value(&arg, args.next())?,
+                "--load-state" => options.load_state = Some(parse_value(&arg, args.next())?),
+                "--save-state" => options.save_state = Some(parse_value(&arg, args.next())?),
+                "--cache" => options.cache = Some(parse_value(&arg, args.next())?),
+                "--prefault" => options.prefault = true,
+                "--no-cache" => options.no_cache = true,
+                "--mlock" => options.mlock = true,
+                "--chunk-size" => {
+                    let size = parse_size_value(&arg, args.next())?;
+                    if size == 0 {
+                        return Err(invalid_input("`--chunk-size` must be positive".to_string()));
+                    }
+                    options.chunk_size = Some(size);
+                }
+                "--queue-depth" => {
+                    let depth = parse_value(&arg, args.next())?;
+                    if depth == 0 {
+                        return Err(invalid_input(
+                            "`--queue-depth` must be positive".to_string(),
+                        ));
+                    }
+                    options.queue_depth = Some(depth);
+                }
+                "--readahead" => options.readahead = Some(parse_size_value(&arg, args.next())?),
+                "--retries" => options.retries = parse_value(&arg, args.next())?,
+                "--retry-backoff" => {
+                    options.retry_backoff = Duration::from_millis(parse_value(&arg, args.next())?)
+                }
+                "--resume" => options.resume = Some(parse_value(&arg, args.next())?),
+                "--stations" => options.stations = Some(parse_value(&arg, args.next())?),
+                "--aliases" => options.aliases = Some(parse_value(&arg, args.next())?),
+                "--trim" => options.trim = true,
+                "--case-insensitive" => options.case_insensitive = true,
+                "--include-file" => options.include_file = Some(parse_value(&arg, args.next())?),
+                "--exclude-file" => options.exclude_file = Some(parse_value(&arg, args.next())?),
+                "--key-hash" => options.key_hash = parse_value(&arg, args.next())?,
+                "--check-key-hash" => options.check_key_hash = true,
+                "--compensated" => options.compensated = true,
+                "--self-check" => options.self_check = true,
+                "--verify-fingerprints" => options.verify_fingerprints = true,
+                "--map" => options.map = parse_value(&arg, args.next())?,
+                "--provenance" => options.provenance = true,
+                "--first-last" => options.first_last = true,
+                "--stats" => {
+                    let value: String = parse_value(&arg, args.next())?;
+                    options.stats = value
+                        .split(',')
+                        .map(|stat| stat.parse::<Stat>())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| {
+                            invalid_input(format!("invalid value `{}` for `{}`", value, arg))
+                        })?;
+                }
+                "--window" => {
+                    let value: String = parse_value(&arg, args.next())?;
+                    options.window = Some(window::parse_width(&value).ok_or_else(|| {
+                        invalid_input(format!("invalid window `{}` for `{}`", value, arg))
+                    })?);
+                }
+                "--time-col" => {
+                    let value: String = parse_value(&arg, args.next())?;
+                    options.time_col = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|&column| column >= 2)
+                            .ok_or_else(|| {
+                                invalid_input(format!("invalid value `{}` for `{}`", value, arg))
+                            })?,
+                    );
+                }
+                _ if arg.starts_with("--") => {
+                    return Err(invalid_input(format!("unknown option `{}`", arg)));
+                }
+                _ => options.path = arg,
+            }
+        }
+
+        if options.count_only && options.sample.is_some() {
+            return Err(invalid_input(
+                "`--count-only` cannot be combined with `--sample`".to_string(),
+            ));
+        }
+
+        if options.format != Format::Text && (options.count_only || options.sample.is_some()) {
+            return Err(invalid_input(
+                "`--format` only applies to full runs".to_string(),
+            ));
+        }
+
+        let uses_state = options.load_state.is_some() || options.save_state.is_some();
+        if uses_state && (options.count_only || options.sample.is_some()) {
+            return Err(invalid_input(
+                "`--load-state` and `--save-state` only apply to full runs".to_string(),
+            ));
+        }
+
+        if remote::is_url(&options.path)
+            && (options.count_only || options.sample.is_some() || options.exact)
+        {
+            return Err(invalid_input(
+                "remote input only applies to full runs".to_string(),
+            ));
+        }
+
+        if remote::is_url(&options.path)
+            && (options.cache.is_some() || options.prefault || options.mlock)
+        {
+            return Err(invalid_input(
+                "`--cache`, `--prefault` and `--mlock` do not apply to remote input".to_string(),
+            ));
+        }
+
+        if options.reads_stdin()
+            && (options.count_only
+                || options.sample.is_some()
+                || options.exact
+                || options.hot_path_stats
+                || options.cache.is_some()
+                || options.prefault
+                || options.mlock
+                || options.sniff_delimiter
+                || options.self_check
+                || options.provenance
+                || options.first_last)
+        {
+            return Err(invalid_input(
+                "stdin input only applies to full runs without `--cache`, `--prefault`, \
+                 `--mlock`, `--delimiter auto`, `--self-check`, `--provenance` or `--first-last`"
+                    .to_string(),
+            ));
+        }
+
+        if options.notation.decimal == options.delimiter {
+            return Err(invalid_input(
+                "`--decimal-comma` needs a `--delimiter` other than `,`".to_string(),
+            ));
+        }
+
+        if options.queue_depth.is_some()
+            && options.implementation != Implementation::Pipeline
+            && !remote::is_url(&options.path)
+            && !options.reads_stdin()
+        {
+            return Err(invalid_input(
+                "`--queue-depth` only applies to `--impl pipeline`, remote input and stdin"
+                    .to_string(),
+            ));
+        }
+
+        if options.resume.is_some() && !remote::is_url(&options.path) {
+            return Err(invalid_input(
+                "`--resume` only applies to remote input".to_string(),
+            ));
+        }
+
+        // Journaled ranges record only their stations.
+        if options.resume.is_some()
+            && (options.lenient || options.recover != Recover::Off || options.max_memory.is_some())
+        {
+            return Err(invalid_input(
+                "`--resume` cannot be combined with `--lenient`, `--recover` or `--max-memory`"
+                    .to_string(),
+            ));
+        }
+
+        if options.output.is_some()
+            && (options.count_only || options.sample.is_some() || options.exact)
+        {
+            return Err(invalid_input(
+                "`--output` only applies to full runs".to_string(),
+            ));
+        }
+
+        if options.output.as_deref().is_some_and(postgres::is_url) && options.format != Format::Text
+        {
+            return Err(invalid_input(
+                "`--format` does not apply to `--output postgres://...`".to_string(),
+            ));
+        }
+
+        if options.output.as_deref().is_some_and(clickhouse::is_url)
+            && options.format != Format::RowBinary
+        {
+            return Err(invalid_input(
+                "`--output http://...` requires `--format rowbinary`".to_string(),
+            ));
+        }
+
+        if options.format == Format::Sqlite && options.output.is_none() {
+            return Err(invalid_input(
+                "`--format sqlite` requires `--output`".to_string(),
+            ));
+        }
+
+        if (options.aliases.is_some()
+            || options.include_file.is_some()
+            || options.exclude_file.is_some())
+            && (options.count_only
+                || options.hot_path_stats
+                || options.sample.is_some()
+                || options.exact)
+        {
+            return Err(invalid_input(
+                "`--aliases`, `--include-file` and `--exclude-file` only apply to full runs \
+                 without `--exact`"
+                    .to_string(),
+            ));
+        }
+
+        if (options.trim || options.case_insensitive)
+            && (options.count_only
+                || options.hot_path_stats
+                || options.sample.is_some()
+                || options.exact)
+        {
+            return Err(invalid_input(
+                "`--trim` and `--case-insensitive` only apply to full runs without `--exact`"
+                    .to_string(),
+            ));
+        }
+
+        if options.discard_output
+            && (options.count_only
+                || options.hot_path_stats
+                || options.sample.is_some()
+                || options.exact
+                || options.output.is_some()
+                || options.chart.is_some()
+                || uses_state)
+        {
+            return Err(invalid_input(
+                "`--discard-output` only applies to full runs without `--exact`, `--output`, \
+                 `--chart`, `--load-state` or `--save-state`"
+                    .to_string(),
+            ));
+        }
+
+        if (options.phase_times || options.energy || options.status_socket.is_some())
+            && (options.count_only
+                || options.hot_path_stats
+                || options.sample.is_some()
+                || options.exact)
+        {
+            return Err(invalid_input(
+                "`--phase-times`, `--energy` and `--status-socket` only apply to full runs \
+                 without `--exact`"
+                    .to_string(),
+            ));
+        }
+
+        if !options.sort.is_by_name()
+            && (options.count_only || options.sample.is_some() || options.exact)
+        {
+            return Err(invalid_input(
+                "`--sort-by`, `--no-sort` and `--desc` only apply to full runs".to_string(),
+            ));
+        }
+
+        if options.exact
+            && (options.count_only
+                || options.sample.is_some()
+                || options.format != Format::Text
+                || options.chart.is_some()
+                || uses_state
+                || options.error_report.is_some()
+                || options.recover != Recover::Off)
+        {
+            return Err(invalid_input(
+                "`--exact` only applies to full runs with text output, \
+                 without charts, state, error reports or recovery"
+                    .to_string(),
+            ));
+        }
+
+        if options.hot_path_stats
+            && (options.count_only
+                || options.sample.is_some()
+                || options.exact
+                || options.format != Format::Text
+                || options.output.is_some()
+                || options.chart.is_some()
+                || !options.sort.is_by_name()
+                || uses_state
+                || options.error_report.is_some()
+                || options.recover != Recover::Off
+                || options.group_by.is_some()
+                || options.window.is_some()
+                || options.tracks_readings()
+                || options.notation != Notation::DEFAULT
+                || remote::is_url(&options.path))
+        {
+            return Err(invalid_input(
+                "`--hot-path-stats` only applies to plain runs on a local file, \
+                 without other modes, output options, state, error reports, recovery, \
+                 `--decimal-comma` or `--numbers`"
+                    .to_string(),
+            ));
+        }
+
+        if options.check_key_hash
+            && (options.count_only
+                || options.sample.is_some()
+                || options.exact
+                || options.hot_path_stats
+                || options.max_memory.is_some()
+                || options.implementation == Implementation::External)
+        {
+            return Err(invalid_input(
+                "`--check-key-hash` only applies to full in-memory runs".to_string(),
+            ));
+        }
+
+        if options.compensated && options.sample.is_none() && options.stats.is_empty() {
+            return Err(invalid_input(
+                "`--compensated` only applies to `--sample` and `--stats`".to_string(),
+            ));
+        }
+
+        if options.self_check
+            && (options.count_only
+                || options.sample.is_some()
+                || options.exact
+                || options.hot_path_stats
+                || options.max_memory.is_some()
+                || options.implementation == Implementation::External
+                || options.group_by.is_some()
+                || options.window.is_some()
+                || options.recover != Recover::Off
+                || options.load_state.is_some())
+        {
+            return Err(invalid_input(
+                "`--self-check` only applies to full in-memory runs by station, \
+                 without `--recover` or `--load-state`"
+                    .to_string(),
+            ));
+        }
+
+        if options.verify_fingerprints && options.key_hash != KeyHash::Fingerprint {
+            return Err(invalid_input(
+                "`--verify-fingerprints` requires `--key-hash fingerprint`".to_string(),
+            ));
+        }
+
+        if options.window.is_some() != options.time_col.is_some() {
+            return Err(invalid_input(
+                "`--window` and `--time-col` must be given together".to_string(),
+            ));
+        }
+
+        if options.window.is_some() && options.group_by.is_some() {
+            return Err(invalid_input(
+                "`--window` cannot be combined with `--group-by`".to_string(),
+            ));
+        }
+
+        if (options.group_by.is_some() || options.window.is_some())
+            && (options.count_only
+                || options.sample.is_some()
+                || options.exact
+                || options.recover != Recover::Off)
+        {
+            return Err(invalid_input(
+                "`--group-by` and `--window` only apply to full runs without `--exact` or \
+                 `--recover`"
+                    .to_string(),
+            ));
+        }
+
+        if options.stations.is_some() {
+            match options.implementation {
+                Implementation::Rayon if options.schedule == Schedule::Adaptive => {
+                    options.implementation = Implementation::TwoPass
+                }
+                Implementation::TwoPass => {}
+                _ => {
+                    return Err(invalid_input(
+                        "`--stations` only applies to `--impl rayon` with `--schedule adaptive`, \
+                         or `--impl two-pass`"
+                            .to_string(),
+                    ))
+                }
+            }
+        }
+
+        let fixed_slots = options.group_by.is_none()
+            && options.window.is_none()
+            && options.recover == Recover::Off
+            && options.max_memory.is_none()
+            && !options.tracks_readings();
+
+        // Both replace the default fold over a station map.
+        let own_fold = options.key_hash == KeyHash::Fingerprint || options.map == MapKind::Index;
+        if options.key_hash == KeyHash::Fingerprint && options.map == MapKind::Index {
+            return Err(invalid_input(
+                "`--key-hash fingerprint` cannot be combined with `--map index`".to_string(),
+            ));
+        }
+        if own_fold
+            && (options.implementation != Implementation::Rayon
+                || options.schedule != Schedule::Adaptive
+                || !fixed_slots
+                || remote::is_url(&options.path)
+                || options.reads_stdin())
+        {
+            return Err(invalid_input(
+                "`--key-hash fingerprint` and `--map index` only apply to `--impl rayon` with \
+                 `--schedule adaptive` on local input, without `--stations`, `--group-by`, \
+                 `--window`, `--recover`, `--max-memory`, `--provenance`, `--first-last` or \
+                 `--stats`"
+                    .to_string(),
+            ));
+        }
+
+        // Builds with a baked station table use it wherever two-pass applies.
+        if crate::baked::ENABLED
+            && options.implementation == Implementation::Rayon
+            && options.schedule == Schedule::Adaptive
+            && !own_fold
+            && fixed_slots
+        {
+            options.implementation = Implementation::TwoPass;
+        }
+
+        if options.implementation == Implementation::Std && !fixed_slots {
+            return Err(invalid_input(
+                "`--impl std` does not support `--group-by`, `--window`, `--recover`, \
+                 `--max-memory`, `--provenance`, `--first-last` or `--stats`"
+                    .to_string(),
+            ));
+        }
+
+        // It reads the file itself instead of mapping it.
+        if options.implementation == Implementation::Std
+            && (options.self_check
+                || options.prefault
+                || options.mlock
+                || options.cache == Some(Cache::Warm))
+        {
+            return Err(invalid_input(
+                "`--impl std` does not map the input, so it does not support `--self-check`, \
+                 `--prefault`, `--mlock` or `--cache warm`"
+                    .to_string(),
+            ));
+        }
+
+        if matches!(
+            options.implementation,
+            Implementation::Gpu | Implementation::Cuda
+        ) && !fixed_slots
+        {
+            return Err(invalid_input(format!(
+                "`--impl {}` does not support `--group-by`, `--window`, `--recover`, \
+                 `--max-memory`, `--provenance`, `--first-last` or `--stats`",
+                options.implementation.label()
+            )));
+        }
+
+        if options.implementation == Implementation::TwoPass && !fixed_slots {
+            return Err(invalid_input(
+                "`--impl two-pass` and `--stations` do not support `--group-by`, `--window`, \
+                 `--recover`, `--max-memory`, `--provenance`, `--first-last` or `--stats`"
+                    .to_string(),
+            ));
+        }
+
+        if options.tracks_readings()
+            && (options.count_only
+                || options.sample.is_some()
+                || options.exact
+                || options.load_state.is_some()
+                || options.resume.is_some()
+                || options.max_memory.is_some()
+                || options.implementation == Implementation::External)
+        {
+            return Err(invalid_input(
+                "`--provenance`, `--first-last` and `--stats` only apply to full in-memory \
+                 runs without `--exact`, `--load-state` or `--resume`"
+                    .to_string(),
+            ));
+        }
+
+        if !options.stats.is_empty()
+            && (!matches!(options.format, Format::Text | Format::Jsonl)
+                || options.output.as_deref().is_some_and(postgres::is_url))
+        {
+            return Err(invalid_input(
+                "`--stats` only applies to `--format text` or `jsonl`".to_string(),
+            ));
+        }
+
+        if options.format == Format::Nested
+            && options.group_by.is_none()
+            && options.window.is_none()
+        {
+            return Err(invalid_input(
+                "`--format nested` requires `--group-by` or `--window`".to_string(),
+            ));
+        }
+
+        if options.error_report.is_some() && !options.lenient {
+            return Err(invalid_input(
+                "`--error-report` requires `--lenient`".to_string(),
+            ));
+        }
+
+        if options.timeout.is_some()
+            && (options.count_only
+                || options.sample.is_some()
+                || options.exact
+                || options.hot_path_stats
+                || !interrupt::supported(&options)
+                || options.reads_stdin()
+                || remote::is_url(&options.path))
+        {
+            return Err(invalid_input(
+                "`--timeout` only applies to full runs over a local file with `--impl rayon` \
+                 (without `--key-hash fingerprint` or `--map index`), `scoped`, `per-core` or \
+                 `external`"
+                    .to_string(),
+            ));
+        }
+
+        // Pins down everything that varies the division of work or the order
+        // of merges and output between runs of the same input.
+        if options.reproducible {
+            if matches!(
+                options.implementation,
+                Implementation::Pipeline | Implementation::TwoPass
+            ) || options.reads_stdin()
+                || remote::is_url(&options.path)
+            {
+                return Err(invalid_input(
+                    "`--reproducible` needs a fixed division of work: `--impl rayon`, `scoped`, \
+                     `per-core`, `external`, `std`, `gpu` or `cuda` on a local file"
+                        .to_string(),
+                ));
+            }
+            if options.schedule == Schedule::Dynamic {
+                return Err(invalid_input(
+                    "`--reproducible` implies `--schedule static`, not `dynamic`".to_string(),
+                ));
+            }
+            if options.sort.is_unordered() {
+                return Err(invalid_input(
+                    "`--reproducible` cannot print stations in hash order with `--no-sort`"
+                        .to_string(),
+                ));
+            }
+            options.schedule = Schedule::Static;
+            options.threads.get_or_insert(REPRODUCIBLE_THREADS);
+            // Ties are broken by name rather than by hash order.
+            if !options.sort.keys.contains(&SortKey::Name) {
+                options.sort.keys.push(SortKey::Name);
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+// Options of the `generate` subcommand. Config defaults do not apply, since
+// they describe runs.
+pub struct GenerateOptions {
+    pub path: String,
+    pub profile: Profile,
+    pub rows: usize,
+    pub seed: u64,
+}
+
+impl GenerateOptions {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> io::Result<Self> {
+        let mut options = GenerateOptions {
+            path: DEFAULT_GENERATE_PATH.to_string(),
+            profile: Profile::Standard,
+            rows: DEFAULT_GENERATE_ROWS,
+            seed: 0,
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--profile" => options.profile = parse_value(&arg, args.next())?,
+                "--rows" => options.rows = parse_value(&arg, args.next())?,
+                "--seed" => options.seed = parse_value(&arg, args.next())?,
+                _ if arg.starts_with("--") => {
+                    return Err(invalid_input(format!("unknown option `{}`", arg)));
+                }
+                _ => options.path = arg,
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+// Options of the `compare` subcommand: two result files and the largest
+// difference tolerated between their statistics.
+pub struct CompareOptions {
+    pub left: String,
+    pub right: String,
+    pub epsilon: f64,
+}
+
+impl CompareOptions {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> io::Result<Self> {
+        let mut files = Vec::new();
+        let mut epsilon: f64 = 0.0;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--epsilon" => {
+                    epsilon = parse_value(&arg, args.next())?;
+                    if epsilon.is_nan() || epsilon < 0.0 {
+                        return Err(invalid_input(format!(
+                            "`--epsilon` expects a non-negative number, got {}",
+                            epsilon
+                        )));
+                    }
+                }
+                _ if arg.starts_with("--") => {
+                    return Err(invalid_input(format!("unknown option `{}`", arg)));
+                }
+                _ => files.push(arg),
+            }
+        }
+
+        let [left, right]: [String; 2] = files
+            .try_into()
+            .map_err(|_| invalid_input("`compare` expects two result files".to_string()))?;
+        Ok(CompareOptions {
+            left,
+            right,
+            epsilon,
+        })
+    }
+}
+
+// Options of the `bench` subcommand. Anything not specific to benchmarking is
+// passed on to `run`.
+pub struct BenchOptions {
+    // The command to compare with, if any.
+    pub against: Option<String>,
+    pub compare_schedulers: bool,
+    // Measured runs per command: exactly `runs`, or at least `runs` and then
+    // more until `max_time` is spent.
+    pub runs: usize,
+    pub max_time: Option<Duration>,
+    // Runs before the measured ones, whose times are discarded.
+    pub warmup: usize,
+    pub epsilon: f64,
+    pub run_args: Vec<String>,
+    pub path: String,
+    pub discard_output: bool,
+    // Whether brc's runs report `--phase-times`, which only full runs do.
+    pub phase_times: bool,
+    // The strategy and thread count of brc's runs, for `--history` and
+    // baselines.
+    pub implementation: Implementation,
+    pub threads: Option<usize>,
+    pub history: Option<String>,
+    pub baseline: Option<String>,
+    pub save_baseline: Option<String>,
+    // Percentage by which brc's median may exceed the `baseline` one.
+    pub max_regression: f64,
+}
+
+impl BenchOptions {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> io::Result<Self> {
+        let mut against = None;
+        let mut compare_schedulers = false;
+        let mut runs = None;
+        let mut min_runs = None;
+        let mut max_time = None;
+        let mut warmup = 0;
+        let mut history = None;
+        let mut baseline = None;
+        let mut save_baseline = None;
+        let mut max_regression = None;
+        let mut epsilon = 0.0;
+        let mut run_args = Vec::new();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--against" => against = Some(parse_value(&arg, args.next())?),
+                "--compare-schedulers" => compare_schedulers = true,
+                "--runs" => runs = Some(parse_value(&arg, args.next())?),
+                "--min-runs" => min_runs = Some(parse_value(&arg, args.next())?),
+                "--max-time" => {
+                    let seconds: f64 = parse_value(&arg, args.next())?;
+                    max_time = Some(Duration::try_from_secs_f64(seconds).map_err(|_| {
+                        invalid_input(format!("invalid value `{}` for `{}`", seconds, arg))
+                    })?);
+                }
+                "--warmup" => warmup = parse_value(&arg, args.next())?,
+                "--history" => history = Some(parse_value(&arg, args.next())?),
+                "--baseline" => baseline = Some(parse_value(&arg, args.next())?),
+                "--save-baseline" => save_baseline = Some(parse_value(&arg, args.next())?),
+                "--max-regression" => {
+                    let value: String = parse_value(&arg, args.next())?;
+                    let percent = value
+                        .strip_suffix('%')
+                        .unwrap_or(&value)
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|percent| percent.is_finite() && *percent >= 0.0)
+                        .ok_or_else(|| {
+                            invalid_input(format!("invalid value `{}` for `{}`", value, arg))
+                        })?;
+                    max_regression = Some(percent);
+                }
+                "--epsilon" => epsilon = parse_value(&arg, args.next())?,
+                _ => run_args.push(arg),
+            }
+        }
+
+        if against.is_some() && compare_schedulers {
+            return Err(invalid_input(
+                "`--against` and `--compare-schedulers` cannot be combined".to_string(),
+            ));
+        }
+        if against.is_none()
+            && !compare_schedulers
+            && baseline.is_none()
+            && save_baseline.is_none()
+            && history.is_none()
+        {
+            return Err(invalid_input(
+                "`bench` requires `--against`, `--compare-schedulers`, `--baseline`, \
+                 `--save-baseline` or `--history`"
+                    .to_string(),
+            ));
+        }
+        if max_regression.is_some() && baseline.is_none() {
+            return Err(invalid_input(
+                "`--max-regression` requires `--baseline`".to_string(),
+            ));
+        }
+        if runs.is_some() && (min_runs.is_some() || max_time.is_some()) {
+            return Err(invalid_input(
+                "`--runs` cannot be combined with `--min-runs` or `--max-time`".to_string(),
+            ));
+        }
+        if min_runs.is_some() && max_time.is_none() {
+            return Err(invalid_input(
+                "`--min-runs` requires `--max-time`".to_string(),
+            ));
+        }
+        let runs = match max_time {
+            Some(_) => min_runs.unwrap_or(DEFAULT_MIN_RUNS),
+            None => runs.unwrap_or(1),
+        };
+        if runs == 0 {
+            return Err(invalid_input(
+                "`--runs` and `--min-runs` must be at least 1".to_string(),
+            ));
+        }
+        // Validates the forwarded options and finds the input path.
+        let run_options = Options::parse(run_args.iter().cloned())?;
+        if compare_schedulers
+            && (run_args.iter().any(|arg| arg == "--impl")
+                || run_options.count_only
+                || run_options.hot_path_stats
+                || run_options.sample.is_some()
+                || run_options.exact
+                || run_options.reads_stdin()
+                || remote::is_url(&run_options.path))
+        {
+            return Err(invalid_input(
+                "`--compare-schedulers` picks `--impl` itself and only applies to full runs \
+                 on a local file without `--exact`"
+                    .to_string(),
+            ));
+        }
+        Ok(BenchOptions {
+            against,
+            compare_schedulers,
+            runs,
+            max_time,
+            warmup,
+            epsilon,
+            run_args,
+            path: run_options.path,
+            discard_output: run_options.discard_output,
+            phase_times: !(run_options.count_only
+                || run_options.hot_path_stats
+                || run_options.sample.is_some()
+                || run_options.exact),
+            implementation: run_options.implementation,
+            threads: run_options.threads,
+            history,
+            baseline,
+            save_baseline,
+            max_regression: max_regression.unwrap_or(DEFAULT_MAX_REGRESSION),
+        })
+    }
+}
+
+// Parses the value following a flag.
+fn parse_value<T: FromStr>(flag: &str, value: Option<String>) -> io::Result<T> {
+    let value = value.ok_or_else(|| invalid_input(format!("`{}` expects a value", flag)))?;
+    value
+        .parse()
+        .map_err(|_| invalid_input(format!("invalid value `{}` for `{}`", value, flag)))
+}
+
+// Parses the byte size following a flag.
+fn parse_size_value(flag: &str, value: Option<String>) -> io::Result<usize> {
+    let value: String = parse_value(flag, value)?;
+    parse_size(&value)
+        .ok_or_else(|| invalid_input(format!("invalid size `{}` for `{}`", value, flag)))
+}
+
+// Parses a byte size with an optional binary `K`, `M` or `G` suffix.
+fn parse_size(value: &str) -> Option<usize> {
+    let (digits, multiplier) = match value.as_bytes().last()?.to_ascii_uppercase() {
+        b'K' => (&value[..value.len() - 1], 1 << 10),
+        b'M' => (&value[..value.len() - 1], 1 << 20),
+        b'G' => (&value[..value.len() - 1], 1 << 30),
+        _ => (value, 1),
+    };
+    digits.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+// Parses a field separator: a single ASCII character other than a newline,
+// `.`, `-` or a digit, or `tab`.
+fn parse_delimiter(value: &str) -> Option<u8> {
+    match value.as_bytes() {
+        b"tab" | b"\\t" => Some(b'\t'),
+        &[b] if b.is_ascii() && !matches!(b, b'\n' | b'\r' | b'.' | b'-' | b'0'..=b'9') => Some(b),
+        _ => None,
+    }
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
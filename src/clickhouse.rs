@@ -0,0 +1,104 @@
+// ClickHouse RowBinary encoding of the results for `--format rowbinary`, for
+// tables with the columns `station String, min Float32, mean Float32,
+// max Float32, count UInt64`.
+
+use crate::error::Error;
+use crate::output::StationRecord;
+use crate::StationData;
+use brc::rounding::Rounding;
+use std::io;
+
+// Whether an `--output` target is a ClickHouse HTTP endpoint rather than a
+// file.
+pub fn is_url(output: &str) -> bool {
+    output.starts_with("http://") || output.starts_with("https://")
+}
+
+// Writes already sorted stations as RowBinary rows: strings are prefixed with
+// their LEB128 length, numbers are little-endian.
+pub fn write(
+    out: &mut impl io::Write,
+    stations: &[(String, StationData)],
+    rounding: Option<Rounding>,
+) -> io::Result<()> {
+    let mut row = Vec::with_capacity(128);
+    for (station, data) in stations {
+        let record = StationRecord::new(station, data, rounding);
+        row.clear();
+        let mut len = record.station.len();
+        while len >= 0x80 {
+            row.push(len as u8 | 0x80);
+            len >>= 7;
+        }
+        row.push(len as u8);
+        row.extend_from_slice(record.station.as_bytes());
+        row.extend_from_slice(&record.min.to_le_bytes());
+        row.extend_from_slice(&record.mean.to_le_bytes());
+        row.extend_from_slice(&record.max.to_le_bytes());
+        row.extend_from_slice(&record.count.to_le_bytes());
+        out.write_all(&row)?;
+    }
+    Ok(())
+}
+
+// Inserts the stations into `table` through the ClickHouse HTTP interface at
+// `url`, creating a MergeTree table if needed. Credentials and settings can
+// be passed as query parameters of `url`.
+#[cfg(feature = "http")]
+pub fn insert(
+    url: &str,
+    table: &str,
+    stations: &[(String, StationData)],
+    rounding: Option<Rounding>,
+) -> Result<(), Error> {
+    let mut body = Vec::with_capacity(stations.len() * 32);
+    write(&mut body, stations, rounding)?;
+
+    let create = format!(
+        "CREATE TABLE IF NOT EXISTS {} (
+             station String,
+             min Float32,
+             mean Float32,
+             max Float32,
+             count UInt64
+         ) ENGINE = MergeTree ORDER BY station",
+        table
+    );
+    send(ureq::post(url), create.as_bytes())?;
+    send(
+        ureq::post(url).query("query", &format!("INSERT INTO {} FORMAT RowBinary", table)),
+        &body,
+    )
+}
+
+// Sends one request, turning failures into an error with ClickHouse's own
+// message when the server answered.
+#[cfg(feature = "http")]
+fn send(request: ureq::Request, body: &[u8]) -> Result<(), Error> {
+    let detail = match request.send_bytes(body) {
+        Ok(_) => return Ok(()),
+        Err(ureq::Error::Status(code, response)) => format!(
+            "HTTP {}: {}",
+            code,
+            response.into_string().unwrap_or_default().trim()
+        ),
+        Err(e) => e.to_string(),
+    };
+    Err(Error::Io(io::Error::other(format!(
+        "failed to insert results into ClickHouse: {}",
+        detail
+    ))))
+}
+
+#[cfg(not(feature = "http"))]
+pub fn insert(
+    _url: &str,
+    _table: &str,
+    _stations: &[(String, StationData)],
+    _rounding: Option<Rounding>,
+) -> Result<(), Error> {
+    Err(Error::Io(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "`--output http://...` requires building with the `http` feature",
+    )))
+}
@@ -0,0 +1,66 @@
+use crate::error::Error;
+use std::cmp::Ordering;
+use std::io;
+
+// Locale-aware ordering of station names for human-facing reports. Byte
+// order remains the default, since that is what result harnesses expect.
+pub struct Collation {
+    #[cfg(feature = "collation")]
+    collator: icu_collator::CollatorBorrowed<'static>,
+}
+
+impl Collation {
+    // Builds a collation from a spec such as `icu:de_DE`.
+    pub fn new(spec: &str) -> Result<Self, Error> {
+        let locale = spec.strip_prefix("icu:").ok_or_else(|| {
+            invalid_input(format!(
+                "unsupported collation `{}`, expected `icu:<locale>`",
+                spec
+            ))
+        })?;
+        Self::for_locale(&locale.replace('_', "-"))
+    }
+
+    #[cfg(feature = "collation")]
+    fn for_locale(locale: &str) -> Result<Self, Error> {
+        use icu_collator::options::CollatorOptions;
+        use icu_collator::Collator;
+        use icu_locale_core::Locale;
+
+        let locale: Locale = locale
+            .parse()
+            .map_err(|e| invalid_input(format!("invalid locale `{}`: {}", locale, e)))?;
+        let collator = Collator::try_new((&locale).into(), CollatorOptions::default())
+            .map_err(|e| invalid_input(format!("no collation data for `{}`: {}", locale, e)))?;
+        Ok(Collation { collator })
+    }
+
+    #[cfg(not(feature = "collation"))]
+    fn for_locale(_locale: &str) -> Result<Self, Error> {
+        Err(disabled())
+    }
+
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        #[cfg(feature = "collation")]
+        return self.collator.compare(a, b);
+        #[cfg(not(feature = "collation"))]
+        return crate::output::station_order(a, b);
+    }
+}
+
+// Checks that collation is compiled in before a run that sorts with it.
+pub fn check_enabled() -> Result<(), Error> {
+    if cfg!(feature = "collation") {
+        Ok(())
+    } else {
+        Err(disabled())
+    }
+}
+
+fn disabled() -> Error {
+    invalid_input("`--collate` requires building with the `collation` feature".to_string())
+}
+
+fn invalid_input(message: String) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidInput, message))
+}
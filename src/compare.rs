@@ -0,0 +1,134 @@
+use crate::cli::CompareOptions;
+use crate::error::Error;
+use std::collections::BTreeMap;
+use std::fs;
+
+// Tolerance added to `--epsilon` so values printed with one decimal compare
+// as intended despite binary floating-point.
+const SLACK: f64 = 1e-9;
+
+// `min`, `mean` and `max` of a station as printed in a result file.
+type Printed = [f64; 3];
+
+// Printed statistics by station.
+pub type Results = BTreeMap<String, Printed>;
+
+// Compares two result files in the challenge's `{name=min/mean/max, ...}`
+// format, printing stations found in only one of them and stations whose
+// statistics differ by more than the epsilon. Differences are a validation
+// failure.
+pub fn run(options: &CompareOptions) -> Result<(), Error> {
+    let left = parse_output(&options.left, &fs::read_to_string(&options.left)?)?;
+    let right = parse_output(&options.right, &fs::read_to_string(&options.right)?)?;
+
+    let differences = differences(
+        (&options.left, &left),
+        (&options.right, &right),
+        options.epsilon,
+    );
+    for difference in &differences {
+        println!("{}", difference);
+    }
+
+    println!(
+        "Compared {} and {} stations; {} differences (epsilon {})",
+        left.len(),
+        right.len(),
+        differences.len(),
+        options.epsilon
+    );
+    if !differences.is_empty() {
+        return Err(Error::Validation(format!(
+            "{} and {} differ in {} stations",
+            options.left,
+            options.right,
+            differences.len()
+        )));
+    }
+    Ok(())
+}
+
+// Describes stations found in only one of two labelled results and stations
+// whose statistics differ by more than `epsilon`.
+pub fn differences(
+    (left_label, left): (&str, &Results),
+    (right_label, right): (&str, &Results),
+    epsilon: f64,
+) -> Vec<String> {
+    let mut differences = Vec::new();
+    for (station, a) in left {
+        match right.get(station) {
+            None => differences.push(format!("only in {}: {}", left_label, station)),
+            Some(b) => {
+                let differs = a
+                    .iter()
+                    .zip(b)
+                    .any(|(a, b)| (a - b).abs() > epsilon + SLACK);
+                if differs {
+                    differences.push(format!(
+                        "differs: {} {}={} {}={}",
+                        station,
+                        left_label,
+                        format_printed(a),
+                        right_label,
+                        format_printed(b)
+                    ));
+                }
+            }
+        }
+    }
+    for station in right.keys().filter(|station| !left.contains_key(*station)) {
+        differences.push(format!("only in {}: {}", right_label, station));
+    }
+    differences
+}
+
+// Parses the first `{...}` result line of the output labelled `label`,
+// ignoring anything else such as timing output.
+pub fn parse_output(label: &str, content: &str) -> Result<Results, Error> {
+    let line = content
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with('{') && line.ends_with('}'))
+        .ok_or_else(|| Error::Parse(format!("{}: no `{{...}}` result line found", label)))?;
+    parse_results(&line[1..line.len() - 1])
+        .ok_or_else(|| Error::Parse(format!("{}: malformed result line", label)))
+}
+
+// Parses `name=min/mean/max` entries separated by `, `. Names may themselves
+// contain `, ` or `=`, so pieces are joined until they end in valid
+// statistics.
+fn parse_results(entries: &str) -> Option<Results> {
+    let mut results = BTreeMap::new();
+    if entries.is_empty() {
+        return Some(results);
+    }
+    let mut pending = String::new();
+    for piece in entries.split(", ") {
+        if !pending.is_empty() {
+            pending.push_str(", ");
+        }
+        pending.push_str(piece);
+        if let Some((name, stats)) = pending.rsplit_once('=') {
+            if let Some(printed) = parse_printed(stats) {
+                results.insert(name.to_string(), printed);
+                pending.clear();
+            }
+        }
+    }
+    pending.is_empty().then_some(results)
+}
+
+// Parses `min/mean/max`; a `±` interval on the mean, as printed by
+// `--sample`, is ignored.
+fn parse_printed(stats: &str) -> Option<Printed> {
+    let mut values = stats.split('/');
+    let min = values.next()?.parse().ok()?;
+    let mean = values.next()?.split('±').next()?.parse().ok()?;
+    let max = values.next()?.parse().ok()?;
+    values.next().is_none().then_some([min, mean, max])
+}
+
+fn format_printed(printed: &Printed) -> String {
+    format!("{:.1}/{:.1}/{:.1}", printed[0], printed[1], printed[2])
+}
@@ -0,0 +1,120 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Prefix of environment variables providing option defaults.
+const ENV_PREFIX: &str = "ONEBRC_";
+
+// Collects option defaults from the config file and the environment as
+// command-line style arguments. Later arguments win, so the result is
+// ordered file first, then environment, and the real command line should be
+// appended after it.
+pub fn default_args() -> io::Result<Vec<String>> {
+    let file = match config_path() {
+        Some(path) => match fs::read_to_string(&path) {
+            Ok(text) => Some((path, text)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        },
+        None => None,
+    };
+    collect_args(
+        file.as_ref()
+            .map(|(path, text)| (path.as_path(), text.as_str())),
+        env::vars(),
+    )
+}
+
+// Like `default_args`, from the text of the config file at `path`, if any,
+// and the environment variables `vars`.
+pub fn collect_args(
+    file: Option<(&Path, &str)>,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> io::Result<Vec<String>> {
+    let mut args = Vec::new();
+
+    if let Some((path, text)) = file {
+        for (number, line) in text.lines().enumerate() {
+            if let Some((key, value)) = parse_line(line).map_err(|message| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: {}", path.display(), number + 1, message),
+                )
+            })? {
+                push_option(&mut args, &key, value);
+            }
+        }
+    }
+
+    let mut vars: Vec<_> = vars
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let key = name.strip_prefix(ENV_PREFIX)?;
+            Some((key.to_ascii_lowercase().replace('_', "-"), value))
+        })
+        .collect();
+    vars.sort();
+    for (key, value) in vars {
+        push_option(&mut args, &key, value);
+    }
+
+    Ok(args)
+}
+
+// `$XDG_CONFIG_HOME/1brc.toml`, falling back to `~/.config/1brc.toml`.
+fn config_path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?)
+            .join(".config"),
+    };
+    Some(dir.join("1brc.toml"))
+}
+
+// Turns `key = value` into `--key value`; booleans become a bare flag when
+// true, and when false remove the flag if an earlier source set it.
+fn push_option(args: &mut Vec<String>, key: &str, value: String) {
+    match value.as_str() {
+        "false" => {
+            let flag = format!("--{}", key);
+            args.retain(|arg| *arg != flag);
+        }
+        "true" => args.push(format!("--{}", key)),
+        _ => {
+            args.push(format!("--{}", key));
+            args.push(value);
+        }
+    }
+}
+
+// Parses one line of the flat TOML subset accepted in the config file:
+// `key = value` pairs with string, integer, float or boolean values, blank
+// lines and `#` comments. Tables and arrays are not supported.
+fn parse_line(line: &str) -> Result<Option<(String, String)>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    if line.starts_with('[') {
+        return Err("tables are not supported".to_string());
+    }
+
+    let (key, value) = line
+        .split_once('=')
+        .ok_or_else(|| format!("expected `key = value`, got `{}`", line))?;
+    let key = key.trim().replace('_', "-");
+    let value = value.trim();
+
+    let value = if let Some(quoted) = value.strip_prefix('"') {
+        let end = quoted
+            .find('"')
+            .ok_or_else(|| format!("unterminated string for `{}`", key))?;
+        quoted[..end].to_string()
+    } else {
+        // Drop any trailing comment after an unquoted value.
+        value.split('#').next().unwrap_or("").trim().to_string()
+    };
+
+    Ok(Some((key, value)))
+}
@@ -0,0 +1,55 @@
+use crate::ESTIMATED_UNIQUE_STATIONS;
+use brc::parse::{parse_line_in, Notation};
+use hashbrown::HashSet;
+use rayon::prelude::*;
+
+// Totals gathered by a count-only scan.
+struct Counts<'a> {
+    stations: HashSet<&'a str>,
+    rows: u64,
+    malformed: u64,
+}
+
+impl<'a> Counts<'a> {
+    fn new() -> Self {
+        Counts {
+            stations: HashSet::with_capacity(ESTIMATED_UNIQUE_STATIONS),
+            rows: 0,
+            malformed: 0,
+        }
+    }
+
+    fn update(mut self, line: &'a str, delimiter: u8, notation: Notation) -> Self {
+        match parse_line_in(line, delimiter, notation) {
+            Ok((station, _)) => {
+                self.stations.insert(station);
+            }
+            Err(_) => self.malformed += 1,
+        }
+        self.rows += 1;
+        self
+    }
+
+    fn aggregate(mut self, other: Counts<'a>) -> Self {
+        self.stations.extend(other.stations);
+        self.rows += other.rows;
+        self.malformed += other.malformed;
+        self
+    }
+}
+
+// Scans the input and prints row, station, byte and malformed-line counts
+// without building per-station statistics.
+pub fn run(content: &str, delimiter: u8, notation: Notation) {
+    let counts = content
+        .par_lines()
+        .fold(Counts::new, |counts, line| {
+            counts.update(line, delimiter, notation)
+        })
+        .reduce(Counts::new, Counts::aggregate);
+
+    println!("Rows: {}", counts.rows);
+    println!("Unique stations: {}", counts.stations.len());
+    println!("Bytes: {}", content.len());
+    println!("Malformed lines: {}", counts.malformed);
+}
@@ -0,0 +1,125 @@
+// Low-overhead per-thread counters for optimization work, compiled in only
+// with the `counters` feature and printed with `--counters`. Without the
+// feature, the recording functions are empty and optimized away.
+
+use crate::cli::Options;
+use crate::error::Error;
+use std::io;
+
+#[cfg(feature = "counters")]
+mod imp {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    // Counters of one thread. Only that thread writes to them, so the
+    // atomics are uncontended.
+    #[derive(Default)]
+    pub struct ThreadCounters {
+        pub lines: AtomicU64,
+        pub lookups: AtomicU64,
+        pub inserts: AtomicU64,
+        pub resizes: AtomicU64,
+    }
+
+    // Counters of every thread that recorded anything, in order of first use.
+    pub static REGISTRY: Mutex<Vec<Arc<ThreadCounters>>> = Mutex::new(Vec::new());
+
+    thread_local! {
+        static LOCAL: Arc<ThreadCounters> = {
+            let counters = Arc::<ThreadCounters>::default();
+            REGISTRY.lock().unwrap().push(Arc::clone(&counters));
+            counters
+        };
+    }
+
+    pub fn add(counter: fn(&ThreadCounters) -> &AtomicU64) {
+        LOCAL.with(|counters| counter(counters).fetch_add(1, Ordering::Relaxed));
+    }
+}
+
+// Counts a parsed line.
+#[inline(always)]
+pub fn line() {
+    #[cfg(feature = "counters")]
+    imp::add(|c| &c.lines);
+}
+
+// Counts a station map lookup, i.e. a hash probe sequence.
+#[inline(always)]
+pub fn lookup() {
+    #[cfg(feature = "counters")]
+    imp::add(|c| &c.lookups);
+}
+
+// Counts a station map insert, and a resize if it changed the capacity.
+#[inline(always)]
+pub fn insert(_capacity_before: usize, _capacity_after: usize) {
+    #[cfg(feature = "counters")]
+    {
+        imp::add(|c| &c.inserts);
+        if _capacity_before != _capacity_after {
+            imp::add(|c| &c.resizes);
+        }
+    }
+}
+
+// Checks that counters are compiled in before a run that reports them.
+pub fn check_enabled() -> Result<(), Error> {
+    if cfg!(feature = "counters") {
+        Ok(())
+    } else {
+        Err(Error::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "`--counters` requires building with the `counters` feature",
+        )))
+    }
+}
+
+// Prints the counters of every thread and their totals.
+#[cfg(feature = "counters")]
+pub fn print(options: &Options) {
+    use crate::report;
+    use std::sync::atomic::Ordering;
+
+    let registry = imp::REGISTRY.lock().unwrap();
+    report(options, format_args!("Counters:"));
+    report(
+        options,
+        format_args!(
+            "{:>6} {:>12} {:>12} {:>12} {:>8}",
+            "thread", "lines", "lookups", "inserts", "resizes"
+        ),
+    );
+    let mut totals = [0u64; 4];
+    for (index, counters) in registry.iter().enumerate() {
+        let values = [
+            counters.lines.load(Ordering::Relaxed),
+            counters.lookups.load(Ordering::Relaxed),
+            counters.inserts.load(Ordering::Relaxed),
+            counters.resizes.load(Ordering::Relaxed),
+        ];
+        if values.iter().all(|&v| v == 0) {
+            continue;
+        }
+        for (total, value) in totals.iter_mut().zip(values) {
+            *total += value;
+        }
+        report(
+            options,
+            format_args!(
+                "{:>6} {:>12} {:>12} {:>12} {:>8}",
+                index, values[0], values[1], values[2], values[3]
+            ),
+        );
+    }
+    report(
+        options,
+        format_args!(
+            "{:>6} {:>12} {:>12} {:>12} {:>8}",
+            "total", totals[0], totals[1], totals[2], totals[3]
+        ),
+    );
+}
+
+#[cfg(not(feature = "counters"))]
+pub fn print(_options: &Options) {}
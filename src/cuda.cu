@@ -0,0 +1,216 @@
+// Aggregation of one chunk of the input for `--impl cuda`, compiled with
+// NVRTC when the backend starts. `SLOTS`, `GROUPS` and `DEFERRED` are
+// prepended by the host.
+//
+// `parse` runs `GROUPS` blocks, each over a contiguous stripe of the chunk,
+// and every thread parses the lines that start in its window of the stripe
+// into its block's table. `reduce` then merges the block tables into the
+// last table, which the host copies back. Lines the kernel does not take,
+// because they are not in the challenge's plain layout or their table is
+// full, are left to the host by offset.
+
+#define NONE 0xffffffffu
+#define MAX_STATION_BYTES 100u
+
+// A station, keyed by the offset of a line naming it plus one, so zeroed
+// memory is an empty table. The extremes are offset to stay unsigned and
+// start at zero: `low` holds 999 minus the minimum in tenths, `high` the
+// maximum plus 999.
+struct Slot {
+    unsigned key;
+    unsigned count;
+    int sum;
+    unsigned low;
+    unsigned high;
+};
+
+// FNV-1a over the name.
+__device__ unsigned hash_name(const unsigned char *name, unsigned len) {
+    unsigned hash = 2166136261u;
+    for (unsigned i = 0; i < len; i++) {
+        hash = (hash ^ name[i]) * 16777619u;
+    }
+    return hash;
+}
+
+// Whether the name stored at `stored`, which ends at a delimiter, is the
+// `len` bytes at `start`. The bytes are compared first, so a shorter stored
+// name stops the comparison at its delimiter.
+__device__ bool same_name(
+    const unsigned char *input,
+    unsigned stored,
+    unsigned start,
+    unsigned len,
+    unsigned delimiter
+) {
+    for (unsigned i = 0; i < len; i++) {
+        if (input[stored + i] != input[start + i]) {
+            return false;
+        }
+    }
+    return input[stored + len] == delimiter;
+}
+
+// Finds or claims the slot of a name in `table` by linear probing. A free
+// slot is claimed with compare-and-swap, so the key is either this name's or
+// another's as soon as it is read. Returns `NONE` when the table is full.
+__device__ unsigned claim(
+    const unsigned char *input,
+    Slot *table,
+    unsigned start,
+    unsigned len,
+    unsigned delimiter
+) {
+    unsigned hash = hash_name(input + start, len);
+    for (unsigned probe = 0; probe < SLOTS; probe++) {
+        unsigned index = (hash + probe) & (SLOTS - 1u);
+        unsigned key = atomicCAS(&table[index].key, 0u, start + 1u);
+        if (key == 0u || same_name(input, key - 1u, start, len, delimiter)) {
+            return index;
+        }
+    }
+    return NONE;
+}
+
+__device__ void add(Slot *slot, unsigned count, int sum, unsigned low, unsigned high) {
+    atomicAdd(&slot->count, count);
+    atomicAdd(&slot->sum, sum);
+    atomicMax(&slot->low, low);
+    atomicMax(&slot->high, high);
+}
+
+// Folds the line `start..end` into `table`. Only the layouts `N.N`, `NN.N`,
+// `-N.N` and `-NN.N` are taken, which every `--numbers` policy accepts; a
+// leading BOM, a trailing `\r` or anything else is left to the host.
+__device__ bool fold(
+    const unsigned char *input,
+    Slot *table,
+    unsigned start,
+    unsigned end,
+    unsigned delimiter,
+    unsigned decimal
+) {
+    if (end - start >= 3u && input[start] == 0xefu && input[start + 1u] == 0xbbu
+        && input[start + 2u] == 0xbfu) {
+        return false;
+    }
+    unsigned limit = min(end, start + MAX_STATION_BYTES + 1u);
+    unsigned separator = start;
+    while (separator < limit && input[separator] != delimiter) {
+        separator++;
+    }
+    if (separator == start || separator == limit) {
+        return false;
+    }
+
+    unsigned value = separator + 1u;
+    bool negative = value < end && input[value] == '-';
+    if (negative) {
+        value++;
+    }
+    unsigned tens = 0u;
+    switch (end - value) {
+    case 3u:
+        break;
+    case 4u:
+        tens = input[value] - '0';
+        value++;
+        break;
+    default:
+        return false;
+    }
+    unsigned ones = input[value] - '0';
+    unsigned tenths = input[value + 2u] - '0';
+    if (input[value + 1u] != decimal || tens > 9u || ones > 9u || tenths > 9u) {
+        return false;
+    }
+    int temp = (int)(tens * 100u + ones * 10u + tenths);
+    if (negative) {
+        temp = -temp;
+    }
+
+    unsigned index = claim(input, table, start, separator - start, delimiter);
+    if (index == NONE) {
+        return false;
+    }
+    add(&table[index], 1u, temp, (unsigned)(999 - temp), (unsigned)(temp + 999));
+    return true;
+}
+
+// `deferred` holds a count followed by up to `DEFERRED` offsets; the host
+// folds the whole chunk when the count is higher.
+__device__ void defer(unsigned *deferred, unsigned offset) {
+    unsigned index = atomicAdd(&deferred[0], 1u);
+    if (index < DEFERRED) {
+        deferred[1u + index] = offset;
+    }
+}
+
+// `totals` counts the lines and bytes taken, and flags a full merged table.
+extern "C" __global__ void parse(
+    const unsigned char *input,
+    unsigned len,
+    unsigned delimiter,
+    unsigned decimal,
+    unsigned window,
+    Slot *tables,
+    unsigned *deferred,
+    unsigned *totals
+) {
+    unsigned begin = (blockIdx.x * blockDim.x + threadIdx.x) * window;
+    if (begin >= len) {
+        return;
+    }
+    unsigned end = min(begin + window, len);
+    // The line running into the window belongs to the thread it started in.
+    unsigned start = begin;
+    while (start < end && start > 0u && input[start - 1u] != '\n') {
+        start++;
+    }
+
+    Slot *table = tables + blockIdx.x * SLOTS;
+    unsigned lines = 0u;
+    unsigned bytes = 0u;
+    while (start < end) {
+        unsigned stop = start;
+        while (stop < len && input[stop] != '\n') {
+            stop++;
+        }
+        if (fold(input, table, start, stop, delimiter, decimal)) {
+            lines++;
+            bytes += stop - start + 1u;
+        } else {
+            defer(deferred, start);
+        }
+        start = stop + 1u;
+    }
+    atomicAdd(&totals[0], lines);
+    atomicAdd(&totals[1], bytes);
+}
+
+// Merges every block table at once: names merged concurrently are safe to
+// claim with compare-and-swap.
+extern "C" __global__ void reduce(
+    const unsigned char *input,
+    unsigned delimiter,
+    Slot *tables,
+    unsigned *totals
+) {
+    unsigned id = blockIdx.x * blockDim.x + threadIdx.x;
+    if (id >= GROUPS * SLOTS || tables[id].key == 0u) {
+        return;
+    }
+    const Slot slot = tables[id];
+    unsigned start = slot.key - 1u;
+    unsigned separator = start;
+    while (input[separator] != delimiter) {
+        separator++;
+    }
+    Slot *merged = tables + GROUPS * SLOTS;
+    unsigned index = claim(input, merged, start, separator - start, delimiter);
+    if (index == NONE) {
+        atomicExch(&totals[2], 1u);
+        return;
+    }
+    add(&merged[index], slot.count, slot.sum, slot.low, slot.high);
+}
@@ -0,0 +1,381 @@
+// The experimental `cuda` strategy, for NVIDIA devices. It works like `gpu`:
+// the mapped input is cut into line-aligned chunks, each staged in
+// page-locked host memory and copied to the device, where the kernels in
+// `cuda.cu` parse and hash its lines into per-block tables and merge those
+// into one table. Only that table is copied back and merged into the result
+// on the CPU, together with the lines the kernel leaves alone, so results do
+// not depend on the device. The driver and NVRTC are loaded when the run
+// starts, and a machine without them fails the run with an error. Compare it
+// with the CPU path with `brc bench --impl cuda --against 'brc run --no-cache
+// {}' FILE`.
+
+use crate::cli::Options;
+use crate::error::Error;
+use crate::thread_stats::ThreadStats;
+use crate::Partial;
+use std::io;
+
+// Checks that the backend is compiled in before a run that selects it.
+pub fn check_enabled() -> Result<(), Error> {
+    if cfg!(feature = "cuda") {
+        Ok(())
+    } else {
+        Err(disabled())
+    }
+}
+
+fn disabled() -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "`--impl cuda` requires building with the `cuda` feature",
+    ))
+}
+
+#[cfg(not(feature = "cuda"))]
+pub fn available() -> bool {
+    false
+}
+
+#[cfg(not(feature = "cuda"))]
+pub fn aggregate<'a>(
+    _content: &'a str,
+    _options: &Options,
+    _stats: Option<&ThreadStats>,
+) -> Result<Partial<'a>, Error> {
+    Err(disabled())
+}
+
+#[cfg(feature = "cuda")]
+pub use imp::{aggregate, available};
+
+#[cfg(feature = "cuda")]
+mod imp {
+    use super::*;
+    use crate::logging;
+    use crate::{aggregate_chunk, aliases, detect_overflow, filter, fold_line, merge_maps};
+    use crate::{overflow_error, StationData};
+    use brc::chunks::{self, Chunk};
+    use cudarc::driver::{
+        sys, CudaContext, CudaFunction, CudaSlice, CudaStream, LaunchConfig, PinnedHostSlice,
+        PushKernelArg,
+    };
+    use cudarc::nvrtc;
+    use hashbrown::HashMap;
+    use serde_json::json;
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    // Bytes copied per launch. At most about 1.4 million lines of `|99.9|`
+    // fit in one, so a station's sum in tenths stays within the kernel's
+    // `int`.
+    const CHUNK_SIZE: usize = 8 << 20;
+    // Blocks of `parse`, each with its own table, and slots per table, a
+    // power of two comfortably above the challenge's 10,000 stations.
+    const GROUPS: u32 = 64;
+    const SLOTS: u32 = 1 << 14;
+    const BLOCK_SIZE: u32 = 256;
+    // Offsets of lines left to the CPU per chunk; a chunk with more is
+    // folded on the CPU entirely.
+    const DEFERRED: usize = 1 << 16;
+
+    const SLOT_WORDS: usize = 5;
+    const TABLE_WORDS: usize = SLOTS as usize * SLOT_WORDS;
+
+    // Whether the driver and NVRTC load and there is a device to run on.
+    pub fn available() -> bool {
+        let loaded = unsafe { sys::is_culib_present() && nvrtc::sys::is_culib_present() };
+        loaded && CudaContext::device_count().is_ok_and(|count| count > 0)
+    }
+
+    pub fn aggregate<'a>(
+        content: &'a str,
+        options: &Options,
+        stats: Option<&ThreadStats>,
+    ) -> Result<Partial<'a>, Error> {
+        let start = Instant::now();
+        let mut partial = Partial::empty();
+        let chunks = chunks::chunks(content, CHUNK_SIZE);
+        let Some(largest) = chunks.iter().map(|chunk| chunk.data.len()).max() else {
+            return Ok(partial);
+        };
+        let mut cuda = Cuda::new(largest)?;
+        let setup = start.elapsed();
+
+        let checked = detect_overflow(options);
+        let mut stations: HashMap<&str, StationData> = HashMap::new();
+        let mut on_cpu = 0;
+        for chunk in chunks {
+            let Some(folded) = cuda.fold(chunk.data.as_bytes(), options)? else {
+                let cpu = aggregate_chunk(chunk, options)?;
+                on_cpu += cpu.rows;
+                partial = partial.merge(cpu)?;
+                continue;
+            };
+            partial.rows += folded.lines;
+            partial.bytes += folded.bytes;
+            for (offset, data) in folded.stations {
+                let name = station_at(chunk.data, offset, options.delimiter);
+                if !filter::admits(options, name) {
+                    continue;
+                }
+                let station = aliases::resolve(options, name);
+                match stations.get_mut(station) {
+                    Some(entry) if checked => entry
+                        .checked_aggregate(&data)
+                        .map_err(|overflow| overflow_error(station, None, overflow))?,
+                    Some(entry) => entry.aggregate(&data),
+                    None => {
+                        stations.insert(station, data);
+                    }
+                }
+            }
+            on_cpu += folded.deferred.len() as u64;
+            for offset in folded.deferred {
+                fold_line(&mut partial, chunk, line_at(chunk, offset), options, None)?;
+            }
+        }
+        partial.stations = merge_maps(
+            partial.stations,
+            stations
+                .into_iter()
+                .map(|(station, data)| (station.to_string(), data))
+                .collect::<HashMap<_, _>>(),
+        )?;
+
+        if let Some(stats) = stats {
+            stats.record(0, &partial, start.elapsed());
+        }
+        if options.verbose {
+            logging::event(
+                "cuda",
+                format_args!(
+                    "CUDA: {}, set up in {:?}, {} lines folded on the CPU",
+                    cuda.name, setup, on_cpu
+                ),
+                json!({
+                    "device": cuda.name,
+                    "setup_seconds": setup.as_secs_f64(),
+                    "cpu_lines": on_cpu,
+                }),
+            );
+        }
+        Ok(partial)
+    }
+
+    // The station name of the line at `offset`, which the kernel has
+    // checked to end at a delimiter.
+    fn station_at(data: &str, offset: usize, delimiter: u8) -> &str {
+        let line = &data.as_bytes()[offset..];
+        let len = line.iter().position(|&b| b == delimiter).unwrap_or(0);
+        &data[offset..offset + len]
+    }
+
+    // The line starting at `offset`, as `str::lines` would yield it.
+    fn line_at(chunk: Chunk<'_>, offset: usize) -> &str {
+        let rest = &chunk.data[offset..];
+        match rest.find('\n') {
+            Some(end) => {
+                let line = &rest[..end];
+                line.strip_suffix('\r').unwrap_or(line)
+            }
+            None => rest,
+        }
+    }
+
+    // What the device made of a chunk: its stations by the offset of a line
+    // naming them, the lines left to the CPU, in input order, and the lines
+    // and bytes it took.
+    struct Folded {
+        stations: Vec<(usize, StationData)>,
+        deferred: Vec<usize>,
+        lines: u64,
+        bytes: u64,
+    }
+
+    struct Cuda {
+        name: String,
+        stream: Arc<CudaStream>,
+        parse: CudaFunction,
+        reduce: CudaFunction,
+        // Chunks are staged in write-combined page-locked memory, which the
+        // device reads directly, and the results come back through plain
+        // page-locked memory, which the CPU reads at full speed.
+        staging: PinnedHostSlice<u8>,
+        readback: PinnedHostSlice<u32>,
+        input: CudaSlice<u8>,
+        tables: CudaSlice<u32>,
+        deferred: CudaSlice<u32>,
+        totals: CudaSlice<u32>,
+    }
+
+    impl Cuda {
+        // Opens the first device, compiles the kernels for it and allocates
+        // buffers for chunks of up to `largest` bytes.
+        fn new(largest: usize) -> Result<Self, Error> {
+            if !available() {
+                return Err(cuda_error(
+                    "no device",
+                    "the CUDA driver or NVRTC did not load",
+                ));
+            }
+            let context = CudaContext::new(0).map_err(|e| cuda_error("no device", e))?;
+            let name = context.name().map_err(|e| cuda_error("no device", e))?;
+            let source = format!(
+                "#define SLOTS {}u\n#define GROUPS {}u\n#define DEFERRED {}u\n{}",
+                SLOTS,
+                GROUPS,
+                DEFERRED,
+                include_str!("cuda.cu")
+            );
+            let ptx = nvrtc::compile_ptx(source).map_err(|e| cuda_error("cuda.cu", e))?;
+            let module = context
+                .load_module(ptx)
+                .map_err(|e| cuda_error("cuda.cu", e))?;
+            let function = |name| {
+                module
+                    .load_function(name)
+                    .map_err(|e| cuda_error("cuda.cu", e))
+            };
+            let (parse, reduce) = (function("parse")?, function("reduce")?);
+
+            let stream = context.default_stream();
+            let allocated = |e| cuda_error("out of memory", e);
+            let staging =
+                unsafe { context.alloc_pinned::<u8>(largest.max(1)) }.map_err(allocated)?;
+            let readback = unsafe {
+                context.alloc_pinned_with_flags::<u32>(TABLE_WORDS + 1 + DEFERRED + 3, 0)
+            }
+            .map_err(allocated)?;
+            let input = unsafe { stream.alloc::<u8>(largest.max(1)) }.map_err(allocated)?;
+            let tables = stream
+                .alloc_zeros::<u32>((GROUPS as usize + 1) * TABLE_WORDS)
+                .map_err(allocated)?;
+            let deferred = stream.alloc_zeros::<u32>(1 + DEFERRED).map_err(allocated)?;
+            let totals = stream.alloc_zeros::<u32>(3).map_err(allocated)?;
+            Ok(Cuda {
+                name,
+                stream,
+                parse,
+                reduce,
+                staging,
+                readback,
+                input,
+                tables,
+                deferred,
+                totals,
+            })
+        }
+
+        // Aggregates one chunk on the device. Returns `None` if it has to be
+        // folded on the CPU instead, because too many of its lines were left
+        // over or its stations overflowed the merged table.
+        fn fold(&mut self, data: &[u8], options: &Options) -> Result<Option<Folded>, Error> {
+            let failed = |e| cuda_error("lost the device", e);
+            let len = data.len();
+            let staging = self.staging.as_mut_slice().map_err(failed)?;
+            staging[..len].copy_from_slice(data);
+            self.stream
+                .memcpy_htod(&staging[..len], &mut self.input.slice_mut(..len))
+                .map_err(failed)?;
+            self.stream.memset_zeros(&mut self.tables).map_err(failed)?;
+            self.stream
+                .memset_zeros(&mut self.deferred)
+                .map_err(failed)?;
+            self.stream.memset_zeros(&mut self.totals).map_err(failed)?;
+
+            let threads = GROUPS as usize * BLOCK_SIZE as usize;
+            let (len, delimiter, decimal) = (
+                len as u32,
+                options.delimiter as u32,
+                options.notation.decimal as u32,
+            );
+            let window = data.len().div_ceil(threads) as u32;
+            let launch = |blocks| LaunchConfig {
+                grid_dim: (blocks, 1, 1),
+                block_dim: (BLOCK_SIZE, 1, 1),
+                shared_mem_bytes: 0,
+            };
+            unsafe {
+                self.stream
+                    .launch_builder(&self.parse)
+                    .arg(&self.input)
+                    .arg(&len)
+                    .arg(&delimiter)
+                    .arg(&decimal)
+                    .arg(&window)
+                    .arg(&mut self.tables)
+                    .arg(&mut self.deferred)
+                    .arg(&mut self.totals)
+                    .launch(launch(GROUPS))
+                    .map_err(failed)?;
+                self.stream
+                    .launch_builder(&self.reduce)
+                    .arg(&self.input)
+                    .arg(&delimiter)
+                    .arg(&mut self.tables)
+                    .arg(&mut self.totals)
+                    .launch(launch(GROUPS * SLOTS / BLOCK_SIZE))
+                    .map_err(failed)?;
+            }
+
+            let readback = self.readback.as_mut_slice().map_err(failed)?;
+            let (table, rest) = readback.split_at_mut(TABLE_WORDS);
+            let (deferred, totals) = rest.split_at_mut(1 + DEFERRED);
+            let merged = GROUPS as usize * TABLE_WORDS;
+            self.stream
+                .memcpy_dtoh(&self.tables.slice(merged..), table)
+                .map_err(failed)?;
+            self.stream
+                .memcpy_dtoh(&self.deferred, deferred)
+                .map_err(failed)?;
+            self.stream
+                .memcpy_dtoh(&self.totals, totals)
+                .map_err(failed)?;
+            self.stream.synchronize().map_err(failed)?;
+            Ok(read_back(readback))
+        }
+    }
+
+    // Decodes the merged table, the deferred lines and the totals from the
+    // readback buffer.
+    fn read_back(words: &[u32]) -> Option<Folded> {
+        let (table, rest) = words.split_at(TABLE_WORDS);
+        let (deferred, totals) = rest.split_at(1 + DEFERRED);
+        let count = deferred[0] as usize;
+        if count > DEFERRED || totals[2] != 0 {
+            return None;
+        }
+        let stations = table
+            .chunks_exact(SLOT_WORDS)
+            .filter(|slot| slot[0] != 0)
+            .map(|slot| {
+                let sum = slot[2] as i32;
+                let data = StationData {
+                    min_temp: (999 - slot[3] as i32) as f32 / 10.0,
+                    max_temp: (slot[4] as i32 - 999) as f32 / 10.0,
+                    total_temp: (sum as f64 / 10.0) as f32,
+                    count: slot[1] as i32,
+                };
+                (slot[0] as usize - 1, data)
+            })
+            .collect();
+        let mut deferred: Vec<usize> = deferred[1..=count]
+            .iter()
+            .map(|&offset| offset as usize)
+            .collect();
+        deferred.sort_unstable();
+        Some(Folded {
+            stations,
+            deferred,
+            lines: totals[0] as u64,
+            bytes: totals[1] as u64,
+        })
+    }
+
+    fn cuda_error(what: &str, error: impl std::fmt::Display) -> Error {
+        Error::Io(io::Error::other(format!(
+            "`--impl cuda`: {}: {}",
+            what, error
+        )))
+    }
+}
@@ -0,0 +1,39 @@
+// Field separator detection for `--delimiter auto`, so CSV and TSV variants
+// of the dataset work without extra flags.
+
+// Candidate separators, in order of preference on a tie.
+const CANDIDATES: [u8; 3] = [b';', b',', b'\t'];
+
+// Bytes at the start of the input that are sniffed.
+pub const SNIFF_BYTES: usize = 4096;
+
+// Picks the candidate that occurs exactly once on the most lines at the start
+// of `bytes`, so separators inside station names such as `Washington, D.C.`
+// do not win. `decimal`, the temperatures' decimal separator, is never
+// picked. Falls back to `;` when no candidate occurs.
+pub fn detect(bytes: &[u8], decimal: u8) -> u8 {
+    let sample = &bytes[..bytes.len().min(SNIFF_BYTES)];
+    let mut scores = [0usize; CANDIDATES.len()];
+    for line in sample.split(|&b| b == b'\n') {
+        for (score, &candidate) in scores.iter_mut().zip(&CANDIDATES) {
+            if candidate != decimal && line.iter().filter(|&&b| b == candidate).count() == 1 {
+                *score += 1;
+            }
+        }
+    }
+    let mut best = 0;
+    for (index, &score) in scores.iter().enumerate() {
+        if score > scores[best] {
+            best = index;
+        }
+    }
+    CANDIDATES[best]
+}
+
+// Human-readable name of a separator, for reports.
+pub fn name(delimiter: u8) -> String {
+    match delimiter {
+        b'\t' => "tab".to_string(),
+        _ => format!("`{}`", delimiter as char),
+    }
+}
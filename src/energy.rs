@@ -0,0 +1,113 @@
+// `--energy`: package energy of the aggregation, read from the RAPL counters
+// Linux exposes through powercap, for Intel and AMD processors alike. Each
+// package domain counts microjoules up to `max_energy_range_uj` and then
+// wraps, so an aggregation shorter than a wrap period, minutes at full load,
+// is measured exactly. The counters cover everything on the package, other
+// processes included, so they are only meaningful on an idle machine. Most
+// kernels make them readable by root only.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const POWERCAP: &str = "/sys/class/powercap";
+
+// The package domains of the machine.
+pub struct Meter {
+    domains: Vec<Domain>,
+}
+
+struct Domain {
+    energy: PathBuf,
+    max_range: u64,
+}
+
+// Counter values of each domain at one point in time.
+pub struct Reading(Vec<u64>);
+
+impl Meter {
+    pub fn open() -> io::Result<Self> {
+        Meter::open_at(Path::new(POWERCAP))
+    }
+
+    // Finds the package domains under `root`: `intel-rapl:N`, but not their
+    // core and memory subdomains `intel-rapl:N:M`, which the package includes.
+    pub fn open_at(root: &Path) -> io::Result<Self> {
+        let unavailable = |e: io::Error| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "`--energy` needs RAPL counters in {}: {}",
+                    root.display(),
+                    e
+                ),
+            )
+        };
+        let mut packages: Vec<_> = fs::read_dir(root)
+            .map_err(unavailable)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| name.strip_prefix("intel-rapl:"))
+                    .is_some_and(|index| index.bytes().all(|b| b.is_ascii_digit()))
+            })
+            .collect();
+        packages.sort();
+        if packages.is_empty() {
+            return Err(unavailable(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no package domains".to_string(),
+            )));
+        }
+        let domains = packages
+            .into_iter()
+            .map(|package| {
+                Ok(Domain {
+                    max_range: read_counter(&package.join("max_energy_range_uj"))?,
+                    energy: package.join("energy_uj"),
+                })
+            })
+            .collect::<io::Result<_>>()
+            .map_err(unavailable)?;
+        let meter = Meter { domains };
+        // Fails now rather than after the aggregation if reading is denied.
+        meter.read().map_err(unavailable)?;
+        Ok(meter)
+    }
+
+    pub fn read(&self) -> io::Result<Reading> {
+        self.domains
+            .iter()
+            .map(|domain| read_counter(&domain.energy))
+            .collect::<io::Result<_>>()
+            .map(Reading)
+    }
+
+    // Joules spent by all packages between `start` and `end`, allowing for
+    // one wrap of each counter.
+    pub fn joules(&self, start: &Reading, end: &Reading) -> f64 {
+        let microjoules: u64 = self
+            .domains
+            .iter()
+            .zip(start.0.iter().zip(&end.0))
+            .map(|(domain, (&start, &end))| {
+                if end >= start {
+                    end - start
+                } else {
+                    domain.max_range - start + end
+                }
+            })
+            .sum();
+        microjoules as f64 / 1e6
+    }
+}
+
+fn read_counter(path: &Path) -> io::Result<u64> {
+    fs::read_to_string(path)?.trim().parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid counter in {}", path.display()),
+        )
+    })
+}
@@ -0,0 +1,59 @@
+use crate::interrupt;
+use std::fmt;
+use std::io;
+
+// Failure categories, each reported with its own process exit code so
+// wrapping scripts can tell them apart without parsing stderr.
+#[derive(Debug)]
+pub enum Error {
+    // Reading input or writing output failed, or the command line is invalid.
+    Io(io::Error),
+    // A malformed line was found in strict mode.
+    Parse(String),
+    // Results did not match what they were checked against.
+    Validation(String),
+    // A configured or system resource limit was exceeded.
+    ResourceLimit(String),
+    // Aggregation was stopped by Ctrl-C or `--timeout` after printing
+    // partial results.
+    Interrupted(String),
+    TimedOut(String),
+}
+
+impl Error {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Io(_) => 1,
+            Error::Parse(_) => 2,
+            Error::Validation(_) => 3,
+            Error::ResourceLimit(_) => 4,
+            Error::Interrupted(_) => interrupt::EXIT_CODE,
+            Error::TimedOut(_) => interrupt::TIMEOUT_EXIT_CODE,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Parse(message) => write!(f, "parse error: {}", message),
+            Error::Validation(message) => write!(f, "validation failed: {}", message),
+            Error::ResourceLimit(message) => write!(f, "resource limit: {}", message),
+            Error::Interrupted(message) => write!(f, "interrupted: {}", message),
+            Error::TimedOut(message) => write!(f, "timed out: {}", message),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::OutOfMemory
+            | io::ErrorKind::StorageFull
+            | io::ErrorKind::QuotaExceeded
+            | io::ErrorKind::FileTooLarge => Error::ResourceLimit(e.to_string()),
+            _ => Error::Io(e),
+        }
+    }
+}
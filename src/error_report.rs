@@ -0,0 +1,25 @@
+use brc::parse::LineError;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+// A line rejected in lenient mode, with its byte offset in the input. The
+// line is borrowed from the mapped input where possible and owned when read
+// into a reused buffer.
+pub struct SkippedLine<'a> {
+    pub offset: usize,
+    pub reason: LineError,
+    pub line: Cow<'a, str>,
+}
+
+// Writes skipped lines as tab-separated `offset<TAB>reason<TAB>line` records,
+// ordered by offset.
+pub fn write(path: &str, mut skipped: Vec<SkippedLine>) -> io::Result<()> {
+    skipped.sort_unstable_by_key(|s| s.offset);
+
+    let mut out = BufWriter::new(File::create(path)?);
+    for s in &skipped {
+        writeln!(out, "{}\t{}\t{}", s.offset, s.reason.code(), s.line)?;
+    }
+    out.flush()
+}
@@ -0,0 +1,172 @@
+// Exact aggregation for `--exact`: temperatures are parsed as integer tenths,
+// sums accumulate in `i128` and means are rounded from the rational
+// `sum / count` as `--rounding` selects, giving a ground truth to validate
+// the fast `f32` path against.
+
+use crate::cli::Options;
+use crate::collate::Collation;
+use crate::error::Error;
+use crate::output::format_output;
+use crate::{offset_of, ESTIMATED_UNIQUE_STATIONS};
+use brc::parse::{check_station, parse_tenths_in, split_once, LineError, Notation, BOM};
+use brc::rounding::{Rounding, Tenths};
+use hashbrown::HashMap;
+use rayon::prelude::*;
+
+// Exact running statistics for a single station, in tenths of a degree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExactData {
+    pub min: i16,
+    pub max: i16,
+    pub sum: i128,
+    pub count: u64,
+}
+
+impl ExactData {
+    fn new() -> Self {
+        ExactData {
+            min: i16::MAX,
+            max: i16::MIN,
+            sum: 0,
+            count: 0,
+        }
+    }
+
+    fn update(&mut self, tenths: i16) {
+        self.min = self.min.min(tenths);
+        self.max = self.max.max(tenths);
+        self.sum += tenths as i128;
+        self.count += 1;
+    }
+
+    fn aggregate(&mut self, other: &ExactData) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+        self.count += other.count;
+    }
+
+    // Mean in tenths, rounded from the exact quotient.
+    pub fn mean(&self, rounding: Rounding) -> i128 {
+        rounding.divide(self.sum, self.count as i128)
+    }
+}
+
+// Stations aggregated exactly, and the number of malformed lines skipped,
+// of which `out_of_range` had temperatures outside the spec's range and
+// `too_long` had overlong station names.
+pub struct Exact {
+    pub stations: HashMap<String, ExactData>,
+    pub skipped: usize,
+    pub out_of_range: usize,
+    pub too_long: usize,
+}
+
+impl Exact {
+    fn new() -> Self {
+        Exact {
+            stations: HashMap::with_capacity(ESTIMATED_UNIQUE_STATIONS),
+            skipped: 0,
+            out_of_range: 0,
+            too_long: 0,
+        }
+    }
+
+    fn merge(mut self, other: Exact) -> Self {
+        for (station, data) in other.stations {
+            self.stations
+                .entry(station)
+                .and_modify(|e| e.aggregate(&data))
+                .or_insert(data);
+        }
+        self.skipped += other.skipped;
+        self.out_of_range += other.out_of_range;
+        self.too_long += other.too_long;
+        self
+    }
+}
+
+// Aggregates every line of `content`, with fields separated by `delimiter`
+// and temperatures written in `notation`, exactly. Malformed lines abort the
+// run unless `lenient` is set, in which case they are counted and skipped.
+pub fn aggregate(
+    content: &str,
+    delimiter: u8,
+    notation: Notation,
+    lenient: bool,
+) -> Result<Exact, Error> {
+    content
+        .par_lines()
+        .try_fold(Exact::new, |mut exact, line| {
+            match parse_exact(line, delimiter, notation) {
+                Ok((station, tenths)) => exact
+                    .stations
+                    .entry_ref(station)
+                    .or_insert_with(ExactData::new)
+                    .update(tenths),
+                Err(reason) if lenient => {
+                    exact.skipped += 1;
+                    match reason {
+                        LineError::OutOfRange => exact.out_of_range += 1,
+                        LineError::StationTooLong => exact.too_long += 1,
+                        _ => {}
+                    }
+                }
+                Err(reason) => {
+                    return Err(Error::Parse(format!(
+                        "malformed line at byte {}: {}",
+                        offset_of(content, line),
+                        reason.code()
+                    )))
+                }
+            }
+            Ok(exact)
+        })
+        .try_reduce(Exact::new, |a, b| Ok(a.merge(b)))
+}
+
+// Formats a station's statistics as `min/mean/max`.
+pub fn format_station(data: &ExactData, rounding: Rounding) -> String {
+    format!(
+        "{}/{}/{}",
+        Tenths(data.min as i128),
+        Tenths(data.mean(rounding)),
+        Tenths(data.max as i128)
+    )
+}
+
+// Aggregates the input exactly and prints the results in the challenge
+// format, followed by the lenient-mode summary.
+pub fn run(content: &str, options: &Options) -> Result<(), Error> {
+    let collation = options.collate.as_deref().map(Collation::new).transpose()?;
+    let exact = aggregate(
+        content,
+        options.delimiter,
+        options.notation,
+        options.lenient,
+    )?;
+    let mut formatted: Vec<_> = exact
+        .stations
+        .iter()
+        .map(|(station, data)| {
+            // Without `--rounding`, ties go the reference implementation's way.
+            let rounding = options.rounding.unwrap_or(Rounding::TowardPositive);
+            (station.clone(), format_station(data, rounding))
+        })
+        .collect();
+    println!("{}", format_output(&mut formatted, collation.as_ref()));
+    if options.lenient {
+        println!("Skipped lines: {}", exact.skipped);
+        println!("Out-of-range temperatures: {}", exact.out_of_range);
+        println!("Overlong station names: {}", exact.too_long);
+    }
+    Ok(())
+}
+
+// Splits a line into its station name and temperature in tenths.
+fn parse_exact(line: &str, delimiter: u8, notation: Notation) -> Result<(&str, i16), LineError> {
+    let line = line.strip_prefix(BOM).unwrap_or(line);
+    let (station, temp) = split_once(line, delimiter).ok_or(LineError::MissingDelimiter)?;
+    check_station(station.as_bytes())?;
+    Ok((station, parse_tenths_in(temp.as_bytes(), notation)?))
+}
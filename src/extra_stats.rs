@@ -0,0 +1,276 @@
+// Optional statistics beyond min/mean/max, selected with `--stats` and
+// written after them by the text and JSON Lines formats. They are tracked in
+// maps of their own next to the stations, so runs without `--stats` pay
+// nothing for them.
+//
+// Every statistic is computed from a per-station histogram of integer counts.
+// Merging histograms only adds counts, so the merged histogram is the same
+// whichever way threads split and combine the input, and the floating-point
+// statistics derived from it are summed in bucket order once, at the end. They
+// are therefore bit-identical across runs, strategies and thread counts, unlike
+// the mean, whose `f32` total is summed in merge order.
+
+use brc::sum::Sum;
+use std::str::FromStr;
+
+// A statistic selected with `--stats`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stat {
+    // The most frequent reading, the lowest on ties.
+    Mode,
+    // The population standard deviation.
+    Stddev,
+    // The population skewness, the third standardized moment; NaN when all
+    // readings are equal.
+    Skew,
+    // The population excess kurtosis, the fourth standardized moment less
+    // the normal distribution's 3; NaN when all readings are equal.
+    Kurtosis,
+}
+
+impl Stat {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stat::Mode => "mode",
+            Stat::Stddev => "stddev",
+            Stat::Skew => "skew",
+            Stat::Kurtosis => "kurtosis",
+        }
+    }
+}
+
+impl FromStr for Stat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mode" => Ok(Stat::Mode),
+            "stddev" => Ok(Stat::Stddev),
+            "skew" => Ok(Stat::Skew),
+            "kurtosis" => Ok(Stat::Kurtosis),
+            _ => Err(()),
+        }
+    }
+}
+
+// Readings span -99.9..=99.9, so a station's histogram has one bucket per
+// possible tenth of a degree.
+const BUCKETS: usize = 1999;
+
+// Counts of a station's readings by tenth of a degree, from which each
+// statistic is computed.
+#[derive(Clone)]
+pub struct Histogram {
+    counts: Box<[u64; BUCKETS]>,
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Histogram {
+            counts: Box::new([0; BUCKETS]),
+        }
+    }
+
+    pub fn update(&mut self, temp: f32) {
+        let bucket = (temp * 10.0).round() as i32 + 999;
+        if let Some(count) = usize::try_from(bucket)
+            .ok()
+            .and_then(|bucket| self.counts.get_mut(bucket))
+        {
+            *count += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *count += other;
+        }
+    }
+
+    // The most frequent reading, the lowest of equally frequent ones.
+    pub fn mode(&self) -> f32 {
+        let (bucket, _) = self
+            .counts
+            .iter()
+            .enumerate()
+            .fold((0, 0), |best, (bucket, &count)| {
+                if count > best.1 {
+                    (bucket, count)
+                } else {
+                    best
+                }
+            });
+        (bucket as i32 - 999) as f32 / 10.0
+    }
+
+    // The population variance and third and fourth central moments, in
+    // degrees, summed over the buckets in order around the exact mean, with
+    // compensation under `--compensated`. The histogram is what threads
+    // merge, exactly, so the moments need no merge formulas of their own.
+    fn central_moments(&self, compensated: bool) -> (f64, f64, f64) {
+        let (mut count, mut total) = (0u64, 0i128);
+        for (bucket, &n) in self.counts.iter().enumerate() {
+            count += n;
+            total += n as i128 * (bucket as i128 - 999);
+        }
+        if count == 0 {
+            return (f64::NAN, f64::NAN, f64::NAN);
+        }
+        let mean = total as f64 / count as f64;
+        let mut moments = [Sum::new(compensated); 3];
+        for (bucket, &n) in self.counts.iter().enumerate() {
+            let deviation = (bucket as f64 - 999.0 - mean) / 10.0;
+            let mut power = n as f64 * deviation;
+            for moment in &mut moments {
+                power *= deviation;
+                moment.add(power);
+            }
+        }
+        let [m2, m3, m4] = moments.map(|moment| moment.value() / count as f64);
+        (m2, m3, m4)
+    }
+
+    pub fn stddev(&self, compensated: bool) -> f32 {
+        self.central_moments(compensated).0.sqrt() as f32
+    }
+
+    pub fn skew(&self, compensated: bool) -> f32 {
+        let (m2, m3, _) = self.central_moments(compensated);
+        (m3 / m2.powf(1.5)) as f32
+    }
+
+    pub fn kurtosis(&self, compensated: bool) -> f32 {
+        let (m2, _, m4) = self.central_moments(compensated);
+        (m4 / (m2 * m2) - 3.0) as f32
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram::new()
+    }
+}
+
+// The values of `stats` for a station with `histogram`, in the order given,
+// with sums compensated under `--compensated`.
+pub fn values(
+    stats: &[Stat],
+    histogram: Option<&Histogram>,
+    compensated: bool,
+) -> Vec<(&'static str, f32)> {
+    stats
+        .iter()
+        .map(|&stat| {
+            let value = match stat {
+                Stat::Mode => histogram.map_or(f32::NAN, Histogram::mode),
+                Stat::Stddev => {
+                    histogram.map_or(f32::NAN, |histogram| histogram.stddev(compensated))
+                }
+                Stat::Skew => histogram.map_or(f32::NAN, |histogram| histogram.skew(compensated)),
+                Stat::Kurtosis => {
+                    histogram.map_or(f32::NAN, |histogram| histogram.kurtosis(compensated))
+                }
+            };
+            (stat.name(), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Options;
+    use crate::error::Error;
+    use crate::{aggregate, collect_stations};
+
+    // Readings of a small station with known moments, and of three stations
+    // spread so that float sums depend on the order they are added in.
+    fn content() -> String {
+        let mut content = String::from("Four;1.0\nFour;2.0\nFour;3.0\nFour;6.0\n");
+        let mut state = 1u32;
+        for i in 0..30_000 {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let tenths = (state >> 16) as i32 % 1999 - 999;
+            let sign = if tenths < 0 { "-" } else { "" };
+            let station = ["Alpha", "Beta", "Gamma"][i % 3];
+            let (whole, tenth) = (tenths.abs() / 10, tenths.abs() % 10);
+            content.push_str(&format!("{};{}{}.{}\n", station, sign, whole, tenth));
+        }
+        content
+    }
+
+    // The bits of each station's stddev, skew and kurtosis with `--impl`
+    // `implementation` on `threads` threads.
+    fn moments(
+        content: &str,
+        implementation: &str,
+        threads: Option<usize>,
+    ) -> Result<Vec<(String, Vec<u32>)>, Error> {
+        let mut args = vec!["--stats", "stddev,skew,kurtosis", "--impl", implementation];
+        let threads = threads.map(|threads| threads.to_string());
+        if let Some(threads) = &threads {
+            args.extend(["--threads", threads]);
+        }
+        args.push("measurements.txt");
+        let options = Options::parse(args.into_iter().map(str::to_string))?;
+        let mut partial = aggregate(content, &options, None, None)?;
+        let stations = collect_stations(&mut partial, None)?;
+        Ok(stations
+            .into_iter()
+            .map(|(station, _)| {
+                let values = values(
+                    &options.stats,
+                    partial.histograms.get(&station),
+                    options.compensated,
+                )
+                .into_iter()
+                .map(|(_, value)| value.to_bits())
+                .collect();
+                (station, values)
+            })
+            .collect())
+    }
+
+    // Population moments of 1, 2, 3 and 6: mean 3, variance 3.5, third
+    // moment 4.5 and fourth moment 24.5.
+    #[test]
+    fn known_moments() {
+        let moments = moments(&content(), "scoped", None).unwrap();
+        let (_, four) = moments
+            .iter()
+            .find(|(station, _)| station == "Four")
+            .unwrap();
+        let expected = [
+            3.5f64.sqrt(),
+            4.5 / 3.5f64.powf(1.5),
+            24.5 / 3.5 / 3.5 - 3.0,
+        ];
+        for (&bits, expected) in four.iter().zip(expected) {
+            let actual = f32::from_bits(bits) as f64;
+            assert!(
+                (actual - expected).abs() <= 1e-6,
+                "{} for {}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    // The values are bit-identical whichever strategy and thread count
+    // produced them.
+    #[test]
+    fn deterministic() {
+        let content = content();
+        let expected = moments(&content, "scoped", None).unwrap();
+        for (implementation, threads) in [
+            ("rayon", None),
+            ("scoped", Some(1)),
+            ("scoped", Some(2)),
+            ("scoped", Some(7)),
+            ("per-core", Some(6)),
+        ] {
+            let actual = moments(&content, implementation, threads).unwrap();
+            assert_eq!(actual, expected, "{} {:?}", implementation, threads);
+        }
+    }
+}
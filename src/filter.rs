@@ -0,0 +1,80 @@
+// Station filters for `--include-file` and `--exclude-file`: lines whose
+// station is not included, or is excluded, are counted but not aggregated.
+// Strategies check each parsed station, after `--aliases` resolves it,
+// before their own map lookup, with the membership test hashing and
+// comparing names the way the station maps do. The lists are loaded once per
+// run and leaked like the alias table.
+
+use crate::aliases;
+use crate::cli::Options;
+use crate::key_hash::{StationHashBuilder, StationKey};
+use crate::perfect_hash::station_list;
+use brc::parse::split_once;
+use hashbrown::HashSet;
+use std::fs;
+use std::io;
+
+type StationSet = HashSet<&'static str, StationHashBuilder>;
+
+pub struct StationFilter {
+    include: Option<StationSet>,
+    exclude: StationSet,
+}
+
+impl StationFilter {
+    #[inline]
+    pub fn admits(&self, station: &str) -> bool {
+        let key = StationKey(station);
+        self.include
+            .as_ref()
+            .is_none_or(|include| include.contains(&key))
+            && !self.exclude.contains(&key)
+    }
+
+    // Sizes of the include list, if any, and the exclude list.
+    pub fn len(&self) -> (Option<usize>, usize) {
+        (self.include.as_ref().map(HashSet::len), self.exclude.len())
+    }
+}
+
+// Reads the lists given with `--include-file` and `--exclude-file`, in the
+// format of `--stations`.
+pub fn load(options: &Options) -> io::Result<&'static StationFilter> {
+    let read = |path: &str| -> io::Result<StationSet> {
+        let content: &'static str = Box::leak(fs::read_to_string(path)?.into_boxed_str());
+        let mut names = HashSet::with_hasher(StationHashBuilder::new(options.key_hash));
+        names.extend(station_list(content, options.delimiter));
+        Ok(names)
+    };
+    let filter = StationFilter {
+        include: options.include_file.as_deref().map(read).transpose()?,
+        exclude: match &options.exclude_file {
+            Some(path) => read(path)?,
+            None => HashSet::with_hasher(StationHashBuilder::new(options.key_hash)),
+        },
+    };
+    Ok(Box::leak(Box::new(filter)))
+}
+
+// Whether lines of `station` are aggregated. `station` is resolved through
+// `--aliases` first.
+#[inline]
+pub fn admits(options: &Options, station: &str) -> bool {
+    match options.station_filter {
+        Some(filter) => filter.admits(aliases::resolve(options, station)),
+        None => true,
+    }
+}
+
+// Like `admits`, for a `--group-by` or `--window` key whose first field is
+// the already resolved station.
+pub fn admits_key(options: &Options, key: &str) -> bool {
+    let station = match split_once(key, options.delimiter) {
+        Some((station, _)) => station,
+        None => key,
+    };
+    match options.station_filter {
+        Some(filter) => filter.admits(station),
+        None => true,
+    }
+}
@@ -0,0 +1,193 @@
+// Aggregation keyed on fingerprints, for `--key-hash fingerprint`: each
+// thread's table maps a 64-bit hash of the full station name to its
+// statistics, so lookups hash the name once and compare one word, never the
+// name itself. Names are kept in a separate table, written only when a
+// fingerprint is first seen. Two names with the same fingerprint would be
+// silently aggregated together; `--verify-fingerprints` rescans the input
+// afterwards and fails if any line's station differs from the name recorded
+// for its fingerprint.
+
+use crate::aliases;
+use crate::cli::Options;
+use crate::counters;
+use crate::error::Error;
+use crate::filter;
+use crate::logging;
+use crate::thread_stats::ThreadStats;
+use crate::{detect_overflow, offset_of, overflow_error, skip_line, Partial};
+use brc::parse::parse_line_in;
+use brc::station::StationData;
+use hashbrown::hash_map::{DefaultHashBuilder, Entry};
+use hashbrown::HashMap;
+use rayon::prelude::*;
+use serde_json::json;
+use std::borrow::Cow;
+use std::hash::{BuildHasher, BuildHasherDefault, Hasher};
+use std::time::Instant;
+
+// Tables keyed by fingerprints, which are already hashes.
+pub type Fingerprinted<T> = HashMap<u64, T, BuildHasherDefault<Identity>>;
+
+#[derive(Default)]
+pub struct Identity(u64);
+
+impl Hasher for Identity {
+    fn write(&mut self, _: &[u8]) {
+        unreachable!("fingerprints are hashed as u64")
+    }
+
+    #[inline]
+    fn write_u64(&mut self, value: u64) {
+        self.0 = value;
+    }
+
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[inline]
+pub fn fingerprint(station: &str) -> u64 {
+    DefaultHashBuilder::default().hash_one(station)
+}
+
+// One thread's statistics and names by fingerprint.
+struct Tables<'a> {
+    data: Fingerprinted<StationData>,
+    names: Fingerprinted<&'a str>,
+}
+
+impl<'a> Tables<'a> {
+    fn new(capacity: usize) -> Self {
+        Tables {
+            data: Fingerprinted::with_capacity_and_hasher(capacity, Default::default()),
+            names: Fingerprinted::with_capacity_and_hasher(capacity, Default::default()),
+        }
+    }
+
+    fn merge(mut self, other: Tables<'a>) -> Result<Self, Error> {
+        for (key, data) in other.data {
+            match self.data.entry(key) {
+                Entry::Occupied(mut entry) => entry
+                    .get_mut()
+                    .checked_aggregate(&data)
+                    .map_err(|overflow| overflow_error(other.names[&key], None, overflow))?,
+                Entry::Vacant(entry) => {
+                    entry.insert(data);
+                    self.names.insert(key, other.names[&key]);
+                }
+            }
+        }
+        Ok(self)
+    }
+}
+
+pub fn aggregate<'a>(
+    content: &'a str,
+    options: &Options,
+    stats: Option<&ThreadStats>,
+) -> Result<Partial<'a>, Error> {
+    let checked = detect_overflow(options);
+    let fold = |(mut partial, mut tables, start): (Partial<'a>, Tables<'a>, Instant),
+                line: &'a str|
+     -> Result<_, Error> {
+        partial.rows += 1;
+        partial.bytes += line.len() as u64 + 1;
+        counters::line();
+        match parse_line_in(line, options.delimiter, options.notation) {
+            Ok((station, _)) if !filter::admits(options, station) => {}
+            Ok((station, temp)) => {
+                let station = aliases::resolve(options, station);
+                let key = fingerprint(station);
+                counters::lookup();
+                let data = match tables.data.entry(key) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        tables.names.insert(key, station);
+                        entry.insert(StationData::new())
+                    }
+                };
+                if checked {
+                    data.checked_update(temp).map_err(|overflow| {
+                        overflow_error(station, Some(offset_of(content, line)), overflow)
+                    })?;
+                } else {
+                    data.update(temp);
+                }
+            }
+            Err(reason) => skip_line(
+                &mut partial,
+                offset_of(content, line),
+                reason,
+                Cow::Borrowed(line),
+                options,
+            )?,
+        }
+        Ok((partial, tables, start))
+    };
+
+    let (mut partial, tables, _) = content
+        .par_lines()
+        .try_fold(
+            || {
+                (
+                    Partial::empty(),
+                    Tables::new(options.map_capacity),
+                    Instant::now(),
+                )
+            },
+            fold,
+        )
+        .map(|folded| {
+            folded.inspect(|(partial, _, start)| {
+                if let Some(stats) = stats {
+                    stats.record(ThreadStats::current_worker(), partial, start.elapsed());
+                }
+            })
+        })
+        .try_reduce(
+            || (Partial::empty(), Tables::new(0), Instant::now()),
+            |(a, a_tables, start), (b, b_tables, _)| {
+                Ok((a.merge(b)?, a_tables.merge(b_tables)?, start))
+            },
+        )?;
+
+    if options.verify_fingerprints {
+        verify(content, options, &tables.names)?;
+    }
+    let Tables { data, names } = tables;
+    partial.stations.extend(
+        data.into_iter()
+            .map(|(key, data)| (names[&key].to_string(), data)),
+    );
+    Ok(partial)
+}
+
+// Checks every line's station against the name recorded for its
+// fingerprint. Malformed lines were already reported or skipped.
+pub fn verify(content: &str, options: &Options, names: &Fingerprinted<&str>) -> Result<(), Error> {
+    let start = Instant::now();
+    let collision = content.par_lines().find_map_any(|line| {
+        let (station, _) = parse_line_in(line, options.delimiter, options.notation).ok()?;
+        let station = aliases::resolve(options, station);
+        let key = fingerprint(station);
+        let recorded = names.get(&key)?;
+        (*recorded != station).then_some((*recorded, station, key))
+    });
+    if options.verbose {
+        let elapsed = start.elapsed();
+        logging::event(
+            "fingerprint_verification",
+            format_args!("Fingerprint verification took {:?}", elapsed),
+            json!({ "seconds": elapsed.as_secs_f64() }),
+        );
+    }
+    match collision {
+        Some((a, b, key)) => Err(Error::Validation(format!(
+            "stations `{}` and `{}` share fingerprint {:016x}",
+            a, b, key
+        ))),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,282 @@
+use crate::cli::GenerateOptions;
+use crate::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::str::FromStr;
+
+// Stations and mean temperatures of the `standard` profile, a subset of the
+// challenge's weather stations.
+const STATIONS: &[(&str, f64)] = &[
+    ("Abha", 18.0),
+    ("Accra", 26.4),
+    ("Addis Ababa", 16.0),
+    ("Alexandria", 20.0),
+    ("Anchorage", 2.8),
+    ("Bangkok", 28.6),
+    ("Bridgetown", 27.0),
+    ("Bulawayo", 18.9),
+    ("Cracow", 9.3),
+    ("Dakar", 24.0),
+    ("Dunedin", 11.1),
+    ("Hamburg", 9.7),
+    ("Istanbul", 13.9),
+    ("Jakarta", 26.7),
+    ("Kinshasa", 25.3),
+    ("Las Palmas de Gran Canaria", 21.2),
+    ("Ljubljana", 10.9),
+    ("Nouakchott", 25.7),
+    ("Oulu", 2.7),
+    ("Palembang", 27.3),
+    ("Reykjavík", 4.3),
+    ("Roseau", 26.2),
+    ("São Paulo", 19.9),
+    ("St. John's", 5.0),
+    ("Tromsø", 2.9),
+    ("Ürümqi", 7.4),
+    ("Vladivostok", 4.9),
+    ("Yellowknife", -4.3),
+    ("Zürich", 9.3),
+];
+
+// Standard deviation of the generated temperatures around a station's mean.
+const STDDEV: f64 = 10.0;
+
+// Temperatures are kept within the challenge's range, in tenths of a degree.
+const MAX_TENTHS: i32 = 999;
+
+// Number of distinct stations allowed by the challenge, produced by
+// `max-stations`.
+const MAX_STATIONS: usize = 10_000;
+
+// Maximum station name length in bytes, produced by `long-names`.
+const MAX_NAME_BYTES: usize = 100;
+
+// Distinct stations produced by `long-names`.
+const LONG_NAME_STATIONS: usize = 1_000;
+
+// Stations with exactly one row appended by `edge-cases`.
+const ONE_ROW_STATIONS: usize = 100;
+
+// Characters of 1 to 4 bytes in UTF-8 cycled through to build long names.
+const NAME_CHARS: &[char] = &['a', 'é', 'Ж', 'ß', '中', 'ü', '😀', 'z', 'ø', '日'];
+
+// Syllables combined into synthetic station names.
+const SYLLABLES: &[&str] = &[
+    "ka", "lo", "mi", "ne", "ru", "sa", "to", "vi", "ze", "bra", "dun", "fel", "gor", "hal", "jus",
+    "kir",
+];
+
+// Temperatures in tenths used by `edge-cases`: the range limits, zero and
+// values around the one- and two-digit boundaries.
+const EDGE_TENTHS: &[i32] = &[-999, 999, 0, -1, 1, -99, 99, -100, 100, -5, 5];
+
+// Kind of data written by `generate`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    // Realistic readings for a fixed set of real stations.
+    Standard,
+    // Boundary names and values: 1- and 100-byte names, names that differ
+    // only in case or Unicode normalization, punctuation used by the output
+    // format, readings at ±99.9 and stations with a single row.
+    EdgeCases,
+    // The challenge's maximum of 10,000 distinct stations.
+    MaxStations,
+    // Every row for one station.
+    SingleStation,
+    // Stations whose names use the full 100 bytes with multi-byte UTF-8.
+    LongNames,
+}
+
+impl FromStr for Profile {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "standard" => Ok(Profile::Standard),
+            "edge-cases" => Ok(Profile::EdgeCases),
+            "max-stations" => Ok(Profile::MaxStations),
+            "single-station" => Ok(Profile::SingleStation),
+            "long-names" => Ok(Profile::LongNames),
+            _ => Err(()),
+        }
+    }
+}
+
+// Writes `options.rows` measurements for the selected profile to
+// `options.path`.
+pub fn run(options: &GenerateOptions) -> Result<(), Error> {
+    let mut out = BufWriter::with_capacity(1 << 20, File::create(&options.path)?);
+    let mut rng = Rng::new(options.seed);
+    let rows = options.rows;
+
+    let stations = match options.profile {
+        Profile::Standard => {
+            for _ in 0..rows {
+                let (name, mean) = STATIONS[rng.below(STATIONS.len())];
+                write_row(&mut out, name, rng.gaussian_tenths(mean))?;
+            }
+            STATIONS.len().min(rows)
+        }
+        Profile::EdgeCases => {
+            let names = edge_case_names();
+            let one_row = ONE_ROW_STATIONS.min(rows);
+            let stations = write_covering(&mut out, &mut rng, &names, rows - one_row, |rng| {
+                EDGE_TENTHS[rng.below(EDGE_TENTHS.len())]
+            })?;
+            for i in 0..one_row {
+                let tenths = if i % 2 == 0 { -MAX_TENTHS } else { MAX_TENTHS };
+                write_row(&mut out, &format!("One Row {}", i), tenths)?;
+            }
+            stations + one_row
+        }
+        Profile::MaxStations => {
+            let names: Vec<_> = (0..MAX_STATIONS).map(synthetic_name).collect();
+            write_covering(&mut out, &mut rng, &names, rows, uniform_tenths)?
+        }
+        Profile::SingleStation => {
+            for _ in 0..rows {
+                write_row(&mut out, "Hamburg", rng.gaussian_tenths(9.7))?;
+            }
+            rows.min(1)
+        }
+        Profile::LongNames => {
+            let names: Vec<_> = (0..LONG_NAME_STATIONS).map(long_name).collect();
+            write_covering(&mut out, &mut rng, &names, rows, uniform_tenths)?
+        }
+    };
+    out.flush()?;
+
+    println!(
+        "Wrote {} rows ({} stations) to {}",
+        rows, stations, options.path
+    );
+    Ok(())
+}
+
+// Writes `rows` rows with temperatures drawn by `tenths`, giving each of
+// `names` one row first so every station appears when there are enough rows.
+// Returns the number of distinct stations written.
+fn write_covering(
+    out: &mut impl Write,
+    rng: &mut Rng,
+    names: &[String],
+    rows: usize,
+    mut tenths: impl FnMut(&mut Rng) -> i32,
+) -> Result<usize, Error> {
+    for row in 0..rows {
+        let name = match names.get(row) {
+            Some(name) => name,
+            None => &names[rng.below(names.len())],
+        };
+        let tenths = tenths(rng);
+        write_row(out, name, tenths)?;
+    }
+    Ok(names.len().min(rows))
+}
+
+// Uniformly distributed temperature over the whole range, in tenths.
+fn uniform_tenths(rng: &mut Rng) -> i32 {
+    rng.below(2 * MAX_TENTHS as usize + 1) as i32 - MAX_TENTHS
+}
+
+// Writes one `name;temperature` line with the temperature given in tenths.
+fn write_row(out: &mut impl Write, name: &str, tenths: i32) -> Result<(), Error> {
+    let sign = if tenths < 0 { "-" } else { "" };
+    let abs = tenths.abs();
+    writeln!(out, "{};{}{}.{}", name, sign, abs / 10, abs % 10)?;
+    Ok(())
+}
+
+// Station names for `edge-cases`.
+fn edge_case_names() -> Vec<String> {
+    let mut names: Vec<String> = [
+        "A",
+        "Z",
+        "é",
+        "😀",
+        "abc",
+        "ABC",
+        // Precomposed and decomposed forms of the same name.
+        "Zürich",
+        "Zu\u{308}rich",
+        "St. John's",
+        "Name, with=separators",
+        "{Braces}",
+        " Leading and trailing spaces ",
+        "Tab\tinside",
+    ]
+    .iter()
+    .map(|name| name.to_string())
+    .collect();
+    names.push("x".repeat(MAX_NAME_BYTES));
+    names.push(long_name(0));
+    names
+}
+
+// A unique name of exactly `MAX_NAME_BYTES` bytes mixing characters of every
+// UTF-8 width.
+fn long_name(index: usize) -> String {
+    let mut name = format!("{}-", index);
+    for c in NAME_CHARS.iter().cycle() {
+        if name.len() + c.len_utf8() > MAX_NAME_BYTES {
+            break;
+        }
+        name.push(*c);
+    }
+    while name.len() < MAX_NAME_BYTES {
+        name.push('a');
+    }
+    name
+}
+
+// A unique, pronounceable name built from the digits of `index`.
+fn synthetic_name(index: usize) -> String {
+    let mut name = String::new();
+    let mut rest = index;
+    loop {
+        name.push_str(SYLLABLES[rest % SYLLABLES.len()]);
+        rest /= SYLLABLES.len();
+        if rest == 0 {
+            break;
+        }
+    }
+    let mut chars = name.chars();
+    let first = chars.next().map(|c| c.to_ascii_uppercase());
+    format!("{}{} {}", first.unwrap_or('X'), chars.as_str(), index)
+}
+
+// Small deterministic generator (SplitMix64), so datasets are reproducible
+// from their seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform value in `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        (((self.next() >> 32) * n as u64) >> 32) as usize
+    }
+
+    // Uniform value in `(0, 1]`.
+    fn unit(&mut self) -> f64 {
+        ((self.next() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    // Normally distributed temperature around `mean`, in tenths and clamped
+    // to the challenge's range.
+    fn gaussian_tenths(&mut self, mean: f64) -> i32 {
+        let (u, v) = (self.unit(), self.unit());
+        let z = (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos();
+        (((mean + STDDEV * z) * 10.0).round() as i32).clamp(-MAX_TENTHS, MAX_TENTHS)
+    }
+}
@@ -0,0 +1,497 @@
+// The experimental `gpu` strategy. The mapped input is cut into line-aligned
+// chunks that are uploaded one at a time through wgpu, where the compute
+// shader in `gpu.wgsl` parses and hashes their lines into per-workgroup
+// tables and merges those into one table on the device. Only that table is
+// read back and merged into the result on the CPU, together with the lines
+// the shader leaves alone: anything outside the challenge's plain layout is
+// folded here like in every other strategy, so results do not depend on the
+// device. Compare it with the CPU path on your own hardware with
+// `brc bench --impl gpu --against 'brc run --no-cache {}' FILE`.
+
+use crate::cli::Options;
+use crate::error::Error;
+use crate::thread_stats::ThreadStats;
+use crate::Partial;
+use std::io;
+
+// Checks that the backend is compiled in before a run that selects it.
+pub fn check_enabled() -> Result<(), Error> {
+    if cfg!(feature = "gpu") {
+        Ok(())
+    } else {
+        Err(disabled())
+    }
+}
+
+fn disabled() -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "`--impl gpu` requires building with the `gpu` feature",
+    ))
+}
+
+#[cfg(not(feature = "gpu"))]
+pub fn aggregate<'a>(
+    _content: &'a str,
+    _options: &Options,
+    _stats: Option<&ThreadStats>,
+) -> Result<Partial<'a>, Error> {
+    Err(disabled())
+}
+
+#[cfg(feature = "gpu")]
+pub use imp::aggregate;
+
+#[cfg(feature = "gpu")]
+mod imp {
+    use super::*;
+    use crate::logging;
+    use crate::{aggregate_chunk, aliases, detect_overflow, filter, fold_line, merge_maps};
+    use crate::{overflow_error, StationData};
+    use brc::chunks::{self, Chunk};
+    use hashbrown::HashMap;
+    use serde_json::json;
+    use std::borrow::Cow;
+    use std::time::Instant;
+    use wgpu::util::DeviceExt;
+
+    // Bytes uploaded per dispatch. At most about 1.4 million lines of
+    // `|99.9|` fit in one, so a station's sum in tenths stays within the
+    // shader's `i32`.
+    const CHUNK_SIZE: usize = 8 << 20;
+    // Workgroups of `parse`, each with its own table, and slots per table,
+    // a power of two comfortably above the challenge's 10,000 stations.
+    const GROUPS: u32 = 64;
+    const SLOTS: u32 = 1 << 14;
+    const WORKGROUP_SIZE: u32 = 256;
+    // Offsets of lines left to the CPU per chunk; a chunk with more is
+    // folded on the CPU entirely.
+    const DEFERRED: usize = 1 << 16;
+
+    const SLOT_BYTES: u64 = 6 * 4;
+    const TABLE_BYTES: u64 = SLOTS as u64 * SLOT_BYTES;
+    const DEFERRED_BYTES: u64 = 4 + DEFERRED as u64 * 4;
+    const TOTALS_BYTES: u64 = 3 * 4;
+
+    pub fn aggregate<'a>(
+        content: &'a str,
+        options: &Options,
+        stats: Option<&ThreadStats>,
+    ) -> Result<Partial<'a>, Error> {
+        let start = Instant::now();
+        let mut partial = Partial::empty();
+        let chunks = chunks::chunks(content, CHUNK_SIZE);
+        let Some(largest) = chunks.iter().map(|chunk| chunk.data.len()).max() else {
+            return Ok(partial);
+        };
+        let gpu = Gpu::new(largest)?;
+        let setup = start.elapsed();
+
+        let checked = detect_overflow(options);
+        let mut stations: HashMap<&str, StationData> = HashMap::new();
+        let mut on_cpu = 0;
+        let mut upload = Vec::new();
+        for chunk in chunks {
+            // A chunk stretched by a line too long for the device's buffers
+            // is folded here.
+            let folded = match chunk.data.len().next_multiple_of(4) <= gpu.capacity {
+                true => gpu.fold(chunk.data.as_bytes(), options, &mut upload)?,
+                false => None,
+            };
+            let Some(folded) = folded else {
+                let cpu = aggregate_chunk(chunk, options)?;
+                on_cpu += cpu.rows;
+                partial = partial.merge(cpu)?;
+                continue;
+            };
+            partial.rows += folded.lines;
+            partial.bytes += folded.bytes;
+            for (offset, data) in folded.stations {
+                let name = station_at(chunk.data, offset, options.delimiter);
+                if !filter::admits(options, name) {
+                    continue;
+                }
+                let station = aliases::resolve(options, name);
+                match stations.get_mut(station) {
+                    Some(entry) if checked => entry
+                        .checked_aggregate(&data)
+                        .map_err(|overflow| overflow_error(station, None, overflow))?,
+                    Some(entry) => entry.aggregate(&data),
+                    None => {
+                        stations.insert(station, data);
+                    }
+                }
+            }
+            on_cpu += folded.deferred.len() as u64;
+            for offset in folded.deferred {
+                fold_line(&mut partial, chunk, line_at(chunk, offset), options, None)?;
+            }
+        }
+        partial.stations = merge_maps(
+            partial.stations,
+            stations
+                .into_iter()
+                .map(|(station, data)| (station.to_string(), data))
+                .collect::<HashMap<_, _>>(),
+        )?;
+
+        if let Some(stats) = stats {
+            stats.record(0, &partial, start.elapsed());
+        }
+        if options.verbose {
+            let info = &gpu.info;
+            logging::event(
+                "gpu",
+                format_args!(
+                    "GPU: {} ({:?}), set up in {:?}, {} lines folded on the CPU",
+                    info.name, info.backend, setup, on_cpu
+                ),
+                json!({
+                    "adapter": info.name,
+                    "backend": format!("{:?}", info.backend),
+                    "setup_seconds": setup.as_secs_f64(),
+                    "cpu_lines": on_cpu,
+                }),
+            );
+        }
+        Ok(partial)
+    }
+
+    // The station name of the line at `offset`, which the shader has
+    // checked to end at a delimiter.
+    fn station_at(data: &str, offset: usize, delimiter: u8) -> &str {
+        let line = &data.as_bytes()[offset..];
+        let len = line.iter().position(|&b| b == delimiter).unwrap_or(0);
+        &data[offset..offset + len]
+    }
+
+    // The line starting at `offset`, as `str::lines` would yield it.
+    fn line_at(chunk: Chunk<'_>, offset: usize) -> &str {
+        let rest = &chunk.data[offset..];
+        match rest.find('\n') {
+            Some(end) => {
+                let line = &rest[..end];
+                line.strip_suffix('\r').unwrap_or(line)
+            }
+            None => rest,
+        }
+    }
+
+    // What the device made of a chunk: its stations by the offset of a line
+    // naming them, the lines left to the CPU, in input order, and the lines
+    // and bytes it took.
+    struct Folded {
+        stations: Vec<(usize, StationData)>,
+        deferred: Vec<usize>,
+        lines: u64,
+        bytes: u64,
+    }
+
+    struct Gpu {
+        info: wgpu::AdapterInfo,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        parse: wgpu::ComputePipeline,
+        reduce: wgpu::ComputePipeline,
+        bind_group: wgpu::BindGroup,
+        params: wgpu::Buffer,
+        input: wgpu::Buffer,
+        tables: wgpu::Buffer,
+        deferred: wgpu::Buffer,
+        totals: wgpu::Buffer,
+        readback: wgpu::Buffer,
+        // Stride of the table indices `reduce` takes at dynamic offsets.
+        stride: u32,
+        // Largest chunk the input buffer holds, padded to whole words.
+        capacity: usize,
+    }
+
+    impl Gpu {
+        // Opens the default adapter, preferring a discrete GPU, with an
+        // input buffer for chunks of up to `largest` bytes, as far as the
+        // adapter allows.
+        fn new(largest: usize) -> Result<Self, Error> {
+            let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+            let adapter =
+                pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::HighPerformance,
+                    ..Default::default()
+                }))
+                .map_err(|e| gpu_error("no adapter", e))?;
+            let limits = adapter.limits();
+            let (device, queue) =
+                pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+                    label: Some("brc"),
+                    required_limits: limits.clone(),
+                    ..Default::default()
+                }))
+                .map_err(|e| gpu_error("no device", e))?;
+
+            let source = format!(
+                "const SLOTS: u32 = {}u;\nconst GROUPS: u32 = {}u;\n{}",
+                SLOTS,
+                GROUPS,
+                include_str!("gpu.wgsl")
+            );
+            let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("gpu.wgsl"),
+                source: wgpu::ShaderSource::Wgsl(Cow::Owned(source)),
+            });
+            let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            };
+            let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    storage(1, true),
+                    storage(2, false),
+                    storage(3, false),
+                    storage(4, false),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: true,
+                            min_binding_size: wgpu::BufferSize::new(4),
+                        },
+                        count: None,
+                    },
+                ],
+            });
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            });
+            let pipeline = |entry_point| {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(entry_point),
+                    layout: Some(&pipeline_layout),
+                    module: &module,
+                    entry_point: Some(entry_point),
+                    compilation_options: Default::default(),
+                    cache: None,
+                })
+            };
+            let (parse, reduce) = (pipeline("parse"), pipeline("reduce"));
+
+            let capacity = largest.next_multiple_of(4).min(
+                (limits.max_storage_buffer_binding_size as usize)
+                    .min(limits.max_buffer_size as usize)
+                    & !3,
+            );
+            let buffer = |label, size, usage| {
+                device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(label),
+                    size,
+                    usage,
+                    mapped_at_creation: false,
+                })
+            };
+            let copied = wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC;
+            let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("params"),
+                contents: &[0; 16],
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+            let input = buffer("input", capacity.max(4) as u64, copied);
+            let tables = buffer("tables", (GROUPS as u64 + 1) * TABLE_BYTES, copied);
+            let deferred = buffer("deferred", DEFERRED_BYTES, copied);
+            let totals = buffer("totals", TOTALS_BYTES, copied);
+            let readback = buffer(
+                "readback",
+                TABLE_BYTES + DEFERRED_BYTES + TOTALS_BYTES,
+                wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            );
+            let stride = limits.min_uniform_buffer_offset_alignment;
+            let merging = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("merging"),
+                contents: &(0..GROUPS)
+                    .flat_map(|table| {
+                        let mut padded = vec![0; stride as usize];
+                        padded[..4].copy_from_slice(&table.to_le_bytes());
+                        padded
+                    })
+                    .collect::<Vec<_>>(),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            let mut entries: Vec<_> = [&params, &input, &tables, &deferred, &totals]
+                .iter()
+                .enumerate()
+                .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                    binding: binding as u32,
+                    resource: buffer.as_entire_binding(),
+                })
+                .collect();
+            entries.push(wgpu::BindGroupEntry {
+                binding: 5,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &merging,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(4),
+                }),
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &layout,
+                entries: &entries,
+            });
+
+            Ok(Gpu {
+                info: adapter.get_info(),
+                device,
+                queue,
+                parse,
+                reduce,
+                bind_group,
+                params,
+                input,
+                tables,
+                deferred,
+                totals,
+                readback,
+                stride,
+                capacity,
+            })
+        }
+
+        // Aggregates one chunk on the device, through `upload` padded to
+        // whole words. Returns `None` if it has to be folded on the CPU
+        // instead, because too many of its lines were left over or its
+        // stations overflowed the merged table.
+        fn fold(
+            &self,
+            data: &[u8],
+            options: &Options,
+            upload: &mut Vec<u8>,
+        ) -> Result<Option<Folded>, Error> {
+            upload.clear();
+            upload.extend_from_slice(data);
+            upload.resize(data.len().next_multiple_of(4), 0);
+            let invocations = GROUPS as usize * WORKGROUP_SIZE as usize;
+            let params = [
+                data.len() as u32,
+                options.delimiter as u32,
+                options.notation.decimal as u32,
+                data.len().div_ceil(invocations) as u32,
+            ];
+            let params: Vec<u8> = params.iter().flat_map(|word| word.to_le_bytes()).collect();
+            self.queue.write_buffer(&self.params, 0, &params);
+            self.queue.write_buffer(&self.input, 0, upload);
+
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+            encoder.clear_buffer(&self.tables, 0, None);
+            encoder.clear_buffer(&self.deferred, 0, None);
+            encoder.clear_buffer(&self.totals, 0, None);
+            {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+                pass.set_bind_group(0, &self.bind_group, &[0]);
+                pass.set_pipeline(&self.parse);
+                pass.dispatch_workgroups(GROUPS, 1, 1);
+                pass.set_pipeline(&self.reduce);
+                for table in 0..GROUPS {
+                    pass.set_bind_group(0, &self.bind_group, &[table * self.stride]);
+                    pass.dispatch_workgroups(SLOTS / WORKGROUP_SIZE, 1, 1);
+                }
+            }
+            let merged = GROUPS as u64 * TABLE_BYTES;
+            encoder.copy_buffer_to_buffer(&self.tables, merged, &self.readback, 0, TABLE_BYTES);
+            encoder.copy_buffer_to_buffer(
+                &self.deferred,
+                0,
+                &self.readback,
+                TABLE_BYTES,
+                DEFERRED_BYTES,
+            );
+            encoder.copy_buffer_to_buffer(
+                &self.totals,
+                0,
+                &self.readback,
+                TABLE_BYTES + DEFERRED_BYTES,
+                TOTALS_BYTES,
+            );
+            self.queue.submit([encoder.finish()]);
+
+            let slice = self.readback.slice(..);
+            slice.map_async(wgpu::MapMode::Read, |_| {});
+            self.device
+                .poll(wgpu::PollType::Wait)
+                .map_err(|e| gpu_error("lost the device", e))?;
+            let folded = {
+                let mapped = slice.get_mapped_range();
+                let words: Vec<u32> = mapped
+                    .chunks_exact(4)
+                    .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+                    .collect();
+                read_back(&words)
+            };
+            self.readback.unmap();
+            Ok(folded)
+        }
+    }
+
+    // Decodes the merged table, the deferred lines and the totals from the
+    // readback buffer.
+    fn read_back(words: &[u32]) -> Option<Folded> {
+        let (table, rest) = words.split_at(TABLE_BYTES as usize / 4);
+        let (deferred, totals) = rest.split_at(DEFERRED_BYTES as usize / 4);
+        let count = deferred[0] as usize;
+        if count > DEFERRED || totals[2] != 0 {
+            return None;
+        }
+        let stations = table
+            .chunks_exact(SLOT_BYTES as usize / 4)
+            .filter(|slot| slot[0] != 0)
+            .map(|slot| {
+                let sum = slot[2] as i32;
+                let data = StationData {
+                    min_temp: (999 - slot[3] as i32) as f32 / 10.0,
+                    max_temp: (slot[4] as i32 - 999) as f32 / 10.0,
+                    total_temp: (sum as f64 / 10.0) as f32,
+                    count: slot[1] as i32,
+                };
+                (slot[0] as usize - 1, data)
+            })
+            .collect();
+        let mut deferred: Vec<usize> = deferred[1..=count]
+            .iter()
+            .map(|&offset| offset as usize)
+            .collect();
+        deferred.sort_unstable();
+        Some(Folded {
+            stations,
+            deferred,
+            lines: totals[0] as u64,
+            bytes: totals[1] as u64,
+        })
+    }
+
+    fn gpu_error(what: &str, error: impl std::fmt::Display) -> Error {
+        Error::Io(io::Error::other(format!(
+            "`--impl gpu`: {}: {}",
+            what, error
+        )))
+    }
+}
@@ -0,0 +1,238 @@
+// Aggregation of one chunk of the input for `--impl gpu`. `SLOTS` and
+// `GROUPS` are prepended by the host.
+//
+// `parse` runs `GROUPS` workgroups, each over a contiguous stripe of the
+// chunk, and every invocation parses the lines that start in its window of
+// the stripe into its workgroup's table. `reduce` then merges the workgroup
+// tables into the last table, which the host reads back, one table per
+// dispatch so the names merged at once are distinct. Lines the shader does
+// not take, because they are not in the challenge's plain layout or their
+// slot could not be claimed, are left to the host by offset.
+
+struct Params {
+    len: u32,
+    delimiter: u32,
+    decimal: u32,
+    window: u32,
+}
+
+// A station, keyed by the offset of a line naming it plus one, so zeroed
+// memory is an empty table. The extremes are offset to stay unsigned and
+// start at zero: `low` holds 999 minus the minimum in tenths, `high` the
+// maximum plus 999. `tickets` counts the invocations that tried to claim it.
+struct Slot {
+    key: atomic<u32>,
+    count: atomic<u32>,
+    sum: atomic<i32>,
+    low: atomic<u32>,
+    high: atomic<u32>,
+    tickets: atomic<u32>,
+}
+
+struct Deferred {
+    count: atomic<u32>,
+    offsets: array<u32>,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> input: array<u32>;
+@group(0) @binding(2) var<storage, read_write> tables: array<Slot>;
+@group(0) @binding(3) var<storage, read_write> deferred: Deferred;
+// Lines and bytes taken, and whether the merged table overflowed.
+@group(0) @binding(4) var<storage, read_write> totals: array<atomic<u32>, 3>;
+// The workgroup table `reduce` merges.
+@group(0) @binding(5) var<uniform> merging: u32;
+
+const NONE: u32 = 0xffffffffu;
+const MAX_STATION_BYTES: u32 = 100u;
+
+fn byte_at(i: u32) -> u32 {
+    return (input[i >> 2u] >> ((i & 3u) * 8u)) & 0xffu;
+}
+
+fn digit(i: u32) -> u32 {
+    return byte_at(i) - 48u;
+}
+
+// FNV-1a over the name.
+fn hash_name(start: u32, len: u32) -> u32 {
+    var hash = 2166136261u;
+    for (var i = 0u; i < len; i++) {
+        hash = (hash ^ byte_at(start + i)) * 16777619u;
+    }
+    return hash;
+}
+
+// Whether the name stored at `stored`, which ends at a delimiter, is the
+// `len` bytes at `start`.
+fn same_name(stored: u32, start: u32, len: u32) -> bool {
+    if (byte_at(stored + len) != params.delimiter) {
+        return false;
+    }
+    for (var i = 0u; i < len; i++) {
+        if (byte_at(stored + i) != byte_at(start + i)) {
+            return false;
+        }
+    }
+    return true;
+}
+
+// Finds or claims the slot of a name in the table starting at `base`, by
+// linear probing. Without compare-and-swap, which the GL backend lacks, a
+// free slot goes to the first invocation to take a ticket on it, and the
+// others compare names once its key is stored. Returns `NONE` when the table
+// is full or, unless the names claimed concurrently are known to be
+// `distinct`, when the slot is still being claimed for an unknown name.
+fn claim(base: u32, start: u32, len: u32, distinct: bool) -> u32 {
+    let hash = hash_name(start, len);
+    for (var probe = 0u; probe < SLOTS; probe++) {
+        let index = base + ((hash + probe) & (SLOTS - 1u));
+        var key = atomicLoad(&tables[index].key);
+        if (key == 0u) {
+            if (atomicAdd(&tables[index].tickets, 1u) == 0u) {
+                atomicStore(&tables[index].key, start + 1u);
+                return index;
+            }
+            key = atomicLoad(&tables[index].key);
+            if (key == 0u) {
+                if (distinct) {
+                    continue;
+                }
+                return NONE;
+            }
+        }
+        if (same_name(key - 1u, start, len)) {
+            return index;
+        }
+    }
+    return NONE;
+}
+
+fn add(index: u32, count: u32, sum: i32, low: u32, high: u32) {
+    atomicAdd(&tables[index].count, count);
+    atomicAdd(&tables[index].sum, sum);
+    atomicMax(&tables[index].low, low);
+    atomicMax(&tables[index].high, high);
+}
+
+fn defer(offset: u32) {
+    let index = atomicAdd(&deferred.count, 1u);
+    if (index < arrayLength(&deferred.offsets)) {
+        deferred.offsets[index] = offset;
+    }
+}
+
+// Folds the line `start..end` into the table at `base`. Only the layouts
+// `N.N`, `NN.N`, `-N.N` and `-NN.N` are taken, which every `--numbers`
+// policy accepts; a leading BOM, a trailing `\r` or anything else is left to
+// the host.
+fn fold(base: u32, start: u32, end: u32) -> bool {
+    if (end - start >= 3u && byte_at(start) == 0xefu && byte_at(start + 1u) == 0xbbu
+        && byte_at(start + 2u) == 0xbfu) {
+        return false;
+    }
+    let limit = min(end, start + MAX_STATION_BYTES + 1u);
+    var separator = start;
+    while (separator < limit && byte_at(separator) != params.delimiter) {
+        separator++;
+    }
+    if (separator == start || separator == limit) {
+        return false;
+    }
+
+    var value = separator + 1u;
+    let negative = value < end && byte_at(value) == 45u;
+    if (negative) {
+        value++;
+    }
+    var tens = 0u;
+    switch (end - value) {
+        case 3u: {}
+        case 4u: {
+            tens = digit(value);
+            value++;
+        }
+        default: {
+            return false;
+        }
+    }
+    let ones = digit(value);
+    let tenths = digit(value + 2u);
+    if (byte_at(value + 1u) != params.decimal || tens > 9u || ones > 9u || tenths > 9u) {
+        return false;
+    }
+    var temp = i32(tens * 100u + ones * 10u + tenths);
+    if (negative) {
+        temp = -temp;
+    }
+
+    let index = claim(base, start, separator - start, false);
+    if (index == NONE) {
+        return false;
+    }
+    add(index, 1u, temp, u32(999 - temp), u32(temp + 999));
+    return true;
+}
+
+@compute @workgroup_size(256)
+fn parse(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(workgroup_id) group: vec3<u32>,
+) {
+    let begin = id.x * params.window;
+    if (begin >= params.len) {
+        return;
+    }
+    let end = min(begin + params.window, params.len);
+    // The line running into the window belongs to the invocation it
+    // started in.
+    var start = begin;
+    while (start < end && start > 0u && byte_at(start - 1u) != 10u) {
+        start++;
+    }
+
+    let base = group.x * SLOTS;
+    var lines = 0u;
+    var bytes = 0u;
+    while (start < end) {
+        var stop = start;
+        while (stop < params.len && byte_at(stop) != 10u) {
+            stop++;
+        }
+        if (fold(base, start, stop)) {
+            lines++;
+            bytes += stop - start + 1u;
+        } else {
+            defer(start);
+        }
+        start = stop + 1u;
+    }
+    atomicAdd(&totals[0], lines);
+    atomicAdd(&totals[1], bytes);
+}
+
+@compute @workgroup_size(256)
+fn reduce(@builtin(global_invocation_id) id: vec3<u32>) {
+    let slot = merging * SLOTS + id.x;
+    let key = atomicLoad(&tables[slot].key);
+    if (key == 0u) {
+        return;
+    }
+    let start = key - 1u;
+    var separator = start;
+    while (byte_at(separator) != params.delimiter) {
+        separator++;
+    }
+    let index = claim(GROUPS * SLOTS, start, separator - start, true);
+    if (index == NONE) {
+        atomicStore(&totals[2], 1u);
+        return;
+    }
+    add(
+        index,
+        atomicLoad(&tables[slot].count),
+        atomicLoad(&tables[slot].sum),
+        atomicLoad(&tables[slot].low),
+        atomicLoad(&tables[slot].high),
+    );
+}
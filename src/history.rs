@@ -0,0 +1,164 @@
+// `bench --history`: one record per benchmarked command is appended to a
+// history file, so performance can be plotted across commits and machines.
+// Files ending in `.jsonl` get one JSON object per line; any other path gets
+// CSV, with a header line when the file is new or empty. Times are in seconds
+// and throughput is in MB/s (10^6 bytes) of input per median run. The same
+// records of brc's runs, saved as a JSON array, are the baselines of `bench
+// --baseline`. JSON records also describe the machine; CSV keeps to flat
+// columns.
+
+use crate::system::System;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize)]
+pub struct Record {
+    // When the benchmark started, in RFC 3339 UTC.
+    pub timestamp: String,
+    // `git describe` of the checkout the binary was built in, if any.
+    pub version: String,
+    // `brc`, or the `--against` command.
+    pub command: String,
+    // The `--impl` and thread count of brc's runs.
+    pub implementation: Option<String>,
+    pub threads: Option<usize>,
+    pub runs: usize,
+    pub best: f64,
+    pub median: f64,
+    // Median `--phase-times` of brc's full runs.
+    pub setup: Option<f64>,
+    pub aggregate: Option<f64>,
+    pub output: Option<f64>,
+    pub throughput: Option<f64>,
+    // The machine, in JSON only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<System>,
+}
+
+const HEADER: &str =
+    "timestamp,version,command,implementation,threads,runs,best,median,setup,aggregate,output,throughput";
+
+pub fn append(path: &str, records: &[Record]) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let empty = file.metadata()?.len() == 0;
+    let mut out = BufWriter::new(file);
+    if path.ends_with(".jsonl") {
+        for record in records {
+            serde_json::to_writer(&mut out, record).map_err(io::Error::from)?;
+            writeln!(out)?;
+        }
+    } else {
+        if empty {
+            writeln!(out, "{}", HEADER)?;
+        }
+        let number = |value: Option<f64>| value.map_or(String::new(), |value| value.to_string());
+        for record in records {
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{},{},{},{},{},{}",
+                record.timestamp,
+                csv_field(&record.version),
+                csv_field(&record.command),
+                record.implementation.as_deref().unwrap_or_default(),
+                record
+                    .threads
+                    .map_or(String::new(), |threads| threads.to_string()),
+                record.runs,
+                record.best,
+                record.median,
+                number(record.setup),
+                number(record.aggregate),
+                number(record.output),
+                number(record.throughput)
+            )?;
+        }
+    }
+    out.flush()
+}
+
+// Writes brc's records, those with a strategy, to `path` for `bench
+// --baseline`, as a JSON array.
+pub fn save_baseline(path: &str, records: &[Record]) -> io::Result<()> {
+    let ours: Vec<_> = records
+        .iter()
+        .filter(|record| record.implementation.is_some())
+        .collect();
+    let mut out = BufWriter::new(fs::File::create(path)?);
+    serde_json::to_writer_pretty(&mut out, &ours).map_err(io::Error::from)?;
+    writeln!(out)?;
+    out.flush()
+}
+
+pub fn load_baseline(path: &str) -> io::Result<Vec<Record>> {
+    let content = fs::read_to_string(path)?;
+    serde_json::from_str(&content).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid baseline {}: {}", path, e),
+        )
+    })
+}
+
+// `field` quoted if it contains a separator, quote or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// The current time in RFC 3339 UTC, to the second.
+pub fn timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    format_timestamp(seconds)
+}
+
+// Formats `seconds` since the Unix epoch as RFC 3339 UTC, converting days to
+// a civil date as in Howard Hinnant's `civil_from_days`.
+pub fn format_timestamp(seconds: u64) -> String {
+    let (days, time) = ((seconds / 86_400) as i64, seconds % 86_400);
+    let shifted = days + 719_468;
+    let era = shifted.div_euclid(146_097);
+    let day_of_era = shifted - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // Months counted from March, so leap days come last.
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3_600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+// `git describe --always --dirty` in the directory of `exe`, which is the
+// checkout for binaries run from its `target` directory, or empty.
+pub fn version(exe: &Path) -> String {
+    let Some(dir) = exe.parent() else {
+        return String::new();
+    };
+    Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["describe", "--always", "--dirty"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_default()
+}
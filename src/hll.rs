@@ -0,0 +1,113 @@
+// A sampled pre-pass that estimates the number of distinct stations with a
+// HyperLogLog sketch, so per-thread station maps start at about the size
+// they end up at instead of a fixed guess: small for a handful of stations,
+// and large enough to avoid repeated rehashing for very large key sets.
+
+use std::hash::{BuildHasher, BuildHasherDefault, DefaultHasher};
+
+// Registers are addressed by the top `PRECISION` bits of a hash, for a
+// standard error of about 1.6%.
+const PRECISION: u32 = 12;
+const REGISTERS: usize = 1 << PRECISION;
+
+// The pre-pass reads `SAMPLES` evenly spaced windows of `SAMPLE_BYTES`, or
+// the whole input if it is smaller.
+const SAMPLES: usize = 64;
+const SAMPLE_BYTES: usize = 64 * 1024;
+
+// Smallest map capacity handed out.
+const MIN_CAPACITY: usize = 16;
+
+pub struct HyperLogLog {
+    registers: Box<[u8; REGISTERS]>,
+    hasher: BuildHasherDefault<DefaultHasher>,
+}
+
+impl HyperLogLog {
+    pub fn new() -> Self {
+        HyperLogLog {
+            registers: Box::new([0; REGISTERS]),
+            hasher: BuildHasherDefault::default(),
+        }
+    }
+
+    pub fn insert(&mut self, key: &[u8]) {
+        let hash = self.hasher.hash_one(key);
+        let register = (hash >> (64 - PRECISION)) as usize;
+        // Position of the first set bit after the register bits, with a
+        // sentinel so it never exceeds the bits available.
+        let rank = ((hash << PRECISION) | (1 << (PRECISION - 1))).leading_zeros() as u8 + 1;
+        let slot = &mut self.registers[register];
+        *slot = (*slot).max(rank);
+    }
+
+    // Estimated number of distinct keys inserted, with linear counting for
+    // small cardinalities.
+    pub fn estimate(&self) -> usize {
+        let m = REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|&rank| 2f64.powi(-(rank as i32)))
+            .sum();
+        let raw = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&rank| rank == 0).count();
+        let estimate = if raw <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        };
+        estimate.round() as usize
+    }
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        HyperLogLog::new()
+    }
+}
+
+// Capacity for each of `threads` station maps over `input`: the distinct
+// stations in a sample of its lines, with some headroom, but no more than
+// the lines a thread is expected to see. Key sets larger than the sample
+// shows grow from there as usual.
+pub fn map_capacity(input: &[u8], delimiter: u8, threads: usize) -> usize {
+    let mut sketch = HyperLogLog::new();
+    let (mut lines, mut sampled) = (0usize, 0usize);
+    let stride = (input.len() / SAMPLES).max(SAMPLE_BYTES);
+    for start in (0..input.len()).step_by(stride) {
+        let window = &input[start..input.len().min(start + SAMPLE_BYTES)];
+        // Skip the partial line the window starts in, and the one it ends in.
+        let first = match start {
+            0 => 0,
+            _ => match window.iter().position(|&b| b == b'\n') {
+                Some(newline) => newline + 1,
+                None => continue,
+            },
+        };
+        let Some(last) = window.iter().rposition(|&b| b == b'\n') else {
+            continue;
+        };
+        if first > last {
+            continue;
+        }
+        let whole = &window[first..last];
+        sampled += whole.len() + 1;
+        for line in whole.split(|&b| b == b'\n') {
+            lines += 1;
+            if let Some(end) = line.iter().position(|&b| b == delimiter) {
+                sketch.insert(&line[..end]);
+            }
+        }
+    }
+    if lines == 0 {
+        return MIN_CAPACITY;
+    }
+
+    let expected_lines = input.len() / sampled.div_ceil(lines).max(1);
+    let per_thread = expected_lines.div_ceil(threads.max(1));
+    (sketch.estimate() + sketch.estimate() / 4)
+        .min(per_thread)
+        .max(MIN_CAPACITY)
+}
@@ -0,0 +1,207 @@
+// An instrumented scan for `--hot-path-stats`: parses every line the way the
+// aggregating strategies do, but records which way the parser's branches go
+// and how station names and temperatures are shaped, then prints how common
+// each case is. The report tells which specialized fast paths, such as a
+// fixed-width temperature parser or names compared in one 16-byte load,
+// would cover enough of a real input to be worth writing.
+
+use brc::parse::{check_station, parse_temperature, split_once, LineError, BOM, MAX_STATION_BYTES};
+use hashbrown::{HashMap, HashSet};
+use rayon::prelude::*;
+
+// Temperature layouts, with `N` for a digit.
+const SHAPES: [&str; 4] = ["N.N", "NN.N", "-N.N", "-NN.N"];
+
+// Name lengths reported as fitting in one load of that many bytes.
+const WIDTHS: [usize; 3] = [8, 16, 32];
+
+// What one thread, or all of them, saw.
+pub struct Profile<'a> {
+    pub lines: u64,
+    pub boms: u64,
+    // Per entry of `SHAPES`, and any other accepted layout last.
+    pub shapes: [u64; SHAPES.len() + 1],
+    // Parsed lines by station name length in bytes.
+    pub name_lengths: Vec<u64>,
+    pub non_ascii: u64,
+    // Stations seen so far; the first reading of each is a map insert, the
+    // others are lookups that hit.
+    stations: HashSet<&'a str>,
+    pub first_sightings: u64,
+    pub rejected: HashMap<&'static str, u64>,
+}
+
+impl<'a> Profile<'a> {
+    fn new() -> Self {
+        Profile {
+            lines: 0,
+            boms: 0,
+            shapes: [0; SHAPES.len() + 1],
+            name_lengths: vec![0; MAX_STATION_BYTES + 1],
+            non_ascii: 0,
+            stations: HashSet::new(),
+            first_sightings: 0,
+            rejected: HashMap::new(),
+        }
+    }
+
+    fn update(mut self, line: &'a str, delimiter: u8) -> Self {
+        self.lines += 1;
+        let line = match line.strip_prefix(BOM) {
+            Some(rest) => {
+                self.boms += 1;
+                rest
+            }
+            None => line,
+        };
+        let parsed = split_once(line, delimiter)
+            .ok_or(LineError::MissingDelimiter)
+            .and_then(|(station, temp)| {
+                check_station(station.as_bytes())?;
+                parse_temperature(temp)?;
+                Ok((station, temp))
+            });
+        match parsed {
+            Ok((station, temp)) => {
+                self.shapes[shape(temp.as_bytes())] += 1;
+                self.name_lengths[station.len()] += 1;
+                if !station.is_ascii() {
+                    self.non_ascii += 1;
+                }
+                if self.stations.insert(station) {
+                    self.first_sightings += 1;
+                }
+            }
+            Err(reason) => *self.rejected.entry(reason.code()).or_default() += 1,
+        }
+        self
+    }
+
+    fn aggregate(mut self, other: Profile<'a>) -> Self {
+        self.lines += other.lines;
+        self.boms += other.boms;
+        for (count, other) in self.shapes.iter_mut().zip(other.shapes) {
+            *count += other;
+        }
+        for (count, other) in self.name_lengths.iter_mut().zip(other.name_lengths) {
+            *count += other;
+        }
+        self.non_ascii += other.non_ascii;
+        // Threads keep their own maps, so first sightings add up rather than
+        // being deduplicated.
+        self.first_sightings += other.first_sightings;
+        for (reason, count) in other.rejected {
+            *self.rejected.entry(reason).or_default() += count;
+        }
+        self
+    }
+}
+
+// Index in `SHAPES` of an accepted temperature's layout, or `SHAPES.len()`.
+fn shape(temp: &[u8]) -> usize {
+    let (negative, digits) = match temp.split_first() {
+        Some((b'-', rest)) => (true, rest),
+        _ => (false, temp),
+    };
+    let width = match digits {
+        [_, b'.', _] => 0,
+        [_, _, b'.', _] => 1,
+        _ => return SHAPES.len(),
+    };
+    if negative {
+        width + 2
+    } else {
+        width
+    }
+}
+
+// Scans the input and prints hot path statistics.
+pub fn run(content: &str, delimiter: u8) {
+    print(scan(content, delimiter));
+}
+
+pub fn scan(content: &str, delimiter: u8) -> Profile<'_> {
+    content
+        .par_lines()
+        .fold(Profile::new, |profile, line| {
+            profile.update(line, delimiter)
+        })
+        .reduce(Profile::new, Profile::aggregate)
+}
+
+fn print(profile: Profile) {
+    let parsed: u64 = profile.name_lengths.iter().sum();
+    let share = |count: u64| 100.0 * count as f64 / parsed.max(1) as f64;
+    println!("Hot path statistics:");
+    println!(
+        "Lines: {}, {} parsed, {} rejected",
+        profile.lines,
+        parsed,
+        profile.lines - parsed
+    );
+
+    let mut shapes: Vec<_> = SHAPES
+        .iter()
+        .copied()
+        .chain(["other"])
+        .zip(profile.shapes)
+        .collect();
+    shapes.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+    let shapes: Vec<_> = shapes
+        .iter()
+        .map(|&(name, count)| format!("{} {:.1}%", name, share(count)))
+        .collect();
+    println!("Temperature formats: {}", shapes.join(", "));
+
+    let total: u64 = profile
+        .name_lengths
+        .iter()
+        .enumerate()
+        .map(|(len, &count)| len as u64 * count)
+        .sum();
+    let max = profile.name_lengths.iter().rposition(|&count| count > 0);
+    let percentile = |fraction: f64| {
+        let target = (parsed as f64 * fraction).ceil() as u64;
+        let mut seen = 0;
+        profile
+            .name_lengths
+            .iter()
+            .position(|&count| {
+                seen += count;
+                seen >= target.max(1)
+            })
+            .unwrap_or(0)
+    };
+    let fits: Vec<_> = WIDTHS
+        .iter()
+        .map(|&width| {
+            let count = profile.name_lengths[..=width].iter().sum();
+            format!("{:.1}% fit {} bytes", share(count), width)
+        })
+        .collect();
+    println!(
+        "Station names: mean {:.1} bytes, median {}, p99 {}, max {}; {}; {:.1}% non-ASCII",
+        total as f64 / parsed.max(1) as f64,
+        percentile(0.5),
+        percentile(0.99),
+        max.unwrap_or(0),
+        fits.join(", "),
+        share(profile.non_ascii)
+    );
+    println!(
+        "Station lookups: {:.1}% hit a station the thread had seen, {:.1}% first sightings",
+        share(parsed - profile.first_sightings),
+        share(profile.first_sightings)
+    );
+    println!("Lines starting with a BOM: {}", profile.boms);
+
+    if !profile.rejected.is_empty() {
+        let mut rejected: Vec<_> = profile.rejected.into_iter().collect();
+        rejected.sort_unstable_by_key(|&(reason, count)| (std::cmp::Reverse(count), reason));
+        let rejected: Vec<_> = rejected
+            .iter()
+            .map(|(reason, count)| format!("{} {}", reason, count))
+            .collect();
+        println!("Rejected: {}", rejected.join(", "));
+    }
+}
@@ -0,0 +1,174 @@
+// The `index` station map backend, for `--map index`: each thread keeps its
+// stations' statistics in one contiguous `Vec<StationData>`, in order of
+// first appearance, and its hash table only maps names to `u32` indexes into
+// it. Table entries stay small whatever `StationData` holds, merging two
+// threads' results walks the other's names and statistics side by side, and
+// the final stations are read off contiguous memory. Names are borrowed from
+// the input, so nothing is allocated per station until the end.
+
+use crate::aliases;
+use crate::cli::Options;
+use crate::counters;
+use crate::error::Error;
+use crate::filter;
+use crate::key_hash::{KeyHash, StationHashBuilder};
+use crate::thread_stats::ThreadStats;
+use crate::{detect_overflow, offset_of, overflow_error, skip_line, Partial};
+use brc::parse::parse_line_in;
+use brc::station::StationData;
+use hashbrown::HashMap;
+use rayon::prelude::*;
+use std::borrow::Cow;
+use std::str::FromStr;
+use std::time::Instant;
+
+// Where station statistics are stored, selected with `--map`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapKind {
+    // A map from name to statistics.
+    Hash,
+    // A map from name to an index into a vector of statistics.
+    Index,
+}
+
+impl FromStr for MapKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hash" => Ok(MapKind::Hash),
+            "index" => Ok(MapKind::Index),
+            _ => Err(()),
+        }
+    }
+}
+
+struct IndexMap<'a> {
+    indexes: HashMap<&'a str, u32, StationHashBuilder>,
+    names: Vec<&'a str>,
+    data: Vec<StationData>,
+}
+
+impl<'a> IndexMap<'a> {
+    fn new(key_hash: KeyHash, capacity: usize) -> Self {
+        IndexMap {
+            indexes: HashMap::with_capacity_and_hasher(capacity, StationHashBuilder::new(key_hash)),
+            names: Vec::with_capacity(capacity),
+            data: Vec::with_capacity(capacity),
+        }
+    }
+
+    // The statistics of `station`, added empty if it is new.
+    #[inline]
+    fn get_or_insert(&mut self, station: &'a str) -> &mut StationData {
+        counters::lookup();
+        let index = match self.indexes.get(station) {
+            Some(&index) => index,
+            None => {
+                let index = self.data.len() as u32;
+                let capacity = self.indexes.capacity();
+                self.indexes.insert(station, index);
+                counters::insert(capacity, self.indexes.capacity());
+                self.names.push(station);
+                self.data.push(StationData::new());
+                index
+            }
+        };
+        &mut self.data[index as usize]
+    }
+
+    fn merge(mut self, other: IndexMap<'a>) -> Result<Self, Error> {
+        if self.data.len() < other.data.len() {
+            return other.merge(self);
+        }
+        for (name, data) in other.names.into_iter().zip(other.data) {
+            match self.indexes.get(name) {
+                Some(&index) => self.data[index as usize]
+                    .checked_aggregate(&data)
+                    .map_err(|overflow| overflow_error(name, None, overflow))?,
+                None => {
+                    self.indexes.insert(name, self.data.len() as u32);
+                    self.names.push(name);
+                    self.data.push(data);
+                }
+            }
+        }
+        Ok(self)
+    }
+}
+
+pub fn aggregate<'a>(
+    content: &'a str,
+    options: &Options,
+    stats: Option<&ThreadStats>,
+) -> Result<Partial<'a>, Error> {
+    let checked = detect_overflow(options);
+    let fold = |(mut partial, mut map, start): (Partial<'a>, IndexMap<'a>, Instant),
+                line: &'a str|
+     -> Result<_, Error> {
+        partial.rows += 1;
+        partial.bytes += line.len() as u64 + 1;
+        counters::line();
+        match parse_line_in(line, options.delimiter, options.notation) {
+            Ok((station, _)) if !filter::admits(options, station) => {}
+            Ok((station, temp)) => {
+                let station = aliases::resolve(options, station);
+                let data = map.get_or_insert(station);
+                if checked {
+                    data.checked_update(temp).map_err(|overflow| {
+                        overflow_error(station, Some(offset_of(content, line)), overflow)
+                    })?;
+                } else {
+                    data.update(temp);
+                }
+            }
+            Err(reason) => skip_line(
+                &mut partial,
+                offset_of(content, line),
+                reason,
+                Cow::Borrowed(line),
+                options,
+            )?,
+        }
+        Ok((partial, map, start))
+    };
+
+    let (mut partial, map, _) = content
+        .par_lines()
+        .try_fold(
+            || {
+                (
+                    Partial::empty(),
+                    IndexMap::new(options.key_hash, options.map_capacity),
+                    Instant::now(),
+                )
+            },
+            fold,
+        )
+        .map(|folded| {
+            folded.inspect(|(partial, _, start)| {
+                if let Some(stats) = stats {
+                    stats.record(ThreadStats::current_worker(), partial, start.elapsed());
+                }
+            })
+        })
+        .try_reduce(
+            || {
+                (
+                    Partial::empty(),
+                    IndexMap::new(options.key_hash, 0),
+                    Instant::now(),
+                )
+            },
+            |(a, a_map, start), (b, b_map, _)| Ok((a.merge(b)?, a_map.merge(b_map)?, start)),
+        )?;
+
+    partial.stations.reserve(map.data.len());
+    partial.stations.extend(
+        map.names
+            .into_iter()
+            .zip(map.data)
+            .map(|(name, data)| (name.to_string(), data)),
+    );
+    Ok(partial)
+}
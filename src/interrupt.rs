@@ -0,0 +1,91 @@
+// Ctrl-C and `--timeout` during aggregation. The first SIGINT, or the
+// deadline, asks the workers to stop taking new lines and chunks; what they
+// aggregated so far is merged and printed as partial results, followed by the
+// share of the input they cover. A second SIGINT exits at once, as without the
+// handler. Only strategies that check for the request install it.
+
+use crate::cli::{Implementation, Options};
+use crate::index_map::MapKind;
+use crate::key_hash::KeyHash;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static TIMED_OUT: AtomicBool = AtomicBool::new(false);
+
+// Exit status of a process stopped by SIGINT, by shell convention, and of one
+// that timed out, as `timeout` reports it.
+pub const EXIT_CODE: u8 = 130;
+pub const TIMEOUT_EXIT_CODE: u8 = 124;
+
+// Whether the strategy `options` select stops when interrupted.
+pub fn supported(options: &Options) -> bool {
+    match options.implementation {
+        Implementation::Rayon => {
+            options.key_hash != KeyHash::Fingerprint && options.map != MapKind::Index
+        }
+        Implementation::Scoped | Implementation::PerCore | Implementation::External => true,
+        Implementation::Pipeline
+        | Implementation::TwoPass
+        | Implementation::Std
+        | Implementation::Gpu
+        | Implementation::Cuda => false,
+    }
+}
+
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+pub fn request() {
+    REQUESTED.store(true, Ordering::Relaxed);
+}
+
+pub fn reset() {
+    REQUESTED.store(false, Ordering::Relaxed);
+    TIMED_OUT.store(false, Ordering::Relaxed);
+}
+
+// Requests a stop once `timeout` has passed, for `--timeout`.
+pub fn request_after(timeout: Duration) {
+    thread::spawn(move || {
+        thread::sleep(timeout);
+        TIMED_OUT.store(true, Ordering::Relaxed);
+        request();
+    });
+}
+
+// Whether the stop was requested by `--timeout` rather than Ctrl-C.
+pub fn timed_out() -> bool {
+    TIMED_OUT.load(Ordering::Relaxed)
+}
+
+#[cfg(unix)]
+pub fn install() {
+    extern "C" fn handle(_signal: libc::c_int) {
+        if REQUESTED.swap(true, Ordering::Relaxed) {
+            unsafe { libc::_exit(EXIT_CODE as libc::c_int) };
+        }
+    }
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            handle as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+// Restores the default SIGINT action once aggregation is over.
+#[cfg(unix)]
+pub fn uninstall() {
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn install() {}
+
+#[cfg(not(unix))]
+pub fn uninstall() {}
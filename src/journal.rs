@@ -0,0 +1,149 @@
+// The `--resume` journal of remote runs: one JSON line per aggregated range
+// with its stations and a checksum of its bytes, after a header naming the
+// input. A run interrupted part-way can then skip the ranges already in the
+// journal instead of fetching the whole input again.
+
+use crate::key_hash::StationMap;
+use crate::StationData;
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// The input a journal belongs to; resuming with a different one is refused.
+#[derive(Serialize, Deserialize, PartialEq)]
+pub struct Header {
+    pub url: String,
+    pub len: u64,
+    pub range_size: u64,
+}
+
+#[derive(Serialize)]
+struct EntryRef<'a> {
+    range: u64,
+    checksum: u64,
+    stations: &'a StationMap,
+}
+
+#[derive(Deserialize)]
+struct Entry {
+    range: u64,
+    checksum: u64,
+    stations: HashMap<String, StationData>,
+}
+
+pub struct Journal {
+    path: PathBuf,
+    file: Mutex<File>,
+    // Checksums of the ranges recorded by earlier runs, by range index.
+    done: HashMap<u64, u64>,
+    resumed: StationMap,
+    last: Option<u64>,
+}
+
+impl Journal {
+    // Opens the journal at `path` for `header`, reading the ranges recorded
+    // by an earlier run of the same input, or starts a new one.
+    pub fn open(path: impl AsRef<Path>, header: &Header) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut done = HashMap::new();
+        let mut resumed = StationMap::default();
+        let mut last = None;
+        let mut existing = false;
+        let mut complete = true;
+
+        if let Ok(content) = fs::read_to_string(path) {
+            let mut lines = content.lines();
+            if let Some(line) = lines.next() {
+                let recorded: Header = serde_json::from_str(line).map_err(io::Error::from)?;
+                if recorded != *header {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "journal {} belongs to a different input; delete it to start over",
+                            path.display()
+                        ),
+                    ));
+                }
+                existing = true;
+                complete = content.ends_with('\n');
+            }
+            for line in lines {
+                // A line cut short by the interruption is fetched again.
+                let Ok(entry) = serde_json::from_str::<Entry>(line) else {
+                    continue;
+                };
+                if done.insert(entry.range, entry.checksum).is_none() {
+                    resumed = crate::merge_maps(resumed, entry.stations)
+                        .map_err(|e| io::Error::other(e.to_string()))?;
+                    last = Some(entry.range);
+                }
+            }
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if !existing {
+            file.set_len(0)?;
+            let mut line = serde_json::to_vec(header).map_err(io::Error::from)?;
+            line.push(b'\n');
+            file.write_all(&line)?;
+        } else if !complete {
+            file.write_all(b"\n")?;
+        }
+        Ok(Journal {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+            done,
+            resumed,
+            last,
+        })
+    }
+
+    // Whether an earlier run already aggregated range `range`.
+    pub fn is_done(&self, range: u64) -> bool {
+        self.done.contains_key(&range)
+    }
+
+    // The last range recorded by an earlier run and its checksum, to check
+    // that the input has not changed since.
+    pub fn last(&self) -> Option<(u64, u64)> {
+        self.last.map(|range| (range, self.done[&range]))
+    }
+
+    pub fn resumed_ranges(&self) -> usize {
+        self.done.len()
+    }
+
+    // Takes the stations of the ranges recorded by earlier runs.
+    pub fn take_resumed(&mut self) -> StationMap {
+        std::mem::take(&mut self.resumed)
+    }
+
+    // Appends range `range`, whose lines were `data`, with its stations.
+    pub fn record(&self, range: u64, data: &[u8], stations: &StationMap) -> io::Result<()> {
+        let entry = EntryRef {
+            range,
+            checksum: checksum(data),
+            stations,
+        };
+        let mut line = serde_json::to_vec(&entry).map_err(io::Error::from)?;
+        line.push(b'\n');
+        self.file.lock().unwrap().write_all(&line)
+    }
+
+    // Removes the journal once the run it covers has completed.
+    pub fn remove(self) -> io::Result<()> {
+        drop(self.file);
+        fs::remove_file(&self.path)
+    }
+}
+
+// FNV-1a hash of a range's bytes; stable across builds, unlike the standard
+// library's hasher.
+pub fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
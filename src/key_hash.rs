@@ -0,0 +1,183 @@
+// How the per-thread station maps hash and compare station names. `full`
+// (the default) hashes the whole name with the map's usual hasher. `prefix`
+// hashes only the first 8 bytes, loaded as one little-endian word with the
+// bytes past a shorter name masked off, mixed with the name's length. In both
+// modes lookups compare that word and the length before falling back to
+// comparing the whole name, so names that share both are still told apart,
+// but under `prefix` they also share a hash and cost extra probes;
+// `--check-key-hash` fails a run whose stations have any. `fingerprint`
+// drops names from the hot table altogether; see `fingerprint.rs`.
+
+use crate::error::Error;
+use brc::station::StationData;
+use hashbrown::hash_map::DefaultHashBuilder;
+use hashbrown::{Equivalent, HashMap};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyHash {
+    Full,
+    Prefix,
+    Fingerprint,
+}
+
+impl FromStr for KeyHash {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => Ok(KeyHash::Full),
+            "prefix" => Ok(KeyHash::Prefix),
+            "fingerprint" => Ok(KeyHash::Fingerprint),
+            _ => Err(()),
+        }
+    }
+}
+
+pub type StationMap = HashMap<String, StationData, StationHashBuilder>;
+
+// A station map with room for `capacity` stations, hashed as `key_hash`
+// selects.
+pub fn station_map(key_hash: KeyHash, capacity: usize) -> StationMap {
+    StationMap::with_capacity_and_hasher(capacity, StationHashBuilder::new(key_hash))
+}
+
+#[derive(Clone, Default)]
+pub struct StationHashBuilder {
+    prefix: bool,
+    full: DefaultHashBuilder,
+}
+
+impl StationHashBuilder {
+    pub fn new(key_hash: KeyHash) -> Self {
+        StationHashBuilder {
+            prefix: key_hash == KeyHash::Prefix,
+            full: DefaultHashBuilder::default(),
+        }
+    }
+}
+
+impl BuildHasher for StationHashBuilder {
+    type Hasher = StationHasher;
+
+    #[inline]
+    fn build_hasher(&self) -> StationHasher {
+        if self.prefix {
+            StationHasher::Prefix(0)
+        } else {
+            StationHasher::Full(self.full.build_hasher())
+        }
+    }
+}
+
+pub enum StationHasher {
+    Full(<DefaultHashBuilder as BuildHasher>::Hasher),
+    Prefix(u64),
+}
+
+impl Hasher for StationHasher {
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        match self {
+            StationHasher::Full(hasher) => hasher.write(bytes),
+            StationHasher::Prefix(hash) => *hash = prefix_hash(bytes),
+        }
+    }
+
+    // Strings end their hash with a `0xff` byte written on its own, which
+    // tells a prefix hash nothing.
+    #[inline]
+    fn write_u8(&mut self, byte: u8) {
+        if let StationHasher::Full(hasher) = self {
+            hasher.write_u8(byte);
+        }
+    }
+
+    #[inline]
+    fn finish(&self) -> u64 {
+        match self {
+            StationHasher::Full(hasher) => hasher.finish(),
+            StationHasher::Prefix(hash) => *hash,
+        }
+    }
+}
+
+// A station name to look up in a `StationMap`, comparing the first word and
+// the length before the whole name.
+pub struct StationKey<'a>(pub &'a str);
+
+impl Hash for StationKey<'_> {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+impl Equivalent<String> for StationKey<'_> {
+    #[inline]
+    fn equivalent(&self, key: &String) -> bool {
+        same_name(self.0.as_bytes(), key.as_bytes())
+    }
+}
+
+impl Equivalent<&str> for StationKey<'_> {
+    #[inline]
+    fn equivalent(&self, key: &&str) -> bool {
+        same_name(self.0.as_bytes(), key.as_bytes())
+    }
+}
+
+#[inline]
+fn same_name(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && prefix_word(a) == prefix_word(b) && a == b
+}
+
+// The first 8 bytes of `bytes` as a little-endian word, zero past its end.
+#[inline]
+fn prefix_word(bytes: &[u8]) -> u64 {
+    match bytes.first_chunk::<8>() {
+        Some(word) => u64::from_le_bytes(*word),
+        None => {
+            let mut word = [0; 8];
+            word[..bytes.len()].copy_from_slice(bytes);
+            u64::from_le_bytes(word)
+        }
+    }
+}
+
+// Mixes the prefix word and length with a folded multiply, so both the high
+// bits the map uses for tags and the low bits it uses for slots vary.
+#[inline]
+fn prefix_hash(bytes: &[u8]) -> u64 {
+    let product = ((prefix_word(bytes) ^ (bytes.len() as u64).rotate_right(8)) as u128)
+        * 0x9e37_79b9_7f4a_7c15_u128;
+    (product as u64) ^ (product >> 64) as u64
+}
+
+// Fails if any two of `names` share their prefix word and length, listing a
+// few such pairs.
+pub fn check<'a>(names: impl Iterator<Item = &'a String>) -> Result<(), Error> {
+    let mut seen: HashMap<(usize, u64), &str> = HashMap::new();
+    let mut collisions = Vec::new();
+    for name in names {
+        let bytes = name.as_bytes();
+        if let Some(other) = seen.insert((bytes.len(), prefix_word(bytes)), name) {
+            collisions.push((other, name.as_str()));
+        }
+    }
+    if collisions.is_empty() {
+        return Ok(());
+    }
+    collisions.sort_unstable();
+    let examples: Vec<_> = collisions
+        .iter()
+        .take(5)
+        .map(|(a, b)| format!("`{}` and `{}`", a, b))
+        .collect();
+    Err(Error::Validation(format!(
+        "stations sharing their first 8 bytes and length with another: {}, e.g. {}",
+        collisions.len(),
+        examples.join(", ")
+    )))
+}
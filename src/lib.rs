@@ -0,0 +1,12 @@
+pub mod aggregator;
+pub mod chunks;
+pub mod mapped;
+pub mod parse;
+pub mod records;
+pub mod results;
+pub mod rounding;
+pub mod state;
+pub mod station;
+pub mod sum;
+pub mod text;
+pub mod window;
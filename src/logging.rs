@@ -0,0 +1,56 @@
+// `--log-format`: how the diagnostics brc writes to stderr are formatted.
+// `text` (default) writes each as a line for people; `json` writes one object
+// per line and event, for systemd and Kubernetes log pipelines, with the time,
+// the event's name, the text line as `message` and the event's values as
+// fields, durations in seconds. Results and reports on stdout are unchanged.
+
+use crate::history;
+use serde_json::{Map, Value};
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn init(format: LogFormat) {
+    JSON.store(format == LogFormat::Json, Ordering::Relaxed);
+}
+
+// Writes `event` to stderr: `message` in text, or as rendered in JSON.
+pub fn event(event: &str, message: impl Display, fields: Value) {
+    if JSON.load(Ordering::Relaxed) {
+        eprintln!("{}", render(event, message, fields));
+    } else {
+        eprintln!("{}", message);
+    }
+}
+
+// The JSON object of `event`, with the fields of `fields`, a JSON object.
+pub fn render(event: &str, message: impl Display, fields: Value) -> String {
+    let mut object = Map::new();
+    object.insert("timestamp".to_string(), history::timestamp().into());
+    object.insert("event".to_string(), event.into());
+    object.insert("message".to_string(), message.to_string().into());
+    if let Value::Object(fields) = fields {
+        object.extend(fields);
+    }
+    Value::Object(object).to_string()
+}
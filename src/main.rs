@@ -1,133 +1,1292 @@
+mod aliases;
+mod baked;
+mod bench;
+mod cache;
+mod cgroup;
+mod chart;
+mod cli;
+mod clickhouse;
+mod collate;
+mod compare;
+mod config;
+mod count;
+mod counters;
+mod cuda;
+mod delimiter;
+mod energy;
+mod error;
+mod error_report;
+mod exact;
+mod extra_stats;
+mod filter;
+mod fingerprint;
+mod generate;
+mod gpu;
+mod history;
+mod hll;
+mod hot_path;
+mod index_map;
+mod interrupt;
+mod journal;
+mod key_hash;
+mod logging;
+mod memory_limit;
+mod normalize;
+mod output;
+mod per_core;
+mod perfect_hash;
+mod pipeline;
+mod postgres;
+mod proto;
+mod provenance;
+mod remote;
+mod reproducible;
+mod result_cache;
+mod sample;
+mod schedule;
+mod scoped;
+mod self_check;
+mod selftest;
+mod spill;
+mod sqlite;
+mod status;
+mod std_only;
+mod system;
+mod thread_stats;
+mod topology;
+mod two_pass;
+mod utf8;
+
+use brc::chunks::{self, Chunk};
+use brc::mapped;
+use brc::parse::{parse_grouped, parse_line_in, parse_windowed, recover_line, LineError, Recovery};
+use brc::state;
+use brc::station::{Overflow, StationData};
+use brc::text;
+use cache::Cache;
+use cli::{
+    BenchOptions, Command, CompareOptions, GenerateOptions, Implementation, Options, Recover,
+    Schedule,
+};
+use collate::Collation;
+use energy::Meter;
+use error::Error;
+use error_report::SkippedLine;
+use extra_stats::Histogram;
 use hashbrown::HashMap;
-use memmap::MmapOptions;
+use index_map::MapKind;
+use key_hash::{KeyHash, StationKey, StationMap};
+use memmap::{Mmap, MmapOptions};
+use output::{Format, StationWriter};
+use provenance::{Ends, Extremes};
 use rayon::prelude::*;
-use std::fs::File;
-use std::io;
-use std::str::FromStr;
-use std::time::Instant;
+use result_cache::Lookup;
+use serde_json::json;
+use spill::Spill;
+use std::borrow::Cow;
+use std::env;
+use std::fs::{self, File};
+use std::hint::black_box;
+use std::io::{self, BufWriter, Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use thread_stats::ThreadStats;
+use topology::Topology;
+
+// Expected number of distinct stations, used to size the maps up front.
+const ESTIMATED_UNIQUE_STATIONS: usize = 10000;
+
+// Default size of the chunks aggregated into separate runs by the external
+// strategy.
+const EXTERNAL_CHUNK_SIZE: usize = 32 * 1024 * 1024;
 
-#[derive(Clone)]
-struct StationData {
-    min_temp: f32,
-    max_temp: f32,
-    total_temp: f32,
-    count: i32,
+fn main() -> ExitCode {
+    match try_main() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            logging::event(
+                "error",
+                format_args!("error: {}", e),
+                json!({ "exit_code": e.exit_code() }),
+            );
+            ExitCode::from(e.exit_code())
+        }
+    }
 }
 
-impl StationData {
-    fn new() -> Self {
-        StationData {
-            min_temp: f32::MAX,
-            max_temp: f32::MIN,
-            total_temp: 0.0,
-            count: 0,
+fn try_main() -> Result<(), Error> {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let command = Command::take(&mut args);
+    if command == Command::Generate {
+        return generate::run(&GenerateOptions::parse(args.into_iter())?);
+    }
+    if command == Command::Compare {
+        return compare::run(&CompareOptions::parse(args.into_iter())?);
+    }
+    if command == Command::Bench {
+        return bench::run(&BenchOptions::parse(args.into_iter())?);
+    }
+    let args: Vec<String> = config::default_args()?.into_iter().chain(args).collect();
+    let mut options = Options::parse(args.iter().cloned())?;
+    logging::init(options.log_format);
+    let topology = Topology::detect();
+    let threads = options
+        .threads
+        .unwrap_or_else(|| topology.default_threads());
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    if options.verbose {
+        let quota = topology.quota.map_or(String::new(), |quota| {
+            format!(", cgroup quota {:.1} CPUs", quota)
+        });
+        logging::event(
+            "topology",
+            format_args!(
+                "Topology: {} physical cores, {} logical CPUs (SMT {}){}; using {} threads",
+                topology.physical,
+                topology.logical,
+                if topology.smt() { "on" } else { "off" },
+                quota,
+                threads
+            ),
+            json!({
+                "physical_cores": topology.physical,
+                "logical_cpus": topology.logical,
+                "smt": topology.smt(),
+                "cgroup_cpus": topology.quota,
+                "threads": threads,
+            }),
+        );
+    }
+    if options.reproducible {
+        let config = reproducible::describe(&args, &options);
+        let hash = format!("{:016x}", reproducible::hash(&config));
+        logging::event(
+            "config_hash",
+            format_args!("Config hash: {} ({})", hash, config),
+            json!({ "hash": hash, "config": config }),
+        );
+    }
+    if options.counters {
+        counters::check_enabled()?;
+    }
+    if options.implementation == Implementation::Gpu {
+        gpu::check_enabled()?;
+    }
+    if options.implementation == Implementation::Cuda {
+        cuda::check_enabled()?;
+    }
+    if options.collate.is_some() {
+        collate::check_enabled()?;
+    }
+    if options.chart.is_some() {
+        chart::check_enabled()?;
+    }
+    if command == Command::Selftest {
+        return selftest::run(&options);
+    }
+    if let Some(timeout) = options.timeout {
+        interrupt::request_after(timeout);
+    }
+    let _status = options
+        .status_socket
+        .as_deref()
+        .map(status::serve)
+        .transpose()?;
+    if options.cache == Some(Cache::Cold) {
+        cache::evict(&options.path)?;
+    }
+    let mut start = Instant::now();
+
+    // Remote input and stdin are streamed through the pipeline strategy
+    // instead of being mapped.
+    let remote = remote::is_url(&options.path);
+    let streamed = remote || options.reads_stdin();
+    if streamed {
+        options.implementation = Implementation::Pipeline;
+    }
+    let full = !(options.count_only
+        || options.hot_path_stats
+        || options.sample.is_some()
+        || options.exact);
+    // `--impl std` reads the file with plain `File` reads, so it is not
+    // mapped either and works where mapping does not.
+    let unmapped = streamed || (full && options.implementation == Implementation::Std);
+    let mmap = if unmapped {
+        None
+    } else {
+        Some(map_input(&options.path)?)
+    };
+    let input = mmap.as_deref().unwrap_or_default();
+    if options.sniff_delimiter {
+        let prefix = if remote {
+            Cow::Owned(remote::prefix(
+                &options.path,
+                delimiter::SNIFF_BYTES,
+                &options,
+            )?)
+        } else if unmapped {
+            let mut prefix = Vec::new();
+            File::open(&options.path)?
+                .take(delimiter::SNIFF_BYTES as u64)
+                .read_to_end(&mut prefix)?;
+            Cow::Owned(prefix)
+        } else {
+            Cow::Borrowed(input)
+        };
+        options.delimiter = delimiter::detect(&prefix, options.notation.decimal);
+        let name = delimiter::name(options.delimiter);
+        logging::event(
+            "delimiter",
+            format_args!("Delimiter: {}", name),
+            json!({ "delimiter": name }),
+        );
+    }
+    if let Some(path) = &options.aliases {
+        let aliases = aliases::load(path, options.delimiter, options.key_hash)?;
+        if options.verbose {
+            logging::event(
+                "aliases",
+                format_args!("Aliases: {}", aliases.len()),
+                json!({ "aliases": aliases.len() }),
+            );
+        }
+        options.alias_map = Some(aliases);
+    }
+    if options.include_file.is_some() || options.exclude_file.is_some() {
+        let filter = filter::load(&options)?;
+        if options.verbose {
+            let (include, exclude) = filter.len();
+            logging::event(
+                "station_filter",
+                format_args!(
+                    "Station filter: {} included, {} excluded",
+                    include.map_or("all".to_string(), |len| len.to_string()),
+                    exclude
+                ),
+                json!({ "included": include, "excluded": exclude }),
+            );
+        }
+        options.station_filter = Some(filter);
+    }
+    if options.mlock {
+        cache::lock(input)?;
+        start = Instant::now();
+    }
+    if options.prefault {
+        let prefault_start = Instant::now();
+        cache::prefault(input);
+        let elapsed = prefault_start.elapsed();
+        logging::event(
+            "prefault",
+            format_args!("Prefault took: {:?}", elapsed),
+            json!({ "seconds": elapsed.as_secs_f64() }),
+        );
+        start = Instant::now();
+    } else if options.cache == Some(Cache::Warm) {
+        cache::prefault(input);
+        start = Instant::now();
+    }
+
+    // The pipeline and std strategies read and decode the file themselves, so
+    // the mapping is left untouched for them.
+    let reads_itself = matches!(
+        options.implementation,
+        Implementation::Pipeline | Implementation::Std
+    ) && !options.count_only
+        && !options.hot_path_stats
+        && options.sample.is_none()
+        && !options.exact;
+    let cache_entry = if result_cache::applies(&options) {
+        result_cache::entry(&options, input)?
+    } else {
+        None
+    };
+    let cached = match &cache_entry {
+        Some(entry) => result_cache::load(entry, input),
+        None => Lookup::Miss,
+    };
+    if !unmapped && !matches!(cached, Lookup::Hit(_)) {
+        options.map_capacity =
+            hll::map_capacity(input, options.delimiter, rayon::current_num_threads());
+        if options.verbose {
+            logging::event(
+                "map_capacity",
+                format_args!("Station maps sized for {} stations", options.map_capacity),
+                json!({ "stations": options.map_capacity }),
+            );
+        }
+    }
+
+    let content = match cached {
+        Lookup::Hit(_) => Cow::Borrowed(""),
+        Lookup::Appended { from, .. } => {
+            utf8::decode(&input[from..], options.utf8, options.delimiter)?
+        }
+        Lookup::Miss if reads_itself => Cow::Borrowed(""),
+        Lookup::Miss => utf8::decode(input, options.utf8, options.delimiter)?,
+    };
+    if full && !streamed && !matches!(cached, Lookup::Hit(_)) {
+        if let Some(limit) = memory_limit::limit(&options) {
+            // `--impl std` reads the whole file into memory.
+            let copied = match (&content, options.implementation) {
+                (Cow::Owned(copy), _) => copy.len(),
+                (_, Implementation::Std) => fs::metadata(&options.path)?.len() as usize,
+                _ => 0,
+            };
+            memory_limit::adapt(&mut options, content.len().max(copied), copied, limit)?;
         }
     }
+    let content = content.as_ref();
+    let setup = start.elapsed();
 
-    fn update(&mut self, temp: f32) {
-        self.min_temp = f32::min(self.min_temp, temp);
-        self.max_temp = f32::max(self.max_temp, temp);
-        self.total_temp += temp;
-        self.count += 1;
+    if options.count_only {
+        count::run(content, options.delimiter, options.notation);
+    } else if options.hot_path_stats {
+        hot_path::run(content, options.delimiter);
+    } else if let Some(fraction) = options.sample {
+        let collation = options.collate.as_deref().map(Collation::new).transpose()?;
+        sample::run(
+            content,
+            fraction,
+            collation.as_ref(),
+            options.delimiter,
+            options.notation,
+            options.compensated,
+        );
+    } else if options.exact {
+        exact::run(content, &options)?;
+    } else {
+        let (aggregation, output) = run(content, input, &options, cached, cache_entry.as_ref())?;
+        if options.phase_times {
+            // In seconds, for `bench --compare-schedulers` to parse.
+            let (setup, aggregation, output) = (
+                setup.as_secs_f64(),
+                aggregation.as_secs_f64(),
+                output.as_secs_f64(),
+            );
+            logging::event(
+                "phase_times",
+                format_args!(
+                    "Phase times (s): setup {:.6}, aggregate {:.6}, output {:.6}",
+                    setup, aggregation, output
+                ),
+                json!({
+                    "setup_seconds": setup,
+                    "aggregate_seconds": aggregation,
+                    "output_seconds": output,
+                }),
+            );
+        }
     }
 
-    fn aggregate(&mut self, other: &StationData) {
-        self.min_temp = f32::min(self.min_temp, other.min_temp);
-        self.max_temp = f32::max(self.max_temp, other.max_temp);
-        self.total_temp += other.total_temp;
-        self.count += other.count;
+    // Report time taken for processing.
+    let duration = start.elapsed();
+    match options.cache {
+        Some(cache) => report(
+            &options,
+            format_args!("Time elapsed is: {:?} ({})", duration, cache.label()),
+        ),
+        None => report(&options, format_args!("Time elapsed is: {:?}", duration)),
     }
+
+    Ok(())
 }
 
-fn main() -> io::Result<()> {
-    let start = Instant::now();
+// Load and map the file into memory for fast access.
+fn map_input(path: impl AsRef<Path>) -> Result<Mmap, Error> {
+    let file = File::open(path)?;
+    Ok(unsafe { MmapOptions::new().map(&file)? })
+}
 
-    // Load and map the file into memory for fast access.
-    let file = File::open("C:\\BRC\\1brc\\measurements.txt")?;
-    let mmap = unsafe { MmapOptions::new().map(&file)? };
-    let content = unsafe { std::str::from_utf8_unchecked(&mmap) };
+// Per-thread state while folding over the input.
+struct Partial<'a> {
+    stations: StationMap,
+    skipped: Vec<SkippedLine<'a>>,
+    // Estimated memory held by `stations`, and runs spilled to disk so far.
+    memory: usize,
+    runs: Vec<PathBuf>,
+    // Lines and bytes folded in, for `--thread-stats`, and those of them
+    // counted in the `--status-socket` progress.
+    rows: u64,
+    bytes: u64,
+    reported: (u64, u64),
+    // Lines recovered by taking the last field as the temperature, and lines
+    // skipped for a missing temperature, under `--recover`.
+    last_field: u64,
+    missing_value: u64,
+    // Where each station's extreme readings are, with `--provenance`, and
+    // its first and last readings, with `--first-last`.
+    extremes: HashMap<String, Extremes>,
+    ends: HashMap<String, Ends>,
+    // Histograms of each station's readings, with `--stats`.
+    histograms: HashMap<String, Histogram>,
+}
 
-    // Process data in parallel using Rayon.
-    let estimated_unique_stations = 10000;
-    let aggregated_results: HashMap<String, StationData> = content
-        .par_lines()
-        .fold(
-            || HashMap::with_capacity(estimated_unique_stations),
-            process_line,
-        )
-        .reduce(HashMap::new, |mut acc, h| {
-            for (station, data) in h {
-                acc.entry(station)
-                    .and_modify(|e| e.aggregate(&data))
-                    .or_insert(data);
+impl<'a> Partial<'a> {
+    // A partial result whose station map starts with room for
+    // `options.map_capacity` stations and hashes them as `--key-hash` selects.
+    fn new(options: &Options) -> Self {
+        Partial {
+            stations: key_hash::station_map(options.key_hash, options.map_capacity),
+            skipped: Vec::new(),
+            memory: 0,
+            runs: Vec::new(),
+            rows: 0,
+            bytes: 0,
+            reported: (0, 0),
+            last_field: 0,
+            missing_value: 0,
+            extremes: HashMap::new(),
+            ends: HashMap::new(),
+            histograms: HashMap::new(),
+        }
+    }
+
+    fn empty() -> Self {
+        Partial {
+            stations: StationMap::default(),
+            skipped: Vec::new(),
+            memory: 0,
+            runs: Vec::new(),
+            rows: 0,
+            bytes: 0,
+            reported: (0, 0),
+            last_field: 0,
+            missing_value: 0,
+            extremes: HashMap::new(),
+            ends: HashMap::new(),
+            histograms: HashMap::new(),
+        }
+    }
+
+    fn merge(mut self, other: Partial<'a>) -> Result<Self, Error> {
+        self.stations = merge_maps(self.stations, other.stations)?;
+        self.skipped.extend(other.skipped);
+        self.memory += other.memory;
+        self.runs.extend(other.runs);
+        self.rows += other.rows;
+        self.bytes += other.bytes;
+        self.reported.0 += other.reported.0;
+        self.reported.1 += other.reported.1;
+        self.last_field += other.last_field;
+        self.missing_value += other.missing_value;
+        self.extremes = provenance::merge_maps(self.extremes, other.extremes, Extremes::merge);
+        self.ends = provenance::merge_maps(self.ends, other.ends, Ends::merge);
+        self.histograms =
+            provenance::merge_maps(self.histograms, other.histograms, Histogram::merge);
+        Ok(self)
+    }
+
+    // Records a reading of `station` at byte `offset` for `--provenance`,
+    // `--first-last` and `--stats`.
+    fn track_reading(&mut self, station: &str, temp: f32, offset: usize, options: &Options) {
+        if options.provenance {
+            match self.extremes.get_mut(station) {
+                Some(extremes) => extremes.update(temp, offset),
+                None => {
+                    self.extremes
+                        .insert(station.to_string(), Extremes::new(temp, offset));
+                }
             }
-            acc
-        });
+        }
+        if options.first_last {
+            match self.ends.get_mut(station) {
+                Some(ends) => ends.update(temp, offset),
+                None => {
+                    self.ends
+                        .insert(station.to_string(), Ends::new(temp, offset));
+                }
+            }
+        }
+        if !options.stats.is_empty() {
+            match self.histograms.get_mut(station) {
+                Some(histogram) => histogram.update(temp),
+                None => {
+                    let mut histogram = Histogram::new();
+                    histogram.update(temp);
+                    self.histograms.insert(station.to_string(), histogram);
+                }
+            }
+        }
+    }
+
+    // Counts the lines and bytes folded since the last report in the
+    // `--status-socket` progress, once they reach `status::STRIDE` bytes.
+    fn report_progress(&mut self) {
+        let (bytes, rows) = self.reported;
+        if self.bytes - bytes >= status::STRIDE {
+            status::advance(self.bytes - bytes, self.rows - rows);
+            self.reported = (self.bytes, self.rows);
+        }
+    }
 
-    // Format results for output.
-    let mut formatted_results: Vec<_> = aggregated_results
-        .into_iter()
-        .map(|(name, data)| {
-            let mean = data.total_temp / data.count as f32;
-            (
-                name,
-                format!(
-                    "{:.1}/{:.1}/{:.1}",
-                    data.min_temp,
-                    mean,
-                    data.max_temp
+    // Spills the station map to a sorted run once it exceeds the budget.
+    fn enforce_budget(&mut self, spill: Option<&Spill>) -> Result<(), Error> {
+        if let Some(spill) = spill {
+            if self.memory > spill.budget {
+                self.runs.push(spill.write_run(&mut self.stations)?);
+                self.memory = 0;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Aggregates every line of the input, on top of a previously saved state if
+// requested, and prints the results. Malformed lines abort the run unless
+// lenient mode is enabled, in which case they are skipped. Stations `cached`
+// by an earlier run replace the aggregation, or only the appended `content`
+// is aggregated on top of them, and the resulting stations are cached at
+// `cache_entry`. `input` is the mapped file, in which `--provenance` and
+// `--first-last` number lines. Returns the time spent aggregating and
+// printing the results.
+fn run(
+    content: &str,
+    input: &[u8],
+    options: &Options,
+    cached: Lookup,
+    cache_entry: Option<&result_cache::Entry>,
+) -> Result<(Duration, Duration), Error> {
+    let meter = options.energy.then(Meter::open).transpose()?;
+    let energy_start = meter.as_ref().map(Meter::read).transpose()?;
+    let start = Instant::now();
+    let spill = new_spill(options)?;
+    let stats = options
+        .thread_stats
+        .then(|| ThreadStats::new(rayon::current_num_threads()));
+    // The pipeline and std strategies read the file instead of `content`.
+    let total = if content.is_empty() {
+        input.len()
+    } else {
+        content.len()
+    };
+    status::begin(total as u64);
+    let interruptible = interrupt::supported(options) && !matches!(cached, Lookup::Hit(_));
+    if interruptible {
+        interrupt::install();
+    }
+    let mut partial = match cached {
+        Lookup::Hit(stations) => {
+            logging::event(
+                "cache_hit",
+                "Using cached results; pass `--no-cache` to recompute",
+                json!({}),
+            );
+            let mut partial = Partial::empty();
+            partial.memory = stations
+                .keys()
+                .map(|station| spill::entry_size(station))
+                .sum();
+            partial.stations.extend(stations);
+            partial.enforce_budget(spill.as_ref())?;
+            partial
+        }
+        Lookup::Appended { stations, from } => {
+            logging::event(
+                "cache_append",
+                format_args!(
+                    "Aggregating {} bytes appended since the cached run at byte {}",
+                    content.len(),
+                    from
                 ),
+                json!({ "bytes": content.len(), "from": from }),
+            );
+            // The pipeline and std strategies would read the whole file again.
+            let tail_options = Options {
+                implementation: match options.implementation {
+                    Implementation::Pipeline | Implementation::Std => Implementation::Rayon,
+                    implementation => implementation,
+                },
+                ..options.clone()
+            };
+            let mut partial = aggregate(content, &tail_options, spill.as_ref(), stats.as_ref())?;
+            partial.memory += stations
+                .keys()
+                .map(|station| spill::entry_size(station))
+                .sum::<usize>();
+            partial.stations = merge_maps(partial.stations, stations)?;
+            partial.enforce_budget(spill.as_ref())?;
+            if let Some(entry) = cache_entry.filter(|_| is_complete(&partial)) {
+                result_cache::store(entry, &partial.stations);
+            }
+            partial
+        }
+        Lookup::Miss => {
+            let partial = aggregate(content, options, spill.as_ref(), stats.as_ref())?;
+            if let Some(entry) = cache_entry.filter(|_| is_complete(&partial)) {
+                result_cache::store(entry, &partial.stations);
+            }
+            partial
+        }
+    };
+    if interruptible {
+        interrupt::uninstall();
+    }
+    status::finish(partial.bytes, partial.rows);
+    let interrupted = interrupt::requested();
+    if let Some(path) = &options.load_state {
+        let saved = state::load_state(path)?;
+        partial.memory += saved
+            .keys()
+            .map(|station| spill::entry_size(station))
+            .sum::<usize>();
+        partial.stations = merge_maps(partial.stations, saved)?;
+        partial.enforce_budget(spill.as_ref())?;
+    }
+    if options.check_key_hash {
+        key_hash::check(partial.stations.keys())?;
+    }
+    if options.self_check && !interrupted {
+        let (checked, chunks) = self_check::check(input, &partial.stations, options)?;
+        logging::event(
+            "self_check",
+            format_args!(
+                "Self-check passed: {} of {} chunks match the results",
+                checked, chunks
+            ),
+            json!({ "checked": checked, "chunks": chunks }),
+        );
+    }
+    let extremes = mem::take(&mut partial.extremes);
+    let ends = mem::take(&mut partial.ends);
+    let aggregation = start.elapsed();
+    let energy = match (&meter, &energy_start) {
+        (Some(meter), Some(energy_start)) => Some(meter.joules(energy_start, &meter.read()?)),
+        _ => None,
+    };
+    let (rows, bytes) = (partial.rows, partial.bytes);
+    let start = Instant::now();
+    print_results(partial, spill.as_ref(), options)?;
+    let output = start.elapsed();
+    if options.provenance {
+        provenance::print_extremes(&extremes, input, options);
+    }
+    if options.first_last {
+        provenance::print_ends(&ends, input, options);
+    }
+    if let Some(stats) = &stats {
+        stats.print(options);
+    }
+    if options.counters {
+        counters::print(options);
+    }
+    if let Some(joules) = energy {
+        print_energy(joules, aggregation, rows);
+    }
+    if interrupted {
+        let coverage = format!(
+            "the results cover {} of {} bytes ({:.1}%) of the input",
+            bytes,
+            content.len(),
+            bytes as f64 / content.len().max(1) as f64 * 100.0
+        );
+        return Err(if interrupt::timed_out() {
+            Error::TimedOut(coverage)
+        } else {
+            Error::Interrupted(coverage)
+        });
+    }
+    Ok((aggregation, output))
+}
+
+// Whether `partial` can be cached: neither partly spilled to runs nor cut
+// short by Ctrl-C.
+fn is_complete(partial: &Partial) -> bool {
+    partial.runs.is_empty() && !interrupt::requested()
+}
+
+// Reports the package energy of the aggregation, its average power, and the
+// energy per billion rows when rows were aggregated rather than cached.
+fn print_energy(joules: f64, aggregation: Duration, rows: u64) {
+    let watts = joules / aggregation.as_secs_f64().max(f64::MIN_POSITIVE);
+    let per_billion = (rows > 0).then(|| joules / rows as f64 * 1e9);
+    let fields = json!({
+        "joules": joules,
+        "aggregate_seconds": aggregation.as_secs_f64(),
+        "watts": watts,
+        "joules_per_billion_rows": per_billion,
+    });
+    match per_billion {
+        None => logging::event(
+            "energy",
+            format_args!(
+                "Energy: {:.2} J over {:?} of aggregation ({:.1} W)",
+                joules, aggregation, watts
+            ),
+            fields,
+        ),
+        Some(per_billion) => logging::event(
+            "energy",
+            format_args!(
+                "Energy: {:.2} J over {:?} of aggregation ({:.1} W), {:.1} J per billion rows",
+                joules, aggregation, watts, per_billion
+            ),
+            fields,
+        ),
+    }
+}
+
+// Creates the spill directory needed by the selected strategy, if any.
+fn new_spill(options: &Options) -> Result<Option<Spill>, Error> {
+    let spill = match (options.implementation, options.max_memory) {
+        (Implementation::External, _) => Some(Spill::new(usize::MAX)?),
+        // With a memory budget, each concurrently built map gets an equal
+        // share.
+        (_, Some(budget)) => Some(Spill::new(budget / rayon::current_num_threads())?),
+        (_, None) => None,
+    };
+    Ok(spill)
+}
+
+// Aggregates the input with the selected strategy. Stations may be partly
+// spilled to runs in `spill`, and per-worker work is recorded in `stats`.
+fn aggregate<'a>(
+    content: &'a str,
+    options: &Options,
+    spill: Option<&Spill>,
+    stats: Option<&ThreadStats>,
+) -> Result<Partial<'a>, Error> {
+    match (options.implementation, spill) {
+        (Implementation::External, Some(spill)) => {
+            aggregate_external(content, options, spill, stats)
+        }
+        (Implementation::External, None) => unreachable!("external strategy without spill"),
+        (Implementation::Scoped, spill) => scoped::aggregate(content, options, spill, stats),
+        (Implementation::PerCore, spill) => per_core::aggregate(content, options, spill, stats),
+        (Implementation::TwoPass, _) => two_pass::aggregate(content, options, stats),
+        (Implementation::Pipeline, spill) if remote::is_url(&options.path) => {
+            remote::aggregate(&options.path, options, spill, stats)
+        }
+        (Implementation::Pipeline, spill) if options.reads_stdin() => {
+            pipeline::aggregate_reader(io::stdin(), options, spill, stats)
+        }
+        (Implementation::Pipeline, spill) => {
+            pipeline::aggregate(Path::new(&options.path), options, spill, stats)
+        }
+        (Implementation::Std, _) => std_only::aggregate(Path::new(&options.path), options, stats),
+        (Implementation::Gpu, _) => gpu::aggregate(content, options, stats),
+        (Implementation::Cuda, _) => cuda::aggregate(content, options, stats),
+        (Implementation::Rayon, spill) => match options.schedule {
+            Schedule::Adaptive if options.key_hash == KeyHash::Fingerprint => {
+                fingerprint::aggregate(content, options, stats)
+            }
+            Schedule::Adaptive if options.map == MapKind::Index => {
+                index_map::aggregate(content, options, stats)
+            }
+            Schedule::Adaptive => aggregate_rayon(content, options, spill, stats),
+            Schedule::Dynamic => schedule::dynamic(content, options, spill, stats),
+            Schedule::Static => schedule::fixed(content, options, spill, stats),
+        },
+    }
+}
+
+// Folds over all lines in parallel, spilling maps only under a memory budget.
+fn aggregate_rayon<'a>(
+    content: &'a str,
+    options: &Options,
+    spill: Option<&Spill>,
+    stats: Option<&ThreadStats>,
+) -> Result<Partial<'a>, Error> {
+    // Process data in parallel using Rayon. The partials are boxed, as
+    // `try_fold` moves its accumulator on every line.
+    let whole = Chunk {
+        offset: 0,
+        data: content,
+    };
+    if let Some(stats) = stats {
+        // Each sequential fold is timed and attributed to the thread it ran
+        // on as soon as it finishes.
+        return content
+            .par_lines()
+            .take_any_while(|_| !interrupt::requested())
+            .try_fold(
+                || (Box::new(Partial::new(options)), Instant::now()),
+                |(mut partial, start), line| {
+                    fold_line(&mut partial, whole, line, options, spill)?;
+                    Ok((partial, start))
+                },
             )
+            .map(|folded: Result<_, Error>| {
+                folded.map(|(partial, start)| {
+                    stats.record(ThreadStats::current_worker(), &partial, start.elapsed());
+                    *partial
+                })
+            })
+            .try_reduce(Partial::empty, |a, b| {
+                let mut merged = a.merge(b)?;
+                merged.enforce_budget(spill)?;
+                Ok(merged)
+            });
+    }
+    content
+        .par_lines()
+        .take_any_while(|_| !interrupt::requested())
+        .try_fold(
+            || Box::new(Partial::new(options)),
+            |mut partial, line| {
+                fold_line(&mut partial, whole, line, options, spill)?;
+                Ok(partial)
+            },
+        )
+        .map(|folded: Result<_, Error>| folded.map(|partial| *partial))
+        .try_reduce(Partial::empty, |a, b| {
+            let mut merged = a.merge(b)?;
+            merged.enforce_budget(spill)?;
+            Ok(merged)
+        })
+}
+
+// Aggregates each chunk into its own sorted run on disk for a later merge,
+// so only one chunk's stations per thread are held in memory.
+fn aggregate_external<'a>(
+    content: &'a str,
+    options: &Options,
+    spill: &Spill,
+    stats: Option<&ThreadStats>,
+) -> Result<Partial<'a>, Error> {
+    let chunk_size = options.chunk_size.unwrap_or(EXTERNAL_CHUNK_SIZE);
+    chunks::chunks(content, chunk_size)
+        .into_par_iter()
+        .map(|chunk| -> Result<Partial, Error> {
+            if let Some(readahead) = options.readahead {
+                let end = chunk.offset + chunk.data.len();
+                cache::will_need(content.as_bytes(), end..end.saturating_add(readahead));
+            }
+            let start = Instant::now();
+            let mut partial = aggregate_chunk(chunk, options)?;
+            if let Some(stats) = stats {
+                stats.record(ThreadStats::current_worker(), &partial, start.elapsed());
+            }
+            if !partial.stations.is_empty() {
+                partial.runs.push(spill.write_run(&mut partial.stations)?);
+            }
+            Ok(partial)
         })
-        .collect();
+        .try_reduce(Partial::empty, |a, b| a.merge(b))
+}
 
-    // Concatenate resulting string
-    let mut output_result = String::with_capacity(estimated_unique_stations * 50);
-    output_result.push('{');
-    formatted_results.sort_unstable_by(|a, b| a.0.cmp(&b.0));
-    for (i, (station, result)) in formatted_results.iter().enumerate() {
-        let temp_result = format!("{}{}={}", if i > 0 { ", " } else { "" }, station, result);
-        output_result += &temp_result;
+// Aggregates a single chunk into a fresh partial result.
+fn aggregate_chunk<'a>(chunk: Chunk<'a>, options: &Options) -> Result<Partial<'a>, Error> {
+    let mut partial = Partial::empty();
+    for line in chunk.data.lines() {
+        if interrupt::requested() {
+            break;
+        }
+        fold_line(&mut partial, chunk, line, options, None)?;
     }
-    output_result.push('}');
-    output_result.push('\n');
+    Ok(partial)
+}
 
-    println!("{}", output_result);
+// Adds one line of `chunk` to `partial`, handling malformed lines per the
+// options.
+fn fold_line<'a>(
+    partial: &mut Partial<'a>,
+    chunk: Chunk<'a>,
+    line: &'a str,
+    options: &Options,
+    spill: Option<&Spill>,
+) -> Result<(), Error> {
+    partial.rows += 1;
+    partial.bytes += line.len() as u64 + 1;
+    partial.report_progress();
+    counters::line();
+    match parse_recovering(partial, line, options) {
+        Ok(None) => {}
+        Ok(Some((station, temp))) => {
+            process_line(partial, &station, temp, detect_overflow(options)).map_err(
+                |overflow| {
+                    let offset = chunk.offset + offset_of(chunk.data, line);
+                    overflow_error(&station, Some(offset), overflow)
+                },
+            )?;
+            if options.tracks_readings() {
+                let offset = chunk.offset + offset_of(chunk.data, line);
+                partial.track_reading(&station, temp, offset, options);
+            }
+            partial.enforce_budget(spill)?;
+        }
+        Err(reason) => {
+            let offset = chunk.offset + offset_of(chunk.data, line);
+            skip_line(partial, offset, reason, Cow::Borrowed(line), options)?;
+        }
+    }
+    Ok(())
+}
 
-    // Report time taken for processing.
-    let duration = start.elapsed();
-    println!("Time elapsed is: {:?}", duration);
+// Parses `line`, applying the recovery rules selected with `--recover` if
+// the parser rejects it, into the key selected with `--group-by` or
+// `--window` and the temperature. Returns `None` for a line skipped by
+// recovery.
+fn parse_recovering<'a>(
+    partial: &mut Partial,
+    line: &'a str,
+    options: &Options,
+) -> Result<Option<(Cow<'a, str>, f32)>, LineError> {
+    if let Some(column) = options.group_by {
+        let (key, temp) = parse_grouped(line, options.delimiter, options.notation, column)?;
+        let key = aliases::resolve_key(options, key);
+        return Ok(filter::admits_key(options, &key).then_some((key, temp)));
+    }
+    if let (Some(width), Some(column)) = (options.window, options.time_col) {
+        let (key, temp) = parse_windowed(line, options.delimiter, options.notation, column, width)?;
+        let key = aliases::resolve_key(options, key);
+        return Ok(filter::admits_key(options, &key).then_some((key, temp)));
+    }
+    // Stations left out by `--include-file` or `--exclude-file` are skipped
+    // like lines without a value.
+    let admitted = |station: &'a str, temp| {
+        filter::admits(options, station)
+            .then(|| (Cow::Borrowed(aliases::resolve(options, station)), temp))
+    };
+    match parse_line_in(line, options.delimiter, options.notation) {
+        Ok((station, temp)) => Ok(admitted(station, temp)),
+        Err(reason) if options.recover == Recover::Off => Err(reason),
+        Err(reason) => match recover_line(line, options.delimiter, options.notation) {
+            Some(Recovery::LastField(station, temp)) => {
+                partial.last_field += 1;
+                Ok(admitted(station, temp))
+            }
+            Some(Recovery::MissingValue) => {
+                partial.missing_value += 1;
+                Ok(None)
+            }
+            None => Err(reason),
+        },
+    }
+}
 
+// Records a malformed line at byte `offset` in lenient mode, or fails.
+fn skip_line<'a>(
+    partial: &mut Partial<'a>,
+    offset: usize,
+    reason: LineError,
+    line: Cow<'a, str>,
+    options: &Options,
+) -> Result<(), Error> {
+    if !options.lenient {
+        return Err(Error::Parse(format!(
+            "malformed line at byte {}: {}",
+            offset,
+            reason.code()
+        )));
+    }
+    partial.skipped.push(SkippedLine {
+        offset,
+        reason,
+        line,
+    });
     Ok(())
 }
 
-// Process a single line of input data.
-fn process_line(mut acc: HashMap<String, StationData>, line: &str) -> HashMap<String, StationData> {
-    let (station, temp_str) = split_once(line, b';');
-    let temp = parse_temperature(temp_str);
+// Prints the aggregated stations, merging any spilled runs, followed by the
+// lenient-mode summary.
+fn print_results(
+    mut partial: Partial,
+    spill: Option<&Spill>,
+    options: &Options,
+) -> Result<(), Error> {
+    let collation = options.collate.as_deref().map(Collation::new).transpose()?;
 
-    acc.entry(station.to_string())
-        .and_modify(|entry| entry.update(temp))
-        .or_insert_with(|| {
-            let mut data = StationData::new();
-            data.update(temp);
-            data
-        });
+    let streamable = matches!(options.format, Format::Text | Format::Jsonl)
+        && collation.is_none()
+        && (options.sort.is_by_name() || options.sort.is_unordered())
+        && options.chart.is_none()
+        && options.save_state.is_none()
+        && options.stats.is_empty()
+        && !normalize::enabled(options)
+        && !options.output.as_deref().is_some_and(postgres::is_url);
+    let stations = match spill {
+        _ if options.discard_output => {
+            discard_stations(&mut partial, spill)?;
+            None
+        }
+        Some(spill) if streamable && !partial.runs.is_empty() => {
+            // Runs are already in output order, so stream stations straight
+            // to the output instead of collecting them.
+            if !partial.stations.is_empty() {
+                partial.runs.push(spill.write_run(&mut partial.stations)?);
+            }
+            let mut writer =
+                StationWriter::new(open_output(options)?, options.format, options.rounding)?;
+            spill.merge(mem::take(&mut partial.runs), |name, data| {
+                writer.write(&name, &data)
+            })?;
+            writer.finish()?;
+            None
+        }
+        _ if streamable && options.sort.is_unordered() => {
+            // Nothing was spilled, so the map holds the final stations.
+            let mut writer =
+                StationWriter::new(open_output(options)?, options.format, options.rounding)?;
+            for (name, data) in partial.stations.drain() {
+                writer.write(&name, &data)?;
+            }
+            writer.finish()?;
+            None
+        }
+        _ => {
+            let unordered = options.sort.is_unordered();
+            let mut stations = if unordered && partial.runs.is_empty() {
+                // Left in the map's order, without the sort by name.
+                partial.stations.drain().collect()
+            } else {
+                collect_stations(&mut partial, spill)?
+            };
+            if let Some(path) = &options.save_state {
+                state::save_state(path, &stations.iter().cloned().collect())?;
+            }
+            // Merged after saving, so resumed runs keep every spelling.
+            if normalize::enabled(options) {
+                stations = normalize::merge(stations, options);
+            }
+            if !unordered && (collation.is_some() || !options.sort.is_by_name()) {
+                output::sort_by(&mut stations, &options.sort, collation.as_ref());
+            }
+            Some(stations)
+        }
+    };
+
+    if let Some(stations) = stations {
+        match (options.format, &options.output) {
+            (_, Some(url)) if postgres::is_url(url) => {
+                postgres::write(url, &options.table, &stations, options.rounding)?
+            }
+            (_, Some(url)) if clickhouse::is_url(url) => {
+                clickhouse::insert(url, &options.table, &stations, options.rounding)?
+            }
+            (Format::Sqlite, Some(path)) => sqlite::write(path, &stations, options.rounding)?,
+            (format, _) => {
+                let mut out = open_output(options)?;
+                match format {
+                    Format::Proto => proto::write(&mut out, &stations, options.rounding)?,
+                    Format::Msgpack => {
+                        output::write_msgpack(&mut out, &stations, options.rounding)?
+                    }
+                    Format::RowBinary => clickhouse::write(&mut out, &stations, options.rounding)?,
+                    Format::Mapped => mapped::write(&mut out, &stations)?,
+                    Format::Nested => output::write_nested(
+                        &mut out,
+                        &stations,
+                        options.delimiter,
+                        options.rounding,
+                    )?,
+                    _ if !options.stats.is_empty() => {
+                        let rendered = output::render_extended(
+                            &stations,
+                            format,
+                            options.rounding,
+                            |station| {
+                                extra_stats::values(
+                                    &options.stats,
+                                    partial.histograms.get(station),
+                                    options.compensated,
+                                )
+                            },
+                        );
+                        match format {
+                            Format::Jsonl => write!(out, "{}", rendered)?,
+                            _ => writeln!(out, "{}", rendered)?,
+                        }
+                    }
+                    Format::Jsonl => write!(
+                        out,
+                        "{}",
+                        output::render(&stations, format, false, options.rounding)
+                    )?,
+                    Format::Text => {
+                        // The whole output, with its trailing blank line, in
+                        // one buffer and one write.
+                        let entries = output::text_entries(&stations);
+                        let mut buf = Vec::with_capacity(text::size_bound(entries.clone()) + 1);
+                        text::write_entries(&mut buf, entries, options.rounding);
+                        buf.push(b'\n');
+                        out.write_all(&buf)?;
+                    }
+                    _ => writeln!(
+                        out,
+                        "{}",
+                        output::render(&stations, format, options.highlight, options.rounding)
+                    )?,
+                }
+                out.flush()?;
+            }
+        }
+        if let Some(path) = &options.chart {
+            chart::draw(path, &stations, options.chart_top)?;
+        }
+    }
+
+    if options.lenient {
+        report(
+            options,
+            format_args!("Skipped lines: {}", partial.skipped.len()),
+        );
+        let skipped_for = |reason| {
+            partial
+                .skipped
+                .iter()
+                .filter(|skipped| skipped.reason == reason)
+                .count()
+        };
+        report(
+            options,
+            format_args!(
+                "Out-of-range temperatures: {}",
+                skipped_for(LineError::OutOfRange)
+            ),
+        );
+        report(
+            options,
+            format_args!(
+                "Overlong station names: {}",
+                skipped_for(LineError::StationTooLong)
+            ),
+        );
+    }
+    if options.recover != Recover::Off {
+        report(
+            options,
+            format_args!("Recovered lines (last field): {}", partial.last_field),
+        );
+        report(
+            options,
+            format_args!("Skipped lines (missing value): {}", partial.missing_value),
+        );
+    }
+    if let Some(path) = &options.error_report {
+        error_report::write(path, partial.skipped)?;
+    }
 
-    acc
+    Ok(())
 }
 
-// Splits a string once based on a given delimiter.
-fn split_once(input: &str, delimiter: u8) -> (&str, &str) {
-    let bytes = input.as_bytes();
-    if let Some(pos) = bytes.iter().position(|&b| b == delimiter) {
-        (&input[..pos], &input[pos + 1..])
+// Finishes the aggregation for `--discard-output` without formatting or
+// writing anything: spilled runs are still merged, and the stations go
+// through `black_box` so the work producing them cannot be optimized away.
+fn discard_stations(partial: &mut Partial, spill: Option<&Spill>) -> Result<(), Error> {
+    if partial.runs.is_empty() {
+        black_box(&partial.stations);
     } else {
-        (input, "")
+        black_box(collect_stations(partial, spill)?);
     }
+    Ok(())
+}
+
+// Opens where results are written: the `--output` file, or stdout.
+fn open_output(options: &Options) -> io::Result<Box<dyn Write>> {
+    Ok(match &options.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    })
+}
+
+// Prints a human-readable report line after the results, on stderr when
+// stdout carries binary results.
+fn report(options: &Options, line: std::fmt::Arguments) {
+    if options.output.is_none() && options.format.is_binary() {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+// Takes all stations out of `partial` in byte order, merging spilled runs.
+fn collect_stations(
+    partial: &mut Partial,
+    spill: Option<&Spill>,
+) -> Result<Vec<(String, StationData)>, Error> {
+    match spill {
+        Some(spill) if !partial.runs.is_empty() => {
+            if !partial.stations.is_empty() {
+                partial.runs.push(spill.write_run(&mut partial.stations)?);
+            }
+            let mut stations = Vec::new();
+            spill.merge(mem::take(&mut partial.runs), |name, data| {
+                stations.push((name, data));
+                Ok(())
+            })?;
+            Ok(stations)
+        }
+        _ => {
+            let mut stations: Vec<_> = partial.stations.drain().collect();
+            output::sort_stations(&mut stations, None);
+            Ok(stations)
+        }
+    }
+}
+
+// Merges the per-thread map `h` into `acc`. Merges are rare enough to always
+// use checked arithmetic.
+fn merge_maps<S>(
+    mut acc: StationMap,
+    h: HashMap<String, StationData, S>,
+) -> Result<StationMap, Error> {
+    for (station, data) in h {
+        match acc.get_mut(&StationKey(&station)) {
+            Some(entry) => entry
+                .checked_aggregate(&data)
+                .map_err(|overflow| overflow_error(&station, None, overflow))?,
+            None => {
+                acc.insert(station, data);
+            }
+        }
+    }
+    Ok(acc)
+}
+
+// Whether per-line updates use checked arithmetic, with `--detect-overflow`
+// or in debug builds.
+fn detect_overflow(options: &Options) -> bool {
+    options.detect_overflow || cfg!(debug_assertions)
+}
+
+// Reports a station whose statistics overflowed, at the byte offset of the
+// offending line if known.
+fn overflow_error(station: &str, offset: Option<usize>, overflow: Overflow) -> Error {
+    Error::ResourceLimit(match offset {
+        Some(offset) => format!(
+            "{} of station `{}` overflowed at byte {}",
+            overflow.code(),
+            station,
+            offset
+        ),
+        None => format!(
+            "{} of station `{}` overflowed while merging",
+            overflow.code(),
+            station
+        ),
+    })
+}
+
+// Process a single parsed record, with checked arithmetic if `checked`.
+fn process_line(
+    partial: &mut Partial,
+    station: &str,
+    temp: f32,
+    checked: bool,
+) -> Result<(), Overflow> {
+    counters::lookup();
+    match partial.stations.get_mut(&StationKey(station)) {
+        Some(entry) if checked => entry.checked_update(temp)?,
+        Some(entry) => entry.update(temp),
+        None => {
+            let mut data = StationData::new();
+            data.update(temp);
+            partial.memory += spill::entry_size(station);
+            let capacity = partial.stations.capacity();
+            partial.stations.insert(station.to_string(), data);
+            counters::insert(capacity, partial.stations.capacity());
+        }
+    }
+    Ok(())
 }
 
-// Parses a temperature value from a string.
-fn parse_temperature(temp_str: &str) -> f32 {
-    f32::from_str(temp_str).unwrap()
+// Byte offset of `line` within `content`, which it must be a slice of.
+fn offset_of(content: &str, line: &str) -> usize {
+    line.as_ptr() as usize - content.as_ptr() as usize
 }
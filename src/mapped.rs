@@ -0,0 +1,171 @@
+// A compact binary results file that is memory-mapped rather than parsed, for
+// consumers that open the same aggregation many times. The layout, all
+// little-endian:
+//
+//   header   magic `BRCM`, version u32, station count u64
+//   entries  one 32-byte entry per station in byte order of the names:
+//            name offset u32, name length u32, min f32, mean f32, max f32,
+//            reserved u32, count u64
+//   names    the UTF-8 names, concatenated; offsets are relative to here
+//
+// Means are not rounded.
+
+use crate::results::Stats;
+use crate::station::StationData;
+use memmap::{Mmap, MmapOptions};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"BRCM";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 16;
+const ENTRY_SIZE: usize = 32;
+
+// Writes `stations` in the mapped layout, sorting them by name.
+pub fn write(out: &mut impl Write, stations: &[(String, StationData)]) -> io::Result<()> {
+    let mut sorted: Vec<_> = stations.iter().collect();
+    sorted.sort_unstable_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    header.extend_from_slice(&(sorted.len() as u64).to_le_bytes());
+    out.write_all(&header)?;
+
+    let mut entries = Vec::with_capacity(sorted.len() * ENTRY_SIZE);
+    let mut offset = 0usize;
+    for (name, data) in &sorted {
+        let stats = Stats::from(data);
+        let name_offset = u32::try_from(offset).map_err(|_| too_large())?;
+        entries.extend_from_slice(&name_offset.to_le_bytes());
+        entries.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        entries.extend_from_slice(&stats.min.to_le_bytes());
+        entries.extend_from_slice(&stats.mean.to_le_bytes());
+        entries.extend_from_slice(&stats.max.to_le_bytes());
+        entries.extend_from_slice(&0u32.to_le_bytes());
+        entries.extend_from_slice(&stats.count.to_le_bytes());
+        offset += name.len();
+    }
+    out.write_all(&entries)?;
+    for (name, _) in &sorted {
+        out.write_all(name.as_bytes())?;
+    }
+    Ok(())
+}
+
+fn too_large() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "station names exceed 4 GiB in total",
+    )
+}
+
+// A mapped results file. Opening it only checks the layout; lookups binary
+// search the mapped entries.
+pub struct MappedResults {
+    mmap: Mmap,
+    len: usize,
+}
+
+impl MappedResults {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let mmap = unsafe { MmapOptions::new().map(&file)? };
+        let invalid = |reason: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("not a mapped results file: {}", reason),
+            )
+        };
+
+        if mmap.len() < HEADER_SIZE || &mmap[..4] != MAGIC {
+            return Err(invalid("bad magic"));
+        }
+        if read_u32(&mmap, 4) != VERSION {
+            return Err(invalid("unsupported version"));
+        }
+        let len = usize::try_from(read_u64(&mmap, 8)).map_err(|_| invalid("bad count"))?;
+        let names_start = len
+            .checked_mul(ENTRY_SIZE)
+            .and_then(|size| size.checked_add(HEADER_SIZE))
+            .filter(|&start| start <= mmap.len())
+            .ok_or_else(|| invalid("truncated entries"))?;
+        let results = MappedResults { mmap, len };
+        // Names are checked once here so lookups can hand out `&str`.
+        let names = &results.mmap[names_start..];
+        for index in 0..len {
+            let (offset, name_len) = results.name_range(index);
+            let name = names
+                .get(offset..offset + name_len)
+                .ok_or_else(|| invalid("truncated names"))?;
+            std::str::from_utf8(name).map_err(|_| invalid("name is not UTF-8"))?;
+        }
+        Ok(results)
+    }
+
+    // Number of distinct stations.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Statistics of `station`, if it appeared in the input.
+    pub fn get(&self, station: &str) -> Option<Stats> {
+        let (mut low, mut high) = (0, self.len);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.name(mid).as_bytes().cmp(station.as_bytes()) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return Some(self.stats(mid)),
+            }
+        }
+        None
+    }
+
+    // Stations with their statistics in byte order of the names.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (&str, Stats)> + '_ {
+        (0..self.len).map(|index| (self.name(index), self.stats(index)))
+    }
+
+    fn entry(&self, index: usize) -> usize {
+        HEADER_SIZE + index * ENTRY_SIZE
+    }
+
+    fn name_range(&self, index: usize) -> (usize, usize) {
+        let entry = self.entry(index);
+        (
+            read_u32(&self.mmap, entry) as usize,
+            read_u32(&self.mmap, entry + 4) as usize,
+        )
+    }
+
+    fn name(&self, index: usize) -> &str {
+        let (offset, len) = self.name_range(index);
+        let start = self.entry(self.len) + offset;
+        // Validated in `open`.
+        unsafe { std::str::from_utf8_unchecked(&self.mmap[start..start + len]) }
+    }
+
+    fn stats(&self, index: usize) -> Stats {
+        let entry = self.entry(index);
+        Stats {
+            min: f32::from_bits(read_u32(&self.mmap, entry + 8)),
+            mean: f32::from_bits(read_u32(&self.mmap, entry + 12)),
+            max: f32::from_bits(read_u32(&self.mmap, entry + 16)),
+            count: read_u64(&self.mmap, entry + 24),
+        }
+    }
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
@@ -0,0 +1,125 @@
+// Fitting a run into a container's memory limit instead of being OOM-killed
+// halfway through it. The limit is the cgroup's, or `--memory-limit`. Before
+// aggregating, the memory a run holds is estimated from the sampled station
+// count: a station table per thread plus the merged one, and any copy of the
+// input. Mapped input pages are not counted, as the kernel can drop them, but
+// are released as they are aggregated when the rest leaves little room for
+// them.
+
+use crate::cgroup;
+use crate::cli::{Implementation, Options, Schedule};
+use crate::error::Error;
+use crate::index_map::MapKind;
+use crate::key_hash::KeyHash;
+use crate::logging;
+use crate::spill;
+use serde_json::json;
+
+// A typical station name's length, for tables not yet built.
+const TYPICAL_NAME_BYTES: usize = 16;
+
+// Memory per station in a table: its entry and name, doubled for the slack of
+// a hash table that grows by doubling.
+const STATION_BYTES: usize = (spill::ENTRY_OVERHEAD + TYPICAL_NAME_BYTES) * 2;
+
+// `--memory-limit`, or the cgroup's limit.
+pub fn limit(options: &Options) -> Option<usize> {
+    options.memory_limit.or_else(|| {
+        cgroup::memory_limit().map(|limit| usize::try_from(limit).unwrap_or(usize::MAX))
+    })
+}
+
+// Adapts `options` to run within `limit` bytes over `input_len` bytes of
+// input, `copied` of which are held in memory rather than mapped. Station
+// tables and copies may take three quarters of the limit, their
+// preallocation one quarter; runs that cannot fit are refused.
+pub fn adapt(
+    options: &mut Options,
+    input_len: usize,
+    copied: usize,
+    limit: usize,
+) -> Result<(), Error> {
+    let threads = rayon::current_num_threads();
+    let budget = limit / 4 * 3;
+    // Spilling strategies bound their own tables.
+    let spills = options.max_memory.is_some() || options.implementation == Implementation::External;
+    let tables = if spills {
+        0
+    } else {
+        (threads + 1) * options.map_capacity * STATION_BYTES
+    };
+    if copied + tables > budget {
+        let mut needs = Vec::new();
+        if copied > 0 {
+            needs.push(format!("{} for a copy of the input", size(copied)));
+        }
+        if tables > 0 {
+            needs.push(format!(
+                "{} for station tables of about {} stations on {} threads",
+                size(tables),
+                options.map_capacity,
+                threads
+            ));
+        }
+        let advice = if tables == 0 {
+            "map valid UTF-8 input instead of copying it"
+        } else {
+            "pass `--max-memory` or `--impl external` to spill stations to disk, or fewer `--threads`"
+        };
+        return Err(Error::ResourceLimit(format!(
+            "the run needs about {}, more than the memory limit of {} allows; {}",
+            needs.join(" and "),
+            size(limit),
+            advice
+        )));
+    }
+
+    let mut capacity = limit / 4 / (threads * STATION_BYTES);
+    if let Some(max_memory) = options.max_memory {
+        capacity = capacity.min(max_memory / threads / STATION_BYTES);
+    }
+    if options.map_capacity > capacity {
+        options.map_capacity = capacity;
+        if options.verbose {
+            logging::event(
+                "map_capacity",
+                format_args!(
+                    "Station maps sized for {} stations to fit the memory limit",
+                    capacity
+                ),
+                json!({ "stations": capacity, "memory_limit_bytes": limit }),
+            );
+        }
+    }
+
+    // The dynamic schedule's chunks can be released one by one, but only
+    // from a file mapping: released pages of a copy would read back as zeros.
+    let windowable = copied == 0
+        && options.implementation == Implementation::Rayon
+        && options.schedule == Schedule::Adaptive
+        && options.key_hash != KeyHash::Fingerprint
+        && options.map != MapKind::Index;
+    if windowable && input_len > budget - tables {
+        options.schedule = Schedule::Dynamic;
+        options.windowed = true;
+        if options.verbose {
+            logging::event(
+                "windowed",
+                format_args!(
+                    "Input of {} exceeds the memory limit of {}: releasing chunks once aggregated",
+                    size(input_len),
+                    size(limit)
+                ),
+                json!({ "input_bytes": input_len, "memory_limit_bytes": limit }),
+            );
+        }
+    }
+    Ok(())
+}
+
+fn size(bytes: usize) -> String {
+    if bytes < 1 << 20 {
+        return format!("{} KiB", bytes.div_ceil(1 << 10));
+    }
+    format!("{:.1} MiB", bytes as f64 / (1 << 20) as f64)
+}
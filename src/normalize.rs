@@ -0,0 +1,77 @@
+// Station name normalization for `--trim` and `--case-insensitive`. Stations
+// are aggregated under their names as read, and spellings that normalize to
+// the same name are merged once aggregation is done, under the smallest of
+// them in byte order. The merged set of spellings does not depend on how the
+// input was split between threads, so neither does the name chosen, and the
+// totals are summed in that same order.
+
+use crate::cli::Options;
+use crate::output::station_order;
+use crate::StationData;
+use brc::parse::split_once;
+use hashbrown::HashMap;
+use std::borrow::Cow;
+
+// Whether `--trim` or `--case-insensitive` is given.
+pub fn enabled(options: &Options) -> bool {
+    options.trim || options.case_insensitive
+}
+
+// The name `station` is merged under by the options given.
+fn normalized<'a>(options: &Options, station: &'a str) -> Cow<'a, str> {
+    let station = if options.trim {
+        station.trim()
+    } else {
+        station
+    };
+    if options.case_insensitive {
+        Cow::Owned(station.to_lowercase())
+    } else {
+        Cow::Borrowed(station)
+    }
+}
+
+// Merges the stations whose names normalize alike, each under its smallest
+// spelling, and returns them in byte order of the names. For `--group-by` and
+// `--window` keys only the station, up to the delimiter, is normalized, and
+// one spelling is chosen per station across all its keys.
+pub fn merge(
+    mut stations: Vec<(String, StationData)>,
+    options: &Options,
+) -> Vec<(String, StationData)> {
+    stations.sort_unstable_by(|a, b| station_order(&a.0, &b.0));
+    let station_of = |key: &str| match split_once(key, options.delimiter) {
+        Some((station, _)) => station.len(),
+        None => key.len(),
+    };
+
+    let mut canonical: HashMap<String, &str> = HashMap::new();
+    for (key, _) in &stations {
+        let station = &key[..station_of(key)];
+        canonical
+            .entry(normalized(options, station).into_owned())
+            .and_modify(|name| {
+                if station_order(station, name).is_lt() {
+                    *name = station;
+                }
+            })
+            .or_insert(station);
+    }
+    let canonical: HashMap<String, String> = canonical
+        .into_iter()
+        .map(|(normalized, name)| (normalized, name.to_string()))
+        .collect();
+
+    let mut merged: HashMap<String, StationData> = HashMap::with_capacity(stations.len());
+    for (key, data) in stations {
+        let (station, rest) = key.split_at(station_of(&key));
+        let name = &canonical[normalized(options, station).as_ref()];
+        merged
+            .entry(format!("{}{}", name, rest))
+            .and_modify(|merged| merged.aggregate(&data))
+            .or_insert(data);
+    }
+    let mut stations: Vec<_> = merged.into_iter().collect();
+    stations.sort_unstable_by(|a, b| station_order(&a.0, &b.0));
+    stations
+}
@@ -0,0 +1,567 @@
+use crate::collate::Collation;
+use crate::{StationData, ESTIMATED_UNIQUE_STATIONS};
+use brc::rounding::{Mean, Rounding};
+use brc::text;
+use hashbrown::HashMap;
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use std::cmp::Ordering;
+use std::fmt::Write;
+use std::io;
+use std::str::FromStr;
+
+// Output format selected with `--format`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    // The challenge's `{name=min/mean/max, ...}` line.
+    Text,
+    Markdown,
+    Html,
+    // One JSON object per station and line, written as soon as each station
+    // is final.
+    Jsonl,
+    // A MessagePack array of the same records as `Jsonl`.
+    Msgpack,
+    // A Protobuf `Results` message as defined in `proto/results.proto`.
+    Proto,
+    // A `station_stats` table in the SQLite database given by `--output`.
+    Sqlite,
+    // ClickHouse RowBinary rows, written to a file or inserted over HTTP.
+    RowBinary,
+    // The memory-mappable layout of `brc::mapped`.
+    Mapped,
+    // One JSON object of stations, each an object of the values of the
+    // `--group-by` field with their statistics.
+    Nested,
+}
+
+impl Format {
+    // Whether results are binary, so human-readable reports must stay off
+    // stdout.
+    pub fn is_binary(self) -> bool {
+        matches!(
+            self,
+            Format::Msgpack | Format::Proto | Format::Sqlite | Format::RowBinary | Format::Mapped
+        )
+    }
+}
+
+impl FromStr for Format {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "markdown" => Ok(Format::Markdown),
+            "html" => Ok(Format::Html),
+            "jsonl" => Ok(Format::Jsonl),
+            "msgpack" => Ok(Format::Msgpack),
+            "proto" => Ok(Format::Proto),
+            "sqlite" => Ok(Format::Sqlite),
+            "rowbinary" => Ok(Format::RowBinary),
+            "mmap" => Ok(Format::Mapped),
+            "nested" => Ok(Format::Nested),
+            _ => Err(()),
+        }
+    }
+}
+
+// Statistic the output can be sorted by, selected with `--sort-by`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    Name,
+    Count,
+    Min,
+    Mean,
+    Max,
+}
+
+impl FromStr for SortKey {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(SortKey::Name),
+            "count" => Ok(SortKey::Count),
+            "min" => Ok(SortKey::Min),
+            "mean" => Ok(SortKey::Mean),
+            "max" => Ok(SortKey::Max),
+            _ => Err(()),
+        }
+    }
+}
+
+// Output order: keys compared in turn until one differs, with the whole order
+// reversed by `desc`.
+#[derive(Clone, PartialEq)]
+pub struct SortOrder {
+    pub keys: Vec<SortKey>,
+    pub desc: bool,
+}
+
+impl SortOrder {
+    // The canonical ascending order by name.
+    pub fn by_name() -> Self {
+        SortOrder {
+            keys: vec![SortKey::Name],
+            desc: false,
+        }
+    }
+
+    pub fn is_by_name(&self) -> bool {
+        *self == SortOrder::by_name()
+    }
+
+    // Whether the output may be in any order, selected with `--sort-by none`
+    // or `--no-sort`.
+    pub fn is_unordered(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+// A station's final statistics as serialized by the machine-readable
+// formats, with the mean rounded to one decimal like the text output, by
+// `rounding` when given.
+#[derive(Serialize)]
+pub struct StationRecord<'a> {
+    pub station: &'a str,
+    pub min: f32,
+    pub mean: f32,
+    pub max: f32,
+    pub count: u64,
+}
+
+impl<'a> StationRecord<'a> {
+    pub fn new(station: &'a str, data: &StationData, rounding: Option<Rounding>) -> Self {
+        StationRecord {
+            station,
+            min: data.min_temp,
+            mean: Mean(data, rounding).value(),
+            max: data.max_temp,
+            count: data.count as u64,
+        }
+    }
+}
+
+// Writes stations one at a time as they become final, for the formats that
+// can be streamed: `Text` and `Jsonl`.
+pub struct StationWriter<W: io::Write> {
+    out: W,
+    format: Format,
+    rounding: Option<Rounding>,
+    first: bool,
+}
+
+impl<W: io::Write> StationWriter<W> {
+    pub fn new(mut out: W, format: Format, rounding: Option<Rounding>) -> io::Result<Self> {
+        if format == Format::Text {
+            out.write_all(b"{")?;
+        }
+        Ok(StationWriter {
+            out,
+            format,
+            rounding,
+            first: true,
+        })
+    }
+
+    pub fn write(&mut self, station: &str, data: &StationData) -> io::Result<()> {
+        if self.format == Format::Jsonl {
+            let record = StationRecord::new(station, data, self.rounding);
+            serde_json::to_writer(&mut self.out, &record)?;
+            self.out.write_all(b"\n")?;
+        } else {
+            let separator = if self.first { "" } else { ", " };
+            write!(
+                self.out,
+                "{}{}={:.1}/{}/{:.1}",
+                separator,
+                station,
+                data.min_temp,
+                Mean(data, self.rounding),
+                data.max_temp
+            )?;
+        }
+        self.first = false;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        if self.format == Format::Text {
+            self.out.write_all(b"}\n\n")?;
+        }
+        self.out.flush()
+    }
+}
+
+// Compares two stations by one statistic.
+type Comparator<'a> = Box<dyn Fn(&(String, StationData), &(String, StationData)) -> Ordering + 'a>;
+
+// Canonical output order of station names: raw UTF-8 byte order, which does
+// not depend on locale or map iteration order. It is code point order, so it
+// differs from Java's natural `String` ordering, which compares UTF-16 code
+// units, only for names with characters from U+E000 to U+FFFF, which Java
+// sorts after supplementary characters.
+pub fn station_order(a: &str, b: &str) -> Ordering {
+    a.as_bytes().cmp(b.as_bytes())
+}
+
+// Sorts stations by name, using `collation` when given and byte order
+// otherwise.
+pub fn sort_stations(stations: &mut [(String, StationData)], collation: Option<&Collation>) {
+    match collation {
+        Some(collation) => stations.sort_by(|a, b| collation.compare(&a.0, &b.0)),
+        None => stations.sort_unstable_by(|a, b| station_order(&a.0, &b.0)),
+    }
+}
+
+// Sorts stations in `order`, comparing names with `collation` when given and
+// in byte order otherwise. Each key becomes a comparator, and the chain of
+// comparators is applied to every pair. The sort is stable.
+pub fn sort_by(
+    stations: &mut [(String, StationData)],
+    order: &SortOrder,
+    collation: Option<&Collation>,
+) {
+    let comparators: Vec<Comparator> = order
+        .keys
+        .iter()
+        .map(|&key| -> Comparator {
+            match key {
+                SortKey::Name => Box::new(move |a, b| match collation {
+                    Some(collation) => collation.compare(&a.0, &b.0),
+                    None => station_order(&a.0, &b.0),
+                }),
+                SortKey::Count => Box::new(|a, b| a.1.count.cmp(&b.1.count)),
+                SortKey::Min => Box::new(|a, b| a.1.min_temp.total_cmp(&b.1.min_temp)),
+                SortKey::Mean => Box::new(|a, b| a.1.mean().total_cmp(&b.1.mean())),
+                SortKey::Max => Box::new(|a, b| a.1.max_temp.total_cmp(&b.1.max_temp)),
+            }
+        })
+        .collect();
+    stations.sort_by(|a, b| {
+        let ordering = comparators
+            .iter()
+            .fold(Ordering::Equal, |ordering, compare| {
+                ordering.then_with(|| compare(a, b))
+            });
+        if order.desc {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+}
+
+// Formats a station's statistics as `min/mean/max`, rounding the mean with
+// `rounding` when given.
+pub fn format_station(data: &StationData, rounding: Option<Rounding>) -> String {
+    format!(
+        "{:.1}/{}/{:.1}",
+        data.min_temp,
+        Mean(data, rounding),
+        data.max_temp
+    )
+}
+
+// Sorts formatted `(station, result)` pairs by name, using `collation` when
+// given and byte order otherwise, and concatenates them.
+pub fn format_output(
+    formatted_results: &mut [(String, String)],
+    collation: Option<&Collation>,
+) -> String {
+    match collation {
+        Some(collation) => formatted_results.sort_by(|a, b| collation.compare(&a.0, &b.0)),
+        None => formatted_results.sort_unstable_by(|a, b| station_order(&a.0, &b.0)),
+    }
+    brace_list(
+        formatted_results
+            .iter()
+            .map(|(station, result)| (station.as_str(), result.as_str())),
+    )
+}
+
+// Renders already sorted stations in `format`, rounding means with `rounding`
+// when given. With `highlight`, the table formats emphasize the overall lowest
+// minimum and highest maximum.
+pub fn render(
+    stations: &[(String, StationData)],
+    format: Format,
+    highlight: bool,
+    rounding: Option<Rounding>,
+) -> String {
+    match format {
+        Format::Text => {
+            let mut out = Vec::new();
+            text::write_results(&mut out, text_entries(stations), rounding);
+            String::from_utf8(out).unwrap_or_default()
+        }
+        Format::Markdown => render_markdown(stations, highlight, rounding),
+        Format::Html => render_html(stations, highlight, rounding),
+        Format::Jsonl => {
+            let mut out = Vec::with_capacity(stations.len() * 80);
+            let mut writer = StationWriter {
+                out: &mut out,
+                format,
+                rounding,
+                first: true,
+            };
+            for (station, data) in stations {
+                let _ = writer.write(station, data);
+            }
+            String::from_utf8(out).unwrap_or_default()
+        }
+        Format::Msgpack | Format::Proto | Format::Sqlite | Format::RowBinary | Format::Mapped => {
+            unreachable!("binary format rendered as text")
+        }
+        Format::Nested => unreachable!("nested output is written with `write_nested`"),
+    }
+}
+
+// Stations as `brc::text` takes them.
+pub fn text_entries(
+    stations: &[(String, StationData)],
+) -> impl Iterator<Item = (&str, &StationData)> + Clone {
+    stations
+        .iter()
+        .map(|(station, data)| (station.as_str(), data))
+}
+
+// Named values of `--stats` for one station, serialized as fields of its
+// record in the given order.
+struct ExtraValues(Vec<(&'static str, f32)>);
+
+impl Serialize for ExtraValues {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, value) in &self.0 {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+#[derive(Serialize)]
+struct ExtendedRecord<'a> {
+    #[serde(flatten)]
+    record: StationRecord<'a>,
+    #[serde(flatten)]
+    extra: ExtraValues,
+}
+
+// Renders already sorted stations in the text or JSON Lines format with the
+// values of `--stats` that `extra` gives for each station after min/mean/max,
+// as `min/mean/max/mode` or extra JSON fields.
+pub fn render_extended(
+    stations: &[(String, StationData)],
+    format: Format,
+    rounding: Option<Rounding>,
+    extra: impl Fn(&str) -> Vec<(&'static str, f32)>,
+) -> String {
+    if format == Format::Jsonl {
+        let mut out = Vec::with_capacity(stations.len() * 96);
+        for (station, data) in stations {
+            let record = ExtendedRecord {
+                record: StationRecord::new(station, data, rounding),
+                extra: ExtraValues(extra(station)),
+            };
+            let _ = serde_json::to_writer(&mut out, &record);
+            out.push(b'\n');
+        }
+        return String::from_utf8(out).unwrap_or_default();
+    }
+    let formatted: Vec<_> = stations
+        .iter()
+        .map(|(station, data)| {
+            let mut result = format_station(data, rounding);
+            for (_, value) in extra(station) {
+                let _ = write!(result, "/{:.1}", value);
+            }
+            (station.as_str(), result)
+        })
+        .collect();
+    brace_list(
+        formatted
+            .iter()
+            .map(|(station, result)| (*station, result.as_str())),
+    )
+}
+
+// Writes already sorted stations as a MessagePack array of maps, with the
+// same field names as the JSON output.
+pub fn write_msgpack(
+    out: &mut impl io::Write,
+    stations: &[(String, StationData)],
+    rounding: Option<Rounding>,
+) -> io::Result<()> {
+    let records: Vec<_> = stations
+        .iter()
+        .map(|(station, data)| StationRecord::new(station, data, rounding))
+        .collect();
+    rmp_serde::encode::write_named(out, &records).map_err(io::Error::other)
+}
+
+// Statistics of one group in the nested output.
+#[derive(Serialize)]
+struct GroupRecord {
+    min: f32,
+    mean: f32,
+    max: f32,
+    count: u64,
+}
+
+// Writes already sorted stations, keyed by station and `--group-by` value
+// joined by `delimiter`, as one JSON object that maps each station to an
+// object of its groups. Stations keep the order of their first group.
+pub fn write_nested(
+    out: &mut impl io::Write,
+    stations: &[(String, StationData)],
+    delimiter: u8,
+    rounding: Option<Rounding>,
+) -> io::Result<()> {
+    let mut order = Vec::new();
+    let mut groups: HashMap<&str, Vec<(&str, GroupRecord)>> = HashMap::new();
+    for (key, data) in stations {
+        let (station, group) = key.split_once(delimiter as char).unwrap_or((key, ""));
+        let record = StationRecord::new(station, data, rounding);
+        groups
+            .entry(station)
+            .or_insert_with(|| {
+                order.push(station);
+                Vec::new()
+            })
+            .push((
+                group,
+                GroupRecord {
+                    min: record.min,
+                    mean: record.mean,
+                    max: record.max,
+                    count: record.count,
+                },
+            ));
+    }
+
+    out.write_all(b"{")?;
+    for (i, station) in order.iter().enumerate() {
+        if i > 0 {
+            out.write_all(b",")?;
+        }
+        serde_json::to_writer(&mut *out, station)?;
+        out.write_all(b":{")?;
+        for (j, (group, record)) in groups[station].iter().enumerate() {
+            if j > 0 {
+                out.write_all(b",")?;
+            }
+            serde_json::to_writer(&mut *out, group)?;
+            out.write_all(b":")?;
+            serde_json::to_writer(&mut *out, record)?;
+        }
+        out.write_all(b"}")?;
+    }
+    out.write_all(b"}\n")
+}
+
+// Concatenates `name=result` entries into the challenge's brace format.
+fn brace_list<'a>(entries: impl Iterator<Item = (&'a str, &'a str)>) -> String {
+    let mut output_result = String::with_capacity(ESTIMATED_UNIQUE_STATIONS * 50);
+    output_result.push('{');
+    for (i, (station, result)) in entries.enumerate() {
+        let temp_result = format!("{}{}={}", if i > 0 { ", " } else { "" }, station, result);
+        output_result += &temp_result;
+    }
+    output_result.push('}');
+    output_result.push('\n');
+    output_result
+}
+
+// Formats min and max for a table row, applying `emphasize` to those equal
+// to the overall `extremes` when highlighting.
+fn table_bounds(
+    data: &StationData,
+    extremes: Option<(f32, f32)>,
+    emphasize: fn(String) -> String,
+) -> (String, String) {
+    let mut min = format!("{:.1}", data.min_temp);
+    let mut max = format!("{:.1}", data.max_temp);
+    if let Some((lowest, highest)) = extremes {
+        if data.min_temp == lowest {
+            min = emphasize(min);
+        }
+        if data.max_temp == highest {
+            max = emphasize(max);
+        }
+    }
+    (min, max)
+}
+
+// Overall lowest minimum and highest maximum, when highlighting is enabled.
+fn extremes(stations: &[(String, StationData)], highlight: bool) -> Option<(f32, f32)> {
+    if !highlight {
+        return None;
+    }
+    let lowest = stations
+        .iter()
+        .map(|(_, data)| data.min_temp)
+        .fold(f32::MAX, f32::min);
+    let highest = stations
+        .iter()
+        .map(|(_, data)| data.max_temp)
+        .fold(f32::MIN, f32::max);
+    Some((lowest, highest))
+}
+
+fn render_markdown(
+    stations: &[(String, StationData)],
+    highlight: bool,
+    rounding: Option<Rounding>,
+) -> String {
+    let extremes = extremes(stations, highlight);
+    let mut out = String::with_capacity(64 + stations.len() * 48);
+    out.push_str("| Station | Min | Mean | Max |\n");
+    out.push_str("| --- | ---: | ---: | ---: |\n");
+    for (station, data) in stations {
+        let (min, max) = table_bounds(data, extremes, |s| format!("**{}**", s));
+        let station = station.replace('\\', "\\\\").replace('|', "\\|");
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} | {} |",
+            station,
+            min,
+            Mean(data, rounding),
+            max
+        );
+    }
+    out
+}
+
+fn render_html(
+    stations: &[(String, StationData)],
+    highlight: bool,
+    rounding: Option<Rounding>,
+) -> String {
+    let extremes = extremes(stations, highlight);
+    let mut out = String::with_capacity(128 + stations.len() * 80);
+    out.push_str("<table>\n<thead>\n");
+    out.push_str("<tr><th>Station</th><th>Min</th><th>Mean</th><th>Max</th></tr>\n");
+    out.push_str("</thead>\n<tbody>\n");
+    for (station, data) in stations {
+        let (min, max) = table_bounds(data, extremes, |s| format!("<strong>{}</strong>", s));
+        let _ = writeln!(
+            out,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape_html(station),
+            min,
+            Mean(data, rounding),
+            max
+        );
+    }
+    out.push_str("</tbody>\n</table>\n");
+    out
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,109 @@
+// Line parsing for measurement files. Everything here is panic-free and
+// reports problems through `Result`, so it can be fuzzed directly; see the
+// targets under `fuzz/`. Lines are split and temperatures decoded by the
+// `no_std` `brc-core` crate in `core/`; this module re-exports it and adds
+// the parsers that build owned keys.
+
+use crate::window::{format_timestamp, parse_timestamp, window_start};
+use std::borrow::Cow;
+
+pub use brc_core::{
+    check_station, parse_line, parse_line_in, parse_line_with, parse_record, parse_temperature,
+    parse_temperature_in, parse_tenths, parse_tenths_in, split_once, LineError, Notation, Numbers,
+    BOM, MAX_STATION_BYTES, MAX_TENTHS,
+};
+
+// Splits a line with extra fields into a composite key and the temperature
+// in the last field. The key is the station, the first field, joined by
+// `delimiter` with field `column`, numbered from 1; for `column` 2 it is
+// borrowed from the line. A line without that field and a temperature after
+// it is missing a delimiter. The temperature is written in `notation`.
+pub fn parse_grouped(
+    line: &str,
+    delimiter: u8,
+    notation: Notation,
+    column: usize,
+) -> Result<(Cow<'_, str>, f32), LineError> {
+    let (line, station, value, temp) = split_fields(line, delimiter, notation, column)?;
+    let key = if column == 2 {
+        Cow::Borrowed(&line[..station.len() + 1 + value.len()])
+    } else {
+        Cow::Owned(format!("{}{}{}", station, delimiter as char, value))
+    };
+    Ok((key, temp))
+}
+
+// Like `parse_grouped`, with field `column` holding a timestamp that is
+// replaced in the key by the start of its `width`-second window.
+pub fn parse_windowed(
+    line: &str,
+    delimiter: u8,
+    notation: Notation,
+    column: usize,
+    width: u64,
+) -> Result<(Cow<'_, str>, f32), LineError> {
+    let (_, station, value, temp) = split_fields(line, delimiter, notation, column)?;
+    let timestamp = parse_timestamp(value).ok_or(LineError::InvalidTimestamp)?;
+    let start = format_timestamp(window_start(timestamp, width));
+    Ok((
+        Cow::Owned(format!("{}{}{}", station, delimiter as char, start)),
+        temp,
+    ))
+}
+
+// Splits a line with extra fields into the line without a BOM, its station,
+// field `column` and the temperature in the last field.
+fn split_fields(
+    line: &str,
+    delimiter: u8,
+    notation: Notation,
+    column: usize,
+) -> Result<(&str, &str, &str, f32), LineError> {
+    let line = line.strip_prefix(BOM).unwrap_or(line);
+    let (station, rest) = split_once(line, delimiter).ok_or(LineError::MissingDelimiter)?;
+    check_station(station.as_bytes())?;
+    let pos = rest
+        .as_bytes()
+        .iter()
+        .rposition(|&b| b == delimiter)
+        .ok_or(LineError::MissingDelimiter)?;
+    let (fields, temp_str) = (&rest[..pos], &rest[pos + 1..]);
+    let value = fields
+        .split(delimiter as char)
+        .nth(column.saturating_sub(2))
+        .filter(|_| column >= 2)
+        .ok_or(LineError::MissingDelimiter)?;
+    let temp = parse_temperature_in(temp_str, notation)?;
+    Ok((line, station, value, temp))
+}
+
+// Result of `recover_line` for a line that `parse_line` rejects.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Recovery<'a> {
+    // The line has extra fields; the first is taken as the station and the
+    // last as the temperature.
+    LastField(&'a str, f32),
+    // The temperature field is empty, so the line should be skipped.
+    MissingValue,
+}
+
+// Applies the recovery rules to a line that `parse_line_in` rejected for
+// `delimiter` and `notation`, or returns `None` if none of them applies.
+pub fn recover_line(line: &str, delimiter: u8, notation: Notation) -> Option<Recovery<'_>> {
+    let line = line.strip_prefix(BOM).unwrap_or(line);
+    let (station, rest) = split_once(line, delimiter)?;
+    check_station(station.as_bytes()).ok()?;
+    let value = match rest.rfind(delimiter as char) {
+        Some(pos) => &rest[pos + 1..],
+        None => rest,
+    };
+    if value.trim().is_empty() {
+        return Some(Recovery::MissingValue);
+    }
+    if value.len() == rest.len() {
+        // A single, invalid temperature field: nothing to recover.
+        return None;
+    }
+    let temp = parse_temperature_in(value, notation).ok()?;
+    Some(Recovery::LastField(station, temp))
+}
@@ -0,0 +1,135 @@
+// The `per-core` strategy: one worker per physical core, pinned to it, each
+// folding one contiguous partition into its own table. The workers then merge
+// in a binary tree among themselves, worker `i` taking the partial of worker
+// `i + 2^k` in round `k`, so no thread is spawned after the start, nothing is
+// stolen and every merge happens on a core whose cache holds one side of it.
+// Pinning is only available on Linux; elsewhere the workers float.
+
+use crate::cli::Options;
+use crate::error::Error;
+use crate::schedule::fold_chunk;
+use crate::spill::Spill;
+use crate::thread_stats::ThreadStats;
+use crate::topology::Topology;
+use crate::Partial;
+use brc::chunks;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Instant;
+
+pub fn aggregate<'a>(
+    content: &'a str,
+    options: &Options,
+    spill: Option<&Spill>,
+    stats: Option<&ThreadStats>,
+) -> Result<Partial<'a>, Error> {
+    let cores = core_cpus();
+    let workers = options
+        .threads
+        .unwrap_or_else(|| Topology::detect().default_threads());
+    let partitions = chunks::chunks(content, content.len().div_ceil(workers).max(1));
+    let workers = partitions.len();
+    if workers == 0 {
+        return Ok(Partial::empty());
+    }
+
+    // Worker `i` sends its merged partial on channel `i`, which worker
+    // `i - 2^k` receives from, `2^k` being the lowest set bit of `i`.
+    let (senders, receivers): (Vec<_>, Vec<_>) =
+        (0..workers).map(|_| mpsc::sync_channel(1)).unzip();
+    let mut receivers: Vec<Option<Receiver<_>>> = receivers.into_iter().map(Some).collect();
+
+    thread::scope(|scope| {
+        let mut root = None;
+        for (index, (partition, sender)) in partitions.into_iter().zip(senders).enumerate() {
+            // The children of `index`, in the order their partials are merged.
+            let children: Vec<_> = (0..usize::BITS)
+                .map(|level| 1 << level)
+                .take_while(|&step| index & step == 0 && index + step < workers)
+                .filter_map(|step| receivers[index + step].take())
+                .collect();
+            let cpu = (!cores.is_empty()).then(|| cores[index % cores.len()]);
+            let worker = scope.spawn(move || {
+                if let Some(cpu) = cpu {
+                    pin(cpu);
+                }
+                let start = Instant::now();
+                let result = fold_chunk(Partial::new(options), partition, options, spill)
+                    .inspect(|partial| {
+                        if let Some(stats) = stats {
+                            stats.record(index, partial, start.elapsed());
+                        }
+                    })
+                    .and_then(|mut partial| {
+                        for child in children {
+                            let other = child.recv().expect("per-core worker panicked")?;
+                            partial = partial.merge(other)?;
+                            partial.enforce_budget(spill)?;
+                        }
+                        Ok(partial)
+                    });
+                if index == 0 {
+                    return Some(result);
+                }
+                // The parent only stops receiving after an error of its own.
+                let _ = sender.send(result);
+                None
+            });
+            if index == 0 {
+                root = Some(worker);
+            }
+        }
+        root.expect("per-core root worker")
+            .join()
+            .expect("per-core worker panicked")
+            .expect("per-core root result")
+    })
+}
+
+// One logical CPU of each physical core the process may run on, as the
+// kernel lists them, or an empty list where that is unknown.
+#[cfg(target_os = "linux")]
+fn core_cpus() -> Vec<usize> {
+    use std::collections::HashSet;
+    use std::fs;
+
+    let mut allowed: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    let size = std::mem::size_of::<libc::cpu_set_t>();
+    if unsafe { libc::sched_getaffinity(0, size, &mut allowed) } != 0 {
+        return Vec::new();
+    }
+    let mut seen = HashSet::new();
+    (0..libc::CPU_SETSIZE as usize)
+        .filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &allowed) })
+        .filter(|&cpu| {
+            let topology = format!("/sys/devices/system/cpu/cpu{}/topology", cpu);
+            let read = |name: &str| fs::read_to_string(format!("{}/{}", topology, name)).ok();
+            // CPUs without topology information count as cores of their own.
+            match (read("physical_package_id"), read("core_id")) {
+                (Some(package), Some(core)) => {
+                    seen.insert((package.trim().to_string(), core.trim().to_string()))
+                }
+                _ => true,
+            }
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn core_cpus() -> Vec<usize> {
+    Vec::new()
+}
+
+// Pins the calling thread to `cpu`. A failure, such as the CPU going offline,
+// leaves the thread floating.
+#[cfg(target_os = "linux")]
+fn pin(cpu: usize) {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin(_cpu: usize) {}
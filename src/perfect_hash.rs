@@ -0,0 +1,165 @@
+// A minimal perfect hash over a known list of station names, for
+// `--stations`: every listed name maps to its own slot in `0..len` with one
+// hash, a seed lookup and a second hash, with no probing. Keys are first
+// hashed into buckets, and buckets are placed largest first by searching for
+// a seed that sends all of their keys to free slots ("hash and displace").
+// A name that is not in the list still lands on some slot, so lookups
+// compare it with the name stored there.
+//
+// `build.rs` includes this file to bake a table into the binary with the
+// `baked-stations` feature, so both sides hash the same way.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Average keys per bucket; larger buckets mean fewer seeds but longer
+// searches.
+const BUCKET_SIZE: usize = 4;
+
+// Seeds tried for one bucket before the build starts over with a different
+// bucket hash.
+const MAX_SEED: u32 = 1 << 20;
+
+pub struct PerfectHash {
+    // Salt of the bucket hash, and per bucket the seed of the slot hash.
+    salt: u64,
+    seeds: Vec<u32>,
+    names: Vec<String>,
+}
+
+impl PerfectHash {
+    // Builds the hash over `names`, ignoring duplicates.
+    pub fn build(mut names: Vec<String>) -> Self {
+        names.sort_unstable();
+        names.dedup();
+        let mut salt = 0;
+        loop {
+            if let Some(hash) = Self::try_build(&names, salt) {
+                return hash;
+            }
+            salt += 1;
+        }
+    }
+
+    fn try_build(names: &[String], salt: u64) -> Option<Self> {
+        let len = names.len();
+        let buckets = len.div_ceil(BUCKET_SIZE).max(1);
+        let mut members = vec![Vec::new(); buckets];
+        for (index, name) in names.iter().enumerate() {
+            members[bucket(name.as_bytes(), salt, buckets)].push(index);
+        }
+        let mut order: Vec<_> = (0..buckets).collect();
+        order.sort_unstable_by_key(|&bucket| std::cmp::Reverse(members[bucket].len()));
+
+        let mut seeds = vec![0; buckets];
+        let mut slots: Vec<Option<usize>> = vec![None; len];
+        let mut taken = Vec::with_capacity(BUCKET_SIZE * 4);
+        for bucket in order {
+            if members[bucket].is_empty() {
+                break;
+            }
+            let seed = (0..MAX_SEED).find(|&seed| {
+                taken.clear();
+                members[bucket].iter().all(|&index| {
+                    let slot = slot(names[index].as_bytes(), seed, len);
+                    let free = slots[slot].is_none() && !taken.contains(&slot);
+                    taken.push(slot);
+                    free
+                })
+            })?;
+            seeds[bucket] = seed;
+            for &index in &members[bucket] {
+                slots[slot(names[index].as_bytes(), seed, len)] = Some(index);
+            }
+        }
+
+        let names = slots
+            .into_iter()
+            .map(|index| index.map(|index| names[index].clone()))
+            .collect::<Option<Vec<_>>>()?;
+        Some(PerfectHash { salt, seeds, names })
+    }
+
+    // The slot of `name`, or `None` if it is not in the list.
+    #[inline]
+    pub fn get(&self, name: &str) -> Option<usize> {
+        lookup(self.salt, &self.seeds, &self.names, name)
+    }
+
+    // The listed names, indexed by slot.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn salt(&self) -> u64 {
+        self.salt
+    }
+
+    pub fn seeds(&self) -> &[u32] {
+        &self.seeds
+    }
+}
+
+// The slot of `name` in a hash with `salt`, `seeds` and `names`, or `None` if
+// it is not in the list. Works on borrowed tables, such as baked ones.
+#[inline]
+pub fn lookup<S: AsRef<str>>(salt: u64, seeds: &[u32], names: &[S], name: &str) -> Option<usize> {
+    if names.is_empty() {
+        return None;
+    }
+    let bytes = name.as_bytes();
+    let seed = seeds[bucket(bytes, salt, seeds.len())];
+    let slot = slot(bytes, seed, names.len());
+    (names[slot].as_ref() == name).then_some(slot)
+}
+
+// Reads a station list into a perfect hash; see `station_list`.
+pub fn load(path: impl AsRef<Path>, delimiter: u8) -> io::Result<PerfectHash> {
+    let content = fs::read_to_string(path)?;
+    let names = station_list(&content, delimiter)
+        .map(str::to_string)
+        .collect();
+    Ok(PerfectHash::build(names))
+}
+
+// The names in a station list: one per line, optionally followed by
+// `delimiter` and further fields as in the challenge's `weather_stations.csv`.
+// Empty lines and lines starting with `#` are skipped.
+pub fn station_list(content: &str, delimiter: u8) -> impl Iterator<Item = &str> {
+    content
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(move |line| {
+            let end = line
+                .bytes()
+                .position(|b| b == delimiter)
+                .unwrap_or(line.len());
+            &line[..end]
+        })
+}
+
+fn bucket(key: &[u8], salt: u64, buckets: usize) -> usize {
+    (hash(key, salt) % buckets as u64) as usize
+}
+
+fn slot(key: &[u8], seed: u32, len: usize) -> usize {
+    (hash(key, 0x9e37_79b9_7f4a_7c15 ^ seed as u64) % len as u64) as usize
+}
+
+// A fast seeded hash of `key`, eight bytes at a time, with a final mix so
+// every input bit affects the low bits used for the modulo.
+fn hash(key: &[u8], seed: u64) -> u64 {
+    let mut hash = seed ^ (key.len() as u64).wrapping_mul(0xff51_afd7_ed55_8ccd);
+    let mut chunks = key.chunks_exact(8);
+    for chunk in &mut chunks {
+        let word = u64::from_le_bytes(chunk.try_into().unwrap());
+        hash = (hash ^ word).wrapping_mul(0x0100_0000_01b3).rotate_left(29);
+    }
+    let mut tail = [0u8; 8];
+    tail[..chunks.remainder().len()].copy_from_slice(chunks.remainder());
+    hash = (hash ^ u64::from_le_bytes(tail)).wrapping_mul(0x0100_0000_01b3);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
@@ -0,0 +1,467 @@
+// The `pipeline` strategy: a dedicated reader thread fills reusable buffers
+// with plain `read` calls and hands them to parser threads through a bounded
+// channel, so storage latency overlaps with parsing instead of surfacing as
+// page faults in the parsers. Stdin is read the same way: once the channel is
+// full the reader blocks, and with it the upstream producer, so memory stays
+// within the buffers in flight however fast the input arrives.
+
+use crate::cli::Options;
+use crate::counters;
+use crate::error::Error;
+use crate::journal::{self, Journal};
+use crate::logging;
+use crate::spill::Spill;
+use crate::thread_stats::ThreadStats;
+use crate::utf8;
+use crate::{
+    detect_overflow, offset_of, overflow_error, parse_recovering, process_line, skip_line, Partial,
+};
+use crossbeam_channel::{bounded, Receiver, Sender};
+use serde_json::json;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, Read};
+use std::mem;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Default size of the buffers filled by the reader.
+const BUFFER_SIZE: usize = 4 * 1024 * 1024;
+
+// Filled buffers queued per parser thread unless `--queue-depth` is given;
+// bounds memory and lets the reader run ahead of the parsers.
+const BUFFERS_PER_PARSER: usize = 2;
+
+// Bytes fetched at a time past the end of a range to complete its last line.
+const LINE_TAIL: u64 = 4096;
+
+// How blocks flowed from the queue to one parser or all of them, reported
+// with `--verbose` to tune `--chunk-size` and `--queue-depth`: the blocks and
+// bytes parsed, the time spent parsing them and waiting for them, and the sum
+// of the queue lengths left on taking each block.
+#[derive(Default)]
+struct Flow {
+    blocks: u64,
+    bytes: u64,
+    busy: Duration,
+    waiting: Duration,
+    queued: u64,
+}
+
+impl Flow {
+    fn add(&mut self, other: &Flow) {
+        self.blocks += other.blocks;
+        self.bytes += other.bytes;
+        self.busy += other.busy;
+        self.waiting += other.waiting;
+        self.queued += other.queued;
+    }
+
+    fn report(&self, depth: usize) {
+        let blocks = self.blocks.max(1);
+        let total = (self.busy + self.waiting)
+            .as_secs_f64()
+            .max(f64::MIN_POSITIVE);
+        let (block_bytes, queued) = (self.bytes / blocks, self.queued as f64 / blocks as f64);
+        let busy = self.busy.as_secs_f64() / total * 100.0;
+        let waiting = self.waiting.as_secs_f64() / total * 100.0;
+        logging::event(
+            "pipeline",
+            format_args!(
+                "Pipeline: {} blocks of {} bytes on average; parsers busy {:.0}% and waiting \
+                 {:.0}% of the time; {:.1} of {} queue slots filled on average",
+                self.blocks, block_bytes, busy, waiting, queued, depth
+            ),
+            json!({
+                "blocks": self.blocks,
+                "block_bytes": block_bytes,
+                "busy_percent": busy,
+                "waiting_percent": waiting,
+                "queued": queued,
+                "queue_depth": depth,
+            }),
+        );
+    }
+}
+
+// Whole lines read from the input starting at byte `offset`, and the index
+// of the range they were fetched for when it is journaled.
+struct Block {
+    offset: usize,
+    data: Vec<u8>,
+    range: Option<u64>,
+}
+
+// Aggregates the file at `path` with one reader thread and one parser thread
+// per configured worker.
+pub fn aggregate(
+    path: &Path,
+    options: &Options,
+    spill: Option<&Spill>,
+    stats: Option<&ThreadStats>,
+) -> Result<Partial<'static>, Error> {
+    aggregate_reader(File::open(path)?, options, spill, stats)
+}
+
+// Aggregates everything `reader`, such as stdin, yields until it ends.
+pub fn aggregate_reader<R: Read + Send>(
+    reader: R,
+    options: &Options,
+    spill: Option<&Spill>,
+    stats: Option<&ThreadStats>,
+) -> Result<Partial<'static>, Error> {
+    let buffer_size = options.chunk_size.unwrap_or(BUFFER_SIZE);
+    aggregate_blocks(
+        |full, free| read_blocks(reader, buffer_size, full, free),
+        None,
+        options,
+        spill,
+        stats,
+    )
+}
+
+// Aggregates an input of `len` bytes that is fetched in ranges of
+// `range_size` bytes by one fetcher thread per parser, each calling
+// `fetch(start, end, buffer)` to append bytes `start..end` to `buffer`.
+// Ranges are widened to whole lines, so the ranges may arrive in any order.
+// Ranges already in `journal` are skipped, and the others are recorded in it
+// as they are aggregated.
+pub fn aggregate_ranges<F>(
+    len: u64,
+    range_size: usize,
+    fetch: F,
+    journal: Option<&Journal>,
+    options: &Options,
+    spill: Option<&Spill>,
+    stats: Option<&ThreadStats>,
+) -> Result<Partial<'static>, Error>
+where
+    F: Fn(u64, u64, &mut Vec<u8>) -> io::Result<()> + Sync,
+{
+    let ranges = len.div_ceil(range_size as u64);
+    let bounds = |index: u64| {
+        let start = index * range_size as u64;
+        (start, (start + range_size as u64).min(len))
+    };
+    if let Some((index, checksum)) = journal.and_then(Journal::last) {
+        let (start, end) = bounds(index);
+        let block = read_range(start, end, len, &fetch, Vec::new())?;
+        if journal::checksum(&block.data) != checksum {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the input changed since the journal was written; delete it to start over",
+            )));
+        }
+    }
+    let next = AtomicU64::new(0);
+    aggregate_blocks(
+        |full, free| {
+            thread::scope(|scope| {
+                let fetchers: Vec<_> = (0..rayon::current_num_threads())
+                    .map(|_| {
+                        let (full, free, next, fetch) = (full.clone(), free.clone(), &next, &fetch);
+                        scope.spawn(move || loop {
+                            let index = next.fetch_add(1, Ordering::Relaxed);
+                            if index >= ranges {
+                                return Ok(());
+                            }
+                            if journal.is_some_and(|journal| journal.is_done(index)) {
+                                continue;
+                            }
+                            let (start, end) = bounds(index);
+                            let data = free.try_recv().unwrap_or_default();
+                            let mut block = read_range(start, end, len, fetch, data)
+                                .inspect_err(|_| next.store(ranges, Ordering::Relaxed))?;
+                            block.range = journal.map(|_| index);
+                            if full.send(block).is_err() {
+                                return Ok(());
+                            }
+                        })
+                    })
+                    .collect();
+                fetchers
+                    .into_iter()
+                    .try_for_each(|fetcher| fetcher.join().expect("fetcher thread panicked"))
+            })
+        },
+        journal,
+        options,
+        spill,
+        stats,
+    )
+}
+
+// Fetches the lines that start within `start..end` into `data`: the partial
+// line before the first newline belongs to the previous range, and the last
+// line is completed from the following bytes.
+fn read_range<F>(start: u64, end: u64, len: u64, fetch: &F, mut data: Vec<u8>) -> io::Result<Block>
+where
+    F: Fn(u64, u64, &mut Vec<u8>) -> io::Result<()>,
+{
+    data.clear();
+    // Fetching the byte before the range tells whether a line starts at
+    // `start`.
+    let from = start.saturating_sub(1);
+    fetch(from, end, &mut data)?;
+    let skip = if start == 0 {
+        0
+    } else {
+        match data[..data.len().saturating_sub(1)]
+            .iter()
+            .position(|&b| b == b'\n')
+        {
+            Some(pos) => pos + 1,
+            // No line starts within the range.
+            None => {
+                data.clear();
+                return Ok(Block {
+                    offset: end as usize,
+                    data,
+                    range: None,
+                });
+            }
+        }
+    };
+    let mut next = end;
+    while next < len && data.last() != Some(&b'\n') {
+        let scanned = data.len();
+        let to = (next + LINE_TAIL.min(end - start)).min(len);
+        fetch(next, to, &mut data)?;
+        if let Some(pos) = data[scanned..].iter().position(|&b| b == b'\n') {
+            data.truncate(scanned + pos + 1);
+            break;
+        }
+        next = to;
+    }
+    data.drain(..skip);
+    Ok(Block {
+        offset: from as usize + skip,
+        data,
+        range: None,
+    })
+}
+
+// Drives `read`, which sends blocks of whole lines to the first channel and
+// may reuse buffers from the second, with one parser thread per configured
+// worker. The first channel holds `--queue-depth` blocks, so at most that
+// many plus one per parser and one being read are in memory.
+fn aggregate_blocks<R>(
+    read: R,
+    journal: Option<&Journal>,
+    options: &Options,
+    spill: Option<&Spill>,
+    stats: Option<&ThreadStats>,
+) -> Result<Partial<'static>, Error>
+where
+    R: FnOnce(Sender<Block>, Receiver<Vec<u8>>) -> io::Result<()> + Send,
+{
+    let parsers = rayon::current_num_threads();
+    let depth = options.queue_depth.unwrap_or(parsers * BUFFERS_PER_PARSER);
+    let (full_tx, full_rx) = bounded::<Block>(depth);
+    let (free_tx, free_rx) = bounded::<Vec<u8>>(depth + parsers);
+    let failed = AtomicBool::new(false);
+
+    let (read, parsed) = thread::scope(|scope| {
+        let reader = scope.spawn(|| read(full_tx, free_rx));
+        let workers: Vec<_> = (0..parsers)
+            .map(|index| {
+                let (full_rx, free_tx, failed) = (full_rx.clone(), free_tx.clone(), &failed);
+                scope.spawn(move || {
+                    let result = parse_blocks(full_rx, free_tx, failed, journal, options, spill);
+                    match &result {
+                        Ok((partial, flow)) => {
+                            if let Some(stats) = stats {
+                                stats.record(index, partial, flow.busy);
+                            }
+                        }
+                        Err(_) => failed.store(true, Ordering::Relaxed),
+                    }
+                    result
+                })
+            })
+            .collect();
+        // Once every parser is gone, the reader's sends fail and it stops.
+        drop(full_rx);
+        let parsed: Vec<_> = workers
+            .into_iter()
+            .map(|worker| worker.join().expect("parser thread panicked"))
+            .collect();
+        (reader.join().expect("reader thread panicked"), parsed)
+    });
+
+    let mut merged = Partial::empty();
+    let mut flow = Flow::default();
+    for result in parsed {
+        let (partial, parser_flow) = result?;
+        merged = merged.merge(partial)?;
+        merged.enforce_budget(spill)?;
+        flow.add(&parser_flow);
+    }
+    if options.verbose {
+        flow.report(depth);
+    }
+    // A read error only matters if the parsers did not already fail.
+    if !failed.load(Ordering::Relaxed) {
+        read?;
+    }
+    Ok(merged)
+}
+
+// Reads `file` into blocks of whole lines of about `buffer_size` bytes,
+// reusing buffers returned by the parsers. A line longer than the buffer
+// grows it.
+fn read_blocks(
+    mut file: impl Read,
+    buffer_size: usize,
+    full: Sender<Block>,
+    free: Receiver<Vec<u8>>,
+) -> io::Result<()> {
+    let mut offset = 0;
+    let mut carry = Vec::new();
+    loop {
+        let mut data = free
+            .try_recv()
+            .unwrap_or_else(|_| Vec::with_capacity(buffer_size));
+        data.clear();
+        data.append(&mut carry);
+
+        let mut target = buffer_size.max(data.len() + 1);
+        let (eof, end) = loop {
+            let filled = data.len();
+            data.resize(target, 0);
+            let (n, eof) = fill(&mut file, &mut data[filled..])?;
+            data.truncate(filled + n);
+            if eof {
+                break (true, data.len());
+            }
+            match data.iter().rposition(|&b| b == b'\n') {
+                Some(pos) => break (false, pos + 1),
+                None => target *= 2,
+            }
+        };
+        carry.extend_from_slice(&data[end..]);
+        data.truncate(end);
+
+        if !data.is_empty() {
+            let len = data.len();
+            if full
+                .send(Block {
+                    offset,
+                    data,
+                    range: None,
+                })
+                .is_err()
+            {
+                return Ok(());
+            }
+            offset += len;
+        }
+        if eof {
+            return Ok(());
+        }
+    }
+}
+
+// Reads into `buf` until it is full or the file ends, returning the number
+// of bytes read and whether the end was reached.
+fn fill(file: &mut impl Read, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+    let mut read = 0;
+    while read < buf.len() {
+        match file.read(&mut buf[read..]) {
+            Ok(0) => return Ok((read, true)),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok((read, false))
+}
+
+// Folds blocks from `full` into a partial result until the reader is done or
+// another parser failed, returning each buffer to `free` for reuse, along
+// with how the blocks flowed. Journaled blocks are folded separately first
+// so their own stations can be recorded.
+fn parse_blocks(
+    full: Receiver<Block>,
+    free: Sender<Vec<u8>>,
+    failed: &AtomicBool,
+    journal: Option<&Journal>,
+    options: &Options,
+    spill: Option<&Spill>,
+) -> Result<(Partial<'static>, Flow), Error> {
+    let mut partial = Partial::new(options);
+    let mut flow = Flow::default();
+    loop {
+        let wait = Instant::now();
+        let Ok(block) = full.recv() else {
+            break;
+        };
+        flow.waiting += wait.elapsed();
+        flow.queued += full.len() as u64;
+        if failed.load(Ordering::Relaxed) {
+            break;
+        }
+        let start = Instant::now();
+        match block.range.zip(journal) {
+            Some((range, journal)) => {
+                let mut own = Partial::new(options);
+                parse_block(&mut own, &block, options, spill)?;
+                journal.record(range, &block.data, &own.stations)?;
+                partial = mem::replace(&mut partial, Partial::empty()).merge(own)?;
+            }
+            None => parse_block(&mut partial, &block, options, spill)?,
+        }
+        flow.busy += start.elapsed();
+        flow.blocks += 1;
+        flow.bytes += block.data.len() as u64;
+        let _ = free.try_send(block.data);
+    }
+    Ok((partial, flow))
+}
+
+// Folds the lines of `block` into `partial`.
+fn parse_block(
+    partial: &mut Partial<'static>,
+    block: &Block,
+    options: &Options,
+    spill: Option<&Spill>,
+) -> Result<(), Error> {
+    // Offsets of lines after a lossy replacement are approximate.
+    let text = utf8::decode_block(&block.data, block.offset, options.utf8, options.delimiter)?;
+    let text = text.as_ref();
+    for line in text.lines() {
+        partial.rows += 1;
+        partial.bytes += line.len() as u64 + 1;
+        partial.report_progress();
+        counters::line();
+        match parse_recovering(partial, line, options) {
+            Ok(None) => {}
+            Ok(Some((station, temp))) => {
+                process_line(partial, &station, temp, detect_overflow(options)).map_err(
+                    |overflow| {
+                        let offset = block.offset + offset_of(text, line);
+                        overflow_error(&station, Some(offset), overflow)
+                    },
+                )?;
+                if options.tracks_readings() {
+                    let offset = block.offset + offset_of(text, line);
+                    partial.track_reading(&station, temp, offset, options);
+                }
+                partial.enforce_budget(spill)?;
+            }
+            Err(reason) => {
+                let offset = block.offset + offset_of(text, line);
+                skip_line(
+                    partial,
+                    offset,
+                    reason,
+                    Cow::Owned(line.to_string()),
+                    options,
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
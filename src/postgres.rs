@@ -0,0 +1,97 @@
+use crate::error::Error;
+use crate::StationData;
+use brc::rounding::Rounding;
+use std::io;
+
+// Table loaded when `--table` is not given.
+pub const DEFAULT_TABLE: &str = "station_stats";
+
+// Whether an `--output` target is a PostgreSQL connection URL rather than a
+// file.
+pub fn is_url(output: &str) -> bool {
+    output.starts_with("postgres://") || output.starts_with("postgresql://")
+}
+
+// Whether `table` is a plain, optionally schema-qualified table name that is
+// safe to splice into SQL.
+pub fn is_table_name(table: &str) -> bool {
+    !table.is_empty()
+        && table.split('.').count() <= 2
+        && table.split('.').all(|part| {
+            part.chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+}
+
+// Appends the stations to `table` in the database at `url`, creating the
+// table if needed, with a single binary `COPY`. Connections are unencrypted.
+#[cfg(feature = "postgres")]
+pub fn write(
+    url: &str,
+    table: &str,
+    stations: &[(String, StationData)],
+    rounding: Option<Rounding>,
+) -> Result<(), Error> {
+    use crate::output::StationRecord;
+    use postgres::binary_copy::BinaryCopyInWriter;
+    use postgres::types::Type;
+    use postgres::{Client, NoTls};
+
+    let result = (|| -> Result<u64, postgres::Error> {
+        let mut client = Client::connect(url, NoTls)?;
+        client.batch_execute(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                 station TEXT NOT NULL,
+                 min REAL NOT NULL,
+                 mean REAL NOT NULL,
+                 max REAL NOT NULL,
+                 count BIGINT NOT NULL
+             )",
+            table
+        ))?;
+        let sink = client.copy_in(&format!(
+            "COPY {} (station, min, mean, max, count) FROM STDIN (FORMAT binary)",
+            table
+        ))?;
+        let types = [
+            Type::TEXT,
+            Type::FLOAT4,
+            Type::FLOAT4,
+            Type::FLOAT4,
+            Type::INT8,
+        ];
+        let mut writer = BinaryCopyInWriter::new(sink, &types);
+        for (station, data) in stations {
+            let record = StationRecord::new(station, data, rounding);
+            writer.write(&[
+                &record.station,
+                &record.min,
+                &record.mean,
+                &record.max,
+                &(record.count as i64),
+            ])?;
+        }
+        writer.finish()
+    })();
+    result.map(|_| ()).map_err(|e| {
+        Error::Io(io::Error::other(format!(
+            "failed to load results into PostgreSQL: {}",
+            e
+        )))
+    })
+}
+
+#[cfg(not(feature = "postgres"))]
+pub fn write(
+    _url: &str,
+    _table: &str,
+    _stations: &[(String, StationData)],
+    _rounding: Option<Rounding>,
+) -> Result<(), Error> {
+    Err(Error::Io(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "`--output postgres://...` requires building with the `postgres` feature",
+    )))
+}
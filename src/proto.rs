@@ -0,0 +1,53 @@
+// Protobuf encoding of the results for `--format proto`. The messages mirror
+// `proto/results.proto`, which is shipped for consumers to generate their own
+// bindings from; keep both in sync.
+
+use crate::output::StationRecord;
+use crate::StationData;
+use brc::rounding::Rounding;
+use prost::Message;
+use std::io;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct StationStats {
+    #[prost(string, tag = "1")]
+    pub station: String,
+    #[prost(float, tag = "2")]
+    pub min: f32,
+    #[prost(float, tag = "3")]
+    pub mean: f32,
+    #[prost(float, tag = "4")]
+    pub max: f32,
+    #[prost(uint64, tag = "5")]
+    pub count: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Results {
+    #[prost(message, repeated, tag = "1")]
+    pub stations: Vec<StationStats>,
+}
+
+// Writes already sorted stations as one `Results` message.
+pub fn write(
+    out: &mut impl io::Write,
+    stations: &[(String, StationData)],
+    rounding: Option<Rounding>,
+) -> io::Result<()> {
+    let results = Results {
+        stations: stations
+            .iter()
+            .map(|(station, data)| {
+                let record = StationRecord::new(station, data, rounding);
+                StationStats {
+                    station: station.clone(),
+                    min: record.min,
+                    mean: record.mean,
+                    max: record.max,
+                    count: record.count,
+                }
+            })
+            .collect(),
+    };
+    out.write_all(&results.encode_to_vec())
+}
@@ -0,0 +1,173 @@
+// Where each station's notable readings come from: the byte offset of the
+// line holding its minimum and maximum for `--provenance`, or its first and
+// last reading in file order for `--first-last`, and the line number when
+// the input is local. Offsets order readings across chunks, so every
+// strategy reports the same lines; equal extremes keep the earliest.
+
+use crate::cli::Options;
+use crate::report;
+use hashbrown::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Reading {
+    pub temp: f32,
+    pub offset: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Extremes {
+    pub min: Reading,
+    pub max: Reading,
+}
+
+impl Extremes {
+    pub fn new(temp: f32, offset: usize) -> Self {
+        let reading = Reading { temp, offset };
+        Extremes {
+            min: reading,
+            max: reading,
+        }
+    }
+
+    pub fn update(&mut self, temp: f32, offset: usize) {
+        self.merge(&Extremes::new(temp, offset));
+    }
+
+    pub fn merge(&mut self, other: &Extremes) {
+        let (min, max) = (other.min, other.max);
+        if (min.temp, min.offset) < (self.min.temp, self.min.offset) {
+            self.min = min;
+        }
+        if max.temp > self.max.temp || (max.temp == self.max.temp && max.offset < self.max.offset) {
+            self.max = max;
+        }
+    }
+}
+
+// A station's first and last reading in file order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ends {
+    pub first: Reading,
+    pub last: Reading,
+}
+
+impl Ends {
+    pub fn new(temp: f32, offset: usize) -> Self {
+        let reading = Reading { temp, offset };
+        Ends {
+            first: reading,
+            last: reading,
+        }
+    }
+
+    pub fn update(&mut self, temp: f32, offset: usize) {
+        self.merge(&Ends::new(temp, offset));
+    }
+
+    pub fn merge(&mut self, other: &Ends) {
+        if other.first.offset < self.first.offset {
+            self.first = other.first;
+        }
+        if other.last.offset > self.last.offset {
+            self.last = other.last;
+        }
+    }
+}
+
+// Merges the readings of one partial result into another's with `merge`.
+pub fn merge_maps<T>(
+    mut acc: HashMap<String, T>,
+    other: HashMap<String, T>,
+    merge: impl Fn(&mut T, &T),
+) -> HashMap<String, T> {
+    for (station, readings) in other {
+        match acc.get_mut(&station) {
+            Some(entry) => merge(entry, &readings),
+            None => {
+                acc.insert(station, readings);
+            }
+        }
+    }
+    acc
+}
+
+// One-based line numbers of the lines at `offsets` in `bytes`, counted in a
+// single pass over them.
+pub fn line_numbers(bytes: &[u8], offsets: &[usize]) -> HashMap<usize, usize> {
+    let mut sorted = offsets.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+    let (mut line, mut position) = (1, 0);
+    let mut lines = HashMap::with_capacity(sorted.len());
+    for offset in sorted {
+        let end = offset.min(bytes.len());
+        line += bytes[position..end].iter().filter(|&&b| b == b'\n').count();
+        position = end;
+        lines.insert(offset, line);
+    }
+    lines
+}
+
+// Prints where each station's minimum and maximum were read, by station
+// name. `input` is the mapped file, or empty for remote input, whose lines
+// are not numbered.
+pub fn print_extremes(extremes: &HashMap<String, Extremes>, input: &[u8], options: &Options) {
+    print_readings(
+        "Provenance:",
+        extremes,
+        |extremes| [("min", extremes.min), ("max", extremes.max)],
+        input,
+        options,
+    );
+}
+
+// Prints each station's first and last reading, like `print_extremes`.
+pub fn print_ends(ends: &HashMap<String, Ends>, input: &[u8], options: &Options) {
+    print_readings(
+        "First and last readings:",
+        ends,
+        |ends| [("first", ends.first), ("last", ends.last)],
+        input,
+        options,
+    );
+}
+
+fn print_readings<T>(
+    title: &str,
+    stations: &HashMap<String, T>,
+    readings: impl Fn(&T) -> [(&'static str, Reading); 2],
+    input: &[u8],
+    options: &Options,
+) {
+    let mut sorted: Vec<_> = stations.iter().collect();
+    sorted.sort_unstable_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+    let lines = (!input.is_empty()).then(|| {
+        let offsets: Vec<_> = stations
+            .values()
+            .flat_map(|station| readings(station).map(|(_, reading)| reading.offset))
+            .collect();
+        line_numbers(input, &offsets)
+    });
+    let location = |reading: Reading| match &lines {
+        Some(lines) => format!("line {} (byte {})", lines[&reading.offset], reading.offset),
+        None => format!("byte {}", reading.offset),
+    };
+
+    report(options, format_args!("{}", title));
+    for (station, tracked) in sorted {
+        let [(a_name, a), (b_name, b)] = readings(tracked);
+        report(
+            options,
+            format_args!(
+                "{}: {} {:.1} at {}, {} {:.1} at {}",
+                station,
+                a_name,
+                a.temp,
+                location(a),
+                b_name,
+                b.temp,
+                location(b)
+            ),
+        );
+    }
+}
@@ -0,0 +1,55 @@
+// Parallel record visiting: the file is memory-mapped, split into line-aligned
+// chunks and parsed on the Rayon pool, and every record is handed to user
+// code, for custom analyses on top of the same parser.
+
+use crate::chunks;
+use crate::parse::parse_record;
+use memmap::MmapOptions;
+use rayon::prelude::*;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+// Smallest chunk handed to a worker, so small files are not split into
+// uselessly tiny pieces.
+const MIN_CHUNK_SIZE: usize = 64 * 1024;
+
+// Chunks per worker thread, to balance uneven progress between threads.
+const CHUNKS_PER_THREAD: usize = 8;
+
+// Calls `f` with the station name and temperature in tenths of every record
+// in the file at `path`. Calls happen concurrently from several threads and
+// in no particular order. Stops at the first malformed line with an
+// `InvalidData` error; records already visited are not rolled back.
+pub fn for_each_record<F>(path: impl AsRef<Path>, f: F) -> io::Result<()>
+where
+    F: Fn(&[u8], i16) + Sync,
+{
+    let file = File::open(path)?;
+    let mmap = unsafe { MmapOptions::new().map(&file)? };
+    let bytes: &[u8] = &mmap;
+    let chunk_size =
+        (bytes.len() / (rayon::current_num_threads() * CHUNKS_PER_THREAD)).max(MIN_CHUNK_SIZE);
+
+    chunks::split(bytes, chunk_size)
+        .into_par_iter()
+        .try_for_each(|range| {
+            let mut offset = range.start;
+            let data = &bytes[range];
+            let data = data.strip_suffix(b"\n").unwrap_or(data);
+            if data.is_empty() {
+                return Ok(());
+            }
+            for line in data.split(|&b| b == b'\n') {
+                let (station, tenths) = parse_record(line).map_err(|reason| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("malformed line at byte {}: {}", offset, reason.code()),
+                    )
+                })?;
+                f(station, tenths);
+                offset += line.len() + 1;
+            }
+            Ok(())
+        })
+}
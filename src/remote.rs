@@ -0,0 +1,348 @@
+// Remote input over HTTP(S) or from object stores: the file's length is
+// probed first and its byte ranges are then fetched concurrently into the
+// `pipeline` strategy, so the input never touches local disk.
+
+use crate::cli::Options;
+use crate::error::Error;
+use crate::journal::{Header, Journal};
+use crate::logging;
+use crate::merge_maps;
+use crate::pipeline;
+use crate::spill::Spill;
+use crate::thread_stats::ThreadStats;
+use crate::Partial;
+use serde_json::json;
+use std::io;
+use std::thread;
+
+// Default size of the ranges fetched per request.
+const RANGE_SIZE: usize = 16 * 1024 * 1024;
+
+// Whether an input path is a URL rather than a local file.
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://") || is_object_url(path)
+}
+
+// Whether an input path names an object in S3, Google Cloud Storage or Azure
+// Blob Storage.
+fn is_object_url(path: &str) -> bool {
+    ["s3://", "gs://", "az://"]
+        .iter()
+        .any(|scheme| path.starts_with(scheme))
+}
+
+// Aggregates the file at `url`, fetching ranges of `--chunk-size` bytes.
+// With `--resume`, ranges recorded by an interrupted run are skipped and the
+// journal is removed once the run completes.
+pub fn aggregate(
+    url: &str,
+    options: &Options,
+    spill: Option<&Spill>,
+    stats: Option<&ThreadStats>,
+) -> Result<Partial<'static>, Error> {
+    let source = Source::open(url)?;
+    let len = retry(
+        options,
+        || "probing the length".to_string(),
+        || source.len(),
+    )?;
+    let range_size = options.chunk_size.unwrap_or(RANGE_SIZE);
+    let header = Header {
+        url: url.to_string(),
+        len,
+        range_size: range_size as u64,
+    };
+    let mut journal = options
+        .resume
+        .as_ref()
+        .map(|path| Journal::open(path, &header))
+        .transpose()?;
+    let resumed = journal.as_mut().map(Journal::take_resumed);
+    if let Some(journal) = journal
+        .as_ref()
+        .filter(|journal| journal.resumed_ranges() > 0)
+    {
+        let (resumed, ranges) = (journal.resumed_ranges(), len.div_ceil(range_size as u64));
+        logging::event(
+            "resume",
+            format_args!(
+                "Resuming: {} of {} ranges already aggregated",
+                resumed, ranges
+            ),
+            json!({ "resumed_ranges": resumed, "ranges": ranges }),
+        );
+    }
+
+    let mut partial = pipeline::aggregate_ranges(
+        len,
+        range_size,
+        |start, end, buffer| fetch(&source, start, end, buffer, options),
+        journal.as_ref(),
+        options,
+        spill,
+        stats,
+    )?;
+    if let Some(resumed) = resumed {
+        partial.stations = merge_maps(partial.stations, resumed)?;
+    }
+    if let Some(journal) = journal {
+        journal.remove()?;
+    }
+    Ok(partial)
+}
+
+// The first `bytes` bytes of the file at `url`, or all of it if shorter.
+pub fn prefix(url: &str, bytes: usize, options: &Options) -> Result<Vec<u8>, Error> {
+    let source = Source::open(url)?;
+    let len = retry(
+        options,
+        || "probing the length".to_string(),
+        || source.len(),
+    )?;
+    let len = len.min(bytes as u64);
+    let mut buffer = Vec::with_capacity(len as usize);
+    if len > 0 {
+        fetch(&source, 0, len, &mut buffer, options)?;
+    }
+    Ok(buffer)
+}
+
+// Appends bytes `start..end` to `buffer`, retrying failed and short reads.
+fn fetch(
+    source: &Source,
+    start: u64,
+    end: u64,
+    buffer: &mut Vec<u8>,
+    options: &Options,
+) -> io::Result<()> {
+    let filled = buffer.len();
+    retry(
+        options,
+        || format!("bytes {}..{}", start, end),
+        || {
+            buffer.truncate(filled);
+            source.fetch(start, end, buffer)?;
+            if (buffer.len() - filled) as u64 != end - start {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("got {} of {} bytes", buffer.len() - filled, end - start),
+                ));
+            }
+            Ok(())
+        },
+    )
+}
+
+// Runs `request` up to `--retries` more times after a failure, doubling the
+// wait from `--retry-backoff` after each attempt.
+fn retry<T>(
+    options: &Options,
+    what: impl Fn() -> String,
+    mut request: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let mut backoff = options.retry_backoff;
+    let mut attempt = 0;
+    loop {
+        match request() {
+            Err(e) if attempt < options.retries => {
+                attempt += 1;
+                let what = what();
+                logging::event(
+                    "retry",
+                    format_args!(
+                        "Retrying {} in {:?} (attempt {} of {}): {}",
+                        what, backoff, attempt, options.retries, e
+                    ),
+                    json!({
+                        "request": what,
+                        "backoff_seconds": backoff.as_secs_f64(),
+                        "attempt": attempt,
+                        "retries": options.retries,
+                        "error": e.to_string(),
+                    }),
+                );
+                thread::sleep(backoff);
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+// Where remote bytes come from.
+enum Source {
+    #[cfg(feature = "http")]
+    Http(String),
+    // Requests are driven by a runtime that the fetcher threads share.
+    #[cfg(feature = "object-store")]
+    Object {
+        runtime: tokio::runtime::Runtime,
+        store: Box<dyn object_store::ObjectStore>,
+        path: object_store::path::Path,
+    },
+}
+
+impl Source {
+    fn open(url: &str) -> Result<Self, Error> {
+        if is_object_url(url) {
+            open_object(url)
+        } else {
+            open_http(url)
+        }
+    }
+
+    // The length of the file in bytes.
+    fn len(&self) -> io::Result<u64> {
+        match self {
+            #[cfg(feature = "http")]
+            Source::Http(url) => {
+                let response = ureq::head(url).call().map_err(request_error)?;
+                response
+                    .header("Content-Length")
+                    .and_then(|len| len.parse().ok())
+                    .ok_or_else(|| {
+                        io::Error::other(format!("{} did not report a Content-Length", url))
+                    })
+            }
+            #[cfg(feature = "object-store")]
+            Source::Object {
+                runtime,
+                store,
+                path,
+            } => {
+                use object_store::ObjectStoreExt;
+                let meta = runtime
+                    .block_on(store.head(path))
+                    .map_err(io::Error::other)?;
+                Ok(meta.size)
+            }
+            #[cfg(not(any(feature = "http", feature = "object-store")))]
+            _ => unreachable!(),
+        }
+    }
+
+    // Appends bytes `start..end` of the file to `buffer`.
+    #[cfg_attr(
+        not(any(feature = "http", feature = "object-store")),
+        allow(unused_variables, clippy::ptr_arg)
+    )]
+    fn fetch(&self, start: u64, end: u64, buffer: &mut Vec<u8>) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "http")]
+            Source::Http(url) => fetch_http(url, start, end, buffer),
+            #[cfg(feature = "object-store")]
+            Source::Object {
+                runtime,
+                store,
+                path,
+            } => {
+                use object_store::ObjectStoreExt;
+                let bytes = runtime
+                    .block_on(store.get_range(path, start..end))
+                    .map_err(io::Error::other)?;
+                buffer.extend_from_slice(&bytes);
+                Ok(())
+            }
+            #[cfg(not(any(feature = "http", feature = "object-store")))]
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(feature = "http")]
+fn open_http(url: &str) -> Result<Source, Error> {
+    Ok(Source::Http(url.to_string()))
+}
+
+#[cfg(not(feature = "http"))]
+fn open_http(_url: &str) -> Result<Source, Error> {
+    Err(Error::Io(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "remote input requires building with the `http` feature",
+    )))
+}
+
+// Connects to the object store named by `url`'s scheme, with credentials and
+// settings such as `AWS_REGION` taken from the environment.
+#[cfg(feature = "object-store")]
+fn open_object(url: &str) -> Result<Source, Error> {
+    use object_store::aws::AmazonS3Builder;
+    use object_store::azure::MicrosoftAzureBuilder;
+    use object_store::gcp::GoogleCloudStorageBuilder;
+    use object_store::path::Path;
+    use object_store::ObjectStore;
+
+    let (scheme, location) = url.split_once("://").unwrap_or_default();
+    let key = location.split_once('/').map_or("", |(_, key)| key);
+    let store = match scheme {
+        "s3" => AmazonS3Builder::from_env()
+            .with_url(url)
+            .build()
+            .map(|store| Box::new(store) as Box<dyn ObjectStore>),
+        "gs" => GoogleCloudStorageBuilder::from_env()
+            .with_url(url)
+            .build()
+            .map(|store| Box::new(store) as Box<dyn ObjectStore>),
+        _ => MicrosoftAzureBuilder::from_env()
+            .with_url(url)
+            .build()
+            .map(|store| Box::new(store) as Box<dyn ObjectStore>),
+    };
+    let invalid = |e: object_store::Error| {
+        Error::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid object URL `{}`: {}", url, e),
+        ))
+    };
+    Ok(Source::Object {
+        runtime: tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?,
+        store: store.map_err(invalid)?,
+        path: Path::from_url_path(key).map_err(|e| invalid(e.into()))?,
+    })
+}
+
+#[cfg(not(feature = "object-store"))]
+fn open_object(_url: &str) -> Result<Source, Error> {
+    Err(Error::Io(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "`s3://`, `gs://` and `az://` input requires building with the `object-store` feature",
+    )))
+}
+
+// Appends bytes `start..end` of the file at `url` to `buffer`, failing if the
+// server ignores the range.
+#[cfg(feature = "http")]
+fn fetch_http(url: &str, start: u64, end: u64, buffer: &mut Vec<u8>) -> io::Result<()> {
+    use std::io::Read;
+
+    let response = ureq::get(url)
+        .set("Range", &format!("bytes={}-{}", start, end - 1))
+        .call()
+        .map_err(request_error)?;
+    if response.status() != 206 {
+        return Err(io::Error::other(format!(
+            "{} does not support range requests",
+            url
+        )));
+    }
+    response
+        .into_reader()
+        .take(end - start)
+        .read_to_end(buffer)?;
+    Ok(())
+}
+
+#[cfg(feature = "http")]
+fn request_error(e: ureq::Error) -> io::Error {
+    match e {
+        ureq::Error::Status(code, response) => io::Error::other(format!(
+            "{}: HTTP {} {}",
+            response.get_url(),
+            code,
+            response.status_text()
+        )),
+        e => io::Error::other(e.to_string()),
+    }
+}
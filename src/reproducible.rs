@@ -0,0 +1,42 @@
+// `--reproducible`: a configuration hash, so that two people debugging a
+// discrepancy can check they run the same build with the same options before
+// comparing results. The configuration is the crate version, the strategy,
+// schedule and thread count in effect, and every other option from the config
+// file, the environment and the command line in order, without the input
+// path. The hash is FNV-1a, which is the same on every platform and build.
+
+use crate::cli::Options;
+
+// Options whose effective values are described instead of their arguments,
+// each followed by a value.
+const RESOLVED: [&str; 3] = ["--impl", "--schedule", "--threads"];
+
+// A description of the configuration of a run parsed from `args` into
+// `options`.
+pub fn describe(args: &[String], options: &Options) -> String {
+    let mut description = format!(
+        "brc {}; --impl {} --schedule {} --threads {}",
+        env!("CARGO_PKG_VERSION"),
+        options.implementation.label(),
+        options.schedule.label(),
+        options.threads.unwrap_or_default()
+    );
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if RESOLVED.contains(&arg.as_str()) {
+            args.next();
+        } else if *arg != options.path && arg != "--reproducible" {
+            description.push(' ');
+            description.push_str(arg);
+        }
+    }
+    description
+}
+
+pub fn hash(description: &str) -> u64 {
+    description
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
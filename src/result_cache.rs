@@ -0,0 +1,224 @@
+// Aggregated stations cached across runs in `~/.cache/1brc/`, keyed by the
+// input's path, size, modification time and a hash of samples of its
+// content, so re-running on an unchanged file with different output flags
+// skips the aggregation, and re-running on a file that was appended to only
+// aggregates the new bytes. Only the stations are cached; output is rendered
+// from them as usual.
+
+use crate::cli::{Implementation, Options, Recover};
+use crate::journal::checksum;
+use crate::key_hash::StationMap;
+use crate::logging;
+use crate::remote;
+use bincode::Options as _;
+use brc::station::StationData;
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+// Bytes hashed at each sampled position, and the number of positions.
+const SAMPLE_BYTES: usize = 64 * 1024;
+const SAMPLES: usize = 16;
+
+// Whether the run can use the cache: a full run on a local file that is
+// mapped, so not with `--impl std`, with nothing that reports on the
+// aggregation itself or on individual lines.
+pub fn applies(options: &Options) -> bool {
+    !options.no_cache
+        && !options.count_only
+        && !options.hot_path_stats
+        && options.sample.is_none()
+        && !options.exact
+        && !remote::is_url(&options.path)
+        && !options.reads_stdin()
+        && options.implementation != Implementation::Std
+        && !options.lenient
+        && options.recover == Recover::Off
+        && options.error_report.is_none()
+        && !options.thread_stats
+        && !options.counters
+        && !options.energy
+        && !options.verify_fingerprints
+        && !options.self_check
+        && options.aliases.is_none()
+        && options.station_filter.is_none()
+        && options.cache.is_none()
+        && !options.prefault
+        && !options.mlock
+        && !options.tracks_readings()
+}
+
+// Where an input's stations are cached, and the fingerprint of the input as
+// it is now.
+pub struct Entry {
+    path: PathBuf,
+    fingerprint: Fingerprint,
+}
+
+// The length and modification time of an input, and a hash of samples of
+// its content.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Fingerprint {
+    len: u64,
+    modified: u128,
+    samples: u64,
+}
+
+#[derive(Serialize)]
+struct CachedRef<'a> {
+    version: u32,
+    fingerprint: Fingerprint,
+    stations: &'a StationMap,
+}
+
+#[derive(Deserialize)]
+struct Cached {
+    version: u32,
+    fingerprint: Fingerprint,
+    stations: HashMap<String, StationData>,
+}
+
+// Bumped whenever the layout of `Cached` or `StationData` changes.
+const CACHE_VERSION: u32 = 2;
+
+// What the cache holds for an input.
+pub enum Lookup {
+    // The stations of the unchanged input.
+    Hit(HashMap<String, StationData>),
+    // The stations of the input's first `from` bytes, which are unchanged;
+    // only the bytes appended since need aggregating.
+    Appended {
+        stations: HashMap<String, StationData>,
+        from: usize,
+    },
+    Miss,
+}
+
+// The cache entry for `input`, the mapped content of `options.path`, or
+// `None` when there is no cache directory. Each input path and parse
+// configuration has one entry, replaced when the input changes.
+pub fn entry(options: &Options, input: &[u8]) -> io::Result<Option<Entry>> {
+    let Some(dir) = cache_dir() else {
+        return Ok(None);
+    };
+    let path = fs::canonicalize(&options.path)?;
+    let modified = fs::metadata(&path)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    // Everything else that changes the aggregated stations names the entry.
+    let name = format!(
+        "{}\0{}\0{:?}\0{}\0{:?}\0{:?}\0{:?}",
+        path.display(),
+        options.delimiter,
+        options.notation,
+        options.utf8 as u8,
+        options.group_by,
+        options.window,
+        options.time_col
+    );
+    Ok(Some(Entry {
+        path: dir.join(format!("{:016x}.bin", checksum(name.as_bytes()))),
+        fingerprint: Fingerprint {
+            len: input.len() as u64,
+            modified: modified.as_nanos(),
+            samples: hash_samples(input),
+        },
+    }))
+}
+
+// Looks up `entry` for `input`. A cached input that is a prefix of `input`
+// ending at a line boundary, going by its samples, was appended to; any
+// other change, or a cache file that cannot be read, is a miss.
+pub fn load(entry: &Entry, input: &[u8]) -> Lookup {
+    let Some(cached) = read(&entry.path) else {
+        return Lookup::Miss;
+    };
+    let old = cached.fingerprint;
+    if cached.version != CACHE_VERSION {
+        Lookup::Miss
+    } else if old == entry.fingerprint {
+        Lookup::Hit(cached.stations)
+    } else if old.len < entry.fingerprint.len
+        && (old.len == 0 || input[old.len as usize - 1] == b'\n')
+        && hash_samples(&input[..old.len as usize]) == old.samples
+    {
+        Lookup::Appended {
+            stations: cached.stations,
+            from: old.len as usize,
+        }
+    } else {
+        Lookup::Miss
+    }
+}
+
+fn read(path: &Path) -> Option<Cached> {
+    let file = File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_limit(len)
+        .deserialize_from(BufReader::new(file))
+        .ok()
+}
+
+// Hash of `SAMPLES` evenly spaced samples of `bytes`, from the first byte to
+// the last.
+fn hash_samples(bytes: &[u8]) -> u64 {
+    let mut samples = Vec::with_capacity(SAMPLES * SAMPLE_BYTES);
+    let stride = bytes.len().saturating_sub(SAMPLE_BYTES) / (SAMPLES - 1);
+    for index in 0..SAMPLES {
+        let start = index * stride;
+        samples.extend_from_slice(&bytes[start..bytes.len().min(start + SAMPLE_BYTES)]);
+    }
+    checksum(&samples)
+}
+
+// Caches `stations` at `entry`, replacing it atomically so concurrent runs
+// never read a partial file. Failing to write the cache does not fail the
+// run.
+pub fn store(entry: &Entry, stations: &StationMap) {
+    let temp = entry
+        .path
+        .with_extension(format!("{}.tmp", std::process::id()));
+    let cached = CachedRef {
+        version: CACHE_VERSION,
+        fingerprint: entry.fingerprint,
+        stations,
+    };
+    let stored = entry
+        .path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| {
+            let mut out = BufWriter::new(File::create(&temp)?);
+            bincode::DefaultOptions::new()
+                .with_fixint_encoding()
+                .serialize_into(&mut out, &cached)
+                .map_err(io::Error::other)?;
+            out.flush()
+        })
+        .and_then(|_| fs::rename(&temp, &entry.path));
+    if let Err(e) = stored {
+        let _ = fs::remove_file(&temp);
+        logging::event(
+            "warning",
+            format_args!("warning: failed to cache results: {}", e),
+            json!({ "error": e.to_string() }),
+        );
+    }
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))?;
+    Some(base.join("1brc"))
+}
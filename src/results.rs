@@ -0,0 +1,88 @@
+// Aggregated results in a form meant for programmatic use: typed statistics
+// per station instead of formatted strings.
+
+use crate::station::StationData;
+use hashbrown::HashMap;
+use std::fmt::Write;
+
+// Final statistics of one station.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stats {
+    pub min: f32,
+    pub mean: f32,
+    pub max: f32,
+    pub count: u64,
+}
+
+impl From<&StationData> for Stats {
+    fn from(data: &StationData) -> Self {
+        Stats {
+            min: data.min_temp,
+            mean: data.mean(),
+            max: data.max_temp,
+            count: data.count as u64,
+        }
+    }
+}
+
+// Stations and their statistics, kept in byte order of the names.
+pub struct Results {
+    stations: Vec<(String, Stats)>,
+}
+
+impl Results {
+    // Statistics of `station`, if it appeared in the input.
+    pub fn get(&self, station: &str) -> Option<Stats> {
+        self.stations
+            .binary_search_by(|(name, _)| name.as_bytes().cmp(station.as_bytes()))
+            .ok()
+            .map(|index| self.stations[index].1)
+    }
+
+    // Stations with their statistics in byte order of the names, as in the
+    // challenge's output.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (&str, Stats)> + '_ {
+        self.stations
+            .iter()
+            .map(|(name, stats)| (name.as_str(), *stats))
+    }
+
+    // The challenge's `{name=min/mean/max, ...}` line, without a newline.
+    pub fn to_brace_string(&self) -> String {
+        let mut out = String::with_capacity(self.stations.len() * 32 + 2);
+        out.push('{');
+        for (i, (name, stats)) in self.iter_sorted().enumerate() {
+            let separator = if i > 0 { ", " } else { "" };
+            let _ = write!(
+                out,
+                "{}{}={:.1}/{:.1}/{:.1}",
+                separator, name, stats.min, stats.mean, stats.max
+            );
+        }
+        out.push('}');
+        out
+    }
+
+    // Number of distinct stations.
+    pub fn len(&self) -> usize {
+        self.stations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stations.is_empty()
+    }
+}
+
+impl From<HashMap<String, StationData>> for Results {
+    fn from(stations: HashMap<String, StationData>) -> Self {
+        let mut stations: Vec<_> = stations
+            .into_iter()
+            .map(|(name, data)| {
+                let stats = Stats::from(&data);
+                (name, stats)
+            })
+            .collect();
+        stations.sort_unstable_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+        Results { stations }
+    }
+}
@@ -0,0 +1,93 @@
+// How means are rounded to tenths of a degree. Minimums and maximums are
+// readings, already whole tenths, so the mean is the only statistic that
+// needs rounding. It is rounded from the exact quotient of the sum of tenths
+// and the count, in integers, so the mode alone decides ties.
+
+use crate::station::StationData;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    // Ties away from zero, like `BigDecimal`'s `HALF_UP`.
+    HalfUp,
+    // Ties to the even neighbour.
+    HalfEven,
+    // Ties toward positive infinity, like Java's `Math.round`, which the
+    // challenge's reference implementation uses.
+    TowardPositive,
+}
+
+impl FromStr for Rounding {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "half-up" => Ok(Rounding::HalfUp),
+            "half-even" => Ok(Rounding::HalfEven),
+            "toward-positive" => Ok(Rounding::TowardPositive),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Rounding {
+    // `numerator / denominator` rounded to an integer; `denominator` must be
+    // positive.
+    pub fn divide(self, numerator: i128, denominator: i128) -> i128 {
+        let (quotient, remainder) = (
+            numerator.div_euclid(denominator),
+            numerator.rem_euclid(denominator),
+        );
+        match (2 * remainder).cmp(&denominator) {
+            std::cmp::Ordering::Less => quotient,
+            std::cmp::Ordering::Greater => quotient + 1,
+            std::cmp::Ordering::Equal => match self {
+                Rounding::HalfUp if quotient < 0 => quotient,
+                Rounding::HalfEven if quotient % 2 == 0 => quotient,
+                _ => quotient + 1,
+            },
+        }
+    }
+
+    // The mean of `data` in tenths. The `f32` total is taken as a whole
+    // number of tenths, which it is until rounding errors accumulate.
+    pub fn mean_tenths(self, data: &StationData) -> i128 {
+        let total = (data.total_temp as f64 * 10.0).round() as i128;
+        self.divide(total, data.count.max(1) as i128)
+    }
+}
+
+// Tenths of a degree, displayed with one fractional digit, e.g. `-12.3`.
+pub struct Tenths(pub i128);
+
+impl fmt::Display for Tenths {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+        write!(f, "{}{}.{}", sign, abs / 10, abs % 10)
+    }
+}
+
+// The mean of a station as printed: its rounded tenths with a mode, and
+// otherwise the `f32` mean with one decimal.
+pub struct Mean<'a>(pub &'a StationData, pub Option<Rounding>);
+
+impl Mean<'_> {
+    // The printed mean as a number, for the formats that store one.
+    pub fn value(&self) -> f32 {
+        match self.1 {
+            Some(rounding) => rounding.mean_tenths(self.0) as f32 / 10.0,
+            None => (self.0.mean() * 10.0).round() / 10.0,
+        }
+    }
+}
+
+impl fmt::Display for Mean<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.1 {
+            Some(rounding) => Tenths(rounding.mean_tenths(self.0)).fmt(f),
+            None => write!(f, "{:.1}", self.0.mean()),
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use crate::chunks::align_to_line;
+use crate::collate::Collation;
+use crate::output::format_output;
+use crate::{StationData, ESTIMATED_UNIQUE_STATIONS};
+use brc::parse::{parse_line_in, Notation};
+use brc::sum::Sum;
+use hashbrown::HashMap;
+use rayon::prelude::*;
+
+// Bounds for the size of a sampled chunk; small files get small chunks so the
+// sample is still spread across the whole input.
+const MIN_CHUNK_SIZE: usize = 4 * 1024;
+const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+
+// Aim for at least this many sampled chunks when the input is large enough.
+const TARGET_SAMPLED_CHUNKS: usize = 64;
+
+// Two-sided 95% quantile of the standard normal distribution.
+const Z_95: f64 = 1.96;
+
+// Station statistics plus the sum of squares needed for confidence intervals.
+// With `--compensated`, the sum of readings is also kept in a compensated
+// `f64` sum, which replaces the `f32` total.
+struct SampleData {
+    data: StationData,
+    total: Sum,
+    total_sq: Sum,
+}
+
+impl SampleData {
+    fn new(compensated: bool) -> Self {
+        SampleData {
+            data: StationData::new(),
+            total: Sum::new(compensated),
+            total_sq: Sum::new(compensated),
+        }
+    }
+
+    fn update(&mut self, temp: f32) {
+        self.data.update(temp);
+        if self.total.is_compensated() {
+            self.total.add(temp as f64);
+        }
+        self.total_sq.add(temp as f64 * temp as f64);
+    }
+
+    fn aggregate(&mut self, other: &SampleData) {
+        self.data.aggregate(&other.data);
+        self.total.merge(&other.total);
+        self.total_sq.merge(&other.total_sq);
+    }
+
+    fn sum(&self) -> f64 {
+        if self.total.is_compensated() {
+            self.total.value()
+        } else {
+            self.data.total_temp as f64
+        }
+    }
+
+    fn mean(&self) -> f32 {
+        if self.total.is_compensated() {
+            (self.total.value() / self.data.count as f64) as f32
+        } else {
+            self.data.total_temp / self.data.count as f32
+        }
+    }
+
+    // Half-width of the 95% confidence interval of the mean, if defined.
+    fn mean_ci(&self) -> Option<f64> {
+        let n = self.data.count as f64;
+        if self.data.count < 2 {
+            return None;
+        }
+        let sum = self.sum();
+        let variance = ((self.total_sq.value() - sum * sum / n) / (n - 1.0)).max(0.0);
+        Some(Z_95 * (variance / n).sqrt())
+    }
+}
+
+// Processes roughly `fraction` of the input by aggregating every n-th chunk,
+// then prints the estimated statistics with 95% confidence intervals for the
+// means. Minimum and maximum are the extremes observed in the sample.
+pub fn run(
+    content: &str,
+    fraction: f64,
+    collation: Option<&Collation>,
+    delimiter: u8,
+    notation: Notation,
+    compensated: bool,
+) {
+    let bytes = content.as_bytes();
+    let chunk_size = ((bytes.len() as f64 * fraction) as usize / TARGET_SAMPLED_CHUNKS)
+        .clamp(MIN_CHUNK_SIZE, MAX_CHUNK_SIZE);
+    let chunk_count = bytes.len().div_ceil(chunk_size);
+    let stride = ((1.0 / fraction).round() as usize).max(1);
+
+    let (sampled_bytes, sampled_rows, results) = (0..chunk_count)
+        .step_by(stride)
+        .par_bridge()
+        .map(|index| {
+            let start = align_to_line(bytes, index * chunk_size);
+            let end = align_to_line(bytes, ((index + 1) * chunk_size).min(bytes.len()));
+            let mut acc = HashMap::with_capacity(ESTIMATED_UNIQUE_STATIONS);
+            let mut rows = 0;
+            for line in content[start..end].lines() {
+                // Malformed lines are ignored; the sample is an estimate
+                // anyway.
+                let Ok((station, temp)) = parse_line_in(line, delimiter, notation) else {
+                    continue;
+                };
+                acc.entry_ref(station)
+                    .or_insert_with(|| SampleData::new(compensated))
+                    .update(temp);
+                rows += 1;
+            }
+            (end - start, rows, acc)
+        })
+        .reduce(
+            || (0, 0u64, HashMap::new()),
+            |(bytes_a, rows_a, mut acc), (bytes_b, rows_b, h)| {
+                for (station, data) in h {
+                    acc.entry(station)
+                        .and_modify(|e: &mut SampleData| e.aggregate(&data))
+                        .or_insert(data);
+                }
+                (bytes_a + bytes_b, rows_a + rows_b, acc)
+            },
+        );
+
+    let mut formatted_results: Vec<_> = results
+        .into_iter()
+        .map(|(name, sample)| {
+            let data = &sample.data;
+            let mean = sample.mean();
+            let ci = match sample.mean_ci() {
+                Some(ci) => format!("±{:.1}", ci),
+                None => String::new(),
+            };
+            (
+                name,
+                format!(
+                    "{:.1}/{:.1}{}/{:.1}",
+                    data.min_temp, mean, ci, data.max_temp
+                ),
+            )
+        })
+        .collect();
+
+    println!("{}", format_output(&mut formatted_results, collation));
+
+    let coverage = if bytes.is_empty() {
+        1.0
+    } else {
+        sampled_bytes as f64 / bytes.len() as f64
+    };
+    println!(
+        "Sampled {:.2}% of input ({} of {} bytes, {} rows); estimated total rows: {:.0}",
+        coverage * 100.0,
+        sampled_bytes,
+        bytes.len(),
+        sampled_rows,
+        sampled_rows as f64 / coverage.max(f64::MIN_POSITIVE)
+    );
+}
@@ -0,0 +1,119 @@
+// Alternatives to Rayon's adaptive splitting of lines for the in-memory
+// strategy, selected with `--schedule`.
+
+use crate::cache;
+use crate::cli::Options;
+use crate::error::Error;
+use crate::interrupt;
+use crate::spill::Spill;
+use crate::thread_stats::ThreadStats;
+use crate::{fold_line, Partial};
+use brc::chunks::{self, Chunk};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::Instant;
+
+// Default size of the chunks handed out by the dynamic scheduler; small
+// enough that a thread stuck in a cold region only delays its last chunk.
+const DYNAMIC_CHUNK_SIZE: usize = 2 * 1024 * 1024;
+
+// Aggregates chunks grabbed from a global atomic cursor, so faster threads
+// take over the work of slower ones.
+pub fn dynamic<'a>(
+    content: &'a str,
+    options: &Options,
+    spill: Option<&Spill>,
+    stats: Option<&ThreadStats>,
+) -> Result<Partial<'a>, Error> {
+    let chunks = chunks::chunks(content, options.chunk_size.unwrap_or(DYNAMIC_CHUNK_SIZE));
+    let cursor = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    run_workers(spill, stats, |_| {
+        let mut partial = Partial::new(options);
+        while !failed.load(Ordering::Relaxed) && !interrupt::requested() {
+            let Some(&chunk) = chunks.get(cursor.fetch_add(1, Ordering::Relaxed)) else {
+                break;
+            };
+            partial = fold_chunk(partial, chunk, options, spill).inspect_err(|_| {
+                failed.store(true, Ordering::Relaxed);
+            })?;
+            if options.windowed {
+                cache::release(
+                    content.as_bytes(),
+                    chunk.offset..chunk.offset + chunk.data.len(),
+                );
+            }
+        }
+        Ok(partial)
+    })
+}
+
+// Aggregates exactly one contiguous, equally sized partition per thread, so
+// the division of work is identical from run to run.
+pub fn fixed<'a>(
+    content: &'a str,
+    options: &Options,
+    spill: Option<&Spill>,
+    stats: Option<&ThreadStats>,
+) -> Result<Partial<'a>, Error> {
+    let threads = rayon::current_num_threads();
+    let partitions = chunks::chunks(content, content.len().div_ceil(threads).max(1));
+    run_workers(spill, stats, |index| match partitions.get(index) {
+        Some(&partition) => fold_chunk(Partial::new(options), partition, options, spill),
+        None => Ok(Partial::empty()),
+    })
+}
+
+// Runs `work` on one scoped thread per configured worker, passing each its
+// index, and merges their results in index order.
+fn run_workers<'a, F>(
+    spill: Option<&Spill>,
+    stats: Option<&ThreadStats>,
+    work: F,
+) -> Result<Partial<'a>, Error>
+where
+    F: Fn(usize) -> Result<Partial<'a>, Error> + Sync,
+{
+    let results: Vec<_> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..rayon::current_num_threads())
+            .map(|index| {
+                let work = &work;
+                scope.spawn(move || -> Result<Partial<'a>, Error> {
+                    let start = Instant::now();
+                    let partial = work(index)?;
+                    if let Some(stats) = stats {
+                        stats.record(index, &partial, start.elapsed());
+                    }
+                    Ok(partial)
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("aggregation worker panicked"))
+            .collect()
+    });
+
+    let mut merged = Partial::empty();
+    for result in results {
+        merged = merged.merge(result?)?;
+        merged.enforce_budget(spill)?;
+    }
+    Ok(merged)
+}
+
+// Folds every line of `chunk` into `partial`.
+pub fn fold_chunk<'a>(
+    mut partial: Partial<'a>,
+    chunk: Chunk<'a>,
+    options: &Options,
+    spill: Option<&Spill>,
+) -> Result<Partial<'a>, Error> {
+    for line in chunk.data.lines() {
+        if interrupt::requested() {
+            break;
+        }
+        fold_line(&mut partial, chunk, line, options, spill)?;
+    }
+    Ok(partial)
+}
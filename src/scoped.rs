@@ -0,0 +1,71 @@
+// The `scoped` strategy: only `std::thread::scope`, with one equal byte
+// partition per thread and a parallel pairwise merge, to measure what Rayon
+// costs for this workload.
+
+use crate::cli::Options;
+use crate::error::Error;
+use crate::schedule::fold_chunk;
+use crate::spill::Spill;
+use crate::thread_stats::ThreadStats;
+use crate::topology::Topology;
+use crate::Partial;
+use brc::chunks;
+use std::thread;
+use std::time::Instant;
+
+pub fn aggregate<'a>(
+    content: &'a str,
+    options: &Options,
+    spill: Option<&Spill>,
+    stats: Option<&ThreadStats>,
+) -> Result<Partial<'a>, Error> {
+    let threads = options
+        .threads
+        .unwrap_or_else(|| Topology::detect().default_threads());
+    let partitions = chunks::chunks(content, content.len().div_ceil(threads).max(1));
+
+    let mut partials = thread::scope(|scope| {
+        let workers: Vec<_> = partitions
+            .iter()
+            .enumerate()
+            .map(|(index, &partition)| {
+                scope.spawn(move || -> Result<Partial<'a>, Error> {
+                    let start = Instant::now();
+                    let partial = fold_chunk(Partial::new(options), partition, options, spill)?;
+                    if let Some(stats) = stats {
+                        stats.record(index, &partial, start.elapsed());
+                    }
+                    Ok(partial)
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("aggregation worker panicked"))
+            .collect::<Result<Vec<_>, Error>>()
+    })?;
+
+    // Merge neighbouring partials in parallel, halving their number each round.
+    while partials.len() > 1 {
+        partials = thread::scope(|scope| {
+            let mut pending = partials.into_iter();
+            let mut merges = Vec::new();
+            while let Some(a) = pending.next() {
+                let b = pending.next();
+                merges.push(scope.spawn(move || -> Result<Partial<'a>, Error> {
+                    let Some(b) = b else {
+                        return Ok(a);
+                    };
+                    let mut merged = a.merge(b)?;
+                    merged.enforce_budget(spill)?;
+                    Ok(merged)
+                }));
+            }
+            merges
+                .into_iter()
+                .map(|merge| merge.join().expect("merge worker panicked"))
+                .collect::<Result<Vec<_>, Error>>()
+        })?;
+    }
+    Ok(partials.pop().unwrap_or_else(Partial::empty))
+}
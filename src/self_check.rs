@@ -0,0 +1,141 @@
+// `--self-check`: after the fast run, a random sample of line-aligned chunks
+// of the input is aggregated again by a plain sequential loop over `lines()`
+// into a std `HashMap`, and checked against the final stations. Every station
+// read in the sample must be in the results, with at least as many readings
+// and extremes at least as wide. A line split at a partition boundary turns
+// into a station fragment the results lack, or a dropped reading, so these
+// show up without a full slow run. When every chunk is sampled, the counts
+// and extremes must match exactly.
+
+use crate::aliases;
+use crate::cli::Options;
+use crate::error::Error;
+use crate::filter;
+use crate::key_hash::{StationKey, StationMap};
+use crate::utf8;
+use brc::chunks;
+use brc::parse::parse_line_in;
+use brc::station::StationData;
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::collections::HashMap;
+use std::hash::{BuildHasher, BuildHasherDefault};
+
+// Size of a checked chunk, and how many are checked.
+const CHUNK_SIZE: usize = 1024 * 1024;
+const SAMPLED_CHUNKS: usize = 16;
+
+// Checks `stations`, aggregated from `input`, against a sample of its chunks,
+// returning the number of chunks checked and in the input.
+pub fn check(
+    input: &[u8],
+    stations: &StationMap,
+    options: &Options,
+) -> Result<(usize, usize), Error> {
+    let ranges = chunks::split(input, CHUNK_SIZE);
+    // Orders chunks by a randomly keyed hash of their index, or a fixed one
+    // with `--reproducible`.
+    let state = RandomState::new();
+    let fixed = BuildHasherDefault::<DefaultHasher>::default();
+    let mut sampled: Vec<_> = (0..ranges.len()).collect();
+    sampled.sort_by_key(|&index| {
+        if options.reproducible {
+            fixed.hash_one(index)
+        } else {
+            state.hash_one(index)
+        }
+    });
+    sampled.truncate(SAMPLED_CHUNKS);
+    let complete = sampled.len() == ranges.len();
+
+    let decoded: Vec<_> = sampled
+        .iter()
+        .map(|&index| {
+            let range = ranges[index].clone();
+            utf8::decode_block(
+                &input[range.clone()],
+                range.start,
+                options.utf8,
+                options.delimiter,
+            )
+            .map(|content| (range.start, content))
+        })
+        .collect::<Result<_, _>>()?;
+
+    // Per station, its statistics in the sample and the offset of a chunk
+    // it was read in.
+    let mut sample: HashMap<&str, (StationData, usize)> = HashMap::new();
+    for (offset, content) in &decoded {
+        for line in content.lines() {
+            let Ok((station, temp)) = parse_line_in(line, options.delimiter, options.notation)
+            else {
+                continue;
+            };
+            if !filter::admits(options, station) {
+                continue;
+            }
+            let station = aliases::resolve(options, station);
+            sample
+                .entry(station)
+                .or_insert_with(|| (StationData::new(), *offset))
+                .0
+                .update(temp);
+        }
+    }
+
+    let mut problems = Vec::new();
+    for (station, (data, offset)) in &sample {
+        let Some(total) = stations.get(&StationKey(station)) else {
+            problems.push(format!(
+                "`{}`, read in the chunk at byte {}, is missing from the results",
+                station, offset
+            ));
+            continue;
+        };
+        let consistent = if complete {
+            data.count == total.count
+                && data.min_temp == total.min_temp
+                && data.max_temp == total.max_temp
+        } else {
+            data.count <= total.count
+                && data.min_temp >= total.min_temp
+                && data.max_temp <= total.max_temp
+        };
+        if !consistent {
+            problems.push(format!(
+                "`{}` has {} readings in {:.1}..={:.1} in the sample, but {} in {:.1}..={:.1} \
+                 in the results",
+                station,
+                data.count,
+                data.min_temp,
+                data.max_temp,
+                total.count,
+                total.min_temp,
+                total.max_temp
+            ));
+        }
+    }
+    if complete && stations.len() > sample.len() {
+        let mut extra: Vec<_> = stations
+            .keys()
+            .filter(|station| !sample.contains_key(station.as_str()))
+            .collect();
+        extra.sort_unstable();
+        for station in extra {
+            problems.push(format!(
+                "`{}` is in the results but not in the input",
+                station
+            ));
+        }
+    }
+
+    if !problems.is_empty() {
+        problems.sort_unstable();
+        let examples: Vec<_> = problems.iter().take(5).map(String::as_str).collect();
+        return Err(Error::Validation(format!(
+            "self-check found {} inconsistent stations, e.g. {}",
+            problems.len(),
+            examples.join("; ")
+        )));
+    }
+    Ok((sampled.len(), ranges.len()))
+}
@@ -0,0 +1,2523 @@
+use crate::aliases;
+use crate::bench::Runs;
+use crate::cgroup;
+use crate::cli::{Implementation, Options, Recover, Schedule};
+use crate::clickhouse;
+use crate::config;
+use crate::cuda;
+use crate::energy::Meter;
+use crate::error::Error;
+use crate::exact;
+use crate::extra_stats::{self, Stat};
+use crate::filter;
+use crate::fingerprint::{self, Fingerprinted};
+use crate::history;
+use crate::hll::{self, HyperLogLog};
+use crate::hot_path;
+use crate::index_map::MapKind;
+use crate::interrupt;
+use crate::journal::{Header, Journal};
+use crate::key_hash::{self, KeyHash, StationMap};
+use crate::logging::{self, LogFormat};
+use crate::memory_limit;
+use crate::normalize;
+use crate::output::{self, Format, SortKey, SortOrder};
+use crate::perfect_hash::{self, PerfectHash};
+use crate::pipeline;
+use crate::proto;
+use crate::provenance;
+use crate::reproducible;
+use crate::self_check;
+use crate::status;
+use crate::system::System;
+use crate::utf8::{self, Utf8};
+use crate::{aggregate, collect_stations, map_input, merge_maps, new_spill, print_results};
+use brc::aggregator::{self, ChunkedAggregator, StreamingAggregator};
+use brc::mapped::{self, MappedResults};
+use brc::parse::{parse_temperature_in, Notation, BOM};
+use brc::records::for_each_record;
+use brc::rounding::Rounding;
+use brc::state;
+use brc::station::{Overflow, StationData};
+use brc::text;
+use brc::window;
+use hashbrown::HashMap;
+use prost::Message;
+use std::borrow::Cow;
+use std::env;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Records of the self-test dataset. They are repeated many times so the
+// parallel strategies split the input across threads and chunks.
+const RECORDS: &[&str] = &[
+    "Hamburg;12.0",
+    "Bulawayo;8.9",
+    "Palembang;38.8",
+    "St. John's;15.2",
+    "Cracow;12.6",
+    "Bridgetown;26.9",
+    "Istanbul;6.2",
+    "Roseau;34.4",
+    "Conakry;31.2",
+    "Istanbul;23.0",
+    "Hamburg;-3.4",
+    "Zürich;-7.1",
+    "Zürich;0.0",
+    "Zürich;2.3",
+    "Ürümqi;-17.9",
+    "Ürümqi;-26.3",
+    "Cracow;-0.4",
+    "Llanfairpwllgwyngyll;-99.9",
+    "Llanfairpwllgwyngyll;99.9",
+    "a;0.1",
+    "Hamburg;8.9",
+];
+const REPETITIONS: usize = 5000;
+
+// Lines appended to the dataset for the malformed-input cases.
+const MALFORMED: &[&str] = &[
+    "no delimiter",
+    ";5.0",
+    "Hamburg;warm",
+    "Hamburg;12.34",
+    "Hamburg;123.4",
+    // 101-byte station name.
+    concat!(
+        "Llanfairpwllgwyngyllgogerychwyrndrobwllllantysiliogogogoch",
+        "Llanfairpwllgwyngyllgogerychwyrndrobwllllan;1.0"
+    ),
+];
+
+// Lines appended to the dataset for the `--recover last-field` case: one
+// with an extra field, recovered without changing the expected output, and
+// one with a missing value.
+const RECOVERABLE: &[&str] = &["Bridgetown;note;26.9", "Roseau;"];
+
+// Expected output for the dataset, computed independently of this crate.
+const EXPECTED: &str = "{Bridgetown=26.9/26.9/26.9, Bulawayo=8.9/8.9/8.9, \
+    Conakry=31.2/31.2/31.2, Cracow=-0.4/6.1/12.6, Hamburg=-3.4/5.8/12.0, \
+    Istanbul=6.2/14.6/23.0, Llanfairpwllgwyngyll=-99.9/0.0/99.9, \
+    Palembang=38.8/38.8/38.8, Roseau=34.4/34.4/34.4, St. John's=15.2/15.2/15.2, \
+    Zürich=-7.1/-1.6/2.3, a=0.1/0.1/0.1, Ürümqi=-26.3/-22.1/-17.9}\n";
+
+// Generates the dataset in a temporary directory, runs every aggregation
+// strategy on it and compares the results with the expected values.
+pub fn run(options: &Options) -> Result<(), Error> {
+    let dir = env::temp_dir().join(format!("brc-selftest-{}", process::id()));
+    fs::create_dir_all(&dir)?;
+    let result = run_in(&dir, options);
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+fn run_in(dir: &Path, options: &Options) -> Result<(), Error> {
+    let mut dataset = String::with_capacity(REPETITIONS * RECORDS.len() * 16);
+    for _ in 0..REPETITIONS {
+        for record in RECORDS {
+            dataset.push_str(record);
+            dataset.push('\n');
+        }
+    }
+    let clean_path = dir.join("measurements.txt");
+    fs::write(&clean_path, &dataset)?;
+    for line in MALFORMED {
+        dataset.push_str(line);
+        dataset.push('\n');
+    }
+    let malformed_path = dir.join("malformed.txt");
+    fs::write(&malformed_path, &dataset)?;
+
+    let clean = map_input(&clean_path)?;
+    let clean = unsafe { std::str::from_utf8_unchecked(&clean) };
+    let malformed = map_input(&malformed_path)?;
+    let malformed = unsafe { std::str::from_utf8_unchecked(&malformed) };
+    let with_bom = format!("{}{}", BOM, clean);
+    let mut recoverable = clean.to_string();
+    for line in RECOVERABLE {
+        recoverable.push_str(line);
+        recoverable.push('\n');
+    }
+
+    // Baked builds would otherwise default to two-pass.
+    let base = Options {
+        implementation: Implementation::Rayon,
+        delimiter: b';',
+        lenient: false,
+        max_memory: None,
+        schedule: Schedule::Adaptive,
+        chunk_size: None,
+        ..options.clone()
+    };
+    let with = |implementation, max_memory, lenient| Options {
+        implementation,
+        max_memory,
+        lenient,
+        ..base.clone()
+    };
+
+    let mut cases: Vec<(&str, Result<(), String>)> = vec![
+        (
+            "rayon",
+            check(clean, &with(Implementation::Rayon, None, false), 0),
+        ),
+        (
+            "rayon --max-memory 1",
+            check(clean, &with(Implementation::Rayon, Some(1), false), 0),
+        ),
+        (
+            "external",
+            check(clean, &with(Implementation::External, None, false), 0),
+        ),
+        (
+            "dynamic",
+            check(
+                clean,
+                &Options {
+                    schedule: Schedule::Dynamic,
+                    chunk_size: Some(4096),
+                    ..with(Implementation::Rayon, None, false)
+                },
+                0,
+            ),
+        ),
+        (
+            "dynamic --lenient",
+            check(
+                malformed,
+                &Options {
+                    schedule: Schedule::Dynamic,
+                    chunk_size: Some(4096),
+                    ..with(Implementation::Rayon, Some(1), true)
+                },
+                MALFORMED.len(),
+            ),
+        ),
+        (
+            "static",
+            check(
+                clean,
+                &Options {
+                    schedule: Schedule::Static,
+                    ..with(Implementation::Rayon, None, false)
+                },
+                0,
+            ),
+        ),
+        (
+            "static --lenient",
+            check(
+                malformed,
+                &Options {
+                    schedule: Schedule::Static,
+                    ..with(Implementation::Rayon, Some(1), true)
+                },
+                MALFORMED.len(),
+            ),
+        ),
+        (
+            "scoped",
+            check(clean, &with(Implementation::Scoped, None, false), 0),
+        ),
+        (
+            "scoped --lenient",
+            check(
+                malformed,
+                &Options {
+                    threads: Some(3),
+                    ..with(Implementation::Scoped, Some(1), true)
+                },
+                MALFORMED.len(),
+            ),
+        ),
+        (
+            "per-core --threads 5",
+            check(
+                clean,
+                &Options {
+                    threads: Some(5),
+                    ..with(Implementation::PerCore, None, false)
+                },
+                0,
+            ),
+        ),
+        (
+            "per-core --lenient",
+            check(
+                malformed,
+                &Options {
+                    threads: Some(3),
+                    ..with(Implementation::PerCore, Some(1), true)
+                },
+                MALFORMED.len(),
+            ),
+        ),
+        (
+            "pipeline",
+            check(
+                clean,
+                &Options {
+                    path: clean_path.display().to_string(),
+                    chunk_size: Some(4096),
+                    ..with(Implementation::Pipeline, None, false)
+                },
+                0,
+            ),
+        ),
+        (
+            "pipeline --lenient",
+            check(
+                malformed,
+                &Options {
+                    path: malformed_path.display().to_string(),
+                    chunk_size: Some(4096),
+                    ..with(Implementation::Pipeline, Some(1), true)
+                },
+                MALFORMED.len(),
+            ),
+        ),
+        (
+            "std",
+            check(
+                clean,
+                &Options {
+                    path: clean_path.display().to_string(),
+                    threads: Some(3),
+                    ..with(Implementation::Std, None, false)
+                },
+                0,
+            ),
+        ),
+        (
+            "std --lenient",
+            check(
+                malformed,
+                &Options {
+                    path: malformed_path.display().to_string(),
+                    ..with(Implementation::Std, None, true)
+                },
+                MALFORMED.len(),
+            ),
+        ),
+        ("ranged reads", check_ranges(clean, &base, 0)),
+        (
+            "ranged reads --lenient",
+            check_ranges(
+                malformed,
+                &Options {
+                    lenient: true,
+                    ..base.clone()
+                },
+                MALFORMED.len(),
+            ),
+        ),
+        (
+            "ranged reads resumed",
+            check_resume(clean, &base, &dir.join("journal.jsonl")),
+        ),
+        (
+            "rayon --lenient",
+            check(
+                malformed,
+                &with(Implementation::Rayon, None, true),
+                MALFORMED.len(),
+            ),
+        ),
+        (
+            "external --lenient",
+            check(
+                malformed,
+                &with(Implementation::External, None, true),
+                MALFORMED.len(),
+            ),
+        ),
+        (
+            "rayon strict",
+            check_rejected(malformed, &with(Implementation::Rayon, None, false)),
+        ),
+        (
+            "external strict",
+            check_rejected(malformed, &with(Implementation::External, None, false)),
+        ),
+        (
+            "dynamic strict",
+            check_rejected(
+                malformed,
+                &Options {
+                    schedule: Schedule::Dynamic,
+                    ..with(Implementation::Rayon, None, false)
+                },
+            ),
+        ),
+        (
+            "static strict",
+            check_rejected(
+                malformed,
+                &Options {
+                    schedule: Schedule::Static,
+                    ..with(Implementation::Rayon, None, false)
+                },
+            ),
+        ),
+        (
+            "scoped strict",
+            check_rejected(malformed, &with(Implementation::Scoped, None, false)),
+        ),
+        (
+            "per-core strict",
+            check_rejected(
+                malformed,
+                &Options {
+                    threads: Some(4),
+                    ..with(Implementation::PerCore, None, false)
+                },
+            ),
+        ),
+        (
+            "pipeline strict",
+            check_rejected(
+                malformed,
+                &Options {
+                    path: malformed_path.display().to_string(),
+                    ..with(Implementation::Pipeline, None, false)
+                },
+            ),
+        ),
+        (
+            "two-pass",
+            check(clean, &with(Implementation::TwoPass, None, false), 0),
+        ),
+        (
+            "two-pass --lenient",
+            check(
+                malformed,
+                &with(Implementation::TwoPass, None, true),
+                MALFORMED.len(),
+            ),
+        ),
+        (
+            "two-pass with BOM",
+            check(&with_bom, &with(Implementation::TwoPass, None, false), 0),
+        ),
+        (
+            "rayon --key-hash prefix",
+            check(
+                clean,
+                &Options {
+                    key_hash: KeyHash::Prefix,
+                    ..with(Implementation::Rayon, None, false)
+                },
+                0,
+            ),
+        ),
+        (
+            "rayon --key-hash fingerprint",
+            check(
+                clean,
+                &Options {
+                    key_hash: KeyHash::Fingerprint,
+                    verify_fingerprints: true,
+                    ..with(Implementation::Rayon, None, false)
+                },
+                0,
+            ),
+        ),
+        (
+            "fingerprint --lenient",
+            check(
+                malformed,
+                &Options {
+                    key_hash: KeyHash::Fingerprint,
+                    ..with(Implementation::Rayon, None, true)
+                },
+                MALFORMED.len(),
+            ),
+        ),
+        (
+            "rayon --map index",
+            check(
+                clean,
+                &Options {
+                    map: MapKind::Index,
+                    ..with(Implementation::Rayon, None, false)
+                },
+                0,
+            ),
+        ),
+        (
+            "index map --lenient --key-hash prefix",
+            check(
+                malformed,
+                &Options {
+                    map: MapKind::Index,
+                    key_hash: KeyHash::Prefix,
+                    ..with(Implementation::Rayon, None, true)
+                },
+                MALFORMED.len(),
+            ),
+        ),
+        (
+            "fingerprint collision",
+            check_fingerprint_collision(clean, &base),
+        ),
+        (
+            "prefix collisions",
+            check_key_hash(&with(Implementation::Rayon, None, false)),
+        ),
+        ("self-check", check_self_check(clean, &base)),
+        ("perfect hash", check_perfect_hash()),
+        (
+            "--stations missing one",
+            check_stations(clean, &base, &dir.join("stations.csv")),
+        ),
+        (
+            "rayon with BOM",
+            check(&with_bom, &with(Implementation::Rayon, None, false), 0),
+        ),
+        (
+            "rayon --recover",
+            check_recovered(
+                &recoverable,
+                &Options {
+                    recover: Recover::LastField,
+                    ..with(Implementation::Rayon, None, false)
+                },
+            ),
+        ),
+        ("sort by count, name", check_sorted(clean, &base)),
+        (
+            "--no-sort",
+            check_unsorted(clean, &base, &dir.join("unsorted.md")),
+        ),
+        (
+            "discard output",
+            check_discard(clean, &base, &dir.join("discarded.txt")),
+        ),
+        ("proto round trip", check_proto(clean, &base)),
+        ("rowbinary rows", check_rowbinary(clean, &base)),
+        (
+            "group by column 2",
+            check_grouped(
+                &with(Implementation::Rayon, None, false),
+                &dir.join("grouped.txt"),
+            ),
+        ),
+        ("window 1h", check_windowed(&base)),
+        ("hyperloglog estimate", check_hll(clean)),
+        ("--aliases", check_aliases(clean, &base, dir)),
+        ("--trim --case-insensitive", check_normalized(&base)),
+        ("station filter", check_filter(clean, &base, dir)),
+        ("hot path statistics", check_hot_path()),
+        ("text output buffer", check_text_output()),
+        (
+            "rayon --provenance",
+            check_provenance(clean, &with(Implementation::Rayon, None, false)),
+        ),
+        (
+            "scoped --provenance",
+            check_provenance(clean, &with(Implementation::Scoped, None, false)),
+        ),
+        (
+            "rayon --stats mode",
+            check_mode(clean, &with(Implementation::Rayon, None, false)),
+        ),
+        (
+            "scoped --stats mode",
+            check_mode(clean, &with(Implementation::Scoped, None, false)),
+        ),
+        (
+            "pipeline --first-last",
+            check_first_last(
+                clean,
+                &Options {
+                    path: clean_path.display().to_string(),
+                    chunk_size: Some(4096),
+                    ..with(Implementation::Pipeline, None, false)
+                },
+            ),
+        ),
+        (
+            "rayon dynamic --first-last",
+            check_first_last(
+                clean,
+                &Options {
+                    schedule: Schedule::Dynamic,
+                    chunk_size: Some(4096),
+                    ..with(Implementation::Rayon, None, false)
+                },
+            ),
+        ),
+        (
+            "pipeline group by column 2",
+            check_grouped(
+                &with(Implementation::Pipeline, None, false),
+                &dir.join("grouped.txt"),
+            ),
+        ),
+        (
+            "mmap round trip",
+            check_mapped(clean, &base, &dir.join("results.brcm")),
+        ),
+        (
+            "state bincode",
+            check_state(clean, &base, &dir.join("state.bin")),
+        ),
+        (
+            "state json",
+            check_state(clean, &base, &dir.join("state.json")),
+        ),
+        ("exact", check_exact(clean, false, 0)),
+        ("rounding", check_rounding()),
+        ("bench statistics", check_bench_statistics()),
+        ("bench history", check_history(dir)),
+        ("energy counters", check_energy(dir)),
+        ("reproducible", check_reproducible(clean, &base)),
+        ("memory limit", check_memory_limit(clean, &base, dir)),
+        ("interrupted", check_interrupted(clean, &base)),
+        ("status socket", check_status(dir)),
+        ("json logs", check_json_log()),
+        ("--decimal-comma", check_decimal_comma(clean, &base)),
+        (
+            "exact --lenient",
+            check_exact(malformed, true, MALFORMED.len()),
+        ),
+        (
+            "exact strict",
+            match exact::aggregate(malformed, b';', Notation::DEFAULT, false) {
+                Err(Error::Parse(_)) => Ok(()),
+                Err(e) => Err(format!("expected a parse error, got: {}", e)),
+                Ok(_) => Err("expected a parse error, got none".to_string()),
+            },
+        ),
+        ("overflow detection", check_overflow()),
+        ("config precedence", check_config()),
+        ("--utf8 lossy", check_lossy()),
+        ("chunked iterator", check_chunked(&clean_path, clean.len())),
+        ("record visitor", check_records(&clean_path)),
+        ("typed results", check_results(&clean_path)),
+        ("streaming aggregator", check_streaming(clean)),
+        (
+            "stdin --queue-depth 1",
+            check_reader(clean, &with(Implementation::Pipeline, None, false)),
+        ),
+    ];
+    // Needs an adapter, if only a software one, where the backend is built.
+    if cfg!(feature = "gpu") {
+        cases.push((
+            "gpu",
+            check(clean, &with(Implementation::Gpu, None, false), 0),
+        ));
+        cases.push((
+            "gpu --lenient",
+            check(
+                malformed,
+                &with(Implementation::Gpu, None, true),
+                MALFORMED.len(),
+            ),
+        ));
+    }
+    // Only where a CUDA device and its driver are present, unlike `gpu`.
+    if cuda::available() {
+        cases.push((
+            "cuda",
+            check(clean, &with(Implementation::Cuda, None, false), 0),
+        ));
+        cases.push((
+            "cuda --lenient",
+            check(
+                malformed,
+                &with(Implementation::Cuda, None, true),
+                MALFORMED.len(),
+            ),
+        ));
+    }
+
+    let mut failures = 0;
+    for (name, result) in &cases {
+        match result {
+            Ok(()) => println!("selftest {:<24} ok", name),
+            Err(message) => {
+                failures += 1;
+                println!("selftest {:<24} FAILED: {}", name, message);
+            }
+        }
+    }
+
+    if failures > 0 {
+        return Err(Error::Validation(format!(
+            "{} of {} self-test cases failed",
+            failures,
+            cases.len()
+        )));
+    }
+    Ok(())
+}
+
+// Checks that a `false` in the environment turns off a flag the config file
+// sets, and that the command line still turns it back on.
+fn check_config() -> Result<(), String> {
+    let lenient = |command_line: &[&str]| -> Result<bool, String> {
+        let args = config::collect_args(
+            Some((Path::new("1brc.toml"), "lenient = true\nthreads = 2\n")),
+            [("ONEBRC_LENIENT".to_string(), "false".to_string())],
+        )
+        .map_err(|e| e.to_string())?;
+        let args = args
+            .into_iter()
+            .chain(command_line.iter().map(|arg| arg.to_string()));
+        let options = Options::parse(args).map_err(|e| e.to_string())?;
+        if options.threads != Some(2) {
+            return Err(format!("expected 2 threads, got {:?}", options.threads));
+        }
+        Ok(options.lenient)
+    };
+    if lenient(&[])? {
+        return Err("`ONEBRC_LENIENT=false` left `lenient = true` on".to_string());
+    }
+    if !lenient(&["--lenient"])? {
+        return Err("`--lenient` did not override `ONEBRC_LENIENT=false`".to_string());
+    }
+    Ok(())
+}
+
+// Checks that `--utf8 lossy` repairs only the station names of the lines with
+// invalid sequences, and rejects one in a temperature.
+fn check_lossy() -> Result<(), String> {
+    let input = b"Abha;1.0\nBad\xffName;2.0\nZ\xc3rich;3.0\n";
+    let expected = "Abha;1.0\nBad\u{fffd}Name;2.0\nZ\u{fffd}rich;3.0\n";
+    let decoded = utf8::decode(input, Utf8::Lossy, b';').map_err(|e| e.to_string())?;
+    if decoded != expected {
+        return Err(format!("expected {:?}, got {:?}", expected, decoded));
+    }
+    let decoded = utf8::decode_block(input, 0, Utf8::Lossy, b';').map_err(|e| e.to_string())?;
+    if decoded != expected {
+        return Err(format!("block: expected {:?}, got {:?}", expected, decoded));
+    }
+    match utf8::decode(b"Abha;1.0\nBern;2.\xff\n", Utf8::Lossy, b';') {
+        Err(Error::Parse(message)) if message.ends_with("byte 16") => Ok(()),
+        Err(e) => Err(format!("expected an error at byte 16, got: {}", e)),
+        Ok(decoded) => Err(format!("repaired a temperature to {:?}", decoded)),
+    }
+}
+
+// Aggregates `content` and compares the output and skipped-line count.
+fn check(content: &str, options: &Options, expected_skipped: usize) -> Result<(), String> {
+    let run = || -> Result<(String, usize), Error> {
+        let spill = new_spill(options)?;
+        let mut partial = aggregate(content, options, spill.as_ref(), None)?;
+        let stations = collect_stations(&mut partial, spill.as_ref())?;
+        Ok((
+            output::render(&stations, Format::Text, false, None),
+            partial.skipped.len(),
+        ))
+    };
+    let (actual, skipped) = run().map_err(|e| e.to_string())?;
+
+    if actual != EXPECTED {
+        return Err(format!("expected {:?}, got {:?}", EXPECTED, actual));
+    }
+    if skipped != expected_skipped {
+        return Err(format!(
+            "expected {} skipped lines, got {}",
+            expected_skipped, skipped
+        ));
+    }
+    Ok(())
+}
+
+// Aggregates `content` as if fetched remotely in ranges much shorter than
+// some lines, and checks the output and that skipped lines are reported at
+// their offsets in `content`.
+fn check_ranges(content: &str, options: &Options, expected_skipped: usize) -> Result<(), String> {
+    let fetch = |start: u64, end: u64, buffer: &mut Vec<u8>| {
+        buffer.extend_from_slice(&content.as_bytes()[start as usize..end as usize]);
+        Ok(())
+    };
+    let run = || -> Result<(String, Vec<(usize, String)>), Error> {
+        let mut partial =
+            pipeline::aggregate_ranges(content.len() as u64, 37, fetch, None, options, None, None)?;
+        let stations = collect_stations(&mut partial, None)?;
+        let skipped = partial
+            .skipped
+            .iter()
+            .map(|skipped| (skipped.offset, skipped.line.to_string()))
+            .collect();
+        Ok((
+            output::render(&stations, Format::Text, false, None),
+            skipped,
+        ))
+    };
+    let (actual, skipped) = run().map_err(|e| e.to_string())?;
+
+    if actual != EXPECTED {
+        return Err(format!("expected {:?}, got {:?}", EXPECTED, actual));
+    }
+    if skipped.len() != expected_skipped {
+        return Err(format!(
+            "expected {} skipped lines, got {}",
+            expected_skipped,
+            skipped.len()
+        ));
+    }
+    match skipped
+        .iter()
+        .find(|(offset, line)| !content[*offset..].starts_with(line.as_str()))
+    {
+        Some((offset, line)) => Err(format!("{:?} reported at byte {}", line, offset)),
+        None => Ok(()),
+    }
+}
+
+// Interrupts a ranged read of `content` half-way, then resumes it from the
+// journal at `path` and compares the output.
+fn check_resume(content: &str, options: &Options, path: &Path) -> Result<(), String> {
+    const RANGE_SIZE: usize = 4096;
+    let len = content.len() as u64;
+    let header = Header {
+        url: "selftest".to_string(),
+        len,
+        range_size: RANGE_SIZE as u64,
+    };
+    let fetch = |cutoff: u64| {
+        move |start: u64, end: u64, buffer: &mut Vec<u8>| {
+            if start >= cutoff {
+                return Err(io::Error::other("interrupted"));
+            }
+            buffer.extend_from_slice(&content.as_bytes()[start as usize..end as usize]);
+            Ok(())
+        }
+    };
+    let run = || -> Result<(String, usize), Error> {
+        let _ = fs::remove_file(path);
+        let journal = Journal::open(path, &header)?;
+        let interrupted = pipeline::aggregate_ranges(
+            len,
+            RANGE_SIZE,
+            fetch(len / 2),
+            Some(&journal),
+            options,
+            None,
+            None,
+        );
+        if interrupted.is_ok() {
+            return Err(Error::Io(io::Error::other(
+                "the first run was not interrupted",
+            )));
+        }
+        drop(journal);
+
+        let mut journal = Journal::open(path, &header)?;
+        let resumed = journal.take_resumed();
+        let mut partial = pipeline::aggregate_ranges(
+            len,
+            RANGE_SIZE,
+            fetch(u64::MAX),
+            Some(&journal),
+            options,
+            None,
+            None,
+        )?;
+        partial.stations = merge_maps(partial.stations, resumed)?;
+        let stations = collect_stations(&mut partial, None)?;
+        let ranges = journal.resumed_ranges();
+        journal.remove()?;
+        Ok((output::render(&stations, Format::Text, false, None), ranges))
+    };
+    let (actual, ranges) = run().map_err(|e| e.to_string())?;
+
+    if actual != EXPECTED {
+        return Err(format!("expected {:?}, got {:?}", EXPECTED, actual));
+    }
+    if ranges == 0 {
+        return Err("no ranges were resumed".to_string());
+    }
+    Ok(())
+}
+
+// Aggregates `content` with recovery rules and compares the output and the
+// number of lines recovered by each rule.
+fn check_recovered(content: &str, options: &Options) -> Result<(), String> {
+    let run = || -> Result<(String, u64, u64), Error> {
+        let mut partial = aggregate(content, options, None, None)?;
+        let stations = collect_stations(&mut partial, None)?;
+        Ok((
+            output::render(&stations, Format::Text, false, None),
+            partial.last_field,
+            partial.missing_value,
+        ))
+    };
+    let (actual, last_field, missing_value) = run().map_err(|e| e.to_string())?;
+
+    if actual != EXPECTED {
+        return Err(format!("expected {:?}, got {:?}", EXPECTED, actual));
+    }
+    if (last_field, missing_value) != (1, 1) {
+        return Err(format!(
+            "expected one line recovered by each rule, got {} and {}",
+            last_field, missing_value
+        ));
+    }
+    Ok(())
+}
+
+// Checks that strict mode rejects `content` with a parse error.
+fn check_rejected(content: &str, options: &Options) -> Result<(), String> {
+    let spill = new_spill(options).map_err(|e| e.to_string())?;
+    match aggregate(content, options, spill.as_ref(), None) {
+        Err(Error::Parse(_)) => Ok(()),
+        Err(e) => Err(format!("expected a parse error, got: {}", e)),
+        Ok(_) => Err("expected a parse error, got none".to_string()),
+    }
+}
+
+// Aggregates `content` with exact arithmetic and compares the output and
+// skipped-line count.
+fn check_exact(content: &str, lenient: bool, expected_skipped: usize) -> Result<(), String> {
+    let exact =
+        exact::aggregate(content, b';', Notation::DEFAULT, lenient).map_err(|e| e.to_string())?;
+    let mut formatted: Vec<_> = exact
+        .stations
+        .iter()
+        .map(|(station, data)| {
+            (
+                station.clone(),
+                exact::format_station(data, Rounding::TowardPositive),
+            )
+        })
+        .collect();
+    let actual = output::format_output(&mut formatted, None);
+
+    if actual != EXPECTED {
+        return Err(format!("expected {:?}, got {:?}", EXPECTED, actual));
+    }
+    if exact.skipped != expected_skipped {
+        return Err(format!(
+            "expected {} skipped lines, got {}",
+            expected_skipped, exact.skipped
+        ));
+    }
+    Ok(())
+}
+
+// Checks that checked updates and merges report a saturated count instead of
+// wrapping it.
+fn check_overflow() -> Result<(), String> {
+    let mut full = StationData::new();
+    full.update(1.0);
+    full.count = i32::MAX;
+    if full.clone().checked_update(1.0) != Err(Overflow::Count) {
+        return Err("checked update did not detect the overflow".to_string());
+    }
+    let acc = StationMap::from_iter([("Hamburg".to_string(), full.clone())]);
+    let other = HashMap::from([("Hamburg".to_string(), full)]);
+    match merge_maps(acc, other) {
+        Err(Error::ResourceLimit(_)) => Ok(()),
+        Err(e) => Err(format!("expected a resource limit error, got: {}", e)),
+        Ok(_) => Err("merge did not detect the overflow".to_string()),
+    }
+}
+
+// Checks a descending multi-key sort: by count, then by name for ties.
+fn check_sorted(content: &str, options: &Options) -> Result<(), String> {
+    let run = || -> Result<Vec<String>, Error> {
+        let mut partial = aggregate(content, options, None, None)?;
+        let mut stations = collect_stations(&mut partial, None)?;
+        let order = SortOrder {
+            keys: vec![SortKey::Count, SortKey::Name],
+            desc: true,
+        };
+        output::sort_by(&mut stations, &order, None);
+        Ok(stations.into_iter().map(|(name, _)| name).collect())
+    };
+    let names = run().map_err(|e| e.to_string())?;
+
+    let expected = ["Zürich", "Hamburg", "Ürümqi", "Llanfairpwllgwyngyll"];
+    if names.len() < expected.len() || names[..expected.len()] != expected {
+        return Err(format!(
+            "expected to start with {:?}, got {:?}",
+            expected, names
+        ));
+    }
+    if names.last().map(String::as_str) != Some("Bridgetown") {
+        return Err(format!("expected to end with Bridgetown, got {:?}", names));
+    }
+    Ok(())
+}
+
+// Checks that `--discard-output` writes nothing, even where an output path is
+// set, and is rejected for runs that print something else.
+fn check_discard(content: &str, options: &Options, path: &Path) -> Result<(), String> {
+    let discarded = Options {
+        discard_output: true,
+        output: Some(path.display().to_string()),
+        ..options.clone()
+    };
+    let partial = aggregate(content, &discarded, None, None).map_err(|e| e.to_string())?;
+    if partial.stations.is_empty() {
+        return Err("expected aggregated stations".to_string());
+    }
+    print_results(partial, None, &discarded).map_err(|e| e.to_string())?;
+    if path.exists() {
+        return Err(format!("expected no output, found {}", path.display()));
+    }
+
+    for flag in ["--exact", "--count-only"] {
+        let args = ["--discard-output", flag].map(str::to_string);
+        if Options::parse(args.into_iter()).is_ok() {
+            return Err(format!(
+                "expected `--discard-output {}` to be rejected",
+                flag
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Checks that `--no-sort` output, collected without sorting for Markdown,
+// holds the same rows as the sorted output.
+fn check_unsorted(content: &str, options: &Options, path: &Path) -> Result<(), String> {
+    let run = || -> Result<(Vec<String>, Vec<String>), Error> {
+        let parsed = Options::parse(["--no-sort".to_string()].into_iter())?;
+        let unsorted = Options {
+            sort: parsed.sort,
+            format: Format::Markdown,
+            output: Some(path.display().to_string()),
+            ..options.clone()
+        };
+        print_results(aggregate(content, &unsorted, None, None)?, None, &unsorted)?;
+        let rows = fs::read_to_string(path)?
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        let mut partial = aggregate(content, options, None, None)?;
+        let stations = collect_stations(&mut partial, None)?;
+        let sorted = output::render(&stations, Format::Markdown, false, None)
+            .lines()
+            .map(str::to_string)
+            .collect();
+        Ok((rows, sorted))
+    };
+    let (mut rows, sorted) = run().map_err(|e| e.to_string())?;
+
+    if rows.len() < 3 {
+        return Err(format!("expected a table, got {:?}", rows));
+    }
+    rows[2..].sort_unstable();
+    if rows != sorted {
+        return Err(format!("expected the rows of {:?}, got {:?}", sorted, rows));
+    }
+    Ok(())
+}
+
+// Checks that the Protobuf output decodes back to the aggregated stations.
+fn check_proto(content: &str, options: &Options) -> Result<(), String> {
+    let run = || -> Result<(usize, proto::Results), Error> {
+        let mut partial = aggregate(content, options, None, None)?;
+        let stations = collect_stations(&mut partial, None)?;
+        let mut encoded = Vec::new();
+        proto::write(&mut encoded, &stations, None)?;
+        let decoded = proto::Results::decode(encoded.as_slice()).map_err(io::Error::other)?;
+        Ok((stations.len(), decoded))
+    };
+    let (len, decoded) = run().map_err(|e| e.to_string())?;
+
+    if decoded.stations.len() != len {
+        return Err(format!(
+            "expected {} stations, got {}",
+            len,
+            decoded.stations.len()
+        ));
+    }
+    let hamburg = decoded
+        .stations
+        .iter()
+        .find(|stats| stats.station == "Hamburg")
+        .ok_or("missing Hamburg")?;
+    if (hamburg.min, hamburg.mean, hamburg.max, hamburg.count)
+        != (-3.4, 5.8, 12.0, 3 * REPETITIONS as u64)
+    {
+        return Err(format!("unexpected Hamburg statistics {:?}", hamburg));
+    }
+    Ok(())
+}
+
+// Checks that RowBinary output decodes to one row per station with the right
+// values.
+fn check_rowbinary(content: &str, options: &Options) -> Result<(), String> {
+    let run = || -> Result<(usize, Vec<u8>), Error> {
+        let mut partial = aggregate(content, options, None, None)?;
+        let stations = collect_stations(&mut partial, None)?;
+        let mut encoded = Vec::new();
+        clickhouse::write(&mut encoded, &stations, None)?;
+        Ok((stations.len(), encoded))
+    };
+    let (len, encoded) = run().map_err(|e| e.to_string())?;
+
+    // Station names here are shorter than 128 bytes, so their length prefix
+    // is a single byte.
+    let mut rows = Vec::new();
+    let mut rest = encoded.as_slice();
+    while let Some((&name_len, tail)) = rest.split_first() {
+        let (name, tail) = tail
+            .split_at_checked(name_len as usize)
+            .ok_or("truncated station name")?;
+        let (fields, tail) = tail.split_at_checked(20).ok_or("truncated row")?;
+        let float = |at: usize| f32::from_le_bytes(fields[at..at + 4].try_into().unwrap());
+        let count = u64::from_le_bytes(fields[12..20].try_into().unwrap());
+        rows.push((name, float(0), float(4), float(8), count));
+        rest = tail;
+    }
+
+    if rows.len() != len {
+        return Err(format!("expected {} rows, got {}", len, rows.len()));
+    }
+    let hamburg = rows
+        .iter()
+        .find(|row| row.0 == b"Hamburg")
+        .ok_or("missing Hamburg")?;
+    let stats = (hamburg.1, hamburg.2, hamburg.3, hamburg.4);
+    if stats != (-3.4, 5.8, 12.0, 3 * REPETITIONS as u64) {
+        return Err(format!("unexpected Hamburg statistics {:?}", stats));
+    }
+    Ok(())
+}
+
+// Checks that `--group-by station,2` aggregates each station per value of the
+// second field, and that the nested output groups them back by station. The
+// input is written to `path` for strategies that read the file themselves.
+fn check_grouped(options: &Options, path: &Path) -> Result<(), String> {
+    let mut content = String::new();
+    for repetition in 0..REPETITIONS {
+        for record in RECORDS {
+            let (station, value) = record.split_once(';').unwrap();
+            let group = if repetition % 2 == 0 { "even" } else { "odd" };
+            content.push_str(&format!("{};{};{}\n", station, group, value));
+        }
+    }
+    let options = Options {
+        group_by: Some(2),
+        path: path.display().to_string(),
+        ..options.clone()
+    };
+    let run = || -> Result<Vec<u8>, Error> {
+        fs::write(path, &content)?;
+        let mut partial = aggregate(&content, &options, None, None)?;
+        let stations = collect_stations(&mut partial, None)?;
+        let mut nested = Vec::new();
+        output::write_nested(&mut nested, &stations, options.delimiter, None)?;
+        Ok(nested)
+    };
+    let nested = run().map_err(|e| e.to_string())?;
+    let nested: serde_json::Value = serde_json::from_slice(&nested).map_err(|e| e.to_string())?;
+
+    let stations = nested.as_object().ok_or("expected a JSON object")?;
+    let expected: hashbrown::HashSet<_> = RECORDS
+        .iter()
+        .map(|record| record.split_once(';').unwrap().0)
+        .collect();
+    if stations.len() != expected.len() {
+        return Err(format!(
+            "expected {} stations, got {}",
+            expected.len(),
+            stations.len()
+        ));
+    }
+    // Each repetition holds three Hamburg readings, and half of the
+    // repetitions fall into each group.
+    for group in ["even", "odd"] {
+        let hamburg = &nested["Hamburg"][group];
+        if hamburg["min"] != -3.4
+            || hamburg["max"] != 12.0
+            || hamburg["count"] != (REPETITIONS / 2 * 3) as u64
+        {
+            return Err(format!(
+                "unexpected Hamburg {} statistics {}",
+                group, hamburg
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Checks that `--window 1h` buckets readings a minute apart, with ISO 8601
+// timestamps, into one row per station and hour.
+fn check_windowed(options: &Options) -> Result<(), String> {
+    let mut content = String::new();
+    for repetition in 0..REPETITIONS {
+        let timestamp = window::format_timestamp(repetition as i64 * 60);
+        for record in RECORDS {
+            let (station, value) = record.split_once(';').unwrap();
+            content.push_str(&format!("{};{};{}\n", station, timestamp, value));
+        }
+    }
+    let options = Options {
+        window: Some(3600),
+        time_col: Some(2),
+        ..options.clone()
+    };
+    let run = || -> Result<Vec<(String, StationData)>, Error> {
+        let mut partial = aggregate(&content, &options, None, None)?;
+        collect_stations(&mut partial, None)
+    };
+    let stations = run().map_err(|e| e.to_string())?;
+
+    let hamburg: Vec<_> = stations
+        .iter()
+        .filter(|(key, _)| key.starts_with("Hamburg;"))
+        .collect();
+    if hamburg.len() != REPETITIONS.div_ceil(60) {
+        return Err(format!(
+            "expected {} Hamburg windows, got {}",
+            REPETITIONS.div_ceil(60),
+            hamburg.len()
+        ));
+    }
+    let (key, data) = hamburg[1];
+    if key != "Hamburg;1970-01-01T01:00:00Z" || data.count != 60 * 3 {
+        return Err(format!(
+            "unexpected second Hamburg window {} with {} readings",
+            key, data.count
+        ));
+    }
+    Ok(())
+}
+
+// Checks that `--provenance` finds the first of the repeated extreme
+// readings: Hamburg's maximum on line 1 and its minimum on line 11.
+fn check_provenance(content: &str, options: &Options) -> Result<(), String> {
+    let options = Options {
+        provenance: true,
+        ..options.clone()
+    };
+    let extremes = aggregate(content, &options, None, None)
+        .map(|partial| partial.extremes)
+        .map_err(|e| e.to_string())?;
+
+    let hamburg = extremes.get("Hamburg").ok_or("missing Hamburg")?;
+    let min_offset: usize = RECORDS[..10].iter().map(|record| record.len() + 1).sum();
+    let expected = (-3.4, min_offset, 12.0, 0);
+    let actual = (
+        hamburg.min.temp,
+        hamburg.min.offset,
+        hamburg.max.temp,
+        hamburg.max.offset,
+    );
+    if actual != expected {
+        return Err(format!(
+            "expected Hamburg extremes {:?}, got {:?}",
+            expected, actual
+        ));
+    }
+    let lines = provenance::line_numbers(content.as_bytes(), &[min_offset, 0]);
+    if (lines[&min_offset], lines[&0]) != (11, 1) {
+        return Err(format!("unexpected line numbers {:?}", lines));
+    }
+    Ok(())
+}
+
+// Checks that `--first-last` orders readings from different chunks by file
+// position: Hamburg's first reading is on the first line and its last on the
+// last line.
+fn check_first_last(content: &str, options: &Options) -> Result<(), String> {
+    let options = Options {
+        first_last: true,
+        ..options.clone()
+    };
+    let ends = aggregate(content, &options, None, None)
+        .map(|partial| partial.ends)
+        .map_err(|e| e.to_string())?;
+
+    let hamburg = ends.get("Hamburg").ok_or("missing Hamburg")?;
+    let last_offset = content.len() - RECORDS.last().unwrap().len() - 1;
+    let expected = (12.0, 0, 8.9, last_offset);
+    let actual = (
+        hamburg.first.temp,
+        hamburg.first.offset,
+        hamburg.last.temp,
+        hamburg.last.offset,
+    );
+    if actual != expected {
+        return Err(format!(
+            "expected Hamburg ends {:?}, got {:?}",
+            expected, actual
+        ));
+    }
+    Ok(())
+}
+
+// Checks that `--stats mode` finds the most frequent reading once one more
+// reading of Hamburg's maximum is appended, and the lowest reading of
+// stations whose readings are all equally frequent.
+fn check_mode(content: &str, options: &Options) -> Result<(), String> {
+    let content = format!("{}Hamburg;8.9\n", content);
+    let options = Options {
+        stats: vec![Stat::Mode],
+        ..options.clone()
+    };
+    let run = || -> Result<String, Error> {
+        let mut partial = aggregate(&content, &options, None, None)?;
+        let stations = collect_stations(&mut partial, None)?;
+        Ok(output::render_extended(
+            &stations,
+            Format::Text,
+            None,
+            |station| {
+                extra_stats::values(
+                    &options.stats,
+                    partial.histograms.get(station),
+                    options.compensated,
+                )
+            },
+        ))
+    };
+    let rendered = run().map_err(|e| e.to_string())?;
+
+    for expected in ["Hamburg=-3.4/5.8/12.0/8.9", "Zürich=-7.1/-1.6/2.3/-7.1"] {
+        if !rendered.contains(expected) {
+            return Err(format!("expected {:?} in {:?}", expected, rendered));
+        }
+    }
+    Ok(())
+}
+
+// Checks that the pre-pass sizes maps for the dataset's 13 stations, and
+// that the sketch estimates a large key set within 5%.
+fn check_hll(content: &str) -> Result<(), String> {
+    let capacity = hll::map_capacity(content.as_bytes(), b';', 4);
+    if !(13..=20).contains(&capacity) {
+        return Err(format!(
+            "expected capacity for 13 stations, got {}",
+            capacity
+        ));
+    }
+    let mut sketch = HyperLogLog::new();
+    for key in 0..100_000 {
+        sketch.insert(format!("station {}", key).as_bytes());
+    }
+    let estimate = sketch.estimate();
+    if estimate.abs_diff(100_000) > 5_000 {
+        return Err(format!(
+            "expected about 100000 keys, estimated {}",
+            estimate
+        ));
+    }
+    Ok(())
+}
+
+// Checks the hot path scan's temperature layouts, BOMs, non-ASCII names and
+// rejected lines on a few lines covering each case.
+fn check_hot_path() -> Result<(), String> {
+    let content = format!(
+        "Hamburg;12.0\nZürich;-7.1\nHamburg;1.5\n{}Bulawayo;-10.5\nno delimiter\nRoseau;5\n",
+        BOM
+    );
+    let profile = hot_path::scan(&content, b';');
+    if profile.shapes != [1, 1, 1, 1, 1] {
+        return Err(format!(
+            "expected one of each layout, got {:?}",
+            profile.shapes
+        ));
+    }
+    let observed = (
+        profile.lines,
+        profile.boms,
+        profile.non_ascii,
+        profile.rejected.get("missing-delimiter").copied(),
+    );
+    if observed != (6, 1, 1, Some(1)) {
+        return Err(format!(
+            "expected 6 lines, 1 BOM, 1 non-ASCII name and 1 rejected line, got {:?}",
+            observed
+        ));
+    }
+    Ok(())
+}
+
+// Checks the best, median, phase medians and MAD outliers `bench` reports.
+fn check_bench_statistics() -> Result<(), String> {
+    let runs = |millis: &[u64]| Runs {
+        times: millis.iter().map(|&ms| Duration::from_millis(ms)).collect(),
+        phases: Vec::new(),
+    };
+
+    // One slow run among steady ones: the median ignores it, the MAD flags it.
+    let noisy = runs(&[100, 102, 98, 101, 99, 250]);
+    if noisy.best() != Duration::from_millis(98) {
+        return Err(format!("best of noisy runs is {:?}", noisy.best()));
+    }
+    if noisy.median() != Duration::from_micros(100_500) {
+        return Err(format!("median of noisy runs is {:?}", noisy.median()));
+    }
+    if noisy.outliers() != [5] {
+        return Err(format!("noisy runs have outliers {:?}", noisy.outliers()));
+    }
+    if !noisy
+        .summary(2)
+        .ends_with("of 6 runs after 2 warmup runs; 1 outliers: 0.250s")
+    {
+        return Err(format!("summary of noisy runs is `{}`", noisy.summary(2)));
+    }
+
+    // Identical and spread out runs have none.
+    for millis in [&[100, 100, 100, 400][..], &[100, 200, 300, 400, 500]] {
+        let outliers = runs(millis).outliers();
+        if !outliers.is_empty() {
+            return Err(format!("runs {:?} have outliers {:?}", millis, outliers));
+        }
+    }
+
+    let phased = Runs {
+        times: vec![Duration::from_millis(10); 3],
+        phases: vec![[1.0, 4.0, 0.5], [3.0, 2.0, 0.5], [2.0, 9.0, 0.1]],
+    };
+    if phased.median_phases() != Some([2.0, 4.0, 0.5]) {
+        return Err(format!("median phases are {:?}", phased.median_phases()));
+    }
+    Ok(())
+}
+
+// Checks `--history` timestamps, and that CSV files get one header and quoted
+// commands, JSON lines files one object per record with the system, and
+// baselines brc's records.
+fn check_history(dir: &Path) -> Result<(), String> {
+    for (seconds, expected) in [
+        (0, "1970-01-01T00:00:00Z"),
+        (951_782_400, "2000-02-29T00:00:00Z"),
+        (1_700_000_000, "2023-11-14T22:13:20Z"),
+        (4_107_542_399, "2100-02-28T23:59:59Z"),
+    ] {
+        let actual = history::format_timestamp(seconds);
+        if actual != expected {
+            return Err(format!("{} seconds formatted as {}", seconds, actual));
+        }
+    }
+
+    let record = |command: &str| history::Record {
+        timestamp: history::format_timestamp(0),
+        version: "v1-2-gabc".to_string(),
+        command: command.to_string(),
+        implementation: None,
+        threads: None,
+        runs: 3,
+        best: 1.5,
+        median: 2.0,
+        setup: None,
+        aggregate: None,
+        output: None,
+        throughput: Some(10.0),
+        system: Some(System::detect()),
+    };
+    let csv = dir.join("history.csv");
+    let jsonl = dir.join("history.jsonl");
+    for path in [&csv, &jsonl] {
+        let path = path.to_str().unwrap();
+        history::append(path, &[record("brc")]).map_err(|e| e.to_string())?;
+        history::append(path, &[record("awk -F, '{print \"x\"}'")]).map_err(|e| e.to_string())?;
+    }
+
+    let csv = fs::read_to_string(&csv).map_err(|e| e.to_string())?;
+    let expected = "timestamp,version,command,implementation,threads,runs,best,median,setup,\
+                    aggregate,output,throughput\n\
+                    1970-01-01T00:00:00Z,v1-2-gabc,brc,,,3,1.5,2,,,,10\n\
+                    1970-01-01T00:00:00Z,v1-2-gabc,\"awk -F, '{print \"\"x\"\"}'\",,,3,1.5,2,,,,10\n";
+    if csv != expected {
+        return Err(format!("CSV history is {:?}", csv));
+    }
+    let jsonl = fs::read_to_string(&jsonl).map_err(|e| e.to_string())?;
+    let commands: Vec<String> = jsonl
+        .lines()
+        .map(|line| {
+            let value: serde_json::Value = serde_json::from_str(line).map_err(|e| e.to_string())?;
+            if value["system"]["os"] != std::env::consts::OS {
+                return Err(format!("JSON lines record without the system: {}", line));
+            }
+            Ok(value["command"].as_str().unwrap_or_default().to_string())
+        })
+        .collect::<Result<_, String>>()?;
+    if commands != ["brc", "awk -F, '{print \"x\"}'"] {
+        return Err(format!("JSON lines history has commands {:?}", commands));
+    }
+
+    // Baselines keep brc's records only.
+    let baseline = dir.join("baseline.json");
+    let baseline = baseline.to_str().unwrap();
+    let ours = history::Record {
+        implementation: Some("per-core".to_string()),
+        threads: Some(4),
+        ..record("brc")
+    };
+    history::save_baseline(baseline, &[ours, record("awk")]).map_err(|e| e.to_string())?;
+    let loaded = history::load_baseline(baseline).map_err(|e| e.to_string())?;
+    if loaded.len() != 1
+        || loaded[0].implementation.as_deref() != Some("per-core")
+        || loaded[0].threads != Some(4)
+        || loaded[0].median != 2.0
+        || loaded[0].system.is_none()
+    {
+        return Err(format!("baseline has {} unexpected records", loaded.len()));
+    }
+    Ok(())
+}
+
+// Checks `--energy` on a fake powercap tree: subdomains are left out, the
+// packages summed and a wrapped counter unwrapped.
+fn check_energy(dir: &Path) -> Result<(), String> {
+    let root = dir.join("powercap");
+    let write = |domain: &str, file: &str, value: u64| {
+        let domain = root.join(domain);
+        fs::create_dir_all(&domain)
+            .and_then(|_| fs::write(domain.join(file), format!("{}\n", value)))
+            .map_err(|e| e.to_string())
+    };
+    for (domain, max_range, energy) in [
+        ("intel-rapl:0", 1_000_000_000, 999_000_000),
+        ("intel-rapl:0:0", 1_000_000_000, 5),
+        ("intel-rapl:1", 2_000_000_000, 10_000_000),
+    ] {
+        write(domain, "max_energy_range_uj", max_range)?;
+        write(domain, "energy_uj", energy)?;
+    }
+    let meter = Meter::open_at(&root).map_err(|e| e.to_string())?;
+    let start = meter.read().map_err(|e| e.to_string())?;
+    write("intel-rapl:0", "energy_uj", 1_500_000)?;
+    write("intel-rapl:0:0", "energy_uj", 900_000_000)?;
+    write("intel-rapl:1", "energy_uj", 12_500_000)?;
+    let end = meter.read().map_err(|e| e.to_string())?;
+    // 2.5 J with the wrap at package 0 and 2.5 J at package 1.
+    let joules = meter.joules(&start, &end);
+    if (joules - 5.0).abs() > 1e-9 {
+        return Err(format!("measured {} J, expected 5 J", joules));
+    }
+    if Meter::open_at(&dir.join("no-powercap")).is_ok() {
+        return Err("opened a missing powercap tree".to_string());
+    }
+    Ok(())
+}
+
+// Checks what `--reproducible` pins down, that its configuration hash ignores
+// the input path and how the effective values were given, and that two
+// aggregations agree to the bit.
+fn check_reproducible(content: &str, options: &Options) -> Result<(), String> {
+    let args = |args: &[&str]| -> Vec<String> { args.iter().map(|arg| arg.to_string()).collect() };
+    let parsed =
+        Options::parse(args(&["--reproducible", "--sort-by", "mean", "a.txt"]).into_iter())
+            .map_err(|e| e.to_string())?;
+    if parsed.threads != Some(4)
+        || parsed.schedule != Schedule::Static
+        || parsed.sort.keys != [SortKey::Mean, SortKey::Name]
+    {
+        return Err("`--reproducible` did not fix threads, schedule and ties".to_string());
+    }
+
+    let describe = |given: &[&str]| -> Result<String, String> {
+        let given = args(given);
+        let parsed = Options::parse(given.iter().cloned()).map_err(|e| e.to_string())?;
+        Ok(reproducible::describe(&given, &parsed))
+    };
+    let implicit = describe(&["--reproducible", "--sort-by", "mean", "a.txt"])?;
+    let explicit = describe(&[
+        "--threads",
+        "4",
+        "b.txt",
+        "--sort-by",
+        "mean",
+        "--reproducible",
+    ])?;
+    let other = describe(&[
+        "--reproducible",
+        "--threads",
+        "8",
+        "--sort-by",
+        "mean",
+        "a.txt",
+    ])?;
+    if implicit != explicit || implicit == other {
+        return Err(format!(
+            "configurations `{}`, `{}` and `{}`",
+            implicit, explicit, other
+        ));
+    }
+    // FNV-1a test vectors.
+    if reproducible::hash("") != 0xcbf2_9ce4_8422_2325
+        || reproducible::hash("a") != 0xaf63_dc4c_8601_ec8c
+    {
+        return Err("configuration hash is not FNV-1a".to_string());
+    }
+
+    let options = Options {
+        reproducible: true,
+        schedule: Schedule::Static,
+        ..options.clone()
+    };
+    let totals = || -> Result<Vec<(String, u32)>, Error> {
+        let mut partial = aggregate(content, &options, None, None)?;
+        let stations = collect_stations(&mut partial, None)?;
+        Ok(stations
+            .into_iter()
+            .map(|(station, data)| (station, data.total_temp.to_bits()))
+            .collect())
+    };
+    let first = totals().map_err(|e| e.to_string())?;
+    let second = totals().map_err(|e| e.to_string())?;
+    if first != second {
+        return Err("two reproducible aggregations differ".to_string());
+    }
+    Ok(())
+}
+
+// Checks that runs are refused or adapted to fit a memory limit, that the
+// lowest limits of a cgroup hierarchy are found, and that releasing aggregated
+// chunks of a mapping leaves the results unchanged.
+fn check_memory_limit(content: &str, options: &Options, dir: &Path) -> Result<(), String> {
+    let limited = |options: &Options, input_len: usize, copied: usize, limit: usize| {
+        let mut options = options.clone();
+        memory_limit::adapt(&mut options, input_len, copied, limit).map(|()| options)
+    };
+    let big = Options {
+        map_capacity: 1_000_000,
+        ..options.clone()
+    };
+    if !matches!(limited(&big, 0, 0, 1 << 20), Err(Error::ResourceLimit(_))) {
+        return Err("oversized station tables were not refused".to_string());
+    }
+    let external = Options {
+        implementation: Implementation::External,
+        ..big.clone()
+    };
+    let adapted = limited(&external, 0, 0, 1 << 20).map_err(|e| e.to_string())?;
+    if adapted.map_capacity >= big.map_capacity {
+        return Err("station map preallocation was not shrunk".to_string());
+    }
+    if !matches!(
+        limited(options, 0, 1 << 20, 1 << 20),
+        Err(Error::ResourceLimit(_))
+    ) {
+        return Err("an oversized copy of the input was not refused".to_string());
+    }
+    let adapted = limited(options, 1 << 30, 0, 1 << 28).map_err(|e| e.to_string())?;
+    if !adapted.windowed || adapted.schedule != Schedule::Dynamic {
+        return Err("input beyond the memory limit is not windowed".to_string());
+    }
+    if limited(options, 1 << 30, 1 << 20, 1 << 28)
+        .map_err(|e| e.to_string())?
+        .windowed
+    {
+        return Err("a copy of the input is windowed".to_string());
+    }
+
+    let root = dir.join("cgroup");
+    let group = root.join("a/b");
+    fs::create_dir_all(&group).map_err(|e| e.to_string())?;
+    for (dir, memory, cpu) in [
+        (&root, "max", "max 100000"),
+        (&root.join("a"), "1048576", "200000 100000"),
+        (&group, "max", "400000 100000"),
+    ] {
+        fs::write(dir.join("memory.max"), format!("{}\n", memory)).map_err(|e| e.to_string())?;
+        fs::write(dir.join("cpu.max"), format!("{}\n", cpu)).map_err(|e| e.to_string())?;
+    }
+    for path in ["/a/b", "/elsewhere"] {
+        let memory = cgroup::hierarchy(&root, path, cgroup::memory_max);
+        let cpus = cgroup::hierarchy(&root, path, cgroup::cpu_max);
+        let expected = (path == "/a/b").then_some((1 << 20, 2.0));
+        if memory.zip(cpus) != expected {
+            return Err(format!(
+                "limits {:?} and {:?} of {}, expected {:?}",
+                memory, cpus, path, expected
+            ));
+        }
+    }
+
+    let repeated = content.repeat(16);
+    let path = dir.join("windowed.txt");
+    fs::write(&path, &repeated).map_err(|e| e.to_string())?;
+    let mmap = map_input(&path).map_err(|e| e.to_string())?;
+    let mapped = std::str::from_utf8(&mmap).map_err(|e| e.to_string())?;
+    let windowed = Options {
+        schedule: Schedule::Dynamic,
+        windowed: true,
+        chunk_size: Some(16 * 1024),
+        ..options.clone()
+    };
+    // Sums depend on the order chunks are merged in, so only order-free
+    // statistics are compared.
+    let totals = |content: &str, options: &Options| -> Result<Vec<_>, Error> {
+        let mut partial = aggregate(content, options, None, None)?;
+        let mut stations: Vec<_> = collect_stations(&mut partial, None)?
+            .into_iter()
+            .map(|(station, data)| {
+                let (min, max) = (data.min_temp.to_bits(), data.max_temp.to_bits());
+                (station, data.count, min, max)
+            })
+            .collect();
+        stations.sort();
+        Ok(stations)
+    };
+    let expected = totals(&repeated, options).map_err(|e| e.to_string())?;
+    let actual = totals(mapped, &windowed).map_err(|e| e.to_string())?;
+    if actual != expected || mapped != repeated {
+        return Err("windowed aggregation changed the results or the input".to_string());
+    }
+    Ok(())
+}
+
+// Checks that every strategy that supports Ctrl-C stops taking lines once it
+// is requested, leaving an empty but valid partial result, and that
+// `--timeout` requests a stop.
+fn check_interrupted(content: &str, options: &Options) -> Result<(), String> {
+    let variants = [
+        (Implementation::Rayon, Schedule::Adaptive),
+        (Implementation::Rayon, Schedule::Dynamic),
+        (Implementation::Rayon, Schedule::Static),
+        (Implementation::Scoped, Schedule::Adaptive),
+        (Implementation::PerCore, Schedule::Adaptive),
+        (Implementation::External, Schedule::Adaptive),
+    ];
+    interrupt::request();
+    let result = variants.iter().try_for_each(|&(implementation, schedule)| {
+        let options = Options {
+            implementation,
+            schedule,
+            ..options.clone()
+        };
+        if !interrupt::supported(&options) {
+            return Err(format!(
+                "{} does not support Ctrl-C",
+                implementation.label()
+            ));
+        }
+        let spill = new_spill(&options).map_err(|e| e.to_string())?;
+        let partial =
+            aggregate(content, &options, spill.as_ref(), None).map_err(|e| e.to_string())?;
+        if partial.rows != 0 || partial.bytes != 0 {
+            return Err(format!(
+                "{} {} aggregated {} lines after Ctrl-C",
+                implementation.label(),
+                schedule.label(),
+                partial.rows
+            ));
+        }
+        Ok(())
+    });
+    interrupt::reset();
+    result?;
+
+    let parse = |args: &[&str]| Options::parse(args.iter().map(|arg| arg.to_string()));
+    if !parse(&["--timeout", "2m", "a.txt"])
+        .is_ok_and(|options| options.timeout == Some(Duration::from_secs(120)))
+        || parse(&["--timeout", "2m", "--impl", "pipeline", "a.txt"]).is_ok()
+        || parse(&["--timeout", "2", "a.txt"]).is_ok()
+    {
+        return Err("`--timeout` parsed wrongly".to_string());
+    }
+    interrupt::request_after(Duration::ZERO);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !interrupt::requested() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(1));
+    }
+    let timed_out = interrupt::requested() && interrupt::timed_out();
+    interrupt::reset();
+    if !timed_out {
+        return Err("`--timeout` did not request a stop".to_string());
+    }
+    Ok(())
+}
+
+// Checks that the status socket answers with a JSON report and is removed
+// with its server.
+#[cfg(unix)]
+fn check_status(dir: &Path) -> Result<(), String> {
+    use std::io::BufRead;
+    use std::os::unix::net::UnixStream;
+
+    let path = dir.join("status.sock");
+    let path = path.to_str().ok_or("non-UTF-8 temporary directory")?;
+    let server = status::serve(path).map_err(|e| e.to_string())?;
+    let stream = UnixStream::connect(path).map_err(|e| e.to_string())?;
+    let mut line = String::new();
+    io::BufReader::new(stream)
+        .read_line(&mut line)
+        .map_err(|e| e.to_string())?;
+    let report: serde_json::Value = serde_json::from_str(&line).map_err(|e| e.to_string())?;
+    if !report["phase"].is_string() || !report["bytes_done"].is_u64() {
+        return Err(format!("unexpected status report {}", line.trim_end()));
+    }
+    drop(server);
+    if Path::new(path).exists() {
+        return Err("status socket was not removed".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_status(_dir: &Path) -> Result<(), String> {
+    Ok(())
+}
+
+// Checks that `--log-format json` events carry their name, message and
+// fields.
+fn check_json_log() -> Result<(), String> {
+    let parsed = Options::parse(
+        ["--log-format", "json", "a.txt"]
+            .map(str::to_string)
+            .into_iter(),
+    )
+    .map_err(|e| e.to_string())?;
+    if parsed.log_format != LogFormat::Json {
+        return Err("`--log-format json` was not parsed".to_string());
+    }
+    let line = logging::render(
+        "self_check",
+        format_args!("Self-check passed: {} of {} chunks", 3, 4),
+        serde_json::json!({ "checked": 3, "chunks": 4 }),
+    );
+    let event: serde_json::Value = serde_json::from_str(&line).map_err(|e| e.to_string())?;
+    if line.contains('\n')
+        || event["event"] != "self_check"
+        || event["message"] != "Self-check passed: 3 of 4 chunks"
+        || event["checked"] != 3
+        || !event["timestamp"].is_string()
+    {
+        return Err(format!("unexpected log line {}", line));
+    }
+    Ok(())
+}
+
+// Checks that `content` rewritten as a European export, tab-separated with
+// decimal commas, aggregates to the same output with `--decimal-comma`, and
+// that a comma cannot also separate the fields.
+fn check_decimal_comma(content: &str, options: &Options) -> Result<(), String> {
+    let mut european = String::new();
+    for line in content.lines() {
+        let (station, value) = line.split_once(';').unwrap();
+        european.push_str(&format!("{}\t{}\n", station, value.replace('.', ",")));
+    }
+    let notation = Notation {
+        decimal: b',',
+        ..Notation::DEFAULT
+    };
+    let options = Options {
+        delimiter: b'\t',
+        notation,
+        ..options.clone()
+    };
+    let run = || -> Result<String, Error> {
+        let mut partial = aggregate(&european, &options, None, None)?;
+        let stations = collect_stations(&mut partial, None)?;
+        Ok(output::render(&stations, Format::Text, false, None))
+    };
+    let actual = run().map_err(|e| e.to_string())?;
+    if actual != EXPECTED {
+        return Err(format!("expected {:?}, got {:?}", EXPECTED, actual));
+    }
+    if parse_temperature_in("-12,3", notation) != Ok(-12.3) {
+        return Err("`-12,3` did not parse to -12.3".to_string());
+    }
+    let parse = |args: &[&str]| Options::parse(args.iter().map(|arg| arg.to_string()));
+    if parse(&["--decimal-comma", "--delimiter", ",", "a.txt"]).is_ok()
+        || !parse(&["--decimal-comma", "--delimiter", "tab", "a.txt"])
+            .is_ok_and(|options| options.notation == notation)
+    {
+        return Err("`--decimal-comma` parsed wrongly".to_string());
+    }
+    Ok(())
+}
+
+// Checks how each `--rounding` mode breaks ties, in integer division, in the
+// exact mean and in the mean of aggregated readings.
+fn check_rounding() -> Result<(), String> {
+    use Rounding::{HalfEven, HalfUp, TowardPositive};
+    let cases: [(i128, i128, [i128; 3]); 8] = [
+        (25, 10, [3, 2, 3]),
+        (-25, 10, [-3, -2, -2]),
+        (35, 10, [4, 4, 4]),
+        (-35, 10, [-4, -4, -3]),
+        (5, 10, [1, 0, 1]),
+        (-5, 10, [-1, 0, 0]),
+        (26, 10, [3, 3, 3]),
+        (-24, 10, [-2, -2, -2]),
+    ];
+    for (numerator, denominator, expected) in cases {
+        for (rounding, expected) in [HalfUp, HalfEven, TowardPositive].into_iter().zip(expected) {
+            let actual = rounding.divide(numerator, denominator);
+            if actual != expected {
+                return Err(format!(
+                    "{:?}: {}/{} rounded to {}, expected {}",
+                    rounding, numerator, denominator, actual, expected
+                ));
+            }
+        }
+    }
+
+    // A mean of -0.25 degrees, from two readings.
+    let exact = exact::ExactData {
+        min: -3,
+        max: -2,
+        sum: -5,
+        count: 2,
+    };
+    let mut data = StationData::new();
+    data.update(-0.2);
+    data.update(-0.3);
+    for (rounding, expected) in [
+        (HalfUp, "-0.3/-0.3/-0.2"),
+        (HalfEven, "-0.3/-0.2/-0.2"),
+        (TowardPositive, "-0.3/-0.2/-0.2"),
+    ] {
+        let actual = exact::format_station(&exact, rounding);
+        if actual != expected {
+            return Err(format!(
+                "exact {:?}: expected {}, got {}",
+                rounding, expected, actual
+            ));
+        }
+        let actual = output::format_station(&data, Some(rounding));
+        if actual != expected {
+            return Err(format!(
+                "{:?}: expected {}, got {}",
+                rounding, expected, actual
+            ));
+        }
+        let mean = output::StationRecord::new("", &data, Some(rounding)).mean;
+        if Some(mean.to_string().as_str()) != expected.split('/').nth(1) {
+            return Err(format!(
+                "{:?}: record mean {}, expected {}",
+                rounding, mean, expected
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Checks that the single-buffer text output matches formatting each station
+// on its own, fits the reserved size, and reuses a buffer's capacity.
+fn check_text_output() -> Result<(), String> {
+    let readings: [(&str, &[f32]); 5] = [
+        ("Abha", &[-99.9, 99.9]),
+        ("Zürich", &[9.9, 10.0]),
+        ("Roseau", &[-0.1, 0.0]),
+        ("Hamburg", &[9.95, 9.94]),
+        ("X", &[-5.0]),
+    ];
+    let stations: Vec<_> = readings
+        .iter()
+        .map(|(name, temps)| {
+            let mut data = StationData::new();
+            temps.iter().for_each(|&temp| data.update(temp));
+            (name.to_string(), data)
+        })
+        .collect();
+    let expected: Vec<_> = stations
+        .iter()
+        .map(|(name, data)| format!("{}={}", name, output::format_station(data, None)))
+        .collect();
+    let expected = format!("{{{}}}\n", expected.join(", "));
+
+    let mut buf = Vec::new();
+    text::write_results(&mut buf, output::text_entries(&stations), None);
+    if buf != expected.as_bytes() {
+        return Err(format!(
+            "expected {:?}, got {:?}",
+            expected,
+            String::from_utf8_lossy(&buf)
+        ));
+    }
+    let bound = text::size_bound(output::text_entries(&stations));
+    if bound < buf.len() || bound > buf.len() + stations.len() {
+        return Err(format!("size bound {} for {} bytes", bound, buf.len()));
+    }
+    let (pointer, capacity) = (buf.as_ptr(), buf.capacity());
+    buf.clear();
+    text::write_results(&mut buf, output::text_entries(&stations), None);
+    if (buf.as_ptr(), buf.capacity()) != (pointer, capacity) {
+        return Err("rendering into a reused buffer reallocated it".to_string());
+    }
+    Ok(())
+}
+
+// Checks that `--self-check` passes on correct results and catches a line
+// split in two and a dropped reading.
+fn check_self_check(content: &str, options: &Options) -> Result<(), String> {
+    let options = Options {
+        self_check: true,
+        ..options.clone()
+    };
+    let partial = aggregate(content, &options, None, None).map_err(|e| e.to_string())?;
+    self_check::check(content.as_bytes(), &partial.stations, &options)
+        .map_err(|e| format!("expected correct results to pass: {}", e))?;
+
+    let mut split = partial.stations.clone();
+    let (name, data) = split.remove_entry("Hamburg").ok_or("expected Hamburg")?;
+    split.insert(name[..3].to_string(), data.clone());
+    split.insert(name[3..].to_string(), data);
+    let mut dropped = partial.stations.clone();
+    dropped.get_mut("Hamburg").ok_or("expected Hamburg")?.count -= 1;
+    for (label, stations, expected) in [
+        ("split line", split, "is missing from the results"),
+        ("dropped reading", dropped, "readings in"),
+    ] {
+        match self_check::check(content.as_bytes(), &stations, &options) {
+            Err(Error::Validation(message)) if message.contains(expected) => {}
+            Err(e) => return Err(format!("{}: expected a mismatch, got: {}", label, e)),
+            Ok(_) => return Err(format!("{}: expected a mismatch, got none", label)),
+        }
+    }
+    Ok(())
+}
+
+// Checks that stations sharing their first 8 bytes and length are still
+// aggregated separately under `--key-hash prefix`, and that `--check-key-hash`
+// reports them.
+fn check_key_hash(options: &Options) -> Result<(), String> {
+    let names = [
+        "Brabrabralo 6553",
+        "Brabrabralo 2457",
+        "Brabrabralo",
+        "Brabra",
+    ];
+    let mut content = String::new();
+    for temp in ["1.0", "-2.0", "3.0"] {
+        for name in names {
+            content.push_str(&format!("{};{}\n", name, temp));
+        }
+    }
+    let options = Options {
+        key_hash: KeyHash::Prefix,
+        ..options.clone()
+    };
+    let partial = aggregate(&content, &options, None, None).map_err(|e| e.to_string())?;
+    for name in names {
+        match partial.stations.get(name) {
+            Some(data) if data.count == 3 => {}
+            data => {
+                return Err(format!(
+                    "expected 3 readings of {}, got {:?}",
+                    name,
+                    data.map(|data| data.count)
+                ))
+            }
+        }
+    }
+    match key_hash::check(partial.stations.keys()) {
+        Err(Error::Validation(message)) if message.contains("length with another: 1,") => Ok(()),
+        Err(e) => Err(format!("expected one collision, got: {}", e)),
+        Ok(()) => Err("colliding stations were not reported".to_string()),
+    }
+}
+
+// Checks that `--trim` and `--case-insensitive` merge spellings under the
+// smallest one, whatever the thread count.
+fn check_normalized(options: &Options) -> Result<(), String> {
+    let content = "abha;1.0\n Abha;3.0\nABHA ;2.0\nBern;5.0\nbern;-5.0\n".repeat(1000);
+    let cases = [
+        (true, true, "{ Abha=1.0/2.0/3.0, Bern=-5.0/0.0/5.0}\n"),
+        (
+            false,
+            true,
+            "{ Abha=3.0/3.0/3.0, ABHA =2.0/2.0/2.0, Bern=-5.0/0.0/5.0, abha=1.0/1.0/1.0}\n",
+        ),
+    ];
+    for (trim, case_insensitive, expected) in cases {
+        for threads in [1, 4] {
+            let normalized = Options {
+                threads: Some(threads),
+                trim,
+                case_insensitive,
+                ..options.clone()
+            };
+            let run = || -> Result<String, Error> {
+                let mut partial = aggregate(&content, &normalized, None, None)?;
+                let stations = collect_stations(&mut partial, None)?;
+                let stations = normalize::merge(stations, &normalized);
+                Ok(output::render(&stations, Format::Text, false, None))
+            };
+            let actual = run().map_err(|e| e.to_string())?;
+            if actual != expected {
+                return Err(format!(
+                    "trim {}, {} threads: expected {:?}, got {:?}",
+                    trim, threads, expected, actual
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Checks that every strategy aggregates aliased stations under their
+// canonical names, as if the input had been rewritten, and that the alias
+// file is validated.
+fn check_aliases(content: &str, options: &Options, dir: &Path) -> Result<(), String> {
+    let path = dir.join("aliases.csv");
+    let extra = [("Hamburgo", "1.5"), ("HH", "-30.0"), ("St. John's", "20.0")];
+    let canonical = |station| match station {
+        "Hamburgo" | "HH" => "Hamburg",
+        "St. John's" => "Saint John's",
+        station => station,
+    };
+    let mut aliased = content.to_string();
+    let mut rewritten = String::new();
+    for line in content.lines() {
+        let (station, temp) = line.split_once(';').unwrap();
+        rewritten.push_str(&format!("{};{}\n", canonical(station), temp));
+    }
+    for (station, temp) in extra {
+        aliased.push_str(&format!("{};{}\n", station, temp));
+        rewritten.push_str(&format!("{};{}\n", canonical(station), temp));
+    }
+    let input = dir.join("aliased.txt");
+    let run = || -> Result<Vec<String>, Error> {
+        fs::write(
+            &path,
+            "# raw;canonical\nHamburgo;Hamburg\nHH;Hamburg\nSt. John's;Saint John's\n",
+        )?;
+        fs::write(&input, &aliased)?;
+        let aliases = aliases::load(&path.display().to_string(), b';', KeyHash::Full)?;
+        let key = aliases::resolve_key(
+            &Options {
+                alias_map: Some(aliases),
+                ..options.clone()
+            },
+            Cow::Borrowed("HH;2024"),
+        );
+        if key != "Hamburg;2024" {
+            return Err(Error::Validation(format!("resolved key `{}`", key)));
+        }
+
+        let mut partial = aggregate(&rewritten, options, None, None)?;
+        let expected = format_results(&collect_stations(&mut partial, None)?);
+        let aliased_options = Options {
+            path: input.display().to_string(),
+            alias_map: Some(aliases),
+            ..options.clone()
+        };
+        let mut mismatches = mismatched_strategies(&aliased, &aliased_options, &expected)?;
+
+        for (contents, error) in [
+            ("A;B\nA;C\n", "`A` is aliased twice"),
+            ("A;B\nB;C\n", "canonical name `B` is itself aliased"),
+            ("A\n", ":1: expected a name"),
+        ] {
+            fs::write(&path, contents)?;
+            match aliases::load(&path.display().to_string(), b';', KeyHash::Full) {
+                Err(e) if e.to_string().contains(error) => {}
+                result => mismatches.push(format!("{:?}: {:?}", contents, result.err())),
+            }
+        }
+        Ok(mismatches)
+    };
+    let mismatches = run().map_err(|e| e.to_string())?;
+    if !mismatches.is_empty() {
+        return Err(format!("mismatched: {:?}", mismatches));
+    }
+    Ok(())
+}
+
+// Runs each in-memory strategy on `content`, which is also at
+// `options.path`, and names those whose results differ from `expected`.
+// Checks that every strategy leaves out stations missing from
+// `--include-file` or listed in `--exclude-file`, as if their lines had been
+// removed from the input.
+fn check_filter(content: &str, options: &Options, dir: &Path) -> Result<(), String> {
+    let include = dir.join("include.txt");
+    let exclude = dir.join("exclude.txt");
+    let input = dir.join("filtered.txt");
+    let kept = |station: &str| station.starts_with('B') && station != "Bulawayo";
+    let remaining: String = content
+        .lines()
+        .filter(|line| kept(line.split_once(';').unwrap().0))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    let run = || -> Result<Vec<String>, Error> {
+        fs::write(&include, "# stations\nBridgetown;13.1\nBulawayo\nAbsent\n")?;
+        fs::write(&exclude, "Bulawayo\n")?;
+        fs::write(&input, content)?;
+        let filtered = Options {
+            path: input.display().to_string(),
+            include_file: Some(include.display().to_string()),
+            exclude_file: Some(exclude.display().to_string()),
+            ..options.clone()
+        };
+        let filtered = Options {
+            station_filter: Some(filter::load(&filtered)?),
+            ..filtered
+        };
+        let mut partial = aggregate(&remaining, options, None, None)?;
+        let expected = format_results(&collect_stations(&mut partial, None)?);
+        if expected.len() != 1 {
+            return Err(Error::Validation(format!(
+                "expected one station, got {:?}",
+                expected
+            )));
+        }
+        mismatched_strategies(content, &filtered, &expected)
+    };
+    let mismatches = run().map_err(|e| e.to_string())?;
+    if !mismatches.is_empty() {
+        return Err(format!("mismatched: {:?}", mismatches));
+    }
+    Ok(())
+}
+
+fn mismatched_strategies(
+    content: &str,
+    options: &Options,
+    expected: &[String],
+) -> Result<Vec<String>, Error> {
+    let strategies = [
+        (
+            "rayon",
+            Options {
+                implementation: Implementation::Rayon,
+                ..options.clone()
+            },
+        ),
+        (
+            "scoped",
+            Options {
+                implementation: Implementation::Scoped,
+                ..options.clone()
+            },
+        ),
+        (
+            "per-core",
+            Options {
+                implementation: Implementation::PerCore,
+                ..options.clone()
+            },
+        ),
+        (
+            "two-pass",
+            Options {
+                implementation: Implementation::TwoPass,
+                ..options.clone()
+            },
+        ),
+        (
+            "std",
+            Options {
+                implementation: Implementation::Std,
+                ..options.clone()
+            },
+        ),
+        (
+            "map index",
+            Options {
+                map: MapKind::Index,
+                ..options.clone()
+            },
+        ),
+        (
+            "fingerprint",
+            Options {
+                key_hash: KeyHash::Fingerprint,
+                verify_fingerprints: true,
+                ..options.clone()
+            },
+        ),
+    ];
+    let mut mismatches = Vec::new();
+    for (name, strategy) in strategies {
+        let mut partial = aggregate(content, &strategy, None, None)?;
+        if format_results(&collect_stations(&mut partial, None)?) != expected {
+            mismatches.push(name.to_string());
+        }
+    }
+    Ok(mismatches)
+}
+
+fn format_results(stations: &[(String, StationData)]) -> Vec<String> {
+    stations
+        .iter()
+        .map(|(name, data)| {
+            format!(
+                "{}={}/{}",
+                name,
+                output::format_station(data, None),
+                data.count
+            )
+        })
+        .collect()
+}
+
+// Checks that `--verify-fingerprints` reports a station whose fingerprint
+// was recorded for another name, as a collision would leave it.
+fn check_fingerprint_collision(content: &str, options: &Options) -> Result<(), String> {
+    let mut names = Fingerprinted::default();
+    for record in RECORDS {
+        let (station, _) = record.split_once(';').unwrap();
+        names.insert(fingerprint::fingerprint(station), station);
+    }
+    fingerprint::verify(content, options, &names).map_err(|e| e.to_string())?;
+    names.insert(fingerprint::fingerprint("Hamburg"), "Bulawayo");
+    match fingerprint::verify(content, options, &names) {
+        Err(Error::Validation(message)) if message.contains("`Bulawayo` and `Hamburg`") => Ok(()),
+        Err(e) => Err(format!("expected a collision, got: {}", e)),
+        Ok(()) => Err("the forged collision was not detected".to_string()),
+    }
+}
+
+// Checks that the perfect hash gives 10,000 names distinct slots and finds
+// no slot for names that are not in the list.
+fn check_perfect_hash() -> Result<(), String> {
+    let names: Vec<_> = (0..10_000)
+        .map(|index| format!("Station {}", index))
+        .collect();
+    let hash = PerfectHash::build(names.clone());
+    let mut seen = vec![false; names.len()];
+    for name in &names {
+        let slot = hash
+            .get(name)
+            .ok_or_else(|| format!("no slot for {}", name))?;
+        if std::mem::replace(&mut seen[slot], true) {
+            return Err(format!("slot {} given twice", slot));
+        }
+    }
+    if let Some(name) = ["Station 10000", "", "station 1"]
+        .into_iter()
+        .find(|name| hash.get(name).is_some())
+    {
+        return Err(format!("found a slot for unlisted {:?}", name));
+    }
+    // Baked tables are looked up from the exported arrays alone.
+    let borrowed: Vec<&str> = hash.names().iter().map(String::as_str).collect();
+    if let Some(name) = names.iter().find(|name| {
+        perfect_hash::lookup(hash.salt(), hash.seeds(), &borrowed, name) != hash.get(name)
+    }) {
+        return Err(format!("table lookup differs for {}", name));
+    }
+    Ok(())
+}
+
+// Checks `--stations` with a list in the challenge's `name;temperature`
+// layout that misses one station of the dataset, which is still aggregated.
+fn check_stations(content: &str, options: &Options, path: &Path) -> Result<(), String> {
+    let list: String = RECORDS
+        .iter()
+        .filter(|record| !record.starts_with("a;"))
+        .map(|record| format!("{}\n", record))
+        .collect();
+    fs::write(path, format!("# known stations\n{}", list)).map_err(|e| e.to_string())?;
+    check(
+        content,
+        &Options {
+            implementation: Implementation::TwoPass,
+            stations: Some(path.display().to_string()),
+            ..options.clone()
+        },
+        0,
+    )
+}
+
+// Checks that a mapped results file written to `path` opens with the same
+// stations in byte order and finds them by name.
+fn check_mapped(content: &str, options: &Options, path: &Path) -> Result<(), String> {
+    let run = || -> Result<(Vec<String>, MappedResults), Error> {
+        let mut partial = aggregate(content, options, None, None)?;
+        let stations = collect_stations(&mut partial, None)?;
+        let mut out = BufWriter::new(fs::File::create(path)?);
+        mapped::write(&mut out, &stations)?;
+        out.flush()?;
+        let names = stations.into_iter().map(|(name, _)| name).collect();
+        Ok((names, MappedResults::open(path)?))
+    };
+    let (names, results) = run().map_err(|e| e.to_string())?;
+
+    let mapped: Vec<_> = results.iter_sorted().map(|(name, _)| name).collect();
+    if mapped != names {
+        return Err(format!("expected stations {:?}, got {:?}", names, mapped));
+    }
+    let hamburg = results.get("Hamburg").ok_or("missing Hamburg")?;
+    if (hamburg.min, hamburg.max, hamburg.count) != (-3.4, 12.0, 3 * REPETITIONS as u64) {
+        return Err(format!("unexpected Hamburg statistics {:?}", hamburg));
+    }
+    if results.get("Atlantis").is_some() {
+        return Err("found a station that is not in the input".to_string());
+    }
+    Ok(())
+}
+
+// Checks that the aggregated stations survive a save and load through `path`.
+fn check_state(content: &str, options: &Options, path: &Path) -> Result<(), String> {
+    let run = || -> Result<String, Error> {
+        let spill = new_spill(options)?;
+        let mut partial = aggregate(content, options, spill.as_ref(), None)?;
+        let stations = collect_stations(&mut partial, spill.as_ref())?;
+        state::save_state(path, &stations.into_iter().collect())?;
+        let mut stations: Vec<_> = state::load_state(path)?.into_iter().collect();
+        output::sort_stations(&mut stations, None);
+        Ok(output::render(&stations, Format::Text, false, None))
+    };
+    let actual = run().map_err(|e| e.to_string())?;
+
+    if actual != EXPECTED {
+        return Err(format!("expected {:?}, got {:?}", EXPECTED, actual));
+    }
+    Ok(())
+}
+
+// Checks that folding the per-chunk results of `ChunkedAggregator` matches the
+// expected output and that its progress ends at the input size.
+fn check_chunked(path: &Path, len: usize) -> Result<(), String> {
+    let run = || -> Result<(String, usize), Error> {
+        let mut total: HashMap<String, StationData> = HashMap::new();
+        let mut processed = 0;
+        for chunk in ChunkedAggregator::with_chunk_size(path, 4096)? {
+            let (bytes, stations) = chunk?;
+            processed = bytes;
+            for (station, data) in stations {
+                total
+                    .entry(station)
+                    .and_modify(|e| e.aggregate(&data))
+                    .or_insert(data);
+            }
+        }
+        let mut stations: Vec<_> = total.into_iter().collect();
+        output::sort_stations(&mut stations, None);
+        Ok((
+            output::render(&stations, Format::Text, false, None),
+            processed,
+        ))
+    };
+    let (actual, processed) = run().map_err(|e| e.to_string())?;
+
+    if actual != EXPECTED {
+        return Err(format!("expected {:?}, got {:?}", EXPECTED, actual));
+    }
+    if processed != len {
+        return Err(format!(
+            "expected {} bytes processed, got {}",
+            len, processed
+        ));
+    }
+    Ok(())
+}
+
+// Checks that statistics built from `for_each_record` match the expected
+// output.
+fn check_records(path: &Path) -> Result<(), String> {
+    let total: Mutex<HashMap<String, StationData>> = Mutex::new(HashMap::new());
+    for_each_record(path, |station, tenths| {
+        let station = String::from_utf8_lossy(station);
+        total
+            .lock()
+            .unwrap()
+            .entry_ref(station.as_ref())
+            .or_insert_with(StationData::new)
+            .update(tenths as f32 / 10.0);
+    })
+    .map_err(|e| e.to_string())?;
+    let mut stations: Vec<_> = total.into_inner().unwrap().into_iter().collect();
+    output::sort_stations(&mut stations, None);
+    let actual = output::render(&stations, Format::Text, false, None);
+
+    if actual != EXPECTED {
+        return Err(format!("expected {:?}, got {:?}", EXPECTED, actual));
+    }
+    Ok(())
+}
+
+// Checks that a reader streamed like stdin, through a queue of one small
+// buffer, matches the expected output, and that stdin rejects modes that need
+// the whole input mapped.
+fn check_reader(content: &str, options: &Options) -> Result<(), String> {
+    let options = Options {
+        queue_depth: Some(1),
+        chunk_size: Some(4096),
+        ..options.clone()
+    };
+    let run = || -> Result<String, Error> {
+        let reader = io::Cursor::new(content.as_bytes().to_vec());
+        let mut partial = pipeline::aggregate_reader(reader, &options, None, None)?;
+        let stations = collect_stations(&mut partial, None)?;
+        Ok(output::render(&stations, Format::Text, false, None))
+    };
+    let actual = run().map_err(|e| e.to_string())?;
+    if actual != EXPECTED {
+        return Err(format!("expected {:?}, got {:?}", EXPECTED, actual));
+    }
+
+    for args in [
+        ["-", "--exact"],
+        ["-", "--self-check"],
+        ["-", "--queue-depth"],
+    ] {
+        let args = args.map(str::to_string);
+        if Options::parse(args.clone().into_iter()).is_ok() {
+            return Err(format!("expected {:?} to be rejected", args));
+        }
+    }
+    Ok(())
+}
+
+// Checks that `StreamingAggregator` fed in chunks splitting lines, or in two
+// merged halves, matches the expected output, that snapshots leave out a
+// partial line until it is flushed, and that malformed lines are errors.
+fn check_streaming(content: &str) -> Result<(), String> {
+    let run = || -> io::Result<(String, String, bool, bool)> {
+        let mut chunked = StreamingAggregator::new();
+        for chunk in content.as_bytes().chunks(7) {
+            chunked.push_chunk(chunk)?;
+        }
+        let chunked = chunked.snapshot().to_brace_string() + "\n";
+
+        // The second half ends without a newline, which `merge` flushes.
+        let middle = content[..content.len() / 2]
+            .rfind('\n')
+            .map_or(0, |pos| pos + 1);
+        let (mut first, mut second) = (StreamingAggregator::new(), StreamingAggregator::new());
+        first.push_chunk(&content.as_bytes()[..middle])?;
+        second.push_chunk(content[middle..].trim_end().as_bytes())?;
+        first.merge(second)?;
+        let merged = first.snapshot().to_brace_string() + "\n";
+
+        first.push_chunk(b"Atlantis;1")?;
+        let before = first.snapshot().get("Atlantis").is_some();
+        first.push_chunk(b".5\r\n")?;
+        let after = first.snapshot().get("Atlantis").map(|stats| stats.max) == Some(1.5);
+        Ok((chunked, merged, before, after))
+    };
+    let (chunked, merged, before, after) = run().map_err(|e| e.to_string())?;
+
+    for (label, actual) in [("chunked", chunked), ("merged", merged)] {
+        if actual != EXPECTED {
+            return Err(format!(
+                "{}: expected {:?}, got {:?}",
+                label, EXPECTED, actual
+            ));
+        }
+    }
+    if before || !after {
+        return Err("expected a partial line in snapshots only once completed".to_string());
+    }
+    let mut streaming = StreamingAggregator::new();
+    match streaming.push_line(b"Hamburg") {
+        Err(e) if e.to_string() == "malformed line 1: missing-delimiter" => Ok(()),
+        Err(e) => Err(format!("expected a missing delimiter, got: {}", e)),
+        Ok(()) => Err("expected an error for a line without a delimiter".to_string()),
+    }
+}
+
+// Checks the library's typed results against the expected output.
+fn check_results(path: &Path) -> Result<(), String> {
+    let results = aggregator::aggregate(path).map_err(|e| e.to_string())?;
+
+    let actual = results.to_brace_string() + "\n";
+    if actual != EXPECTED {
+        return Err(format!("expected {:?}, got {:?}", EXPECTED, actual));
+    }
+    let hamburg = results.get("Hamburg").ok_or("missing Hamburg")?;
+    if (hamburg.min, hamburg.max, hamburg.count) != (-3.4, 12.0, 3 * REPETITIONS as u64) {
+        return Err(format!("unexpected Hamburg statistics {:?}", hamburg));
+    }
+    if results.get("Atlantis").is_some() || results.len() != results.iter_sorted().count() {
+        return Err("inconsistent station lookup".to_string());
+    }
+    Ok(())
+}
@@ -0,0 +1,204 @@
+use crate::output::station_order;
+use crate::StationData;
+use hashbrown::HashMap;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::mem::size_of;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Approximate cost of a map entry on top of the name's bytes: the key
+// `String`, the value and the hash table's control bytes.
+pub const ENTRY_OVERHEAD: usize = size_of::<String>() + size_of::<StationData>() + 8;
+
+// Maximum number of runs merged at once, to stay well below file handle limits.
+const MAX_FAN_IN: usize = 128;
+
+// Estimated memory held by a map entry for `station`.
+pub fn entry_size(station: &str) -> usize {
+    station.len() + ENTRY_OVERHEAD
+}
+
+// Temporary directory holding sorted runs spilled from in-memory maps. The
+// directory and its runs are removed when this is dropped.
+pub struct Spill {
+    dir: PathBuf,
+    pub budget: usize,
+    next_run: AtomicUsize,
+}
+
+impl Spill {
+    // Creates the spill directory; `budget` is the estimated size at which a
+    // single map should be spilled.
+    pub fn new(budget: usize) -> io::Result<Self> {
+        let dir = std::env::temp_dir().join(format!("brc-spill-{}", process::id()));
+        fs::create_dir_all(&dir)?;
+        Ok(Spill {
+            dir,
+            budget,
+            next_run: AtomicUsize::new(0),
+        })
+    }
+
+    // Writes `stations` to a new run sorted by name and empties the map.
+    pub fn write_run<S>(
+        &self,
+        stations: &mut HashMap<String, StationData, S>,
+    ) -> io::Result<PathBuf> {
+        let mut entries: Vec<_> = stations.drain().collect();
+        entries.sort_unstable_by(|a, b| station_order(&a.0, &b.0));
+
+        let mut run = RunWriter::create(self.next_path())?;
+        for (name, data) in &entries {
+            run.write(name, data)?;
+        }
+        run.finish()
+    }
+
+    // Merges `runs` in name order, calling `emit` once per station with its
+    // fully aggregated statistics.
+    pub fn merge(
+        &self,
+        mut runs: Vec<PathBuf>,
+        emit: impl FnMut(String, StationData) -> io::Result<()>,
+    ) -> io::Result<()> {
+        // Collapse runs in batches until a single pass can merge them all.
+        while runs.len() > MAX_FAN_IN {
+            let mut merged = Vec::with_capacity(runs.len().div_ceil(MAX_FAN_IN));
+            for batch in runs.chunks(MAX_FAN_IN) {
+                let mut run = RunWriter::create(self.next_path())?;
+                merge_runs(batch, |name, data| run.write(&name, &data))?;
+                merged.push(run.finish()?);
+                for path in batch {
+                    fs::remove_file(path)?;
+                }
+            }
+            runs = merged;
+        }
+        merge_runs(&runs, emit)
+    }
+
+    fn next_path(&self) -> PathBuf {
+        let id = self.next_run.fetch_add(1, Ordering::Relaxed);
+        self.dir.join(format!("run-{}", id))
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+// K-way merge of sorted runs, combining entries for the same station.
+fn merge_runs(
+    runs: &[PathBuf],
+    mut emit: impl FnMut(String, StationData) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut readers = runs
+        .iter()
+        .map(RunReader::open)
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut pending = Vec::with_capacity(readers.len());
+    let mut heap = BinaryHeap::with_capacity(readers.len());
+    for (index, reader) in readers.iter_mut().enumerate() {
+        let next = reader.next()?;
+        if let Some((name, _)) = &next {
+            heap.push(Reverse((name.clone(), index)));
+        }
+        pending.push(next.map(|(_, data)| data));
+    }
+
+    // `String` orders by bytes, so the heap pops names in `station_order`.
+    let mut current: Option<(String, StationData)> = None;
+    while let Some(Reverse((name, index))) = heap.pop() {
+        let data = pending[index]
+            .take()
+            .expect("heap entry without pending data");
+        if let Some((next_name, next_data)) = readers[index].next()? {
+            pending[index] = Some(next_data);
+            heap.push(Reverse((next_name, index)));
+        }
+
+        match &mut current {
+            Some((station, acc)) if *station == name => acc.aggregate(&data),
+            _ => {
+                if let Some((station, acc)) = current.replace((name, data)) {
+                    emit(station, acc)?;
+                }
+            }
+        }
+    }
+    if let Some((station, acc)) = current {
+        emit(station, acc)?;
+    }
+
+    Ok(())
+}
+
+// Writes run records: a little-endian `u32` name length, the name bytes,
+// then min, max and total as `f32` and the count as `i32`.
+struct RunWriter {
+    path: PathBuf,
+    out: BufWriter<File>,
+}
+
+impl RunWriter {
+    fn create(path: PathBuf) -> io::Result<Self> {
+        let out = BufWriter::new(File::create(&path)?);
+        Ok(RunWriter { path, out })
+    }
+
+    fn write(&mut self, name: &str, data: &StationData) -> io::Result<()> {
+        self.out.write_all(&(name.len() as u32).to_le_bytes())?;
+        self.out.write_all(name.as_bytes())?;
+        self.out.write_all(&data.min_temp.to_le_bytes())?;
+        self.out.write_all(&data.max_temp.to_le_bytes())?;
+        self.out.write_all(&data.total_temp.to_le_bytes())?;
+        self.out.write_all(&data.count.to_le_bytes())
+    }
+
+    fn finish(mut self) -> io::Result<PathBuf> {
+        self.out.flush()?;
+        Ok(self.path)
+    }
+}
+
+struct RunReader {
+    input: BufReader<File>,
+}
+
+impl RunReader {
+    fn open(path: &PathBuf) -> io::Result<Self> {
+        Ok(RunReader {
+            input: BufReader::new(File::open(path)?),
+        })
+    }
+
+    fn next(&mut self) -> io::Result<Option<(String, StationData)>> {
+        let mut len = [0; 4];
+        match self.input.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let mut name = vec![0; u32::from_le_bytes(len) as usize];
+        self.input.read_exact(&mut name)?;
+        let name =
+            String::from_utf8(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let mut fields = [0; 16];
+        self.input.read_exact(&mut fields)?;
+        let field = |i: usize| [fields[i], fields[i + 1], fields[i + 2], fields[i + 3]];
+        let data = StationData {
+            min_temp: f32::from_le_bytes(field(0)),
+            max_temp: f32::from_le_bytes(field(4)),
+            total_temp: f32::from_le_bytes(field(8)),
+            count: i32::from_le_bytes(field(12)),
+        };
+        Ok(Some((name, data)))
+    }
+}
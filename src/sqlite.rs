@@ -0,0 +1,68 @@
+use crate::error::Error;
+use crate::StationData;
+use brc::rounding::Rounding;
+use std::io;
+
+// Writes the stations to a `station_stats` table in the SQLite database at
+// `path`, replacing the table if it exists, in output order.
+#[cfg(feature = "sqlite")]
+pub fn write(
+    path: &str,
+    stations: &[(String, StationData)],
+    rounding: Option<Rounding>,
+) -> Result<(), Error> {
+    use crate::output::StationRecord;
+    use rusqlite::{params, Connection};
+
+    let result = (|| -> rusqlite::Result<()> {
+        let mut connection = Connection::open(path)?;
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(
+            "DROP TABLE IF EXISTS station_stats;
+             CREATE TABLE station_stats (
+                 station TEXT PRIMARY KEY,
+                 min REAL NOT NULL,
+                 mean REAL NOT NULL,
+                 max REAL NOT NULL,
+                 count INTEGER NOT NULL
+             );",
+        )?;
+        {
+            let mut insert = transaction.prepare(
+                "INSERT INTO station_stats (station, min, mean, max, count)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for (station, data) in stations {
+                let record = StationRecord::new(station, data, rounding);
+                // Through the shortest decimal form, so `12.3` is stored as
+                // 12.3 rather than the widened 12.300000190734863.
+                insert.execute(params![
+                    record.station,
+                    widen(record.min),
+                    widen(record.mean),
+                    widen(record.max),
+                    record.count as i64,
+                ])?;
+            }
+        }
+        transaction.commit()
+    })();
+    result.map_err(|e| Error::Io(io::Error::other(format!("failed to write SQLite: {}", e))))
+}
+
+#[cfg(feature = "sqlite")]
+fn widen(value: f32) -> f64 {
+    value.to_string().parse().unwrap_or(value as f64)
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn write(
+    _path: &str,
+    _stations: &[(String, StationData)],
+    _rounding: Option<Rounding>,
+) -> Result<(), Error> {
+    Err(Error::Io(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "`--format sqlite` requires building with the `sqlite` feature",
+    )))
+}
@@ -0,0 +1,96 @@
+// Saving and loading aggregation state, so a later run can fold new input
+// into the statistics of earlier ones. States ending in `.json` are JSON,
+// anything else is the more compact bincode encoding.
+
+use crate::station::StationData;
+use bincode::Options;
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+// Bumped whenever the layout of `StationData` changes.
+const STATE_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct StateRef<'a> {
+    version: u32,
+    stations: &'a HashMap<String, StationData>,
+}
+
+#[derive(Deserialize)]
+struct State {
+    version: u32,
+    stations: HashMap<String, StationData>,
+}
+
+// Writes `stations` to `path`.
+pub fn save_state(
+    path: impl AsRef<Path>,
+    stations: &HashMap<String, StationData>,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut out = BufWriter::new(File::create(path)?);
+    let state = StateRef {
+        version: STATE_VERSION,
+        stations,
+    };
+    if is_json(path) {
+        serde_json::to_writer(&mut out, &state).map_err(io::Error::from)?;
+    } else {
+        bincode_options()
+            .serialize_into(&mut out, &state)
+            .map_err(invalid_data)?;
+    }
+    out.flush()
+}
+
+// Reads stations previously written by `save_state`.
+pub fn load_state(path: impl AsRef<Path>) -> io::Result<HashMap<String, StationData>> {
+    let path = path.as_ref();
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let input = BufReader::new(file);
+    let state: State = if is_json(path) {
+        serde_json::from_reader(input).map_err(|e| corrupt(path, e))?
+    } else {
+        // Bounding reads by the file size keeps corrupt length prefixes from
+        // triggering huge allocations.
+        bincode_options()
+            .with_limit(len)
+            .deserialize_from(input)
+            .map_err(|e| corrupt(path, e))?
+    };
+    if state.version != STATE_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "unsupported state version {} in {}",
+                state.version,
+                path.display()
+            ),
+        ));
+    }
+    Ok(state.stations)
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
+
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::new().with_fixint_encoding()
+}
+
+fn corrupt(path: &Path, e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid state file {}: {}", path.display(), e),
+    )
+}
+
+fn invalid_data(e: bincode::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+
+// Statistic of a station that no longer fits its field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overflow {
+    // More readings than an `i32` can count.
+    Count,
+    // The running total is no longer finite.
+    Total,
+}
+
+impl Overflow {
+    pub fn code(self) -> &'static str {
+        match self {
+            Overflow::Count => "count",
+            Overflow::Total => "total",
+        }
+    }
+}
+
+// Running statistics for a single station.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StationData {
+    pub min_temp: f32,
+    pub max_temp: f32,
+    pub total_temp: f32,
+    pub count: i32,
+}
+
+impl StationData {
+    pub fn new() -> Self {
+        StationData {
+            min_temp: f32::MAX,
+            max_temp: f32::MIN,
+            total_temp: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn update(&mut self, temp: f32) {
+        self.min_temp = f32::min(self.min_temp, temp);
+        self.max_temp = f32::max(self.max_temp, temp);
+        self.total_temp += temp;
+        self.count += 1;
+    }
+
+    // Like `update`, but fails instead of wrapping the count or letting the
+    // total become infinite. The station is left unchanged on failure.
+    pub fn checked_update(&mut self, temp: f32) -> Result<(), Overflow> {
+        let count = self.count.checked_add(1).ok_or(Overflow::Count)?;
+        let total_temp = self.total_temp + temp;
+        if !total_temp.is_finite() {
+            return Err(Overflow::Total);
+        }
+        self.min_temp = f32::min(self.min_temp, temp);
+        self.max_temp = f32::max(self.max_temp, temp);
+        self.total_temp = total_temp;
+        self.count = count;
+        Ok(())
+    }
+
+    pub fn mean(&self) -> f32 {
+        self.total_temp / self.count as f32
+    }
+
+    pub fn aggregate(&mut self, other: &StationData) {
+        self.min_temp = f32::min(self.min_temp, other.min_temp);
+        self.max_temp = f32::max(self.max_temp, other.max_temp);
+        self.total_temp += other.total_temp;
+        self.count += other.count;
+    }
+
+    // Like `aggregate`, but fails instead of wrapping the count or letting
+    // the total become infinite. The station is left unchanged on failure.
+    pub fn checked_aggregate(&mut self, other: &StationData) -> Result<(), Overflow> {
+        let count = self.count.checked_add(other.count).ok_or(Overflow::Count)?;
+        let total_temp = self.total_temp + other.total_temp;
+        if !total_temp.is_finite() {
+            return Err(Overflow::Total);
+        }
+        self.min_temp = f32::min(self.min_temp, other.min_temp);
+        self.max_temp = f32::max(self.max_temp, other.max_temp);
+        self.total_temp = total_temp;
+        self.count = count;
+        Ok(())
+    }
+}
+
+impl Default for StationData {
+    fn default() -> Self {
+        StationData::new()
+    }
+}
@@ -0,0 +1,157 @@
+// `--status-socket`: progress of a running aggregation for orchestration
+// tooling, without scraping stderr. Every connection to the Unix socket gets
+// one line of JSON and is closed:
+//
+//     {"phase":"aggregate","elapsed_seconds":12.5,"bytes_done":4294967296,
+//      "bytes_total":13795000000,"rows_done":305000000,"rows_per_second":24400000.0,
+//      "eta_seconds":27.6}
+//
+// Workers count their lines and bytes into global counters every
+// `STRIDE` bytes, so progress lags by at most that much per worker.
+// Strategies with their own fold (`two-pass`, `std`, `--key-hash fingerprint`
+// and `--map index`) report only their phase.
+
+use serde::Serialize;
+use std::io;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+// Bytes a worker folds between updates of the global counters.
+pub const STRIDE: u64 = 1 << 20;
+
+static PHASE: AtomicU8 = AtomicU8::new(Phase::Setup as u8);
+static BYTES_DONE: AtomicU64 = AtomicU64::new(0);
+static ROWS_DONE: AtomicU64 = AtomicU64::new(0);
+// Zero while unknown, as for stdin and remote input.
+static BYTES_TOTAL: AtomicU64 = AtomicU64::new(0);
+// When the socket was opened and the aggregation started.
+static STARTED: OnceLock<Instant> = OnceLock::new();
+static AGGREGATION_STARTED: OnceLock<Instant> = OnceLock::new();
+
+#[derive(Clone, Copy, PartialEq)]
+enum Phase {
+    Setup,
+    Aggregate,
+    Output,
+}
+
+impl Phase {
+    fn label(self) -> &'static str {
+        match self {
+            Phase::Setup => "setup",
+            Phase::Aggregate => "aggregate",
+            Phase::Output => "output",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Report {
+    phase: &'static str,
+    elapsed_seconds: f64,
+    bytes_done: u64,
+    bytes_total: Option<u64>,
+    rows_done: u64,
+    rows_per_second: Option<f64>,
+    eta_seconds: Option<f64>,
+}
+
+// Starts the aggregation of `total` bytes, zero if unknown.
+pub fn begin(total: u64) {
+    BYTES_TOTAL.store(total, Ordering::Relaxed);
+    AGGREGATION_STARTED.get_or_init(Instant::now);
+    PHASE.store(Phase::Aggregate as u8, Ordering::Relaxed);
+}
+
+// Ends the aggregation after `bytes` and `rows`, including those not yet
+// counted by their workers.
+pub fn finish(bytes: u64, rows: u64) {
+    BYTES_DONE.store(bytes, Ordering::Relaxed);
+    ROWS_DONE.store(rows, Ordering::Relaxed);
+    PHASE.store(Phase::Output as u8, Ordering::Relaxed);
+}
+
+// Counts `bytes` and `rows` more as aggregated.
+pub fn advance(bytes: u64, rows: u64) {
+    BYTES_DONE.fetch_add(bytes, Ordering::Relaxed);
+    ROWS_DONE.fetch_add(rows, Ordering::Relaxed);
+}
+
+fn report() -> Report {
+    let phase = match PHASE.load(Ordering::Relaxed) {
+        phase if phase == Phase::Aggregate as u8 => Phase::Aggregate,
+        phase if phase == Phase::Output as u8 => Phase::Output,
+        _ => Phase::Setup,
+    };
+    let bytes_done = BYTES_DONE.load(Ordering::Relaxed);
+    let rows_done = ROWS_DONE.load(Ordering::Relaxed);
+    let bytes_total = Some(BYTES_TOTAL.load(Ordering::Relaxed)).filter(|&total| total > 0);
+    let aggregating = AGGREGATION_STARTED
+        .get()
+        .map(|start| start.elapsed().as_secs_f64())
+        .filter(|&seconds| seconds > 0.0);
+    let eta_seconds = match (phase, bytes_total, aggregating) {
+        (Phase::Aggregate, Some(total), Some(seconds)) if bytes_done > 0 => {
+            Some(total.saturating_sub(bytes_done) as f64 * seconds / bytes_done as f64)
+        }
+        _ => None,
+    };
+    Report {
+        phase: phase.label(),
+        elapsed_seconds: STARTED
+            .get()
+            .map_or(0.0, |start| start.elapsed().as_secs_f64()),
+        bytes_done,
+        bytes_total,
+        rows_done,
+        rows_per_second: aggregating.map(|seconds| rows_done as f64 / seconds),
+        eta_seconds,
+    }
+}
+
+// The listening socket, removed when dropped.
+pub struct Server {
+    path: String,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// Listens on `path`, replacing a stale socket left there, and answers
+// connections on a background thread.
+#[cfg(unix)]
+pub fn serve(path: &str) -> io::Result<Server> {
+    use std::io::Write;
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    if std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot listen on {}: {}", path, e)))?;
+    STARTED.get_or_init(Instant::now);
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let line = serde_json::to_string(&report()).unwrap_or_default();
+            // Clients that hang up early miss the report.
+            let _ = writeln!(stream, "{}", line);
+        }
+    });
+    Ok(Server {
+        path: path.to_string(),
+    })
+}
+
+#[cfg(not(unix))]
+pub fn serve(_path: &str) -> io::Result<Server> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "`--status-socket` needs Unix domain sockets",
+    ))
+}
@@ -0,0 +1,112 @@
+// The `std` strategy: the whole aggregation with nothing but the standard
+// library, as a portability fallback and a baseline for what the crate's
+// dependencies buy. The input is read into memory with plain `File` reads
+// instead of being mapped, threads come from `std::thread::scope` with one
+// equal byte partition each, and stations are held in std `HashMap`s with
+// their default SipHash hasher, merged on the calling thread. Only the result
+// is handed over to the usual output path.
+
+use crate::aliases;
+use crate::cli::Options;
+use crate::error::Error;
+use crate::filter;
+use crate::thread_stats::ThreadStats;
+use crate::utf8;
+use crate::{detect_overflow, offset_of, overflow_error, skip_line, Partial};
+use brc::chunks;
+use brc::parse::parse_line_in;
+use brc::station::StationData;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::thread;
+use std::time::Instant;
+
+pub fn aggregate<'a>(
+    path: &Path,
+    options: &Options,
+    stats: Option<&ThreadStats>,
+) -> Result<Partial<'a>, Error> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    let content = utf8::decode_block(&bytes, 0, options.utf8, options.delimiter)?;
+    let content = content.as_ref();
+
+    let threads = options
+        .threads
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |threads| threads.get()));
+    let partitions = chunks::chunks(content, content.len().div_ceil(threads).max(1));
+    let checked = detect_overflow(options);
+
+    let folded = thread::scope(|scope| {
+        let workers: Vec<_> = partitions
+            .iter()
+            .enumerate()
+            .map(|(index, partition)| {
+                scope.spawn(move || -> Result<_, Error> {
+                    let start = Instant::now();
+                    let mut partial = Partial::empty();
+                    let mut stations: HashMap<&str, StationData> = HashMap::new();
+                    for line in partition.data.lines() {
+                        partial.rows += 1;
+                        partial.bytes += line.len() as u64 + 1;
+                        match parse_line_in(line, options.delimiter, options.notation) {
+                            Ok((station, _)) if !filter::admits(options, station) => {}
+                            Ok((station, temp)) => {
+                                let station = aliases::resolve(options, station);
+                                let data = stations.entry(station).or_default();
+                                if checked {
+                                    data.checked_update(temp).map_err(|overflow| {
+                                        let offset = offset_of(content, line);
+                                        overflow_error(station, Some(offset), overflow)
+                                    })?;
+                                } else {
+                                    data.update(temp);
+                                }
+                            }
+                            Err(reason) => skip_line(
+                                &mut partial,
+                                offset_of(content, line),
+                                reason,
+                                Cow::Owned(line.to_string()),
+                                options,
+                            )?,
+                        }
+                    }
+                    if let Some(stats) = stats {
+                        stats.record(index, &partial, start.elapsed());
+                    }
+                    Ok((partial, stations))
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("aggregation worker panicked"))
+            .collect::<Result<Vec<_>, Error>>()
+    })?;
+
+    let mut merged = Partial::empty();
+    let mut stations: HashMap<&str, StationData> = HashMap::new();
+    for (partial, other) in folded {
+        merged = merged.merge(partial)?;
+        for (station, data) in other {
+            match stations.get_mut(station) {
+                Some(entry) => entry
+                    .checked_aggregate(&data)
+                    .map_err(|overflow| overflow_error(station, None, overflow))?,
+                None => {
+                    stations.insert(station, data);
+                }
+            }
+        }
+    }
+    merged.stations.extend(
+        stations
+            .into_iter()
+            .map(|(station, data)| (station.to_string(), data)),
+    );
+    Ok(merged)
+}
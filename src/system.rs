@@ -0,0 +1,139 @@
+// The machine a benchmark ran on, recorded with `bench` measurements so that
+// results shared between contributors can be told apart: the CPU, its
+// frequency policy, memory, kernel and the cgroup limits the process runs
+// under. Everything is read from Linux's `/proc` and `/sys` and left out where
+// missing or unreadable; memory speed comes from SMBIOS tables, which only
+// root can usually read.
+
+use crate::cgroup;
+use crate::topology::Topology;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct System {
+    pub os: String,
+    pub arch: String,
+    pub kernel: Option<String>,
+    pub cpu_model: Option<String>,
+    pub physical_cores: usize,
+    pub logical_cpus: usize,
+    // The `cpufreq` governor of CPU 0, and whether turbo or boost clocks are
+    // enabled.
+    pub governor: Option<String>,
+    pub turbo: Option<bool>,
+    pub memory_bytes: Option<u64>,
+    // Configured speed of the fastest memory device, in MT/s.
+    pub memory_speed: Option<u32>,
+    // The cgroup's CPU quota in CPUs and memory limit in bytes, if limited.
+    pub cgroup_cpus: Option<f64>,
+    pub cgroup_memory_bytes: Option<u64>,
+}
+
+impl System {
+    pub fn detect() -> Self {
+        let topology = Topology::detect();
+        System {
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            kernel: read_trimmed("/proc/sys/kernel/osrelease"),
+            cpu_model: cpu_model(),
+            physical_cores: topology.physical,
+            logical_cpus: topology.logical,
+            governor: read_trimmed("/sys/devices/system/cpu/cpu0/cpufreq/scaling_governor"),
+            turbo: turbo(),
+            memory_bytes: meminfo_total(),
+            memory_speed: memory_speed(),
+            cgroup_cpus: topology.quota,
+            cgroup_memory_bytes: cgroup::memory_limit(),
+        }
+    }
+
+    // A one-line description of the known parts, for the report.
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        parts.push(self.cpu_model.clone().unwrap_or_else(|| self.arch.clone()));
+        parts.push(format!(
+            "{} cores, {} CPUs",
+            self.physical_cores, self.logical_cpus
+        ));
+        if let Some(governor) = &self.governor {
+            parts.push(format!("governor {}", governor));
+        }
+        if let Some(turbo) = self.turbo {
+            parts.push(format!("turbo {}", if turbo { "on" } else { "off" }));
+        }
+        match (self.memory_bytes, self.memory_speed) {
+            (Some(bytes), Some(speed)) => parts.push(format!("{} at {} MT/s", gib(bytes), speed)),
+            (Some(bytes), None) => parts.push(gib(bytes)),
+            (None, Some(speed)) => parts.push(format!("memory at {} MT/s", speed)),
+            (None, None) => {}
+        }
+        match &self.kernel {
+            Some(kernel) => parts.push(format!("{} {}", self.os, kernel)),
+            None => parts.push(self.os.clone()),
+        }
+        if let Some(cpus) = self.cgroup_cpus {
+            parts.push(format!("cgroup limit {:.1} CPUs", cpus));
+        }
+        if let Some(bytes) = self.cgroup_memory_bytes {
+            parts.push(format!("cgroup limit {}", gib(bytes)));
+        }
+        parts.join(", ")
+    }
+}
+
+fn gib(bytes: u64) -> String {
+    format!("{:.1} GiB", bytes as f64 / (1u64 << 30) as f64)
+}
+
+fn read_trimmed(path: impl AsRef<Path>) -> Option<String> {
+    let content = fs::read_to_string(path).ok()?;
+    let content = content.trim();
+    (!content.is_empty()).then(|| content.to_string())
+}
+
+// The first `model name` in `/proc/cpuinfo`, as x86 kernels list it.
+fn cpu_model() -> Option<String> {
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo").ok()?;
+    cpuinfo.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key.trim() == "model name").then(|| value.trim().to_string())
+    })
+}
+
+// `intel_pstate` reports disabled turbo, `acpi-cpufreq` enabled boost.
+fn turbo() -> Option<bool> {
+    if let Some(no_turbo) = read_trimmed("/sys/devices/system/cpu/intel_pstate/no_turbo") {
+        return Some(no_turbo == "0");
+    }
+    read_trimmed("/sys/devices/system/cpu/cpufreq/boost").map(|boost| boost == "1")
+}
+
+fn meminfo_total() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+// The highest configured speed among the SMBIOS memory devices (type 17),
+// falling back to their rated speed, from the raw tables.
+fn memory_speed() -> Option<u32> {
+    let entries = fs::read_dir("/sys/firmware/dmi/entries").ok()?;
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("17-"))
+        .filter_map(|entry| fs::read(entry.path().join("raw")).ok())
+        .filter_map(|raw| {
+            let word = |offset: usize| {
+                let length = *raw.get(1)? as usize;
+                (offset + 2 <= length.min(raw.len()))
+                    .then(|| u16::from_le_bytes([raw[offset], raw[offset + 1]]))
+                    .filter(|&speed| speed != 0 && speed != 0xFFFF)
+            };
+            word(0x20).or_else(|| word(0x15)).map(u32::from)
+        })
+        .max()
+}
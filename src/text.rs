@@ -0,0 +1,73 @@
+// The challenge's `{name=min/mean/max, ...}` output, formatted straight into
+// one byte buffer so it can be emitted with a single write. The buffer is
+// reserved up front from the station count and name lengths, and callers that
+// render repeatedly can pass the same one back to reuse its capacity.
+
+use crate::rounding::{Mean, Rounding};
+use crate::station::StationData;
+use std::io::Write;
+
+// Bytes of `{:.1}` for `value`: a sign, the integer digits after rounding,
+// a dot and one decimal. Exact except right at a rounding boundary, where it
+// may be one byte over.
+fn value_bound(value: f32) -> usize {
+    if !value.is_finite() {
+        return 4;
+    }
+    let magnitude = (value.abs() as f64 + 0.05).trunc();
+    let digits = if magnitude < 1.0 {
+        1
+    } else {
+        magnitude.log10() as usize + 1
+    };
+    value.is_sign_negative() as usize + digits + 2
+}
+
+// Upper bound of the bytes `write_results` appends for `stations`.
+pub fn size_bound<'a>(stations: impl IntoIterator<Item = (&'a str, &'a StationData)>) -> usize {
+    // The braces and the newline.
+    let mut size = 3;
+    for (i, (station, data)) in stations.into_iter().enumerate() {
+        size += (i > 0) as usize * 2 + station.len() + 3;
+        size += value_bound(data.min_temp) + value_bound(data.mean()) + value_bound(data.max_temp);
+    }
+    size
+}
+
+// Appends `stations`, in the order given, as the challenge's brace list and a
+// newline to `buf`, reserving `size_bound` bytes first. Means are rounded with
+// `rounding` when given.
+pub fn write_results<'a, I>(buf: &mut Vec<u8>, stations: I, rounding: Option<Rounding>)
+where
+    I: IntoIterator<Item = (&'a str, &'a StationData)>,
+    I::IntoIter: Clone,
+{
+    let stations = stations.into_iter();
+    buf.reserve(size_bound(stations.clone()));
+    write_entries(buf, stations, rounding);
+}
+
+// `write_results` without the reservation, for callers that reserve the
+// buffer themselves, e.g. with room for more output after the list.
+pub fn write_entries<'a>(
+    buf: &mut Vec<u8>,
+    stations: impl IntoIterator<Item = (&'a str, &'a StationData)>,
+    rounding: Option<Rounding>,
+) {
+    buf.push(b'{');
+    for (i, (station, data)) in stations.into_iter().enumerate() {
+        if i > 0 {
+            buf.extend_from_slice(b", ");
+        }
+        buf.extend_from_slice(station.as_bytes());
+        // Writing to a `Vec` cannot fail.
+        let _ = write!(
+            buf,
+            "={:.1}/{}/{:.1}",
+            data.min_temp,
+            Mean(data, rounding),
+            data.max_temp
+        );
+    }
+    buf.extend_from_slice(b"}\n");
+}
@@ -0,0 +1,90 @@
+use crate::cli::Options;
+use crate::{report, Partial};
+use hashbrown::HashSet;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Work done by one worker thread.
+#[derive(Default)]
+struct WorkerStats {
+    bytes: u64,
+    rows: u64,
+    stations: HashSet<String>,
+    busy: Duration,
+}
+
+// Per-worker work statistics collected with `--thread-stats`, to diagnose
+// load imbalance between threads.
+pub struct ThreadStats {
+    workers: Vec<Mutex<WorkerStats>>,
+}
+
+impl ThreadStats {
+    pub fn new(workers: usize) -> Self {
+        ThreadStats {
+            workers: (0..workers).map(|_| Mutex::default()).collect(),
+        }
+    }
+
+    // Adds a finished unit of work, the lines folded into `partial` in
+    // `busy` time, to worker `worker`. Stations already spilled to disk are
+    // not counted.
+    pub fn record(&self, worker: usize, partial: &Partial, busy: Duration) {
+        let Some(slot) = self.workers.get(worker) else {
+            return;
+        };
+        let mut stats = slot.lock().unwrap();
+        stats.bytes += partial.bytes;
+        stats.rows += partial.rows;
+        stats.busy += busy;
+        for station in partial.stations.keys() {
+            if !stats.stations.contains(station.as_str()) {
+                stats.stations.insert(station.clone());
+            }
+        }
+    }
+
+    // Worker index of the current Rayon thread.
+    pub fn current_worker() -> usize {
+        rayon::current_thread_index().unwrap_or(0)
+    }
+
+    pub fn print(&self, options: &Options) {
+        report(options, format_args!("Thread stats:"));
+        report(
+            options,
+            format_args!(
+                "{:>6} {:>14} {:>12} {:>9} {:>12}",
+                "worker", "bytes", "rows", "stations", "busy"
+            ),
+        );
+        let mut busiest = Duration::ZERO;
+        let mut total = Duration::ZERO;
+        for (index, slot) in self.workers.iter().enumerate() {
+            let stats = slot.lock().unwrap();
+            busiest = busiest.max(stats.busy);
+            total += stats.busy;
+            report(
+                options,
+                format_args!(
+                    "{:>6} {:>14} {:>12} {:>9} {:>12}",
+                    index,
+                    stats.bytes,
+                    stats.rows,
+                    stats.stations.len(),
+                    format!("{:.1?}", stats.busy)
+                ),
+            );
+        }
+        let mean = total.as_secs_f64() / self.workers.len().max(1) as f64;
+        if mean > 0.0 {
+            report(
+                options,
+                format_args!(
+                    "Imbalance (busiest / mean busy time): {:.2}",
+                    busiest.as_secs_f64() / mean
+                ),
+            );
+        }
+    }
+}
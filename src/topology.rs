@@ -0,0 +1,36 @@
+use crate::cgroup;
+
+// CPU topology used to pick the default number of worker threads.
+pub struct Topology {
+    // Physical cores and logical CPUs available to the process.
+    pub physical: usize,
+    pub logical: usize,
+    // The cgroup's CPU quota in CPUs, as containers set it, if limited.
+    pub quota: Option<f64>,
+}
+
+impl Topology {
+    pub fn detect() -> Self {
+        let logical = num_cpus::get();
+        Topology {
+            physical: num_cpus::get_physical().clamp(1, logical),
+            logical,
+            quota: cgroup::cpu_quota(),
+        }
+    }
+
+    pub fn smt(&self) -> bool {
+        self.logical > self.physical
+    }
+
+    // One worker per physical core: aggregation is memory-bound, and
+    // hyperthreads competing for the same core's caches and load ports slow
+    // it down on several machines. Within a CPU quota, more workers than
+    // whole CPUs of quota are throttled in turn rather than run in parallel.
+    pub fn default_threads(&self) -> usize {
+        match self.quota {
+            Some(quota) => self.physical.min((quota as usize).max(1)),
+            None => self.physical,
+        }
+    }
+}
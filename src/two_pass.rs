@@ -0,0 +1,227 @@
+// The `two-pass` strategy: a first pass collects the exact set of station
+// names, which fixes a read-only table from name to slot, and a second pass
+// folds readings into per-thread arrays of those slots. The second pass
+// never inserts or resizes, at the cost of reading the input twice.
+//
+// With `--stations`, the listed names replace the first pass and slots are
+// found with a perfect hash. Stations missing from the list fall back to the
+// usual station map. Builds with the `baked-stations` feature use the table
+// baked into the binary the same way when `--stations` is not given.
+
+use crate::aliases;
+use crate::baked;
+use crate::cli::Options;
+use crate::counters;
+use crate::error::Error;
+use crate::filter;
+use crate::logging;
+use crate::perfect_hash::{self, PerfectHash};
+use crate::thread_stats::ThreadStats;
+use crate::{detect_overflow, offset_of, overflow_error, process_line, skip_line, Partial};
+use brc::parse::{parse_line_in, split_once, BOM};
+use brc::station::StationData;
+use hashbrown::{HashMap, HashSet};
+use rayon::prelude::*;
+use serde_json::json;
+use std::borrow::Cow;
+use std::time::Instant;
+
+// Where each station's slot is.
+enum Slots<'a> {
+    // Found by the first pass.
+    Collected(Vec<&'a str>, HashMap<&'a str, usize>),
+    // Listed in `--stations`.
+    Known(PerfectHash),
+    // Baked into the binary at build time.
+    Baked,
+}
+
+impl Slots<'_> {
+    #[inline]
+    fn get(&self, station: &str) -> Option<usize> {
+        match self {
+            Slots::Collected(_, slots) => slots.get(station).copied(),
+            Slots::Known(hash) => hash.get(station),
+            Slots::Baked => baked::get(station),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Slots::Collected(names, _) => names.len(),
+            Slots::Known(hash) => hash.names().len(),
+            Slots::Baked => baked::names().len(),
+        }
+    }
+
+    fn name(&self, slot: usize) -> &str {
+        match self {
+            Slots::Collected(names, _) => names[slot],
+            Slots::Known(hash) => &hash.names()[slot],
+            Slots::Baked => baked::names()[slot],
+        }
+    }
+}
+
+pub fn aggregate<'a>(
+    content: &'a str,
+    options: &Options,
+    stats: Option<&ThreadStats>,
+) -> Result<Partial<'a>, Error> {
+    let start = Instant::now();
+    let slots = match &options.stations {
+        Some(path) => Slots::Known(perfect_hash::load(path, options.delimiter)?),
+        None if baked::ENABLED => Slots::Baked,
+        None => {
+            let names = station_names(content, options);
+            let slots = names
+                .iter()
+                .enumerate()
+                .map(|(slot, &name)| (name, slot))
+                .collect();
+            Slots::Collected(names, slots)
+        }
+    };
+    let len = slots.len();
+    if options.verbose {
+        let source = match slots {
+            Slots::Collected(..) => "pass",
+            Slots::Known(_) => "perfect hash",
+            Slots::Baked => "baked",
+        };
+        let elapsed = start.elapsed();
+        logging::event(
+            "station_set",
+            format_args!("Station set {}: {} stations in {:?}", source, len, elapsed),
+            json!({ "source": source, "stations": len, "seconds": elapsed.as_secs_f64() }),
+        );
+    }
+
+    let checked = detect_overflow(options);
+    let fold = |(mut partial, mut data, start): (Partial<'a>, Vec<StationData>, Instant),
+                line: &'a str|
+     -> Result<_, Error> {
+        partial.rows += 1;
+        partial.bytes += line.len() as u64 + 1;
+        counters::line();
+        match parse_line_in(line, options.delimiter, options.notation) {
+            Ok((station, _)) if !filter::admits(options, station) => {}
+            Ok((station, temp)) => {
+                let station = aliases::resolve(options, station);
+                let overflowed =
+                    |overflow| overflow_error(station, Some(offset_of(content, line)), overflow);
+                match slots.get(station) {
+                    Some(slot) if checked => {
+                        counters::lookup();
+                        data[slot].checked_update(temp).map_err(overflowed)?;
+                    }
+                    Some(slot) => {
+                        counters::lookup();
+                        data[slot].update(temp);
+                    }
+                    // Only stations missing from `--stations` have no slot.
+                    None => {
+                        process_line(&mut partial, station, temp, checked).map_err(overflowed)?
+                    }
+                }
+            }
+            Err(reason) => skip_line(
+                &mut partial,
+                offset_of(content, line),
+                reason,
+                Cow::Borrowed(line),
+                options,
+            )?,
+        }
+        Ok((partial, data, start))
+    };
+
+    let (mut partial, data, _) = content
+        .par_lines()
+        .try_fold(
+            || {
+                (
+                    Partial::empty(),
+                    vec![StationData::new(); len],
+                    Instant::now(),
+                )
+            },
+            fold,
+        )
+        .map(|folded| {
+            folded.inspect(|(partial, _, start)| {
+                if let Some(stats) = stats {
+                    stats.record(ThreadStats::current_worker(), partial, start.elapsed());
+                }
+            })
+        })
+        .try_reduce(
+            || {
+                (
+                    Partial::empty(),
+                    vec![StationData::new(); len],
+                    Instant::now(),
+                )
+            },
+            |(a, mut a_data, start), (b, b_data, _)| {
+                for (index, (slot, other)) in a_data.iter_mut().zip(&b_data).enumerate() {
+                    slot.checked_aggregate(other)
+                        .map_err(|overflow| overflow_error(slots.name(index), None, overflow))?;
+                }
+                Ok((a.merge(b)?, a_data, start))
+            },
+        )?;
+
+    if !partial.stations.is_empty() {
+        let list = match (&slots, &options.stations) {
+            (Slots::Known(_), Some(path)) => Some(path.as_str()),
+            (Slots::Baked, _) => Some("the baked station table"),
+            _ => None,
+        };
+        if let Some(list) = list {
+            logging::event(
+                "warning",
+                format_args!(
+                    "warning: stations missing from {}: {}",
+                    list,
+                    partial.stations.len()
+                ),
+                json!({ "stations_missing": partial.stations.len(), "list": list }),
+            );
+        }
+    }
+    // Listed stations without readings, and stations seen only on malformed
+    // lines, are left out.
+    partial.stations.extend(
+        data.into_iter()
+            .enumerate()
+            .filter(|(_, data)| data.count > 0)
+            .map(|(slot, data)| (slots.name(slot).to_string(), data)),
+    );
+    Ok(partial)
+}
+
+// The distinct station fields of `content`'s lines, split at the delimiter
+// the way the parser splits them, filtered and resolved through `--aliases`,
+// without parsing temperatures.
+fn station_names<'a>(content: &'a str, options: &Options) -> Vec<&'a str> {
+    content
+        .par_lines()
+        .fold(HashSet::new, |mut names, line| {
+            let line = line.strip_prefix(BOM).unwrap_or(line);
+            match split_once(line, options.delimiter) {
+                Some((station, _)) if filter::admits(options, station) => {
+                    names.insert(aliases::resolve(options, station));
+                }
+                _ => {}
+            }
+            names
+        })
+        .reduce(HashSet::new, |a, b| {
+            let (mut large, small) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+            large.extend(small);
+            large
+        })
+        .into_iter()
+        .collect()
+}
@@ -0,0 +1,134 @@
+// Decoding of the mapped input as UTF-8, with the policy selected by
+// `--utf8`.
+
+use crate::error::Error;
+use brc::chunks;
+use rayon::prelude::*;
+use std::borrow::Cow;
+use std::str::FromStr;
+
+// Bytes validated per task by the parallel check.
+const VALIDATE_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+// How input that is not valid UTF-8 is handled, selected with `--utf8`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Utf8 {
+    // Validate the whole input up front and reject it at the first invalid
+    // sequence.
+    Strict,
+    // Replace invalid sequences in station names with U+FFFD, copying the
+    // input only if it has any. Invalid sequences after a line's delimiter
+    // are still rejected.
+    Lossy,
+    // Skip validation, for trusted generated files.
+    Trust,
+}
+
+impl FromStr for Utf8 {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(Utf8::Strict),
+            "lossy" => Ok(Utf8::Lossy),
+            "trust" => Ok(Utf8::Trust),
+            _ => Err(()),
+        }
+    }
+}
+
+// Decodes the whole input according to `policy`, validating in parallel.
+// `delimiter` ends the station names that `Lossy` repairs.
+pub fn decode(bytes: &[u8], policy: Utf8, delimiter: u8) -> Result<Cow<'_, str>, Error> {
+    let invalid = match policy {
+        Utf8::Trust => None,
+        Utf8::Strict | Utf8::Lossy => first_invalid(bytes),
+    };
+    match (invalid, policy) {
+        // Either validated or trusted by the user.
+        (None, _) => Ok(Cow::Borrowed(unsafe {
+            std::str::from_utf8_unchecked(bytes)
+        })),
+        (Some(offset), Utf8::Strict) => Err(invalid_at(offset)),
+        (Some(offset), _) => replace_in_names(bytes, offset, 0, delimiter).map(Cow::Owned),
+    }
+}
+
+// Decodes a block read by the pipeline strategy according to `policy`.
+// `offset` is the block's position in the input, for error messages.
+pub fn decode_block(
+    bytes: &[u8],
+    offset: usize,
+    policy: Utf8,
+    delimiter: u8,
+) -> Result<Cow<'_, str>, Error> {
+    match policy {
+        Utf8::Strict => std::str::from_utf8(bytes)
+            .map(Cow::Borrowed)
+            .map_err(|e| invalid_at(offset + e.valid_up_to())),
+        Utf8::Lossy => match std::str::from_utf8(bytes) {
+            Ok(text) => Ok(Cow::Borrowed(text)),
+            Err(e) => replace_in_names(bytes, e.valid_up_to(), offset, delimiter).map(Cow::Owned),
+        },
+        Utf8::Trust => Ok(Cow::Borrowed(unsafe {
+            std::str::from_utf8_unchecked(bytes)
+        })),
+    }
+}
+
+// Byte offset of the first invalid UTF-8 sequence, if any. Line-aligned
+// chunks are validated in parallel; no valid sequence contains a newline, so
+// splitting after one never cuts through a character.
+fn first_invalid(bytes: &[u8]) -> Option<usize> {
+    chunks::split(bytes, VALIDATE_CHUNK_SIZE)
+        .into_par_iter()
+        .filter_map(|range| {
+            std::str::from_utf8(&bytes[range.clone()])
+                .err()
+                .map(|e| range.start + e.valid_up_to())
+        })
+        .min()
+}
+
+// Copies `bytes`, valid up to `invalid`, validating each line from the one
+// holding `invalid` on its own: valid lines are copied as they are, and
+// invalid sequences in the station name before `delimiter` become U+FFFD.
+// One after the delimiter is an error at its offset plus `base`, since only
+// names are repaired.
+fn replace_in_names(
+    bytes: &[u8],
+    invalid: usize,
+    base: usize,
+    delimiter: u8,
+) -> Result<String, Error> {
+    let start = bytes[..invalid]
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |newline| newline + 1);
+    let mut text = String::with_capacity(bytes.len() + 16);
+    // Everything before the line is valid.
+    text.push_str(unsafe { std::str::from_utf8_unchecked(&bytes[..start]) });
+    let mut offset = start;
+    for line in bytes[start..].split_inclusive(|&b| b == b'\n') {
+        match std::str::from_utf8(line) {
+            Ok(line) => text.push_str(line),
+            Err(_) => {
+                let end = line
+                    .iter()
+                    .position(|&b| b == delimiter)
+                    .unwrap_or(line.len());
+                let (name, rest) = line.split_at(end);
+                let rest = std::str::from_utf8(rest)
+                    .map_err(|e| invalid_at(base + offset + end + e.valid_up_to()))?;
+                text.push_str(&String::from_utf8_lossy(name));
+                text.push_str(rest);
+            }
+        }
+        offset += line.len();
+    }
+    Ok(text)
+}
+
+fn invalid_at(offset: usize) -> Error {
+    Error::Parse(format!("invalid UTF-8 at byte {}", offset))
+}
@@ -0,0 +1,157 @@
+// Time windows for `--window`: timestamps are parsed into Unix seconds,
+// floored to a multiple of the window width and labelled with the start of
+// their window. Like `parse`, everything here is panic-free on any input.
+
+// Earliest and latest timestamps accepted, 0000-01-01T00:00:00Z and
+// 9999-12-31T23:59:59Z, so every window start formats as four-digit years.
+const MIN_TIMESTAMP: i64 = -62_167_219_200;
+const MAX_TIMESTAMP: i64 = 253_402_300_799;
+
+// Parses a window width in seconds: a positive integer followed by `s`, `m`,
+// `h` or `d`.
+pub fn parse_width(value: &str) -> Option<u64> {
+    let multiplier = match value.as_bytes().last()? {
+        b's' => 1,
+        b'm' => 60,
+        b'h' => 60 * 60,
+        b'd' => 24 * 60 * 60,
+        _ => return None,
+    };
+    let count: u64 = value[..value.len() - 1].parse().ok()?;
+    count.checked_mul(multiplier).filter(|&width| width > 0)
+}
+
+// Parses a timestamp into Unix seconds: either integer seconds since the
+// epoch, or an ISO 8601 `YYYY-MM-DDTHH:MM:SS` with a space allowed for the
+// `T`, optional fractional seconds, which are truncated, and an optional `Z`
+// or `+HH:MM`/`-HH:MM` offset. Times without an offset are taken as UTC.
+pub fn parse_timestamp(value: &str) -> Option<i64> {
+    let bytes = value.as_bytes();
+    let timestamp = if bytes.len() >= 19 && bytes[4] == b'-' {
+        parse_datetime(bytes)?
+    } else {
+        value.parse().ok()?
+    };
+    (MIN_TIMESTAMP..=MAX_TIMESTAMP)
+        .contains(&timestamp)
+        .then_some(timestamp)
+}
+
+fn parse_datetime(bytes: &[u8]) -> Option<i64> {
+    let separators = [(4, b'-'), (7, b'-'), (13, b':'), (16, b':')];
+    if separators.iter().any(|&(at, byte)| bytes[at] != byte) || !matches!(bytes[10], b'T' | b' ') {
+        return None;
+    }
+    let year = digits(&bytes[0..4])?;
+    let month = digits(&bytes[5..7])?;
+    let day = digits(&bytes[8..10])?;
+    let hour = digits(&bytes[11..13])?;
+    let minute = digits(&bytes[14..16])?;
+    let second = digits(&bytes[17..19])?;
+    if !(1..=12).contains(&month)
+        || day == 0
+        || day > days_in_month(year, month)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return None;
+    }
+
+    let mut rest = &bytes[19..];
+    if let Some(fraction) = rest.strip_prefix(b".") {
+        let len = fraction.iter().take_while(|b| b.is_ascii_digit()).count();
+        if len == 0 {
+            return None;
+        }
+        rest = &fraction[len..];
+    }
+    let offset = match rest {
+        [] | [b'Z'] => 0,
+        [sign @ (b'+' | b'-'), hours @ .., b':', m1, m2] if hours.len() == 2 => {
+            let hours = digits(hours)?;
+            let minutes = digits(&[*m1, *m2])?;
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            let offset = (hours * 60 + minutes) as i64 * 60;
+            if *sign == b'+' {
+                offset
+            } else {
+                -offset
+            }
+        }
+        _ => return None,
+    };
+
+    let days = days_from_civil(year as i64, month, day);
+    Some(days * 86_400 + (hour * 3600 + minute * 60 + second) as i64 - offset)
+}
+
+// Start of the `width`-second window that contains `timestamp`, with windows
+// aligned to the epoch.
+pub fn window_start(timestamp: i64, width: u64) -> i64 {
+    let width = i64::try_from(width).unwrap_or(i64::MAX);
+    timestamp - timestamp.rem_euclid(width)
+}
+
+// Formats Unix seconds as `YYYY-MM-DDTHH:MM:SSZ`, which sorts in time order.
+pub fn format_timestamp(timestamp: i64) -> String {
+    let (days, seconds) = (timestamp.div_euclid(86_400), timestamp.rem_euclid(86_400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn digits(bytes: &[u8]) -> Option<u32> {
+    bytes.iter().try_fold(0, |value: u32, &b| {
+        b.is_ascii_digit().then(|| value * 10 + (b - b'0') as u32)
+    })
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 of a proleptic Gregorian date, and back; Howard
+// Hinnant's `days_from_civil` and `civil_from_days`.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month as i64 + 9) % 12) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}